@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use futures::StreamExt;
 
 use clap::Parser;
@@ -20,7 +22,7 @@ fn spawn_tube_handler(mut tube: Tube) {
       while let Some(tube_event) = tube.next().await {
           println!("TubeLoop: Tube({}) event: {:?}", tube_id, tube_event);
           match tube_event {
-              TubeEvent::ClientHasFinishedSending => {
+              TubeEvent::ClientHasFinishedSending(_) => {
                   println!("TubeLoop:  responding with ServerHasFinishedSending...");
                   tube.has_finished_sending().await.unwrap();
                   println!("TubeLoop:    sent!");
@@ -36,13 +38,23 @@ fn spawn_channel_handler(mut channel: tubez::server::Channel) {
     tokio::spawn(async move {
         while let Some(channel_event) = channel.next().await {
             match channel_event {
-                ChannelEvent::NewTube(tube) => {
-                    println!("ChannelLoop: Tube({}) arrived!", tube.get_id());
-                    spawn_tube_handler(tube);
+                ChannelEvent::NewTube(pending_tube) => {
+                    println!("ChannelLoop: Tube({}) arrived!", pending_tube.get_id());
+                    match pending_tube.accept(HashMap::new()).await {
+                        Ok(tube) => spawn_tube_handler(tube),
+                        Err(e) => println!("ChannelLoop: Error accepting Tube: {:?}", e),
+                    }
 
                     // Only expect 1 Tube
                     break;
-                }
+                },
+                ChannelEvent::GoAway { error_code, message, .. } => {
+                    println!(
+                        "ChannelLoop: Client is going away ({:?}): {}",
+                        error_code,
+                        message,
+                    );
+                },
             }
         }
         println!("ChannelLoop: Dropping channel!");
@@ -71,7 +83,7 @@ async fn main() {
             while let Some(tube_event) = tube.next().await {
               println!("TubeEvent: {:?}", tube_event);
               match tube_event {
-                tubez::tube::TubeEvent::ClientHasFinishedSending => {
+                tubez::tube::TubeEvent::ClientHasFinishedSending(_) => {
                   println!("  responding with ServerHasFinishedSending...");
                   tube.has_finished_sending().await.unwrap();
                   println!("    sent!");
@@ -0,0 +1,742 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::io::Read;
+
+use bytes::Bytes;
+use clap::Parser;
+
+use tubez::capture;
+use tubez::Features;
+use tubez::Frame;
+use tubez::FrameParseError;
+use tubez::FrameTag;
+use tubez::PeerType;
+
+#[derive(Clone,Copy,Debug,PartialEq,clap::ArgEnum)]
+enum InputFormat {
+    /**
+     * Detect the format from the input's contents.
+     */
+    Auto,
+    /**
+     * Raw frames, exactly as they were sent on the wire.
+     */
+    Binary,
+    /**
+     * A capture written by tubez::capture::FrameRecorder.
+     */
+    Capture,
+    /**
+     * Raw frames as hex digits (whitespace is ignored).
+     */
+    Hex,
+}
+
+/**
+ * Decodes a stream of tubez frames and flags malformed frames and frames that
+ * violate the protocol.
+ */
+#[derive(Parser)]
+#[clap(name = "tubez-inspect")]
+struct CLIArgs {
+    /**
+     * The file to read, or "-" to read stdin.
+     */
+    #[clap(default_value = "-")]
+    input: String,
+
+    #[clap(long, arg_enum, default_value = "auto")]
+    format: InputFormat,
+
+    /**
+     * Print one JSON object per frame instead of human-readable text.
+     */
+    #[clap(long)]
+    json: bool,
+}
+
+/**
+ * Where an inspected frame came from: an offset into a raw byte stream, or a
+ * record in a capture.
+ */
+#[derive(Debug,PartialEq)]
+enum Origin {
+    Offset(usize),
+    Record {
+        timestamp_micros: u64,
+        channel_id: u64,
+        peer_type: PeerType,
+        direction: capture::Direction,
+    },
+}
+
+#[derive(Debug)]
+struct InspectedFrame {
+    origin: Origin,
+    /**
+     * None if the frame was malformed, or if it has a FrameType the decoder
+     * skips (see RESERVED_EXTENSION_FRAMETYPES).
+     */
+    frame: Option<Frame>,
+    problems: Vec<String>,
+}
+
+/**
+ * Tracks the frames sent by one peer on one channel and flags frames that
+ * arrive out of order.
+ */
+#[derive(Debug,Default)]
+struct OrderChecker {
+    aborted_tubes: HashSet<u64>,
+    finished_tubes: HashSet<u64>,
+    fragmenting_tubes: HashSet<u64>,
+    frames_seen: usize,
+    is_going_away: bool,
+    new_tubes: HashSet<u64>,
+    seen_settings: bool,
+}
+impl OrderChecker {
+    fn check(&mut self, frame: &Frame) -> Vec<String> {
+        let mut violations = vec![];
+        let tag = FrameTag::from(frame);
+        match frame {
+            Frame::Settings { features, .. } => {
+                if self.seen_settings {
+                    // A resumable channel's peers send Settings again on each
+                    // new transport, followed by anything the other peer may
+                    // not have received (including NewTube and 
+                    // HasFinishedSending frames), and any payload that was 
+                    // only partially sent on the old transport is sent again 
+                    // in full.
+                    if features.contains(Features::SESSION_RESUMPTION) {
+                        self.fragmenting_tubes.clear();
+                        self.finished_tubes.clear();
+                        self.new_tubes.clear();
+                    } else {
+                        violations.push("Duplicate Settings frame".to_string());
+                    }
+                } else if self.frames_seen > 0 {
+                    violations.push(format!(
+                        "Settings frame preceded by {} other frame(s)",
+                        self.frames_seen,
+                    ));
+                }
+                self.seen_settings = true;
+            },
+            Frame::NewTube { tube_id, .. } => {
+                if self.is_going_away {
+                    violations.push(format!("NewTube(id={}) after GoAway", tube_id));
+                }
+                // A Tube's id may be reused once the Tube has been finished or
+                // aborted.
+                let is_open = self.new_tubes.contains(tube_id)
+                    && !self.finished_tubes.contains(tube_id)
+                    && !self.aborted_tubes.contains(tube_id);
+                if is_open {
+                    violations.push(format!("Duplicate NewTube(id={})", tube_id));
+                }
+                self.new_tubes.insert(*tube_id);
+                self.aborted_tubes.remove(tube_id);
+                self.finished_tubes.remove(tube_id);
+                self.fragmenting_tubes.remove(tube_id);
+            },
+            Frame::Payload { tube_id, .. }
+            | Frame::CompressedPayload { tube_id, .. }
+            | Frame::PayloadFragment { tube_id, .. } => {
+                if self.finished_tubes.contains(tube_id) {
+                    violations.push(format!(
+                        "{:?} on Tube(id={}) after HasFinishedSending",
+                        tag,
+                        tube_id,
+                    ));
+                }
+                if self.aborted_tubes.contains(tube_id) {
+                    violations.push(format!(
+                        "{:?} on Tube(id={}) after Abort",
+                        tag,
+                        tube_id,
+                    ));
+                }
+                if let Frame::PayloadFragment { .. } = frame {
+                    self.fragmenting_tubes.insert(*tube_id);
+                } else {
+                    self.fragmenting_tubes.remove(tube_id);
+                }
+            },
+            Frame::ClientHasFinishedSending { tube_id, .. }
+            | Frame::ServerHasFinishedSending { tube_id, .. } => {
+                if self.fragmenting_tubes.remove(tube_id) {
+                    violations.push(format!(
+                        "{:?} on Tube(id={}) before its fragmented payload \
+                         was terminated",
+                        tag,
+                        tube_id,
+                    ));
+                }
+                if !self.finished_tubes.insert(*tube_id) {
+                    violations.push(format!(
+                        "Duplicate {:?} on Tube(id={})",
+                        tag,
+                        tube_id,
+                    ));
+                }
+            },
+            Frame::Abort { tube_id, .. }
+            | Frame::NewTubeRejected { tube_id, .. } => {
+                self.aborted_tubes.insert(*tube_id);
+                self.fragmenting_tubes.remove(tube_id);
+            },
+            Frame::GoAway { .. } => self.is_going_away = true,
+            _ => (),
+        }
+        self.frames_seen += 1;
+        violations
+    }
+}
+
+fn describe_parse_error(error: &FrameParseError) -> String {
+    match error {
+        FrameParseError::BodyTooLong { frame_type, expected, actual }
+        | FrameParseError::BodyTooShort { frame_type, expected, actual } => format!(
+            "Body length mismatch: FrameType(0x{:02X}) expects a {} byte body, \
+             but the frame header declares {} bytes",
+            frame_type,
+            expected,
+            actual,
+        ),
+        error => format!("Malformed frame: {:?}", error),
+    }
+}
+
+fn decode_frame(frame_data: Bytes) -> Result<Option<Frame>, String> {
+    match tubez::Decoder::new().decode(frame_data) {
+        Ok(mut frames) => Ok(frames.pop_front()),
+        Err(e) => Err(describe_parse_error(&e.parse_error)),
+    }
+}
+
+fn inspect_frame(
+    origin: Origin,
+    frame_data: Bytes,
+    order_checker: &mut OrderChecker,
+) -> InspectedFrame {
+    match decode_frame(frame_data) {
+        Ok(frame) => {
+            let problems = match &frame {
+                Some(frame) => order_checker.check(frame),
+                None => vec![],
+            };
+            InspectedFrame { origin, frame, problems }
+        },
+        Err(problem) => InspectedFrame {
+            origin,
+            frame: None,
+            problems: vec![problem],
+        },
+    }
+}
+
+/**
+ * Returns the length of the frame at the start of `data` (header included), or
+ * None if `data` doesn't hold all of it yet.
+ */
+fn complete_frame_len(data: &[u8]) -> Option<usize> {
+    match data {
+        [_, len_hi, len_lo, ..] => {
+            let frame_len = 3 + usize::from(u16::from_be_bytes([*len_hi, *len_lo]));
+            if data.len() >= frame_len {
+                Some(frame_len)
+            } else {
+                None
+            }
+        },
+        _ => None,
+    }
+}
+
+fn truncated_frame_problem(remaining: &[u8]) -> String {
+    let needed = match remaining {
+        [_, len_hi, len_lo, ..] => 3 + usize::from(u16::from_be_bytes([*len_hi, *len_lo])),
+        _ => 3,
+    };
+    format!(
+        "Truncated frame: {} byte(s) remain, but the frame needs {}",
+        remaining.len(),
+        needed,
+    )
+}
+
+/**
+ * Splits a raw stream of frames (sent by a single peer) on the body length in
+ * each frame's header and inspects each one in turn.
+ */
+fn inspect_raw_stream(data: &[u8]) -> Vec<InspectedFrame> {
+    let mut inspected_frames = vec![];
+    let mut order_checker = OrderChecker::default();
+    let mut offset = 0;
+    while offset < data.len() {
+        let remaining = &data[offset..];
+        let frame_len = match complete_frame_len(remaining) {
+            Some(frame_len) => frame_len,
+            None => {
+                inspected_frames.push(InspectedFrame {
+                    origin: Origin::Offset(offset),
+                    frame: None,
+                    problems: vec![truncated_frame_problem(remaining)],
+                });
+                break;
+            },
+        };
+
+        inspected_frames.push(inspect_frame(
+            Origin::Offset(offset),
+            Bytes::copy_from_slice(&remaining[..frame_len]),
+            &mut order_checker,
+        ));
+        offset += frame_len;
+    }
+    inspected_frames
+}
+
+/**
+ * The frames sent in one direction on one channel of a capture.
+ */
+#[derive(Default)]
+struct CapturedStream {
+    order_checker: OrderChecker,
+    /**
+     * Bytes at the end of the stream's records so far that don't yet form a
+     * complete frame.
+     */
+    pending: Vec<u8>,
+    last_record: Option<(u64, PeerType)>,
+}
+
+/**
+ * Inspects each frame in a capture. A capture records data in the chunks it
+ * was sent or received in, so the data recorded in each direction on each
+ * channel is split into frames the same way as a raw stream. A frame that
+ * spans several records is reported at the record that completes it.
+ *
+ * Frames are checked for ordering violations against the other frames sent in
+ * the same direction on the same channel.
+ */
+fn inspect_capture(data: &[u8]) -> Result<Vec<InspectedFrame>, String> {
+    let reader = match capture::CaptureReader::new(data) {
+        Ok(reader) => reader,
+        Err(e) => return Err(format!("Error reading capture: {:?}", e)),
+    };
+
+    let mut streams: HashMap<(u64, capture::Direction), CapturedStream> = HashMap::new();
+    let mut stream_order = vec![];
+    let mut inspected_frames = vec![];
+    for record in reader {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => return Err(format!("Error reading capture: {:?}", e)),
+        };
+        let stream_key = (record.channel_id, record.direction);
+        let stream = streams.entry(stream_key).or_insert_with(|| {
+            stream_order.push(stream_key);
+            CapturedStream::default()
+        });
+        stream.last_record = Some((record.timestamp_micros, record.peer_type));
+        stream.pending.extend_from_slice(&record.data);
+
+        let mut offset = 0;
+        while let Some(frame_len) = complete_frame_len(&stream.pending[offset..]) {
+            inspected_frames.push(inspect_frame(
+                Origin::Record {
+                    timestamp_micros: record.timestamp_micros,
+                    channel_id: record.channel_id,
+                    peer_type: record.peer_type,
+                    direction: record.direction,
+                },
+                Bytes::copy_from_slice(&stream.pending[offset..offset + frame_len]),
+                &mut stream.order_checker,
+            ));
+            offset += frame_len;
+        }
+        stream.pending.drain(..offset);
+    }
+
+    for (channel_id, direction) in stream_order {
+        let stream = &streams[&(channel_id, direction)];
+        if let (false, Some((timestamp_micros, peer_type))) =
+            (stream.pending.is_empty(), stream.last_record) {
+            inspected_frames.push(InspectedFrame {
+                origin: Origin::Record {
+                    timestamp_micros,
+                    channel_id,
+                    peer_type,
+                    direction,
+                },
+                frame: None,
+                problems: vec![truncated_frame_problem(&stream.pending)],
+            });
+        }
+    }
+    Ok(inspected_frames)
+}
+
+fn parse_hex(data: &[u8]) -> Result<Vec<u8>, String> {
+    let digits: Vec<u8> = data.iter()
+        .copied()
+        .filter(|byte| !byte.is_ascii_whitespace())
+        .collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err("Hex input has an odd number of digits".to_string());
+    }
+    digits.chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).unwrap_or("");
+            u8::from_str_radix(pair, 16)
+                .map_err(|_| format!("Invalid hex digits: {:?}", pair))
+        })
+        .collect()
+}
+
+fn detect_format(data: &[u8]) -> InputFormat {
+    if data.starts_with(capture::CAPTURE_MAGIC) {
+        InputFormat::Capture
+    } else if !data.is_empty()
+        && data.iter().all(|byte| byte.is_ascii_hexdigit() || byte.is_ascii_whitespace()) {
+        InputFormat::Hex
+    } else {
+        InputFormat::Binary
+    }
+}
+
+fn hex_string(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn frame_json(frame: &Frame) -> serde_json::Value {
+    let mut json = match frame {
+        Frame::ClientHasFinishedSending { tube_id, trailers }
+        | Frame::ServerHasFinishedSending { tube_id, trailers } =>
+            serde_json::json!({ "tube_id": tube_id, "trailers": trailers }),
+        Frame::AbortAck { tube_id } =>
+            serde_json::json!({ "tube_id": tube_id }),
+        Frame::Drain { reason } =>
+            serde_json::json!({ "reason": format!("{:?}", reason) }),
+        Frame::NewTube { tube_id, headers }
+        | Frame::NewTubeAccepted { tube_id, headers } =>
+            serde_json::json!({ "tube_id": tube_id, "headers": headers }),
+        Frame::NewTubeRejected { tube_id, code, headers } => serde_json::json!({
+            "tube_id": tube_id,
+            "code": code,
+            "headers": headers,
+        }),
+        Frame::Payload { tube_id, ack_id, processing_ack, data }
+        | Frame::CompressedPayload { tube_id, ack_id, processing_ack, data } => serde_json::json!({
+            "tube_id": tube_id,
+            "ack_id": ack_id,
+            "processing_ack": processing_ack,
+            "data": hex_string(data),
+        }),
+        Frame::PayloadAck { tube_id, ack_id, processed } => serde_json::json!({
+            "tube_id": tube_id,
+            "ack_id": ack_id,
+            "processed": processed,
+        }),
+        Frame::PayloadSequence { tube_id, sequence } => serde_json::json!({
+            "tube_id": tube_id,
+            "sequence": sequence,
+        }),
+        Frame::CumulativePayloadAck { tube_id, up_to, ranges } => serde_json::json!({
+            "tube_id": tube_id,
+            "up_to": up_to,
+            "ranges": ranges.iter()
+                .map(|range| [*range.start(), *range.end()])
+                .collect::<Vec<_>>(),
+        }),
+        Frame::Abort { tube_id, reason } => serde_json::json!({
+            "tube_id": tube_id,
+            "reason": format!("{:?}", reason),
+        }),
+        Frame::PayloadFragment { tube_id, data } =>
+            serde_json::json!({ "tube_id": tube_id, "data": hex_string(data) }),
+        Frame::GoAway { last_tube_id, error_code, message } => serde_json::json!({
+            "last_tube_id": last_tube_id,
+            "error_code": format!("{:?}", error_code),
+            "message": message,
+        }),
+        Frame::Ping { opaque } | Frame::Pong { opaque } =>
+            serde_json::json!({ "opaque": opaque }),
+        Frame::Settings {
+            version,
+            features,
+            max_frame_size,
+            max_concurrent_tubes,
+            initial_window_size,
+        } => serde_json::json!({
+            "version": version,
+            "features": features.bits(),
+            "max_frame_size": max_frame_size,
+            "max_concurrent_tubes": max_concurrent_tubes,
+            "initial_window_size": initial_window_size,
+        }),
+        Frame::Session { token } =>
+            serde_json::json!({ "token": format!("{:032x}", token) }),
+        Frame::WindowUpdate { tube_id, credit } => serde_json::json!({
+            "tube_id": tube_id,
+            "credit": credit,
+        }),
+        Frame::Priority { tube_id, weight } => serde_json::json!({
+            "tube_id": tube_id,
+            "weight": weight,
+        }),
+        Frame::Extension { tube_id, ext_type, data } => serde_json::json!({
+            "tube_id": tube_id,
+            "ext_type": ext_type,
+            "data": hex_string(data),
+        }),
+    };
+    json["type"] = format!("{:?}", FrameTag::from(frame)).into();
+    json
+}
+
+fn origin_label(origin: &Origin) -> String {
+    match origin {
+        Origin::Offset(offset) => format!("0x{:06x}", offset),
+        Origin::Record { timestamp_micros, channel_id, peer_type, direction } => format!(
+            "{} channel={} {:?} {:?}",
+            timestamp_micros,
+            channel_id,
+            peer_type,
+            direction,
+        ),
+    }
+}
+
+fn print_inspected_frame(inspected: &InspectedFrame, as_json: bool) {
+    if as_json {
+        let mut json = serde_json::json!({
+            "frame": inspected.frame.as_ref().map(frame_json),
+            "problems": inspected.problems,
+        });
+        match &inspected.origin {
+            Origin::Offset(offset) => json["offset"] = (*offset).into(),
+            Origin::Record { timestamp_micros, channel_id, peer_type, direction } => {
+                json["timestamp_micros"] = (*timestamp_micros).into();
+                json["channel_id"] = (*channel_id).into();
+                json["peer_type"] = format!("{:?}", peer_type).into();
+                json["direction"] = format!("{:?}", direction).into();
+            },
+        }
+        println!("{}", json);
+        return;
+    }
+
+    let label = origin_label(&inspected.origin);
+    match &inspected.frame {
+        Some(frame) => println!("{}  {:?}", label, frame),
+        None if inspected.problems.is_empty() =>
+            println!("{}  (skipped frame with an unrecognized extension FrameType)", label),
+        None => println!("{}  <malformed>", label),
+    }
+    for problem in &inspected.problems {
+        println!("    !! {}", problem);
+    }
+}
+
+fn read_input(input: &str) -> Result<Vec<u8>, std::io::Error> {
+    if input == "-" {
+        let mut data = vec![];
+        std::io::stdin().read_to_end(&mut data)?;
+        Ok(data)
+    } else {
+        std::fs::read(input)
+    }
+}
+
+fn main() {
+    let cli_args = CLIArgs::parse();
+
+    let data = match read_input(&cli_args.input) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", cli_args.input, e);
+            std::process::exit(2);
+        },
+    };
+
+    let format = match cli_args.format {
+        InputFormat::Auto => detect_format(&data),
+        format => format,
+    };
+    let inspected_frames = match format {
+        InputFormat::Capture => inspect_capture(&data),
+        InputFormat::Hex => parse_hex(&data).map(|data| inspect_raw_stream(&data)),
+        InputFormat::Auto | InputFormat::Binary => Ok(inspect_raw_stream(&data)),
+    };
+    let inspected_frames = match inspected_frames {
+        Ok(inspected_frames) => inspected_frames,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        },
+    };
+
+    let mut num_problems = 0;
+    for inspected in &inspected_frames {
+        print_inspected_frame(inspected, cli_args.json);
+        num_problems += inspected.problems.len();
+    }
+    if num_problems > 0 {
+        eprintln!(
+            "Found {} problem(s) in {} frame(s).",
+            num_problems,
+            inspected_frames.len(),
+        );
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod inspect_tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    #[derive(Clone,Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+    impl Write for SharedBuf {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(data)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn flags_payload_after_has_finished_sending() {
+        // ClientHasFinishedSending(tube_id=1), then Payload(tube_id=1, "late").
+        let data = parse_hex(b"00 0002 0001  03 0008 0001 0000 6c617465").unwrap();
+        let inspected = inspect_raw_stream(&data);
+        assert_eq!(inspected.len(), 2);
+        assert!(inspected[0].problems.is_empty());
+        assert_eq!(inspected[1].origin, Origin::Offset(5));
+        assert_eq!(inspected[1].frame, Some(Frame::Payload {
+            tube_id: 1,
+            ack_id: None,
+            processing_ack: false,
+            data: Bytes::from_static(b"late"),
+        }));
+        assert_eq!(inspected[1].problems, vec![
+            "Payload on Tube(id=1) after HasFinishedSending".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn flags_body_length_mismatch_and_continues() {
+        // A PayloadAck whose body is one byte short, followed by a Drain.
+        let data = parse_hex(b"04 0003 000100  01 0000").unwrap();
+        let inspected = inspect_raw_stream(&data);
+        assert_eq!(inspected.len(), 2);
+        assert!(inspected[0].frame.is_none());
+        assert!(inspected[0].problems[0].starts_with("Body length mismatch"));
+        assert_eq!(
+            inspected[1].frame,
+            Some(Frame::Drain { reason: tubez::DrainReason::Unspecified }),
+        );
+    }
+
+    #[test]
+    fn resumable_channels_may_repeat_settings() {
+        let settings = |features| Frame::Settings {
+            version: tubez::PROTOCOL_VERSION,
+            features,
+            max_frame_size: 1024,
+            max_concurrent_tubes: 10,
+            initial_window_size: 0,
+        };
+        let new_tube = Frame::NewTube { tube_id: 1, headers: Default::default() };
+        let has_finished_sending = 
+            Frame::ClientHasFinishedSending { tube_id: 1, trailers: None };
+        let mut checker = OrderChecker::default();
+        assert!(checker.check(&settings(Features::SESSION_RESUMPTION)).is_empty());
+        assert!(checker.check(&new_tube).is_empty());
+        assert!(checker.check(&has_finished_sending).is_empty());
+        // Frames the peer may not have received are replayed after resuming.
+        assert!(checker.check(&settings(Features::SESSION_RESUMPTION)).is_empty());
+        assert!(checker.check(&new_tube).is_empty());
+        assert!(checker.check(&has_finished_sending).is_empty());
+        assert_eq!(
+            checker.check(&settings(Features::empty())),
+            vec!["Duplicate Settings frame".to_string()],
+        );
+    }
+
+    #[test]
+    fn tube_ids_may_be_reused_once_the_tube_is_done() {
+        let new_tube = Frame::NewTube { tube_id: 1, headers: Default::default() };
+        let payload = Frame::Payload {
+            tube_id: 1,
+            ack_id: None,
+            processing_ack: false,
+            data: Bytes::from_static(b"again"),
+        };
+        let mut checker = OrderChecker::default();
+        assert!(checker.check(&new_tube).is_empty());
+        assert_eq!(
+            checker.check(&new_tube),
+            vec!["Duplicate NewTube(id=1)".to_string()],
+        );
+        assert!(checker.check(&Frame::ClientHasFinishedSending {
+            tube_id: 1,
+            trailers: None,
+        }).is_empty());
+        assert!(checker.check(&new_tube).is_empty());
+        assert!(checker.check(&payload).is_empty());
+        assert!(checker.check(&Frame::Abort {
+            tube_id: 1,
+            reason: tubez::AbortReason::ApplicationAbort,
+        }).is_empty());
+        assert!(checker.check(&new_tube).is_empty());
+        assert!(checker.check(&payload).is_empty());
+    }
+
+    #[test]
+    fn flags_truncated_frame() {
+        let data = parse_hex(b"03 0010 0001").unwrap();
+        let inspected = inspect_raw_stream(&data);
+        assert_eq!(inspected.len(), 1);
+        assert!(inspected[0].problems[0].starts_with("Truncated frame"));
+    }
+
+    #[test]
+    fn splits_captured_chunks_into_frames() {
+        let capture = SharedBuf::default();
+        {
+            let recorder = capture::FrameRecorder::new(capture.clone()).unwrap();
+            // Ping(opaque=1), Ping(opaque=2), split mid-way through the second
+            // Ping, then the start of a frame that never completes.
+            let pings = parse_hex(b"0a 0008 0000000000000001  0a 0008 0000000000000002").unwrap();
+            let (first_chunk, second_chunk) = pings.split_at(15);
+            let direction = capture::Direction::Received;
+            recorder.record_chunk(1, PeerType::Server, direction, first_chunk);
+            recorder.record_chunk(1, PeerType::Server, direction, second_chunk);
+            recorder.record_chunk(1, PeerType::Server, direction, &[0x0A, 0x00]);
+        }
+
+        let inspected = inspect_capture(&capture.0.lock().unwrap()).unwrap();
+        assert_eq!(inspected.len(), 3);
+        assert_eq!(inspected[0].frame, Some(Frame::Ping { opaque: 1 }));
+        assert_eq!(inspected[1].frame, Some(Frame::Ping { opaque: 2 }));
+        assert!(inspected[1].problems.is_empty());
+        assert!(inspected[2].frame.is_none());
+        assert!(inspected[2].problems[0].starts_with("Truncated frame"));
+    }
+
+    #[test]
+    fn detects_input_format() {
+        assert_eq!(detect_format(b"01 0000\n"), InputFormat::Hex);
+        assert_eq!(detect_format(&[0x01, 0x00, 0x00]), InputFormat::Binary);
+        assert_eq!(detect_format(b"TUBEZCAP\x01"), InputFormat::Capture);
+    }
+}
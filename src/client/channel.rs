@@ -1,49 +1,251 @@
 use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Duration;
 
 use hyper::body::HttpBody;
 
+use crate::common::capture;
+use crate::common::ChannelConfig;
+use crate::common::COMPRESSION_HEADER;
+use crate::common::Compression;
+use crate::common::DEFAULT_TUBE_WEIGHT;
+use crate::common::DrainError;
+use crate::common::FLOW_CONTROL_HEADER;
+use crate::common::FlowControl;
 use crate::common::frame;
+use crate::common::GoAwayError;
+use crate::common::InvertedFuture;
+use crate::common::Keepalive;
+use crate::common::NegotiatedSettings;
 use crate::common::PeerType;
+use crate::common::PRIORITY_HEADER;
+use crate::common::resume_transport;
+use crate::common::send_drain;
+use crate::common::send_go_away;
+use crate::common::SendScheduler;
+use crate::common::send_settings;
+use crate::common::SendSettingsError;
+use crate::common::SESSION_HEADER;
+use crate::common::session_header_value;
+use crate::common::spawn_drain_deadline;
+use crate::common::spawn_keepalive;
 use crate::common::tube;
 use crate::common::UniqueIdError;
 use crate::common::UniqueIdManager;
+use crate::common::weight_from_header_value;
+
+/**
+ * How long a resumable channel waits before its first attempt to reconnect 
+ * to the server once its transport is lost. The wait doubles after each 
+ * failed attempt, up to MAX_RESUME_BACKOFF.
+ */
+const MIN_RESUME_BACKOFF: Duration = Duration::from_millis(50);
+const MAX_RESUME_BACKOFF: Duration = Duration::from_secs(1);
 
 #[derive(Debug)]
 pub enum ChannelConnectError {
     InitError(hyper::Error),
+    SendSettingsError(SendSettingsError),
+    /**
+     * The server didn't send its Settings frame within the channel's 
+     * settings_timeout (see ChannelConfig).
+     */
+    TimedOutWaitingOnSettings(Duration),
+}
+
+#[derive(Debug)]
+pub enum ChannelEvent {
+    GoAway {
+        last_tube_id: u64,
+        error_code: frame::GoAwayErrorCode,
+        message: String,
+    },
+}
+
+#[derive(Debug)]
+struct ChannelContext {
+    /**
+     * Set once either peer has started draining the channel. No new Tubes 
+     * may be made after this.
+     */
+    is_draining: bool,
+    /**
+     * Set once either peer has sent a GoAway. No further events will be
+     * published (and no new Tubes may be made) after this.
+     */
+    is_going_away: bool,
+    pending_events: VecDeque<ChannelEvent>,
+    /**
+     * Set once the server's Session frame has arrived, in which case the 
+     * channel is resumed on a new transport if its current one is lost.
+     */
+    session_token: Option<u128>,
+    /**
+     * Set once the server's Settings frame has arrived.
+     */
+    settings: Option<NegotiatedSettings>,
+    waker: Option<std::task::Waker>,
+}
+impl ChannelContext {
+    fn new() -> Self {
+        ChannelContext {
+            is_draining: false,
+            is_going_away: false,
+            pending_events: VecDeque::new(),
+            session_token: None,
+            settings: None,
+            waker: None,
+        }
+    }
+}
+
+fn mark_going_away(ctx: &std::sync::Weak<Mutex<ChannelContext>>) {
+    if let Some(ctx) = ctx.upgrade() {
+        let mut ctx = ctx.lock().unwrap();
+        ctx.is_going_away = true;
+        if let Some(waker) = ctx.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/**
+ * Tries to resume the channel identified by `token` on a new transport until 
+ * the channel's session_resume_window has elapsed. Returns the body of the 
+ * server's response once the channel has been moved onto the new transport, 
+ * or None if the channel couldn't be resumed in time.
+ */
+async fn resume_channel(
+    hyper_client: &hyper::Client<hyper::client::HttpConnector>,
+    server_uri: &hyper::Uri,
+    token: u128,
+    body_sender: &Arc<tokio::sync::Mutex<hyper::body::Sender>>,
+    tube_managers: &Mutex<HashMap<u64, Arc<Mutex<tube::TubeManager>>>>,
+    config: &ChannelConfig,
+    channel_id: u64,
+) -> Option<hyper::Body> {
+    let window = config.session_resume_window?;
+    let deadline = tokio::time::Instant::now() + window;
+    let mut backoff = MIN_RESUME_BACKOFF;
+    loop {
+        if tokio::time::Instant::now() + backoff >= deadline {
+            return None;
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_RESUME_BACKOFF);
+
+        let (new_sender, req_body) = hyper::Body::channel();
+        let req_body = match &config.frame_recorder {
+            Some(recorder) => capture::record_sent_body(
+                req_body,
+                recorder.clone(),
+                channel_id,
+                PeerType::Client,
+            ),
+            None => req_body,
+        };
+        let req = hyper::Request::builder()
+          .method(hyper::Method::POST)
+          .uri(format!("{}", server_uri))
+          .header(SESSION_HEADER, session_header_value(token))
+          .body(req_body)
+          .unwrap();
+
+        log::trace!("Resuming channel with {}...", server_uri);
+        let response = match tokio::time::timeout_at(deadline, hyper_client.request(req)).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(e)) => {
+                log::trace!("Error reconnecting to server: {:?}", e);
+                continue;
+            },
+            Err(_) => return None,
+        };
+        if !response.status().is_success() {
+            log::error!(
+                "Server refused to resume the channel (status={}).",
+                response.status(),
+            );
+            return None;
+        }
+        match resume_transport(body_sender, new_sender, config, tube_managers).await {
+            Ok(()) => return Some(response.into_body()),
+            Err(e) => log::trace!("Error resuming channel: {:?}", e),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum MakeTubeError {
+    ChannelDraining,
+    ChannelGoingAway,
     FrameEncodeError(frame::encode::FrameEncodeError),
-    InternalErrorDuplicateTubeId(u16),
+    InternalErrorDuplicateTubeId(u64),
+    MaxConcurrentTubesExceeded(u16),
+    InvalidPriority(String),
+    /**
+     * The server didn't advertise Features::NEWTUBE_HANDSHAKE, so it will 
+     * never accept or reject the Tube.
+     */
+    NewTubeHandshakeNotSupported,
+    /**
+     * The Tube was aborted (or the channel went away) before the server
+     * responded to it.
+     */
+    Aborted(frame::AbortReason),
+    Rejected {
+        code: u16,
+        headers: HashMap<String, String>,
+    },
+    /**
+     * The server neither accepted nor rejected the Tube within the channel's 
+     * tube_acceptance_timeout, so the Tube was aborted.
+     */
+    TimedOutWaitingOnAcceptance(std::time::Duration),
+    UnsupportedCompression(String),
     TubeIdsExhausted,
     UnknownTransportError,
 }
 
 pub struct Channel {
+    config: ChannelConfig,
     body_sender: Arc<tokio::sync::Mutex<hyper::body::Sender>>,
+    ctx: Arc<Mutex<ChannelContext>>,
+    id: u64,
+    keepalive: Arc<Mutex<Keepalive>>,
+    send_scheduler: Arc<SendScheduler>,
     tube_id_manager: UniqueIdManager,
-    tube_managers: Arc<Mutex<HashMap<u16, Arc<Mutex<tube::TubeManager>>>>>,
+    tube_managers: Arc<Mutex<HashMap<u64, Arc<Mutex<tube::TubeManager>>>>>,
 }
 impl Channel {
     pub(in crate::client) async fn new(
         hyper_client: &hyper::Client<hyper::client::HttpConnector>,
         headers: HashMap<String, String>,
         server_uri: &hyper::Uri,
+        config: ChannelConfig,
     ) -> Result<Self, ChannelConnectError> {
-        Self::new_impl(hyper_client, headers, server_uri).await
+        Self::new_impl(hyper_client, headers, server_uri, config).await
     }
 
     async fn new_impl(
         hyper_client: &hyper::Client<hyper::client::HttpConnector>,
         _headers: HashMap<String, String>, // TODO
         server_uri: &hyper::Uri,
+        config: ChannelConfig,
     ) -> Result<Self, ChannelConnectError> {
         let (body_sender, req_body) = hyper::Body::channel();
         let body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+        let channel_id = capture::next_channel_id();
+        let req_body = match &config.frame_recorder {
+            Some(recorder) => capture::record_sent_body(
+                req_body,
+                recorder.clone(),
+                channel_id,
+                PeerType::Client,
+            ),
+            None => req_body,
+        };
         let req = hyper::Request::builder()
           .method(hyper::Method::POST)
           .uri(format!("{}", &server_uri))
@@ -56,117 +258,650 @@ impl Channel {
             Err(e) => return Err(ChannelConnectError::InitError(e)),
         };
         let mut res_body = response.into_body();
+        if let Err(e) = send_settings(&body_sender, &config).await {
+            return Err(ChannelConnectError::SendSettingsError(e));
+        }
+
         let tube_managers = Arc::new(Mutex::new(HashMap::new()));
+        let ctx = Arc::new(Mutex::new(ChannelContext::new()));
+        let send_scheduler = Arc::new(SendScheduler::new());
 
         let body_sender_weak = Arc::downgrade(&body_sender);
+        let ctx_weak = Arc::downgrade(&ctx);
+        let keepalive = Arc::new(Mutex::new(Keepalive::new()));
+        let (settings_arrived, settings_resolver) = InvertedFuture::new();
+        let mut settings_resolver = Some(settings_resolver);
+
+        let dead_ctx_weak = ctx_weak.clone();
+        spawn_keepalive(
+            keepalive.clone(),
+            body_sender_weak.clone(),
+            tube_managers.clone(),
+            &config,
+            move || mark_going_away(&dead_ctx_weak),
+        );
+
+        let keepalive2 = keepalive.clone();
         let tube_mgrs2 = tube_managers.clone();
+        let tube_mgrs3 = tube_managers.clone();
+        let config2 = config.clone();
+        let send_scheduler2 = send_scheduler.clone();
+        let hyper_client = hyper_client.clone();
+        let server_uri = server_uri.clone();
         tokio::spawn(async move {
             let mut tube_mgrs = tube_mgrs2;
-            let mut frame_decoder = frame::Decoder::new();
-            let mut frame_handler = frame::FrameHandler::new(
+            let mut frame_decoder = frame::Decoder::new_with_config(
+                config2.decoder_config.clone(),
+            );
+            let mut frame_handler = frame::FrameHandler::new_with_send_scheduler(
                 PeerType::Client,
                 &mut tube_mgrs,
+                &config2,
+                send_scheduler2,
             );
 
-            while let Some(data_result) = res_body.data().await {
-                // This seems hacky...but it works.
-                //
-                // When the sender is dropped, res_body.data().await yields 
-                // Some(Buf{}) (an empty Buf)...presumably to indicate EOM? 
-                // Weird...but I guess it works?
-                //
-                // A better solution might be to wrap res_body.data() inside some
-                // stream that ends when EITHER .data() returns None OR 
-                // body_sender is dropped. That way the async loop 
-                // /intentionally/ polls and stops iterating when all tubes + 
-                // channels have been dropped.
-                let mut body_sender = match body_sender_weak.upgrade() {
-                    Some(body_sender) => body_sender,
-                    None => break,
-                };
+            'transport: loop {
+                while let Some(data_result) = res_body.data().await {
+                    // This seems hacky...but it works.
+                    //
+                    // When the sender is dropped, res_body.data().await yields 
+                    // Some(Buf{}) (an empty Buf)...presumably to indicate EOM? 
+                    // Weird...but I guess it works?
+                    //
+                    // A better solution might be to wrap res_body.data() inside some
+                    // stream that ends when EITHER .data() returns None OR 
+                    // body_sender is dropped. That way the async loop 
+                    // /intentionally/ polls and stops iterating when all tubes + 
+                    // channels have been dropped.
+                    let mut body_sender = match body_sender_weak.upgrade() {
+                        Some(body_sender) => body_sender,
+                        None => break 'transport,
+                    };
+
+                    let raw_data = match data_result {
+                        Ok(data) => data,
+                        Err(e) => {
+                            log::trace!("Stream of data from server has errored: `{:?}`", e);
+                            break;
+                        }
+                    };
 
-                let raw_data = match data_result {
-                    Ok(data) => data,
-                    Err(e) => {
-                        log::trace!("Stream of data from server has errored: `{:?}`", e);
-                        break;
+                    if let Some(recorder) = &config2.frame_recorder {
+                        recorder.record_chunk(
+                            channel_id,
+                            PeerType::Client,
+                            capture::Direction::Received,
+                            &raw_data,
+                        );
                     }
-                };
 
-                let mut new_frames = match frame_decoder.decode(raw_data.to_vec()) {
-                    Ok(frames) => frames,
-                    Err(e) => {
-                        log::error!("Frame decode error: {:?}", e);
-                        return;
-                    },
-                };
+                    // Frames that were decoded before any malformed data in 
+                    // this chunk are handled before the decode error is.
+                    let (mut new_frames, decode_error) = 
+                        match frame_decoder.decode(raw_data) {
+                            Ok(frames) => (frames, None),
+                            Err(mut e) => (std::mem::take(&mut e.decoded_frames), Some(e)),
+                        };
+
+                    let mut protocol_violation = None;
+                    while let Some(frame) = new_frames.pop_front() {
+                        log::trace!("Processing frame: {:?}", frame);
+                        match frame_handler.handle_frame(frame, &mut body_sender).await {
+                            Ok(frame::FrameHandlerResult::NewTube(_pending_tube)) => {
+                                // TODO: Server-initiated tubes aren't supported yet.
+                                log::error!(
+                                    "Received a NewTube frame from the server, but \
+                                     server-initiated tubes aren't supported yet!"
+                                );
+                            },
+                            Ok(frame::FrameHandlerResult::Drain(reason)) => {
+                                log::trace!("Server is draining the channel ({:?}).", reason);
+                                if let Some(ctx) = ctx_weak.upgrade() {
+                                    ctx.lock().unwrap().is_draining = true;
+                                }
+                            },
+                            Ok(frame::FrameHandlerResult::GoAway {
+                                last_tube_id,
+                                error_code,
+                                message,
+                            }) => {
+                                if let Some(ctx) = ctx_weak.upgrade() {
+                                    let mut ctx = ctx.lock().unwrap();
+                                    ctx.pending_events.push_back(
+                                        ChannelEvent::GoAway {
+                                            last_tube_id,
+                                            error_code,
+                                            message,
+                                        }
+                                    );
+                                    ctx.is_going_away = true;
+                                    if let Some(waker) = ctx.waker.take() {
+                                        waker.wake();
+                                    }
+                                }
+                                keepalive2.lock().unwrap().stop();
+                            },
+                            Ok(frame::FrameHandlerResult::Pong { opaque }) => {
+                                keepalive2.lock().unwrap().handle_pong(opaque);
+                            },
+                            Ok(frame::FrameHandlerResult::Session { token }) => {
+                                if let Some(ctx) = ctx_weak.upgrade() {
+                                    ctx.lock().unwrap().session_token = Some(token);
+                                }
+                            },
+                            Ok(frame::FrameHandlerResult::Settings(settings)) => {
+                                keepalive2.lock().unwrap().set_negotiated(
+                                    settings.features.contains(frame::Features::KEEPALIVE)
+                                );
+                                if let Some(ctx) = ctx_weak.upgrade() {
+                                    ctx.lock().unwrap().settings = Some(settings);
+                                }
+                                if let Some(mut resolver) = settings_resolver.take() {
+                                    resolver.resolve(());
+                                }
+                            },
+                            Ok(frame::FrameHandlerResult::FullyHandled) => (),
+                            Err(e) if e.is_fatal() => {
+                                protocol_violation = Some((
+                                    frame::GoAwayErrorCode::from(&e),
+                                    format!("{:?}", e),
+                                ));
+                                break;
+                            },
+                            Err(e) => log::error!("Error handling frame: {:?}", e),
+                        }
+                    }
 
-                while let Some(frame) = new_frames.pop_front() {
-                    log::trace!("Processing frame: {:?}", frame);
-                    match frame_handler.handle_frame(frame, &mut body_sender).await {
-                        Ok(frame::FrameHandlerResult::NewTube(_tube)) => {
-                            // TODO: Server-initiated tubes aren't supported yet.
-                            log::error!(
-                                "Received a NewTube frame from the server, but \
-                                 server-initiated tubes aren't supported yet!"
-                            );
-                        },
-                        Ok(frame::FrameHandlerResult::FullyHandled) => (),
-                        Err(e) => log::error!("Error handling frame: {:?}", e),
+                    // A fatal FrameHandler error ends the channel right away, but a 
+                    // decode error only does once the frames before it are handled.
+                    let protocol_violation = protocol_violation.or_else(|| decode_error.map(|e| (
+                        frame::GoAwayErrorCode::from(&e.parse_error),
+                        format!("{:?}", e.parse_error),
+                    )));
+                    if let Some((error_code, message)) = protocol_violation {
+                        // The server has violated the protocol, so nothing else it 
+                        // sends on this channel can be trusted. Tell the server why, 
+                        // terminate every Tube, and stop reading from the server.
+                        log::error!("Protocol violation from server: {}", message);
+                        if let Err(e) = send_go_away(
+                            &body_sender,
+                            frame_handler.last_peer_tube_id(),
+                            error_code,
+                            &message,
+                            frame_handler.peer_max_frame_size(),
+                        ).await {
+                            log::error!("Error sending GoAway: {:?}", e);
+                        }
+                        frame_handler.abort_all_tubes(
+                            frame::AbortReason::ProtocolViolation
+                        );
+                        keepalive2.lock().unwrap().stop();
+                        if let Some(ctx) = ctx_weak.upgrade() {
+                            let mut ctx = ctx.lock().unwrap();
+                            ctx.is_going_away = true;
+                            if let Some(waker) = ctx.waker.take() {
+                                waker.wake();
+                            }
+                        }
+                        return;
                     }
                 }
+
+                // The transport has been lost. A resumable channel tries to carry
+                // on over a new one; any other channel is done.
+                let token = match ctx_weak.upgrade() {
+                    Some(ctx) => {
+                        let ctx = ctx.lock().unwrap();
+                        if ctx.is_going_away { None } else { ctx.session_token }
+                    },
+                    None => None,
+                };
+                let (token, body_sender) = match (token, body_sender_weak.upgrade()) {
+                    (Some(token), Some(body_sender)) => (token, body_sender),
+                    _ => break 'transport,
+                };
+                log::trace!("Transport to server was lost. Resuming channel...");
+                res_body = match resume_channel(
+                    &hyper_client,
+                    &server_uri,
+                    token,
+                    &body_sender,
+                    &tube_mgrs3,
+                    &config2,
+                    channel_id,
+                ).await {
+                    Some(res_body) => res_body,
+                    None => {
+                        log::error!("Unable to resume channel. Aborting every Tube on it.");
+                        tube::abort_all_tube_managers(
+                            &tube_mgrs3,
+                            frame::AbortReason::TransportErrorWhileSynchronizingTubeState,
+                            tube::TubeEvent::Abort(
+                                frame::AbortReason::TransportErrorWhileSynchronizingTubeState
+                            ),
+                        );
+                        mark_going_away(&ctx_weak);
+                        break 'transport;
+                    },
+                };
+                frame_decoder = frame::Decoder::new_with_config(
+                    config2.decoder_config.clone(),
+                );
+                keepalive2.lock().unwrap().restart();
+                let dead_ctx_weak = ctx_weak.clone();
+                spawn_keepalive(
+                    keepalive2.clone(),
+                    body_sender_weak.clone(),
+                    tube_mgrs3.clone(),
+                    &config2,
+                    move || mark_going_away(&dead_ctx_weak),
+                );
             }
+            keepalive2.lock().unwrap().stop();
         });
 
+        // Tubes can't be made until the features negotiated with the server 
+        // are known.
+        let settings_timeout = config.settings_timeout;
+        if tokio::time::timeout(settings_timeout, settings_arrived).await.is_err() {
+            log::error!("Timed out waiting on the server's Settings frame.");
+            keepalive.lock().unwrap().stop();
+            return Err(ChannelConnectError::TimedOutWaitingOnSettings(settings_timeout));
+        }
+
         Ok(Channel {
-            body_sender: body_sender,
+            config,
+            body_sender,
+            ctx,
+            id: channel_id,
+            keepalive,
+            send_scheduler,
             tube_id_manager: UniqueIdManager::new_with_odd_ids(),
             tube_managers,
         })
     }
 
+    /**
+     * Gracefully shuts down the channel: every open Tube receives a 
+     * TubeEvent::ServerMustDrain(reason), make_tube() fails with 
+     * MakeTubeError::ChannelDraining, and any Tube still open once 
+     * `deadline` has elapsed is aborted with AbortReason::ChannelGoingAway.
+     *
+     * The server is only sent a Drain frame (so that its Tubes receive the 
+     * same event) if both peers advertised Features::DRAIN.
+     */
+    pub async fn drain(
+        &mut self,
+        reason: frame::DrainReason,
+        deadline: Duration,
+    ) -> Result<(), DrainError> {
+        let features = {
+            let mut ctx = self.ctx.lock().unwrap();
+            if ctx.is_going_away {
+                return Err(DrainError::AlreadyGoingAway);
+            }
+            if ctx.is_draining {
+                return Err(DrainError::AlreadyDraining);
+            }
+            ctx.is_draining = true;
+            match &ctx.settings {
+                Some(settings) => settings.features,
+                None => frame::Features::empty(),
+            }
+        };
+
+        tube::drain_all_tube_managers(&self.tube_managers, reason);
+        spawn_drain_deadline(
+            self.body_sender.clone(), 
+            self.tube_managers.clone(), 
+            deadline,
+        );
+        if features.contains(frame::Features::DRAIN) {
+            send_drain(&self.body_sender, reason).await
+        } else {
+            Ok(())
+        }
+    }
+
+    /**
+     * Tells the server that this channel is being torn down and terminates
+     * every Tube on it.
+     */
+    pub async fn go_away(
+        &mut self,
+        error_code: frame::GoAwayErrorCode,
+        message: &str,
+    ) -> Result<(), GoAwayError> {
+        let peer_max_frame_size = {
+            let mut ctx = self.ctx.lock().unwrap();
+            if ctx.is_going_away {
+                return Err(GoAwayError::AlreadyGoingAway);
+            }
+            ctx.is_going_away = true;
+            if let Some(waker) = ctx.waker.take() {
+                waker.wake();
+            }
+            ctx.settings.as_ref().map(|settings| settings.peer_max_frame_size)
+        };
+        self.keepalive.lock().unwrap().stop();
+
+        tube::abort_all_tube_managers(
+            &self.tube_managers,
+            frame::AbortReason::ChannelGoingAway,
+            tube::TubeEvent::GoAway(error_code, message.to_string()),
+        );
+
+        // Server-initiated tubes aren't supported yet, so the server has never
+        // made a Tube that this client could have processed.
+        send_go_away(&self.body_sender, 0, error_code, message, peer_max_frame_size).await
+    }
+
+    /**
+     * Identifies this channel's frames in a capture (see 
+     * ChannelConfig::frame_recorder).
+     */
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /**
+     * The round-trip time measured from the most recent keepalive Ping that 
+     * the server answered, or None if no Ping has been answered yet.
+     */
+    pub fn rtt(&self) -> Option<Duration> {
+        self.keepalive.lock().unwrap().rtt()
+    }
+
+    /**
+     * The settings negotiated with the server, whose Settings frame 
+     * Channel::new() waits for before returning.
+     */
+    pub fn settings(&self) -> Option<NegotiatedSettings> {
+        self.ctx.lock().unwrap().settings.clone()
+    }
+
+    /**
+     * Creates a new Tube on this channel.
+     *
+     * Payloads on the Tube can be compressed by requesting an algorithm in 
+     * the COMPRESSION_HEADER header (e.g. `"tubez-compression": "zstd"`). 
+     * The request only takes effect if the server acknowledged support for 
+     * compression in its Settings frame; otherwise the header is dropped and 
+     * the Tube is uncompressed (see Tube::compression()). Payloads sent on 
+     * the Tube are only compressed once the server has echoed the algorithm 
+     * back when accepting the Tube, which requires 
+     * Features::NEWTUBE_HANDSHAKE.
+     *
+     * If both peers advertised Features::FLOW_CONTROL, the Tube is flow 
+     * controlled and FLOW_CONTROL_HEADER is set on it automatically. 
+     * Otherwise any FLOW_CONTROL_HEADER supplied by the application is 
+     * dropped.
+     *
+     * The Tube's Weight (see Tube::set_priority()) can be set with the 
+     * PRIORITY_HEADER header (e.g. `"tubez-priority": "200"`), and otherwise 
+     * defaults to DEFAULT_TUBE_WEIGHT. The header is only sent to the server 
+     * if the server acknowledged support for Features::PRIORITY.
+     *
+     * Until both peers have advertised Features::VARINT_IDS, at most 32,768 
+     * client-initiated Tubes (the odd ids up to u16::MAX) can be open on the 
+     * channel at once before this fails with MakeTubeError::TubeIdsExhausted.
+     */
     pub async fn make_tube(
         &mut self, 
         headers: HashMap<String, String>,
     ) -> Result<tube::Tube, MakeTubeError> {
+        let (tube, _acceptance) = self.make_tube_internal(headers, false).await?;
+        Ok(tube)
+    }
+
+    /**
+     * Like make_tube(), but waits for the server to accept the Tube (see 
+     * PendingTube::accept()) and returns the server's response headers 
+     * alongside it. Fails with MakeTubeError::Rejected if the server refuses
+     * the Tube.
+     *
+     * If the server hasn't responded within the channel's 
+     * tube_acceptance_timeout (see ChannelConfig), the Tube is aborted and 
+     * this fails with MakeTubeError::TimedOutWaitingOnAcceptance.
+     *
+     * Requires that both peers advertised Features::NEWTUBE_HANDSHAKE.
+     */
+    pub async fn make_tube_and_await_acceptance(
+        &mut self,
+        headers: HashMap<String, String>,
+    ) -> Result<(tube::Tube, HashMap<String, String>), MakeTubeError> {
+        let (tube, acceptance) = self.make_tube_internal(headers, true).await?;
+        let acceptance = match acceptance {
+            Some(acceptance) => acceptance,
+            None => return Err(MakeTubeError::NewTubeHandshakeNotSupported),
+        };
+        let timeout = self.config.tube_acceptance_timeout;
+        let response = match tokio::time::timeout(timeout, acceptance).await {
+            Ok(response) => response,
+            Err(_) => {
+                let mut tube = tube;
+                let tube_id = tube.get_id();
+                log::trace!(
+                    "Timed out waiting on the server to accept Tube(id={}). \
+                     Aborting it...",
+                    tube_id,
+                );
+                if let Err(e) = tube.abort().await {
+                    log::error!("Error aborting Tube(id={}): {:?}", tube_id, e);
+                }
+                // Any response the server sends later is for a Tube that is 
+                // no longer tracked, and is dropped (see 
+                // FrameHandlerError::UntrackedTubeId).
+                self.tube_managers.lock().unwrap().remove(&tube_id);
+                return Err(MakeTubeError::TimedOutWaitingOnAcceptance(timeout));
+            },
+        };
+        match response {
+            tube::NewTubeResponse::Accepted(headers) => Ok((tube, headers)),
+            tube::NewTubeResponse::Rejected { code, headers } =>
+                Err(MakeTubeError::Rejected { code, headers }),
+            tube::NewTubeResponse::Aborted(reason) => Err(MakeTubeError::Aborted(reason)),
+        }
+    }
+
+    async fn make_tube_internal(
+        &mut self, 
+        mut headers: HashMap<String, String>,
+        await_acceptance: bool,
+    ) -> Result<(tube::Tube, Option<InvertedFuture<tube::NewTubeResponse>>), MakeTubeError> {
+        let (negotiated_features, peer_initial_window_size, peer_max_frame_size) = {
+            let ctx = self.ctx.lock().unwrap();
+            if ctx.is_going_away {
+                return Err(MakeTubeError::ChannelGoingAway);
+            }
+            if ctx.is_draining {
+                return Err(MakeTubeError::ChannelDraining);
+            }
+            match &ctx.settings {
+                Some(settings) => {
+                    let max_tubes = settings.peer_max_concurrent_tubes;
+                    if self.tube_managers.lock().unwrap().len() >= usize::from(max_tubes) {
+                        return Err(MakeTubeError::MaxConcurrentTubesExceeded(max_tubes));
+                    }
+                    (
+                        settings.features, 
+                        settings.peer_initial_window_size,
+                        Some(settings.peer_max_frame_size),
+                    )
+                },
+                None => (frame::Features::empty(), 0, None),
+            }
+        };
+        if await_acceptance 
+            && !negotiated_features.contains(frame::Features::NEWTUBE_HANDSHAKE) {
+            return Err(MakeTubeError::NewTubeHandshakeNotSupported);
+        }
+
+        let compression = match headers.get(COMPRESSION_HEADER) {
+            Some(value) => match Compression::from_header_value(value) {
+                Some(compression) 
+                    if negotiated_features.contains(frame::Features::COMPRESSION) => 
+                    Some(compression),
+                Some(_) => {
+                    log::trace!(
+                        "Server hasn't acknowledged support for compression. \
+                         Making an uncompressed Tube instead.",
+                    );
+                    headers.remove(COMPRESSION_HEADER);
+                    None
+                },
+                None => return Err(MakeTubeError::UnsupportedCompression(value.clone())),
+            },
+            None => None,
+        };
+
+        let flow_control = 
+            if negotiated_features.contains(frame::Features::FLOW_CONTROL) {
+                headers.insert(FLOW_CONTROL_HEADER.to_string(), "1".to_string());
+                Some(FlowControl::new(
+                    peer_initial_window_size,
+                    self.config.initial_window_size,
+                ))
+            } else {
+                headers.remove(FLOW_CONTROL_HEADER);
+                None
+            };
+
+        let weight = match headers.get(PRIORITY_HEADER) {
+            Some(value) => match weight_from_header_value(value) {
+                Some(weight) => weight,
+                None => return Err(MakeTubeError::InvalidPriority(value.clone())),
+            },
+            None => DEFAULT_TUBE_WEIGHT,
+        };
+        let announces_priority = negotiated_features.contains(frame::Features::PRIORITY);
+        if announces_priority {
+            headers.insert(PRIORITY_HEADER.to_string(), weight.to_string());
+        } else {
+            headers.remove(PRIORITY_HEADER);
+        }
+
+        let varint_ids = negotiated_features.contains(frame::Features::VARINT_IDS);
+        if varint_ids {
+            self.tube_id_manager.set_max_id(frame::MAX_TUBE_ID);
+        }
         let tube_id = match self.tube_id_manager.take_id() {
           Ok(id) => id,
           Err(UniqueIdError::NoIdsAvailable) => 
             return Err(MakeTubeError::TubeIdsExhausted),
         };
         let tube_id_val = tube_id.val();
-        let estab_tube_frame = match frame::encode::newtube_frame(tube_id.val(), headers) {
+        let header_encoding = 
+            if negotiated_features.contains(frame::Features::BINARY_HEADERS) {
+                frame::HeaderEncoding::Binary
+            } else {
+                frame::HeaderEncoding::Json
+            };
+        let estab_tube_frame = match frame::encode::newtube_frame_with_encoding(
+            tube_id.val(), 
+            &headers,
+            header_encoding,
+        ) {
             Ok(data) => data,
             Err(e) => return Err(MakeTubeError::FrameEncodeError(e)),
         };
 
+        let mut tube_mgr = tube::TubeManager::new();
+        tube_mgr.compression = compression;
+        tube_mgr.flow_control = flow_control;
+        tube_mgr.weight = weight;
+        tube_mgr.announces_priority = announces_priority;
+        tube_mgr.varint_ids = varint_ids;
+        tube_mgr.processing_acks = 
+            negotiated_features.contains(frame::Features::PROCESSING_ACKS);
+        tube_mgr.trailers = negotiated_features.contains(frame::Features::TRAILERS);
+        tube_mgr.abort_codes = negotiated_features.contains(frame::Features::ABORT_CODES);
+        tube_mgr.payload_fragmentation = 
+            negotiated_features.contains(frame::Features::PAYLOAD_FRAGMENTATION);
+        if let Some(peer_max_frame_size) = peer_max_frame_size {
+            tube_mgr.peer_max_frame_size = 
+                usize::try_from(peer_max_frame_size).unwrap_or(usize::MAX);
+        }
+        tube_mgr.resumable = 
+            negotiated_features.contains(frame::Features::SESSION_RESUMPTION);
+        tube_mgr.payload_sequences = 
+            negotiated_features.contains(frame::Features::PAYLOAD_SEQUENCES);
+        let acceptance = if await_acceptance {
+            let (acceptance, resolver) = InvertedFuture::new();
+            tube_mgr.acceptance = Some(resolver);
+            Some(acceptance)
+        } else {
+            None
+        };
+        let tube_mgr = Arc::new(Mutex::new(tube_mgr));
+
+        // The TubeManager is tracked before the NewTube frame is sent so that
+        // the server's response can't arrive for a Tube we don't know about.
+        if self.tube_managers.lock().unwrap().try_insert(tube_id_val, tube_mgr.clone()).is_err() {
+            return Err(MakeTubeError::InternalErrorDuplicateTubeId(tube_id_val));
+        }
+
         {
             let mut body_sender = self.body_sender.lock().await;
+            let resumable = {
+                let mut tube_mgr = tube_mgr.lock().unwrap();
+                if tube_mgr.resumable {
+                    tube_mgr.replay_log.push(tube::ReplayEntry::NewTube(estab_tube_frame.clone()));
+                }
+                tube_mgr.resumable
+            };
             log::trace!("Sending MakeTube(id={}) frame...", &tube_id);
-            if let Err(_bytes) = body_sender.send_data(estab_tube_frame.into()).await {
-                // TODO: Should we panic here? Is it possible that the data was 
-                //       sent (even with some kind of error here) and now the 
-                //       client/server have disjoint states?
-                //      
-                //       Need to think this through more...
-                return Err(MakeTubeError::UnknownTransportError);
+            match body_sender.send_data(estab_tube_frame).await {
+                Ok(_) => (),
+                Err(_) if resumable => log::trace!(
+                    "Transport lost while sending NewTube(id={}). It will be \
+                     sent again once the channel resumes.",
+                    &tube_id,
+                ),
+                Err(_bytes) => {
+                    // TODO: Should we panic here? Is it possible that the data 
+                    //       was sent (even with some kind of error here) and 
+                    //       now the client/server have disjoint states?
+                    //      
+                    //       Need to think this through more...
+                    self.tube_managers.lock().unwrap().remove(&tube_id_val);
+                    return Err(MakeTubeError::UnknownTransportError);
+                },
             }
         };
 
-        let tube_mgr = Arc::new(Mutex::new(tube::TubeManager::new()));
         let tube = tube::Tube::new(
             PeerType::Client, 
             tube_id, 
             self.body_sender.clone(), 
-            tube_mgr.clone(),
+            tube_mgr,
+            self.send_scheduler.clone(),
+            self.config.max_message_size,
         );
 
-        let mut tube_managers = self.tube_managers.lock().unwrap();
-        if let Err(_) = tube_managers.try_insert(tube_id_val, tube_mgr) {
-            return Err(MakeTubeError::InternalErrorDuplicateTubeId(tube_id_val));
-        }
+        Ok((tube, acceptance))
+    }
+}
 
-        Ok(tube)
+impl futures::stream::Stream for Channel {
+    type Item = ChannelEvent;
+
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut futures::task::Context,
+    ) -> futures::task::Poll<Option<Self::Item>> {
+        let mut ctx = self.ctx.lock().unwrap();
+        ctx.waker = Some(cx.waker().clone());
+
+        match ctx.pending_events.pop_front() {
+            Some(channel_event) => futures::task::Poll::Ready(Some(channel_event)),
+            None =>
+                if ctx.is_going_away {
+                    futures::task::Poll::Ready(None)
+                } else {
+                    futures::task::Poll::Pending
+                },
+        }
     }
 }
 
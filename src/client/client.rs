@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use crate::common::ChannelConfig;
 use crate::tube;
 use super::channel;
 
@@ -9,18 +10,31 @@ pub enum ServerMakeTubeError {
 }
 
 pub struct Client {
+  channel_config: ChannelConfig,
   hyper_client: hyper::Client<hyper::client::HttpConnector>,
   implicit_channel: Option<channel::Channel>,
   server_uri: hyper::Uri,
 }
 impl Client {
   pub fn new(server_uri: hyper::Uri) -> Self {
+    Self::new_with_config(server_uri, ChannelConfig::default())
+  }
+
+  /**
+   * Like Client::new(), but every channel made by this client will use the 
+   * provided ChannelConfig.
+   */
+  pub fn new_with_config(
+    server_uri: hyper::Uri,
+    channel_config: ChannelConfig,
+  ) -> Self {
     let hyper_client: hyper::Client<hyper::client::HttpConnector> = 
       hyper::Client::builder()
         .http2_only(true)
         .build_http();
 
     Client {
+      channel_config,
       hyper_client,
       implicit_channel: None,
       server_uri,
@@ -31,7 +45,12 @@ impl Client {
     &mut self,
     headers: HashMap<String, String>,
   ) -> Result<channel::Channel, channel::ChannelConnectError> {
-    channel::Channel::new(&self.hyper_client, headers, &self.server_uri).await
+    channel::Channel::new(
+      &self.hyper_client,
+      headers,
+      &self.server_uri,
+      self.channel_config.clone(),
+    ).await
   }
 
   pub async fn new_tube(
@@ -0,0 +1,110 @@
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use crate::common::frame;
+use crate::common::tube::TubeManager;
+
+/**
+ * The most received ack ids a Tube holds on to before acking them without
+ * waiting out the rest of the channel's ack_batch_delay. This keeps every
+ * batch well within a single CumulativePayloadAck frame.
+ */
+pub(in crate) const MAX_BATCHED_ACK_IDS: usize = 1024;
+
+/**
+ * Collapses a batch of ack ids into the UpTo and Ranges fields of a
+ * CumulativePayloadAck frame.
+ */
+fn ack_ranges(mut ack_ids: Vec<u64>) -> (Option<u64>, Vec<RangeInclusive<u64>>) {
+    ack_ids.sort_unstable();
+    ack_ids.dedup();
+
+    let mut ranges: Vec<RangeInclusive<u64>> = vec![];
+    for ack_id in ack_ids {
+        match ranges.last_mut() {
+            Some(range) if range.end() + 1 == ack_id =>
+                *range = *range.start()..=ack_id,
+            _ => ranges.push(ack_id..=ack_id),
+        }
+    }
+
+    match ranges.first() {
+        Some(range) if *range.start() == 0 => {
+            let up_to = *ranges.remove(0).end();
+            (Some(up_to), ranges)
+        },
+        _ => (None, ranges),
+    }
+}
+
+/**
+ * Encodes a batch of received ack ids as a plain PayloadAck frame if it holds
+ * a single ack id (which every peer understands), or as a
+ * CumulativePayloadAck frame otherwise.
+ */
+pub(in crate) fn ack_batch_frame(
+    tube_id: u64,
+    ack_ids: Vec<u64>,
+) -> Result<Bytes, frame::encode::FrameEncodeError> {
+    if let [ack_id] = ack_ids[..] {
+        return frame::encode::payload_ack_frame(tube_id, ack_id, false);
+    }
+    let (up_to, ranges) = ack_ranges(ack_ids);
+    frame::encode::cumulative_payload_ack_frame(tube_id, up_to, &ranges)
+}
+
+/**
+ * Acks whatever ack ids have been batched up for a Tube once `delay` has
+ * elapsed. Started when the first ack id of a batch arrives.
+ */
+pub(in crate) fn spawn_ack_batch_flush(
+    sender: Arc<tokio::sync::Mutex<hyper::body::Sender>>,
+    tube_mgr: Arc<Mutex<TubeManager>>,
+    tube_id: u64,
+    delay: Duration,
+) {
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        let ack_ids = std::mem::take(&mut tube_mgr.lock().unwrap().pending_acks);
+        if ack_ids.is_empty() {
+            return;
+        }
+
+        let num_acks = ack_ids.len();
+        let frame_data = match ack_batch_frame(tube_id, ack_ids) {
+            Ok(data) => data,
+            Err(e) => {
+                log::error!("Error encoding batched PayloadAck frame: {:?}", e);
+                return;
+            },
+        };
+        let mut sender = sender.lock().await;
+        log::trace!("Sending {} batched acks for Tube(id={})...", num_acks, tube_id);
+        if let Err(e) = sender.send_data(frame_data).await {
+            log::trace!("Error sending batched PayloadAck frame: {:?}", e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod ack_batch_tests {
+    use super::*;
+
+    #[test]
+    fn contiguous_ack_ids_from_zero_collapse_into_up_to() {
+        assert_eq!(ack_ranges(vec![2, 0, 1, 3]), (Some(3), vec![]));
+    }
+
+    #[test]
+    fn gaps_in_ack_ids_become_ranges() {
+        assert_eq!(
+            ack_ranges(vec![0, 1, 4, 5, 6, 9, 1]),
+            (Some(1), vec![4..=6, 9..=9]),
+        );
+        assert_eq!(ack_ranges(vec![7, 3]), (None, vec![3..=3, 7..=7]));
+    }
+}
@@ -0,0 +1,15 @@
+mod reader;
+mod recorder;
+mod replay;
+
+pub use reader::CaptureReadError;
+pub use reader::CaptureReader;
+pub use reader::CaptureRecord;
+pub use recorder::CAPTURE_FORMAT_VERSION;
+pub use recorder::CAPTURE_MAGIC;
+pub use recorder::Direction;
+pub use recorder::FrameRecorder;
+#[cfg(any(feature = "client", feature = "server"))] pub(in crate) use recorder::next_channel_id;
+#[cfg(any(feature = "client", feature = "server"))] pub(in crate) use recorder::record_sent_body;
+pub use replay::replay;
+pub use replay::Replay;
@@ -0,0 +1,120 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::io::Read;
+use std::path::Path;
+
+use bytes::Bytes;
+
+use crate::common::PeerType;
+use super::recorder::CAPTURE_FORMAT_VERSION;
+use super::recorder::CAPTURE_MAGIC;
+use super::recorder::decode_flags;
+use super::recorder::Direction;
+
+#[derive(Debug)]
+pub enum CaptureReadError {
+    BadMagic,
+    IoError(std::io::Error),
+    /**
+     * The capture ends partway through a record (e.g. because the recording
+     * process exited before flushing).
+     */
+    Truncated,
+    UnsupportedFormatVersion(u8),
+}
+
+/**
+ * A single chunk of data read from a capture.
+ */
+#[derive(Clone,Debug)]
+pub struct CaptureRecord {
+    pub timestamp_micros: u64,
+    pub channel_id: u64,
+    pub peer_type: PeerType,
+    pub direction: Direction,
+    /**
+     * The data exactly as it crossed the transport. Frames may straddle the 
+     * records of a channel, so the data of every record sent in the same 
+     * direction on the same channel should be fed through a single 
+     * frame::Decoder in order.
+     */
+    pub data: Bytes,
+}
+
+/**
+ * Reads the records of a capture written by a FrameRecorder, in the order
+ * they were recorded.
+ */
+pub struct CaptureReader<R: Read> {
+    reader: R,
+}
+impl CaptureReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, CaptureReadError> {
+        match File::open(path) {
+            Ok(file) => Self::new(BufReader::new(file)),
+            Err(e) => Err(CaptureReadError::IoError(e)),
+        }
+    }
+}
+impl<R: Read> CaptureReader<R> {
+    pub fn new(mut reader: R) -> Result<Self, CaptureReadError> {
+        let mut file_header = [0; 9];
+        if let Err(e) = reader.read_exact(&mut file_header) {
+            return Err(match e.kind() {
+                std::io::ErrorKind::UnexpectedEof => CaptureReadError::BadMagic,
+                _ => CaptureReadError::IoError(e),
+            });
+        }
+        if &file_header[..8] != CAPTURE_MAGIC {
+            return Err(CaptureReadError::BadMagic);
+        }
+        if file_header[8] != CAPTURE_FORMAT_VERSION {
+            return Err(CaptureReadError::UnsupportedFormatVersion(file_header[8]));
+        }
+        Ok(CaptureReader { reader })
+    }
+
+    fn read_record(&mut self) -> Result<Option<CaptureRecord>, CaptureReadError> {
+        let mut record_header = [0; 21];
+        let mut header_len = 0;
+        while header_len < record_header.len() {
+            match self.reader.read(&mut record_header[header_len..]) {
+                Ok(0) if header_len == 0 => return Ok(None),
+                Ok(0) => return Err(CaptureReadError::Truncated),
+                Ok(n) => header_len += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(CaptureReadError::IoError(e)),
+            }
+        }
+
+        let timestamp_micros = u64::from_be_bytes(record_header[0..8].try_into().unwrap());
+        let channel_id = u64::from_be_bytes(record_header[8..16].try_into().unwrap());
+        let (peer_type, direction) = decode_flags(record_header[16]);
+        let data_len = u32::from_be_bytes(record_header[17..21].try_into().unwrap());
+
+        // data_len comes from the capture, so it isn't trusted to size the 
+        // buffer up front.
+        let mut data = vec![];
+        if let Err(e) = (&mut self.reader).take(data_len.into()).read_to_end(&mut data) {
+            return Err(CaptureReadError::IoError(e));
+        }
+        if data.len() < data_len as usize {
+            return Err(CaptureReadError::Truncated);
+        }
+
+        Ok(Some(CaptureRecord {
+            timestamp_micros,
+            channel_id,
+            peer_type,
+            direction,
+            data: data.into(),
+        }))
+    }
+}
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = Result<CaptureRecord, CaptureReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
@@ -0,0 +1,182 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use bytes::BytesMut;
+use futures::StreamExt;
+
+use crate::common::frame;
+use crate::common::PeerType;
+
+/**
+ * The first bytes of every capture file.
+ */
+pub const CAPTURE_MAGIC: &[u8; 8] = b"TUBEZCAP";
+
+/**
+ * The version of the capture format written by FrameRecorder. Bumped whenever
+ * the record layout changes.
+ */
+pub const CAPTURE_FORMAT_VERSION: u8 = 2;
+
+const DIRECTION_FLAG: u8 = 0b01;
+const PEER_TYPE_FLAG: u8 = 0b10;
+
+static NEXT_CHANNEL_ID: AtomicU64 = AtomicU64::new(1);
+
+/**
+ * Returns an identifier for a new channel that is unique within this process,
+ * so that the frames of several channels recorded to the same capture can be
+ * told apart.
+ */
+pub(in crate) fn next_channel_id() -> u64 {
+    NEXT_CHANNEL_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+#[derive(Clone,Copy,Debug,Eq,Hash,PartialEq)]
+pub enum Direction {
+    Received,
+    Sent,
+}
+
+pub(in super) fn encode_flags(peer_type: PeerType, direction: Direction) -> u8 {
+    let mut flags = 0;
+    if let Direction::Sent = direction {
+        flags |= DIRECTION_FLAG;
+    }
+    if let PeerType::Server = peer_type {
+        flags |= PEER_TYPE_FLAG;
+    }
+    flags
+}
+
+pub(in super) fn decode_flags(flags: u8) -> (PeerType, Direction) {
+    let peer_type =
+        if flags & PEER_TYPE_FLAG == 0 { PeerType::Client } else { PeerType::Server };
+    let direction =
+        if flags & DIRECTION_FLAG == 0 { Direction::Received } else { Direction::Sent };
+    (peer_type, direction)
+}
+
+/**
+ * Writes all of the data sent or received by the channels it is attached to 
+ * (see ChannelConfig::frame_recorder) to a capture that can later be read with
+ * a CaptureReader and fed back through capture::replay().
+ *
+ * A capture begins with CAPTURE_MAGIC and CAPTURE_FORMAT_VERSION, followed by
+ * one record per chunk of data read from or written to the transport:
+ *
+ *   +-----------------------+-----------------+-----------+---------------+----------+
+ *   |  TimestampMicros(u64) |  ChannelId(u64) | Flags(u8) |  DataLen(u32) |  Data(*) |
+ *   +-----------------------+-----------------+-----------+---------------+----------+
+ *
+ * TimestampMicros counts microseconds since the unix epoch. Bit 0 of Flags is
+ * set for data the recording peer sent (and clear for data it received); bit 
+ * 1 is set if the recording peer was a server. Data is recorded exactly as it
+ * crossed the transport, before any attempt to decode it, so a chunk may hold
+ * several frames, end partway through a frame, or be malformed altogether. 
+ * Splitting the data into frames is left to whoever reads the capture.
+ *
+ * Recording is best-effort: errors writing to the capture are logged, but
+ * never affect the channel being recorded.
+ */
+pub struct FrameRecorder {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+impl FrameRecorder {
+    pub fn new<W>(mut writer: W) -> Result<Self, std::io::Error>
+        where W: Write + Send + 'static {
+        writer.write_all(CAPTURE_MAGIC)?;
+        writer.write_all(&[CAPTURE_FORMAT_VERSION])?;
+        Ok(FrameRecorder {
+            writer: Mutex::new(Box::new(writer)),
+        })
+    }
+
+    /**
+     * Creates (or truncates) the file at `path` and records to it.
+     */
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, std::io::Error> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn flush(&self) -> Result<(), std::io::Error> {
+        self.writer.lock().unwrap().flush()
+    }
+
+    /**
+     * Encodes `frame` and records it as a chunk of its own.
+     */
+    pub fn record_frame(
+        &self,
+        channel_id: u64,
+        peer_type: PeerType,
+        direction: Direction,
+        frame: &frame::Frame,
+    ) {
+        let mut frame_data = BytesMut::new();
+        if let Err(e) = frame::encode::frame_into(&mut frame_data, frame) {
+            log::error!("Error encoding frame for capture: {:?}", e);
+            return;
+        }
+        self.record_chunk(channel_id, peer_type, direction, &frame_data);
+    }
+
+    /**
+     * Records a chunk of data exactly as it was read from or written to the 
+     * transport.
+     */
+    pub fn record_chunk(
+        &self,
+        channel_id: u64,
+        peer_type: PeerType,
+        direction: Direction,
+        data: &[u8],
+    ) {
+        let timestamp_micros = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(elapsed) => u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX),
+            Err(_) => 0,
+        };
+
+        let mut record = Vec::with_capacity(21 + data.len());
+        record.extend_from_slice(&timestamp_micros.to_be_bytes());
+        record.extend_from_slice(&channel_id.to_be_bytes());
+        record.push(encode_flags(peer_type, direction));
+        record.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        record.extend_from_slice(data);
+
+        let mut writer = self.writer.lock().unwrap();
+        if let Err(e) = writer.write_all(&record) {
+            log::error!("Error writing data to capture: {:?}", e);
+        }
+    }
+}
+impl std::fmt::Debug for FrameRecorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FrameRecorder").finish_non_exhaustive()
+    }
+}
+
+/**
+ * Wraps the body that carries this peer's frames to the transport so that
+ * every chunk written to it is also recorded.
+ */
+pub(in crate) fn record_sent_body(
+    body: hyper::Body,
+    recorder: Arc<FrameRecorder>,
+    channel_id: u64,
+    peer_type: PeerType,
+) -> hyper::Body {
+    hyper::Body::wrap_stream(body.map(move |chunk| {
+        if let Ok(data) = &chunk {
+            recorder.record_chunk(channel_id, peer_type, Direction::Sent, data);
+        }
+        chunk
+    }))
+}
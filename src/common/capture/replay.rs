@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use futures::FutureExt;
+use hyper::body::HttpBody;
+
+use crate::common::ChannelConfig;
+use crate::common::frame;
+use super::reader::CaptureRecord;
+use super::recorder::Direction;
+
+/**
+ * The outcome of replaying a capture with replay().
+ */
+#[derive(Debug)]
+pub struct Replay {
+    /**
+     * The result of handling each replayed frame, in the order the frames
+     * were received. Tubes created by replayed NewTube frames are returned
+     * here as FrameHandlerResult::NewTube.
+     */
+    pub results: Vec<Result<frame::FrameHandlerResult, frame::FrameHandlerError>>,
+
+    /**
+     * The frames that were sent back to the (mock) peer while handling the
+     * replayed frames (e.g. PayloadAck and Pong frames).
+     */
+    pub sent_frames: Vec<frame::Frame>,
+
+    /**
+     * Set if the replayed frames failed to decode. As on a real channel,
+     * nothing after the offending frame is replayed.
+     */
+    pub decode_error: Option<frame::FrameDecodeError>,
+}
+
+/**
+ * Feeds the data that channel `channel_id` received in a capture back
+ * through a frame::Decoder (which splits it into frames) and a FrameHandler 
+ * configured with `config`, as if it had arrived from the peer. Frames that
+ * the FrameHandler sends in response are written to a mock transport rather
+ * than a real peer and collected in Replay::sent_frames.
+ *
+ * Frames are replayed one at a time, and everything the FrameHandler sends 
+ * while handling a frame is collected before the next frame is replayed. 
+ * Acks are never batched during a replay (config.ack_batch_delay is 
+ * ignored), so they are always sent while handling the frame they ack. 
+ * WindowUpdate frames are sent in the background, though, so they only 
+ * appear in Replay::sent_frames if they were sent before the replay ended.
+ */
+pub async fn replay<I>(
+    records: I,
+    channel_id: u64,
+    config: &ChannelConfig,
+) -> Replay where I: IntoIterator<Item = CaptureRecord> {
+    let mut replay = Replay {
+        results: vec![],
+        sent_frames: vec![],
+        decode_error: None,
+    };
+
+    let mut records = records.into_iter()
+        .filter(|record| record.channel_id == channel_id)
+        .filter(|record| record.direction == Direction::Received)
+        .peekable();
+    let peer_type = match records.peek() {
+        Some(record) => record.peer_type,
+        None => return replay,
+    };
+
+    let (body_sender, mut mock_transport) = hyper::Body::channel();
+    let mut body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+    let mut tube_managers = Arc::new(Mutex::new(HashMap::new()));
+    let config = ChannelConfig {
+        ack_batch_delay: std::time::Duration::ZERO,
+        ..config.clone()
+    };
+    let mut frame_handler = frame::FrameHandler::new(
+        peer_type,
+        &mut tube_managers,
+        &config,
+    );
+    let mut frame_decoder =
+        frame::Decoder::new_with_config(config.decoder_config.clone());
+    let mut sent_frame_decoder = frame::Decoder::new();
+
+    for record in records {
+        let (mut frames, decode_error) = match frame_decoder.decode(record.data) {
+            Ok(frames) => (frames, None),
+            Err(mut e) => (std::mem::take(&mut e.decoded_frames), Some(e)),
+        };
+
+        while let Some(frame) = frames.pop_front() {
+            let result = frame_handler.handle_frame(frame, &mut body_sender).await;
+            replay.results.push(result);
+
+            // Drain the mock transport so that the next frame the handler
+            // sends never waits on it.
+            while let Some(Some(Ok(data))) = mock_transport.data().now_or_never() {
+                match sent_frame_decoder.decode(data) {
+                    Ok(sent_frames) => replay.sent_frames.extend(sent_frames),
+                    Err(e) => log::error!(
+                        "Error decoding frame sent during replay: {:?}",
+                        e,
+                    ),
+                }
+            }
+        }
+
+        if decode_error.is_some() {
+            replay.decode_error = decode_error;
+            break;
+        }
+    }
+
+    replay
+}
+
+#[cfg(test)]
+mod replay_tests {
+    use std::collections::HashMap;
+    use std::io::Write;
+
+    use bytes::Bytes;
+
+    use crate::common::PeerType;
+    use super::*;
+    use super::super::CaptureReader;
+    use super::super::FrameRecorder;
+
+    #[derive(Clone,Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+    impl Write for SharedBuf {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(data)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn read_records(capture: &SharedBuf) -> Vec<CaptureRecord> {
+        let capture_data = capture.0.lock().unwrap().clone();
+        CaptureReader::new(&capture_data[..]).unwrap()
+            .map(|record| record.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn recorded_chunks_read_back_in_order() {
+        let capture = SharedBuf::default();
+        let recorder = FrameRecorder::new(capture.clone()).unwrap();
+        let drain = frame::Frame::Drain { reason: frame::DrainReason::Shutdown };
+        recorder.record_frame(1, PeerType::Server, Direction::Received, &drain);
+        let mut encoded = bytes::BytesMut::new();
+        frame::encode::ping_frame_into(&mut encoded, 42).unwrap();
+        frame::encode::pong_frame_into(&mut encoded, 42).unwrap();
+        recorder.record_chunk(2, PeerType::Client, Direction::Sent, &encoded);
+
+        let records = read_records(&capture);
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            (records[0].channel_id, records[0].peer_type, records[0].direction),
+            (1, PeerType::Server, Direction::Received),
+        );
+        assert_eq!(records[0].data, frame::encode::drain_frame(frame::DrainReason::Shutdown).unwrap());
+        assert_eq!(
+            (records[1].channel_id, records[1].peer_type, records[1].direction),
+            (2, PeerType::Client, Direction::Sent),
+        );
+        assert_eq!(records[1].data, encoded);
+        assert!(records[0].timestamp_micros <= records[1].timestamp_micros);
+    }
+
+    #[test]
+    fn truncated_capture_errors() {
+        let capture = SharedBuf::default();
+        let recorder = FrameRecorder::new(capture.clone()).unwrap();
+        let drain = frame::Frame::Drain { reason: frame::DrainReason::Shutdown };
+        recorder.record_frame(1, PeerType::Server, Direction::Received, &drain);
+
+        let mut capture_data = capture.0.lock().unwrap().clone();
+        capture_data.pop();
+        let mut reader = CaptureReader::new(&capture_data[..]).unwrap();
+        match reader.next() {
+            Some(Err(super::super::CaptureReadError::Truncated)) => (),
+            unexpected => panic!("Unexpected CaptureReader::next() result: {:?}", unexpected),
+        }
+    }
+
+    #[test]
+    fn oversized_record_length_errors_as_truncated() {
+        let capture = SharedBuf::default();
+        let recorder = FrameRecorder::new(capture.clone()).unwrap();
+        let drain = frame::Frame::Drain { reason: frame::DrainReason::Shutdown };
+        recorder.record_frame(1, PeerType::Server, Direction::Received, &drain);
+
+        // Claim that the record holds far more data than the capture does.
+        let mut capture_data = capture.0.lock().unwrap().clone();
+        let drain_len = frame::encode::drain_frame(frame::DrainReason::Shutdown).unwrap().len();
+        let data_len_offset = capture_data.len() - drain_len - 4;
+        capture_data[data_len_offset..data_len_offset + 4]
+            .copy_from_slice(&u32::MAX.to_be_bytes());
+        let mut reader = CaptureReader::new(&capture_data[..]).unwrap();
+        match reader.next() {
+            Some(Err(super::super::CaptureReadError::Truncated)) => (),
+            unexpected => panic!("Unexpected CaptureReader::next() result: {:?}", unexpected),
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_received_frames_for_one_channel() {
+        let capture = SharedBuf::default();
+        let recorder = FrameRecorder::new(capture.clone()).unwrap();
+        let received_frames = [
+            frame::Frame::NewTube { tube_id: 1, headers: HashMap::new() },
+            frame::Frame::Payload {
+                tube_id: 1,
+                ack_id: Some(7),
+                processing_ack: false,
+                data: Bytes::from_static(b"hello"),
+            },
+            frame::Frame::Ping { opaque: 3 },
+        ];
+        for frame in &received_frames {
+            recorder.record_frame(10, PeerType::Server, Direction::Received, frame);
+        }
+        // Frames sent by the recorded channel and frames from other channels
+        // aren't replayed.
+        let drain = frame::Frame::Drain { reason: frame::DrainReason::Shutdown };
+        recorder.record_frame(10, PeerType::Server, Direction::Sent, &drain);
+        recorder.record_frame(11, PeerType::Server, Direction::Received, &drain);
+
+        let replay = replay(read_records(&capture), 10, &ChannelConfig::default()).await;
+        assert!(replay.decode_error.is_none());
+        assert_eq!(replay.results.len(), 3);
+        match &replay.results[0] {
+            Ok(frame::FrameHandlerResult::NewTube(tube)) => assert_eq!(tube.get_id(), 1),
+            unexpected => panic!("Unexpected replay result: {:?}", unexpected),
+        }
+        assert_eq!(replay.sent_frames, vec![
+            frame::Frame::PayloadAck { tube_id: 1, ack_id: 7, processed: false },
+            frame::Frame::Pong { opaque: 3 },
+        ]);
+    }
+
+    #[tokio::test]
+    async fn replays_frames_split_across_chunks_up_to_malformed_data() {
+        let capture = SharedBuf::default();
+        let recorder = FrameRecorder::new(capture.clone()).unwrap();
+        let mut encoded = bytes::BytesMut::new();
+        frame::encode::ping_frame_into(&mut encoded, 1).unwrap();
+        frame::encode::ping_frame_into(&mut encoded, 2).unwrap();
+        // A frame with an unknown FrameType follows the second Ping.
+        encoded.extend_from_slice(&[0xFF, 0, 0]);
+        let (first_chunk, second_chunk) = encoded.split_at(5);
+        recorder.record_chunk(10, PeerType::Server, Direction::Received, first_chunk);
+        recorder.record_chunk(10, PeerType::Server, Direction::Received, second_chunk);
+
+        let replay = replay(read_records(&capture), 10, &ChannelConfig::default()).await;
+        assert_eq!(replay.results.len(), 2);
+        assert_eq!(replay.sent_frames, vec![
+            frame::Frame::Pong { opaque: 1 },
+            frame::Frame::Pong { opaque: 2 },
+        ]);
+        match &replay.decode_error {
+            Some(frame::FrameDecodeError { 
+                parse_error: frame::FrameParseError::UnknownFrameType(0xFF), 
+                .. 
+            }) => (),
+            unexpected => panic!("Unexpected replay decode error: {:?}", unexpected),
+        }
+    }
+}
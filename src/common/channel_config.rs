@@ -0,0 +1,171 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::common::capture::FrameRecorder;
+use crate::common::frame::DecoderConfig;
+use crate::common::frame::Features;
+
+/**
+ * The default upper bound on the size of a single (possibly fragmented)
+ * payload: 16MiB.
+ */
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/**
+ * By default a channel sends a Ping frame every 30 seconds.
+ */
+pub const DEFAULT_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/**
+ * By default a channel is declared dead once its peer fails to answer 3 Pings
+ * in a row.
+ */
+pub const DEFAULT_MAX_MISSED_PONGS: u32 = 3;
+
+/**
+ * By default a channel allows its peer to have up to 1024 Tubes open at once.
+ */
+pub const DEFAULT_MAX_CONCURRENT_TUBES: u16 = 1024;
+
+/**
+ * By default a channel grants its peer 1MiB of payload data per 
+ * flow-controlled Tube before the application must consume some of it.
+ */
+pub const DEFAULT_INITIAL_WINDOW_SIZE: u32 = 1024 * 1024;
+
+/**
+ * By default a channel waits up to 5 milliseconds to batch the acks it sends 
+ * for a Tube's payloads into a single CumulativePayloadAck frame.
+ */
+pub const DEFAULT_ACK_BATCH_DELAY: Duration = Duration::from_millis(5);
+
+/**
+ * By default a client gives the server 30 seconds to accept or reject a Tube
+ * made with Channel::make_tube_and_await_acceptance().
+ */
+pub const DEFAULT_TUBE_ACCEPTANCE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/**
+ * By default a client gives the server 10 seconds to send its Settings frame 
+ * once the channel is established.
+ */
+pub const DEFAULT_SETTINGS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/**
+ * Settings that govern the behavior of every Tube hosted by a channel.
+ */
+#[derive(Clone,Debug)]
+pub struct ChannelConfig {
+    /**
+     * The largest payload (in bytes) that a Tube on this channel will send or
+     * reassemble from received PayloadFragment frames.
+     */
+    pub max_message_size: usize,
+
+    /**
+     * Limits applied while decoding the stream of frames received from the 
+     * peer on this channel.
+     */
+    pub decoder_config: DecoderConfig,
+
+    /**
+     * How often to send a Ping frame to the peer. None disables keepalive 
+     * Pings (and therefore round-trip time measurement) entirely.
+     */
+    pub keepalive_interval: Option<Duration>,
+
+    /**
+     * The number of consecutive Pings the peer may leave unanswered before 
+     * the channel is declared dead and every Tube on it is aborted with 
+     * AbortReason::KeepaliveTimeout.
+     */
+    pub max_missed_pongs: u32,
+
+    /**
+     * The optional protocol features this peer advertises in its Settings 
+     * frame. Only features advertised by both peers are used.
+     */
+    pub features: Features,
+
+    /**
+     * The most Tubes the peer may have open on this channel at once 
+     * (advertised in this peer's Settings frame).
+     */
+    pub max_concurrent_tubes: u16,
+
+    /**
+     * The bytes of payload data the peer may send on each flow-controlled 
+     * Tube before the application consumes any of it (advertised in this 
+     * peer's Settings frame). Only used if both peers advertise 
+     * Features::FLOW_CONTROL.
+     */
+    pub initial_window_size: u32,
+
+    /**
+     * How long to hold on to the acks for received payloads so that several 
+     * of them can be sent in a single CumulativePayloadAck frame. Only used 
+     * if both peers advertise Features::CUMULATIVE_ACKS. Zero sends every ack
+     * as soon as its payload arrives.
+     */
+    pub ack_batch_delay: Duration,
+
+    /**
+     * How long Channel::make_tube_and_await_acceptance() waits for the server
+     * to accept or reject a Tube before giving up on it.
+     */
+    pub tube_acceptance_timeout: Duration,
+
+    /**
+     * How long Channel::new() waits for the server's Settings frame before 
+     * giving up on the channel.
+     */
+    pub settings_timeout: Duration,
+
+    /**
+     * If set, all of the data sent or received on this channel is recorded 
+     * (see capture::FrameRecorder). Recording is off by default.
+     */
+    pub frame_recorder: Option<Arc<FrameRecorder>>,
+
+    /**
+     * If set, the channel survives the loss of its transport: the client 
+     * reconnects and resumes the channel (see Features::SESSION_RESUMPTION)
+     * as long as it manages to do so within this long, and the server holds 
+     * on to the channel's Tubes for this long while waiting on it. Only used 
+     * if both peers set it and advertise Features::PAYLOAD_SEQUENCES, and 
+     * resumable channels don't use Features::FLOW_CONTROL (see 
+     * Features::negotiate()). Resumption is off by default.
+     */
+    pub session_resume_window: Option<Duration>,
+}
+impl ChannelConfig {
+    /**
+     * The Features this peer advertises in its Settings frame. 
+     * Features::SESSION_RESUMPTION is only advertised if 
+     * session_resume_window is set.
+     */
+    pub fn advertised_features(&self) -> Features {
+        match self.session_resume_window {
+            Some(_) => self.features,
+            None => self.features.difference(Features::SESSION_RESUMPTION),
+        }
+    }
+}
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        ChannelConfig {
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            decoder_config: DecoderConfig::default(),
+            keepalive_interval: Some(DEFAULT_KEEPALIVE_INTERVAL),
+            max_missed_pongs: DEFAULT_MAX_MISSED_PONGS,
+            features: Features::supported(),
+            max_concurrent_tubes: DEFAULT_MAX_CONCURRENT_TUBES,
+            initial_window_size: DEFAULT_INITIAL_WINDOW_SIZE,
+            ack_batch_delay: DEFAULT_ACK_BATCH_DELAY,
+            tube_acceptance_timeout: DEFAULT_TUBE_ACCEPTANCE_TIMEOUT,
+            settings_timeout: DEFAULT_SETTINGS_TIMEOUT,
+            frame_recorder: None,
+            session_resume_window: None,
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use std::io::Read;
+
+use bytes::Bytes;
+
+/**
+ * The NewTube header a Tube's creator uses to request that payloads on the
+ * Tube be compressed (e.g. `"tubez-compression": "zstd"`).
+ *
+ * A server that supports the requested algorithm echoes it back in this 
+ * header when it accepts the Tube, and the client only compresses the 
+ * payloads it sends once it has seen that confirmation (which requires 
+ * Features::NEWTUBE_HANDSHAKE). The server compresses its own payloads as soon
+ * as it has recognized the algorithm.
+ */
+pub const COMPRESSION_HEADER: &str = "tubez-compression";
+
+/**
+ * Payloads smaller than this are always sent uncompressed: they rarely shrink
+ * enough to be worth the CPU.
+ */
+pub const MIN_COMPRESSED_PAYLOAD_SIZE: usize = 256;
+
+#[derive(Debug)]
+pub enum DecompressError {
+    IoError(std::io::Error),
+    MessageTooLarge {
+        max_message_size: usize,
+    },
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Compression {
+    Deflate,
+    Zstd,
+}
+impl Compression {
+    pub fn from_header_value(value: &str) -> Option<Self> {
+        match value {
+            "deflate" => Some(Compression::Deflate),
+            "zstd" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    pub fn header_value(&self) -> &'static str {
+        match self {
+            Compression::Deflate => "deflate",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    pub(in crate) fn compress(&self, data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+        match self {
+            Compression::Deflate => {
+                let mut encoder = flate2::read::DeflateEncoder::new(
+                    data,
+                    flate2::Compression::default(),
+                );
+                let mut compressed = vec![];
+                encoder.read_to_end(&mut compressed)?;
+                Ok(compressed)
+            },
+            Compression::Zstd => zstd::stream::encode_all(data, 0),
+        }
+    }
+
+    /**
+     * Decompresses `data`, failing as soon as the decompressed size exceeds
+     * `max_message_size` (rather than trusting the peer not to send a
+     * decompression bomb).
+     */
+    pub(in crate) fn decompress(
+        &self,
+        data: &[u8],
+        max_message_size: usize,
+    ) -> Result<Bytes, DecompressError> {
+        let decoder: Box<dyn Read + '_> = match self {
+            Compression::Deflate => Box::new(flate2::read::DeflateDecoder::new(data)),
+            Compression::Zstd => match zstd::stream::read::Decoder::new(data) {
+                Ok(decoder) => Box::new(decoder),
+                Err(e) => return Err(DecompressError::IoError(e)),
+            },
+        };
+
+        let mut decompressed = vec![];
+        let read_limit = u64::try_from(max_message_size).unwrap_or(u64::MAX).saturating_add(1);
+        if let Err(e) = decoder.take(read_limit).read_to_end(&mut decompressed) {
+            return Err(DecompressError::IoError(e));
+        }
+        if decompressed.len() > max_message_size {
+            return Err(DecompressError::MessageTooLarge { max_message_size });
+        }
+        Ok(decompressed.into())
+    }
+}
+
+#[cfg(test)]
+mod compression_tests {
+    use super::*;
+
+    #[test]
+    fn compressed_data_round_trips() {
+        let data = "{\"metric\":\"cpu\",\"value\":42}".repeat(100);
+        for compression in [Compression::Deflate, Compression::Zstd] {
+            let compressed = compression.compress(data.as_bytes()).unwrap();
+            assert!(compressed.len() < data.len());
+            let decompressed = compression.decompress(&compressed, data.len()).unwrap();
+            assert_eq!(&decompressed[..], data.as_bytes());
+        }
+    }
+
+    #[test]
+    fn decompress_errors_if_output_exceeds_max_message_size() {
+        let data = vec![0; 4096];
+        for compression in [Compression::Deflate, Compression::Zstd] {
+            let compressed = compression.compress(&data).unwrap();
+            match compression.decompress(&compressed, data.len() - 1) {
+                Err(DecompressError::MessageTooLarge { max_message_size: 4095 }) => (),
+                unexpected => panic!("Unexpected decompress() result: {:?}", unexpected),
+            }
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::common::frame;
+use crate::common::tube;
+
+#[derive(Debug)]
+pub enum DrainError {
+    AlreadyDraining,
+    AlreadyGoingAway,
+    FrameEncodeError(frame::encode::FrameEncodeError),
+    NotConnected,
+    TransportError(hyper::Error),
+}
+
+pub(in crate) async fn send_drain(
+    sender: &Arc<tokio::sync::Mutex<hyper::body::Sender>>,
+    reason: frame::DrainReason,
+) -> Result<(), DrainError> {
+    let frame_data = match frame::encode::drain_frame(reason) {
+        Ok(data) => data,
+        Err(e) => return Err(DrainError::FrameEncodeError(e)),
+    };
+
+    let mut sender = sender.lock().await;
+    log::trace!("Sending Drain(reason={:?})...", reason);
+    match sender.send_data(frame_data).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DrainError::TransportError(e)),
+    }
+}
+
+/**
+ * Aborts every Tube that is still open on a draining channel once `deadline`
+ * has elapsed.
+ */
+pub(in crate) fn spawn_drain_deadline(
+    sender: Arc<tokio::sync::Mutex<hyper::body::Sender>>,
+    tube_managers: Arc<Mutex<HashMap<u64, Arc<Mutex<tube::TubeManager>>>>>,
+    deadline: Duration,
+) {
+    tokio::spawn(async move {
+        tokio::time::sleep(deadline).await;
+        let tube_ids = tube::abort_open_tube_managers(
+            &tube_managers,
+            frame::AbortReason::ChannelGoingAway,
+        );
+        if tube_ids.is_empty() {
+            return;
+        }
+
+        let mut sender = sender.lock().await;
+        for tube_id in tube_ids {
+            let frame_data = match frame::encode::abort_frame(
+                tube_id,
+                frame::AbortReason::ChannelGoingAway,
+            ) {
+                Ok(data) => data,
+                Err(e) => {
+                    log::error!("Error encoding Abort frame: {:?}", e);
+                    continue;
+                },
+            };
+            log::trace!("Drain deadline expired. Sending Abort(tube_id={})...", tube_id);
+            if let Err(e) = sender.send_data(frame_data).await {
+                log::trace!("Error sending Abort frame: {:?}", e);
+                return;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod drain_tests {
+    use super::*;
+
+    use hyper::body::HttpBody;
+
+    #[tokio::test]
+    async fn open_tubes_are_aborted_once_the_deadline_expires() {
+        let open_tube_mgr = Arc::new(Mutex::new(tube::TubeManager::new()));
+        let closed_tube_mgr = Arc::new(Mutex::new(tube::TubeManager::new()));
+        closed_tube_mgr.lock().unwrap().completion_state = tube::TubeCompletionState::Closed;
+        let tube_managers = Arc::new(Mutex::new(HashMap::from([
+            (1, open_tube_mgr.clone()),
+            (3, closed_tube_mgr.clone()),
+        ])));
+        let (body_sender, mut body) = hyper::Body::channel();
+        let body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+
+        tube::drain_all_tube_managers(&tube_managers, frame::DrainReason::Restart);
+        spawn_drain_deadline(body_sender, tube_managers.clone(), Duration::from_millis(10));
+
+        let frame_data = body.data().await.unwrap().unwrap();
+        assert_eq!(
+            frame_data,
+            frame::encode::abort_frame(1, frame::AbortReason::ChannelGoingAway).unwrap(),
+        );
+
+        let events: Vec<_> = open_tube_mgr.lock().unwrap().pending_events.drain(..).collect();
+        assert_eq!(events, vec![
+            tube::TubeEvent::ServerMustDrain(frame::DrainReason::Restart),
+            tube::TubeEvent::Abort(frame::AbortReason::ChannelGoingAway),
+        ]);
+        assert!(closed_tube_mgr.lock().unwrap().pending_events.is_empty());
+
+        // The aborted Tube is tracked until the peer's AbortAck arrives.
+        assert!(tube_managers.lock().unwrap().contains_key(&1));
+    }
+}
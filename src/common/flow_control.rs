@@ -0,0 +1,271 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crate::common::frame;
+
+/**
+ * The NewTube header a client sets to indicate that payloads on the Tube are
+ * subject to flow control. It is only sent when both peers advertised
+ * Features::FLOW_CONTROL.
+ */
+pub const FLOW_CONTROL_HEADER: &str = "tubez-flow-control";
+
+/**
+ * Tracks the per-Tube windows that keep either peer from sending payload data
+ * faster than the other peer's application consumes it.
+ *
+ * Every byte of payload data (i.e. the Data of each Payload,
+ * CompressedPayload, and PayloadFragment frame) consumes a byte of credit. A
+ * peer starts with as much credit as the other peer advertised as its
+ * InitialWindowSize, and regains credit as WindowUpdate frames arrive. The
+ * receiving peer sends a WindowUpdate once the application has consumed (or
+ * the receiver has dropped) at least half a window's worth of data.
+ *
+ * The data of PayloadFragment frames is credited back as soon as it has been
+ * buffered for reassembly, rather than once the reassembled payload is
+ * consumed. Otherwise a payload larger than the window could never be
+ * completed. The data buffered for reassembly is bounded by max_message_size
+ * instead.
+ */
+#[derive(Debug)]
+pub struct FlowControl {
+    credit_notify: Arc<tokio::sync::Notify>,
+    initial_recv_window: u32,
+    initial_send_window: u32,
+    /**
+     * The credit consumed by each payload that has been queued for the
+     * application but not yet consumed, in the order they were queued.
+     */
+    pending_payload_credits: VecDeque<u32>,
+    /**
+     * The credit consumed by the frame currently being received that hasn't
+     * been queued, buffered, or released yet.
+     */
+    partial_payload_credit: u32,
+    recv_window: u32,
+    send_credit: u32,
+    unreported_credit: u32,
+}
+impl FlowControl {
+    pub fn new(send_credit: u32, recv_window: u32) -> Self {
+        FlowControl {
+            credit_notify: Arc::new(tokio::sync::Notify::new()),
+            initial_recv_window: recv_window,
+            initial_send_window: send_credit,
+            pending_payload_credits: VecDeque::new(),
+            partial_payload_credit: 0,
+            recv_window,
+            send_credit,
+            unreported_credit: 0,
+        }
+    }
+
+    pub fn add_send_credit(&mut self, credit: u32) {
+        self.send_credit = self.send_credit.saturating_add(credit);
+        self.credit_notify.notify_one();
+    }
+
+    /**
+     * Notified whenever credit is added (or the Tube is aborted) so that a
+     * sender waiting on credit can try again.
+     */
+    pub fn credit_notify(&self) -> Arc<tokio::sync::Notify> {
+        self.credit_notify.clone()
+    }
+
+    /**
+     * Takes up to `wanted` bytes of send credit, returning the number of bytes
+     * taken.
+     */
+    pub fn take_send_credit(&mut self, wanted: usize) -> usize {
+        let taken = self.send_credit.min(u32::try_from(wanted).unwrap_or(u32::MAX));
+        self.send_credit -= taken;
+        taken as usize
+    }
+
+    /**
+     * Takes exactly `wanted` bytes of send credit if that much is available, 
+     * and none otherwise. Returns whether it was taken.
+     */
+    pub fn take_whole_send_credit(&mut self, wanted: usize) -> bool {
+        match u32::try_from(wanted) {
+            Ok(wanted) if wanted <= self.send_credit => {
+                self.send_credit -= wanted;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /**
+     * The credit the peer granted before sending any WindowUpdates, which is 
+     * the most that can ever be available at once for a payload that can't 
+     * be split into fragments.
+     */
+    pub fn initial_send_window(&self) -> u32 {
+        self.initial_send_window
+    }
+
+    /**
+     * Wakes a sender waiting on credit without adding any (e.g. because the
+     * Tube was aborted).
+     */
+    pub fn wake_senders(&self) {
+        self.credit_notify.notify_one();
+    }
+
+    /**
+     * Charges `len` bytes of received payload data against the receive window.
+     * Returns false (and charges nothing) if the peer has exceeded the window.
+     */
+    pub fn receive(&mut self, len: usize) -> bool {
+        let len = match u32::try_from(len) {
+            Ok(len) if len <= self.recv_window => len,
+            _ => return false,
+        };
+        self.recv_window -= len;
+        self.partial_payload_credit += len;
+        true
+    }
+
+    /**
+     * Called once the data of a PayloadFragment has been buffered for
+     * reassembly. Returns the credit to send back to the peer in a
+     * WindowUpdate, if any.
+     */
+    pub fn buffer_fragment(&mut self) -> Option<u32> {
+        let credit = std::mem::take(&mut self.partial_payload_credit);
+        self.release(credit)
+    }
+
+    /**
+     * Called once the payload being received has been queued for the
+     * application.
+     */
+    pub fn finish_payload(&mut self) {
+        self.pending_payload_credits.push_back(self.partial_payload_credit);
+        self.partial_payload_credit = 0;
+    }
+
+    /**
+     * Called when the payload being received is dropped rather than queued for
+     * the application. Returns the credit to send back to the peer in a
+     * WindowUpdate, if any.
+     */
+    pub fn drop_payload(&mut self) -> Option<u32> {
+        let credit = std::mem::take(&mut self.partial_payload_credit);
+        self.release(credit)
+    }
+
+    /**
+     * Called when the application consumes a queued payload. Returns the
+     * credit to send back to the peer in a WindowUpdate, if any.
+     */
+    pub fn consume_payload(&mut self) -> Option<u32> {
+        let credit = self.pending_payload_credits.pop_front().unwrap_or(0);
+        self.release(credit)
+    }
+
+    fn release(&mut self, credit: u32) -> Option<u32> {
+        self.unreported_credit += credit;
+        let threshold = (self.initial_recv_window / 2).max(1);
+        if self.unreported_credit < threshold {
+            return None;
+        }
+        let credit = std::mem::take(&mut self.unreported_credit);
+        self.recv_window += credit;
+        Some(credit)
+    }
+}
+
+/**
+ * Sends a WindowUpdate frame in the background. Used where the credit is
+ * released while a TubeManager's lock is held.
+ */
+pub(in crate) fn spawn_window_update(
+    sender: Arc<tokio::sync::Mutex<hyper::body::Sender>>,
+    tube_id: u64,
+    credit: u32,
+) {
+    tokio::spawn(async move {
+        let frame_data = match frame::encode::window_update_frame(tube_id, credit) {
+            Ok(data) => data,
+            Err(e) => {
+                log::error!("Error encoding WindowUpdate frame: {:?}", e);
+                return;
+            },
+        };
+        let mut sender = sender.lock().await;
+        log::trace!("Sending WindowUpdate(tube_id={}, credit={})...", tube_id, credit);
+        if let Err(e) = sender.send_data(frame_data).await {
+            log::trace!("Error sending WindowUpdate frame: {:?}", e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod flow_control_tests {
+    use super::*;
+
+    #[test]
+    fn send_credit_is_taken_up_to_what_is_available() {
+        let mut flow_control = FlowControl::new(10, 100);
+        assert_eq!(flow_control.take_send_credit(4), 4);
+        assert_eq!(flow_control.take_send_credit(20), 6);
+        assert_eq!(flow_control.take_send_credit(1), 0);
+
+        flow_control.add_send_credit(3);
+        assert_eq!(flow_control.take_send_credit(20), 3);
+    }
+
+    #[test]
+    fn whole_send_credit_is_only_taken_if_all_of_it_is_available() {
+        let mut flow_control = FlowControl::new(10, 100);
+        assert!(flow_control.take_whole_send_credit(4));
+        assert!(!flow_control.take_whole_send_credit(7));
+        assert!(flow_control.take_whole_send_credit(6));
+        assert_eq!(flow_control.initial_send_window(), 10);
+    }
+
+    #[test]
+    fn receiving_beyond_the_window_fails() {
+        let mut flow_control = FlowControl::new(0, 10);
+        assert!(flow_control.receive(6));
+        assert!(!flow_control.receive(5));
+        assert!(flow_control.receive(4));
+        assert!(!flow_control.receive(1));
+    }
+
+    #[test]
+    fn credit_is_reported_once_half_a_window_is_consumed() {
+        let mut flow_control = FlowControl::new(0, 10);
+        assert!(flow_control.receive(3));
+        flow_control.finish_payload();
+        assert!(flow_control.receive(2));
+        assert!(flow_control.receive(2));
+        flow_control.finish_payload();
+        assert!(flow_control.receive(3));
+        assert!(!flow_control.receive(1));
+
+        assert_eq!(flow_control.consume_payload(), None);
+        assert_eq!(flow_control.consume_payload(), Some(7));
+        assert!(flow_control.receive(7));
+        assert_eq!(flow_control.drop_payload(), Some(10));
+    }
+
+    #[test]
+    fn fragment_credit_is_reported_once_buffered() {
+        let mut flow_control = FlowControl::new(0, 10);
+        assert!(flow_control.receive(4));
+        assert_eq!(flow_control.buffer_fragment(), None);
+        assert!(flow_control.receive(4));
+        assert_eq!(flow_control.buffer_fragment(), Some(8));
+
+        // The payload is larger than the window, but only its final frame 
+        // waits on the application.
+        assert!(flow_control.receive(9));
+        flow_control.finish_payload();
+        assert!(!flow_control.receive(2));
+        assert_eq!(flow_control.consume_payload(), Some(9));
+    }
+}
@@ -0,0 +1,153 @@
+use bytes::BytesMut;
+
+use super::decode;
+use super::decode::DecoderConfig;
+use super::decode::FrameParseError;
+use super::encode;
+use super::frame;
+
+#[derive(Debug)]
+pub enum FrameCodecError {
+    FrameEncodeError(encode::FrameEncodeError),
+    FrameParseError(FrameParseError),
+    IoError(std::io::Error),
+}
+impl From<std::io::Error> for FrameCodecError {
+    fn from(err: std::io::Error) -> Self {
+        FrameCodecError::IoError(err)
+    }
+}
+
+/**
+ * Adapts the tubez framing protocol to tokio_util's codec traits so that a
+ * stream of Frames can be sent and received over any AsyncRead + AsyncWrite
+ * transport (rather than only over hyper bodies):
+ *
+ *   let mut framed = tokio_util::codec::Framed::new(tcp_stream, FrameCodec::new());
+ *   framed.send(Frame::Ping { opaque: 42 }).await?;
+ *   while let Some(frame) = framed.next().await { ... }
+ *
+ * Decoding is zero-copy in the same way as frame::Decoder: Payload data is
+ * sliced out of the read buffer rather than copied.
+ */
+#[derive(Debug,Default)]
+pub struct FrameCodec {
+    config: DecoderConfig,
+}
+impl FrameCodec {
+    pub fn new() -> Self {
+        Self::new_with_config(DecoderConfig::default())
+    }
+
+    pub fn new_with_config(config: DecoderConfig) -> Self {
+        FrameCodec {
+            config,
+        }
+    }
+}
+impl tokio_util::codec::Decoder for FrameCodec {
+    type Item = frame::Frame;
+    type Error = FrameCodecError;
+
+    fn decode(
+        &mut self,
+        src: &mut BytesMut,
+    ) -> Result<Option<Self::Item>, Self::Error> {
+        loop {
+            match decode::full_frame_len(src, &self.config) {
+                Ok(Some(frame_len)) => {
+                    let frame_data = src.split_to(frame_len).freeze();
+                    if decode::is_skippable_frame(&frame_data) {
+                        continue;
+                    }
+                    return match decode::parse_frame(frame_data) {
+                        Ok(frame) => Ok(Some(frame)),
+                        Err(e) => Err(FrameCodecError::FrameParseError(e)),
+                    };
+                },
+                Ok(None) => return Ok(None),
+                Err(e) => return Err(FrameCodecError::FrameParseError(e)),
+            }
+        }
+    }
+}
+impl tokio_util::codec::Encoder<frame::Frame> for FrameCodec {
+    type Error = FrameCodecError;
+
+    fn encode(
+        &mut self,
+        item: frame::Frame,
+        dst: &mut BytesMut,
+    ) -> Result<(), Self::Error> {
+        match encode::frame_into(dst, &item) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(FrameCodecError::FrameEncodeError(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod frame_codec_tests {
+    use bytes::Bytes;
+    use futures::SinkExt;
+    use futures::StreamExt;
+    use tokio_util::codec::Decoder;
+    use tokio_util::codec::Framed;
+
+    use super::*;
+
+    #[test]
+    fn waits_for_full_frame_before_decoding() {
+        let mut codec = FrameCodec::new();
+        let encoded_bytes = encode::client_has_finished_sending_frame(43).unwrap();
+
+        let mut src = BytesMut::from(&encoded_bytes[..encoded_bytes.len() - 1]);
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+
+        src.extend_from_slice(&encoded_bytes[encoded_bytes.len() - 1..]);
+        assert_eq!(
+            codec.decode(&mut src).unwrap(),
+            Some(frame::Frame::ClientHasFinishedSending { tube_id: 43, trailers: None }),
+        );
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn errors_if_frametype_value_is_unknown() {
+        let mut codec = FrameCodec::new();
+        let mut src = BytesMut::from(&[255, 0, 0][..]);
+        match codec.decode(&mut src) {
+            Err(FrameCodecError::FrameParseError(FrameParseError::UnknownFrameType(255))) => (),
+            unexpected => panic!("Unexpected result from FrameCodec::decode(): {:?}", unexpected),
+        }
+    }
+
+    #[tokio::test]
+    async fn frames_round_trip_over_duplex_transport() {
+        let (client_io, server_io) = tokio::io::duplex(1024);
+        let mut client = Framed::new(client_io, FrameCodec::new());
+        let mut server = Framed::new(server_io, FrameCodec::new());
+
+        let frames = vec![
+            frame::Frame::Payload {
+                tube_id: 1,
+                ack_id: Some(42),
+                processing_ack: false,
+                data: Bytes::from(vec![7; 4096]),
+            },
+            frame::Frame::ClientHasFinishedSending { tube_id: 1, trailers: None },
+            frame::Frame::Drain { reason: frame::DrainReason::Restart },
+        ];
+        let expected_frames = frames.clone();
+        let sender = tokio::spawn(async move {
+            for frame in frames {
+                client.send(frame).await.unwrap();
+            }
+        });
+
+        for expected_frame in expected_frames {
+            assert_eq!(server.next().await.unwrap().unwrap(), expected_frame);
+        }
+        sender.await.unwrap();
+    }
+}
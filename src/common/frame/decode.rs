@@ -1,9 +1,15 @@
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::collections::VecDeque;
 
+use bytes::Buf;
+use bytes::Bytes;
+use bytes::BytesMut;
 use serde_json;
 
 use super::frame;
+use super::header_block;
+use super::varint;
 
 // Returned by Decoder::decode() and provides context around 
 // a FrameParseError
@@ -14,14 +20,282 @@ pub struct FrameDecodeError {
     pub parse_error: FrameParseError,
     #[allow(dead_code)]
     pub num_frames_parsed_successfully: usize,
+    // The frames that preceded the malformed one in the same chunk of data. 
+    // They were well-formed, so they should be handled before the error is.
+    pub decoded_frames: VecDeque<frame::Frame>,
 }
 
 #[derive(Debug)]
 pub enum FrameParseError {
-    InternalByteOffsetLogicError(String),
+    BodyTooLong {
+        frame_type: u8,
+        expected: usize,
+        actual: usize,
+    },
+    BodyTooShort {
+        frame_type: u8,
+        expected: usize,
+        actual: usize,
+    },
+    DisallowedFrameType(u8),
+    ExtTypeOutOfRange(u8),
+    HeaderBlockTrailingBytes(usize),
+    HeaderBlockTruncated,
     HeaderJsonDecodeError(serde_json::error::Error),
     HeaderUtf8Error(std::str::Utf8Error),
+    AckRangeOverflow {
+        start: u64,
+        len: u64,
+    },
+    AbortMessageUtf8Error(std::str::Utf8Error),
+    GoAwayMessageUtf8Error(std::str::Utf8Error),
+    LimitExceeded {
+        limit: DecoderLimit,
+        max: usize,
+        actual: usize,
+    },
     UnknownFrameType(u8),
+    UnknownStaticHeaderIndex(u8),
+    VarintTruncated(u8),
+}
+
+impl From<&FrameParseError> for frame::GoAwayErrorCode {
+    fn from(err: &FrameParseError) -> Self {
+        use FrameParseError::*;
+        match err {
+            BodyTooLong { .. }
+            | BodyTooShort { .. }
+            | DisallowedFrameType(_)
+            | ExtTypeOutOfRange(_)
+            | HeaderBlockTrailingBytes(_)
+            | HeaderBlockTruncated
+            | HeaderJsonDecodeError(_)
+            | HeaderUtf8Error(_)
+            | AckRangeOverflow { .. }
+            | AbortMessageUtf8Error(_)
+            | GoAwayMessageUtf8Error(_)
+            | UnknownFrameType(_)
+            | UnknownStaticHeaderIndex(_)
+            | VarintTruncated(_) => frame::GoAwayErrorCode::ProtocolError,
+            LimitExceeded { .. } => frame::GoAwayErrorCode::LimitExceeded,
+        }
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum DecoderLimit {
+    MaxBufferedBytes,
+    MaxFrameSize,
+}
+
+/**
+ * Bounds the resources a Decoder will commit to a peer's stream of bytes. A 
+ * Decoder that exceeds one of these limits fails with 
+ * FrameParseError::LimitExceeded (or FrameParseError::DisallowedFrameType) 
+ * and the peer should be treated as having violated the protocol.
+ */
+#[derive(Clone,Debug)]
+pub struct DecoderConfig {
+    /**
+     * The most bytes of incomplete frame data the Decoder will hold on to 
+     * while it waits for the rest of a frame to arrive.
+     */
+    pub max_buffered_bytes: usize,
+
+    /**
+     * The largest frame (including its 3-byte header) that the Decoder will 
+     * accept. Frames that exceed this are rejected as soon as their header 
+     * arrives rather than after their body has been buffered.
+     */
+    pub max_frame_size: usize,
+
+    /**
+     * When set, only frames of these types are accepted.
+     */
+    pub allowed_frame_types: Option<HashSet<frame::FrameTag>>,
+}
+impl DecoderConfig {
+    /**
+     * Checks whatever portion of a frame's header is present at the start of 
+     * `data` against the configured limits.
+     */
+    fn check_frame_header(&self, data: &[u8]) -> Result<(), FrameParseError> {
+        if let (Some(frame_type), Some(allowed_frame_types)) = 
+                (data.first(), &self.allowed_frame_types) {
+            let is_allowed = match frame::FrameTag::from_frametype(*frame_type) {
+                Some(frame_tag) => allowed_frame_types.contains(&frame_tag),
+                None => false,
+            };
+            if !is_allowed {
+                return Err(FrameParseError::DisallowedFrameType(*frame_type));
+            }
+        }
+
+        if data.len() >= 3 {
+            let frame_size = 3 + usize::from(double_u8_to_u16(data[1], data[2]));
+            if frame_size > self.max_frame_size {
+                return Err(FrameParseError::LimitExceeded {
+                    limit: DecoderLimit::MaxFrameSize,
+                    max: self.max_frame_size,
+                    actual: frame_size,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_buffered_bytes(&self, buffered_bytes: usize) -> Result<(), FrameParseError> {
+        if buffered_bytes > self.max_buffered_bytes {
+            return Err(FrameParseError::LimitExceeded {
+                limit: DecoderLimit::MaxBufferedBytes,
+                max: self.max_buffered_bytes,
+                actual: buffered_bytes,
+            });
+        }
+        Ok(())
+    }
+}
+impl Default for DecoderConfig {
+    fn default() -> Self {
+        DecoderConfig {
+            max_buffered_bytes: 3 + frame::MAX_FRAME_BODY_LEN,
+            max_frame_size: 3 + frame::MAX_FRAME_BODY_LEN,
+            allowed_frame_types: None,
+        }
+    }
+}
+
+enum ExpectedBodyLen {
+    AtLeast(usize),
+    Exactly(usize),
+}
+
+fn expected_body_len(frame_type: u8) -> Option<ExpectedBodyLen> {
+    use ExpectedBodyLen::*;
+    match frame_type {
+        // TubeId(u16)
+        frame::CLIENT_HAS_FINISHED_SENDING_FRAMETYPE => Some(Exactly(2)),
+        // [DrainReason(u8)] + RESERVED(*)
+        frame::DRAIN_FRAMETYPE => Some(AtLeast(0)),
+        // TubeId(u16) + Utf8EncodedJSONHeaders(*)
+        frame::NEWTUBE_FRAMETYPE => Some(AtLeast(2)),
+        // TubeId(u16) + HeaderBlock(*)
+        frame::NEWTUBE_BINARY_FRAMETYPE => Some(AtLeast(2)),
+        // TubeId(u16) + HeaderBlock(*)
+        frame::NEWTUBE_ACCEPTED_FRAMETYPE => Some(AtLeast(2)),
+        // TubeId(u16) + Code(u16) + HeaderBlock(*)
+        frame::NEWTUBE_REJECTED_FRAMETYPE => Some(AtLeast(4)),
+        // TubeId(u16) + AckId(u16) + Data(*)
+        frame::PAYLOAD_FRAMETYPE => Some(AtLeast(4)),
+        // TubeId(u16) + AckId(u16) + Data(*)
+        frame::COMPRESSED_PAYLOAD_FRAMETYPE => Some(AtLeast(4)),
+        // TubeId(u16) + AckId(u16) + Data(*)
+        frame::PROCESSING_ACK_PAYLOAD_FRAMETYPE => Some(AtLeast(4)),
+        // TubeId(u16) + AckId(u16) + Data(*)
+        frame::PROCESSING_ACK_COMPRESSED_PAYLOAD_FRAMETYPE => Some(AtLeast(4)),
+        // TubeId(u16) + Processed(1) + AckId(15)
+        frame::PAYLOAD_ACK_FRAMETYPE => Some(Exactly(4)),
+        // TubeId(u16) + UpTo(varint) + Ranges(*)
+        frame::CUMULATIVE_PAYLOAD_ACK_FRAMETYPE => Some(AtLeast(3)),
+        // TubeId(u16) + HeaderBlock(*)
+        frame::CLIENT_HAS_FINISHED_SENDING_WITH_TRAILERS_FRAMETYPE => Some(AtLeast(2)),
+        // TubeId(u16) + HeaderBlock(*)
+        frame::SERVER_HAS_FINISHED_SENDING_WITH_TRAILERS_FRAMETYPE => Some(AtLeast(2)),
+        // TubeId(u16)
+        frame::SERVER_HAS_FINISHED_SENDING_FRAMETYPE => Some(Exactly(2)),
+        // TubeId(u16) + AbortReason(u8)
+        frame::ABORT_FRAMETYPE => Some(Exactly(3)),
+        // TubeId(u16) + Code(u32) + Utf8Message(*)
+        frame::ABORT_WITH_CODE_FRAMETYPE => Some(AtLeast(6)),
+        // TubeId(u16)
+        frame::ABORTACK_FRAMETYPE => Some(Exactly(2)),
+        // TubeId(u16) + Data(*)
+        frame::PAYLOAD_FRAGMENT_FRAMETYPE => Some(AtLeast(2)),
+        // LastTubeId(u16) + ErrorCode(u8) + Utf8Message(*)
+        frame::GOAWAY_FRAMETYPE => Some(AtLeast(3)),
+        // Opaque(u64)
+        frame::PING_FRAMETYPE => Some(Exactly(8)),
+        // Opaque(u64)
+        frame::PONG_FRAMETYPE => Some(Exactly(8)),
+        // Version(u8) + Features(u32) + MaxFrameSize(u32) + 
+        // MaxConcurrentTubes(u16) + [InitialWindowSize(u32)] + RESERVED(*)
+        frame::SETTINGS_FRAMETYPE => Some(AtLeast(11)),
+        // Token(u128)
+        frame::SESSION_FRAMETYPE => Some(Exactly(16)),
+        // TubeId(u16) + Sequence(u64)
+        frame::PAYLOAD_SEQUENCE_FRAMETYPE => Some(Exactly(10)),
+        // TubeId(u16) + Credit(u32)
+        frame::WINDOW_UPDATE_FRAMETYPE => Some(Exactly(6)),
+        // TubeId(u16) + Weight(u8)
+        frame::PRIORITY_FRAMETYPE => Some(Exactly(3)),
+        // TubeId(u16) + Data(*)
+        frame_type if frame::APPLICATION_EXTENSION_FRAMETYPES.contains(&frame_type) => 
+            Some(AtLeast(2)),
+        frame_type if frame::VARINT_IDS_FRAMETYPES.contains(&frame_type) => 
+            varint_ids_expected_body_len(frame_type),
+        _ => None,
+    }
+}
+
+/**
+ * The varint-id form of a frame has the same fields as its fixed-width form, 
+ * but each id may be as short as a single byte, so only a lower bound can be 
+ * checked up front. Anything else is checked as the ids are parsed.
+ */
+fn varint_ids_expected_body_len(frame_type: u8) -> Option<ExpectedBodyLen> {
+    use ExpectedBodyLen::*;
+    if frame_type == frame::VARINT_IDS_EXTENSION_FRAMETYPE {
+        // ExtType(u8) + TubeId(varint) + Data(*)
+        return Some(AtLeast(2));
+    }
+    match frame_type & !frame::VARINT_IDS_FRAMETYPE_FLAG {
+        // TubeId(varint) + ...
+        frame::CLIENT_HAS_FINISHED_SENDING_FRAMETYPE
+        | frame::SERVER_HAS_FINISHED_SENDING_FRAMETYPE
+        | frame::ABORTACK_FRAMETYPE
+        | frame::NEWTUBE_FRAMETYPE
+        | frame::NEWTUBE_BINARY_FRAMETYPE
+        | frame::NEWTUBE_ACCEPTED_FRAMETYPE
+        | frame::CLIENT_HAS_FINISHED_SENDING_WITH_TRAILERS_FRAMETYPE
+        | frame::SERVER_HAS_FINISHED_SENDING_WITH_TRAILERS_FRAMETYPE
+        | frame::PAYLOAD_FRAGMENT_FRAMETYPE => Some(AtLeast(1)),
+        // TubeId(varint) + AckId(varint) + ..., etc
+        frame::PAYLOAD_FRAMETYPE
+        | frame::COMPRESSED_PAYLOAD_FRAMETYPE
+        | frame::PROCESSING_ACK_PAYLOAD_FRAMETYPE
+        | frame::PROCESSING_ACK_COMPRESSED_PAYLOAD_FRAMETYPE
+        | frame::PAYLOAD_ACK_FRAMETYPE
+        | frame::CUMULATIVE_PAYLOAD_ACK_FRAMETYPE
+        | frame::ABORT_FRAMETYPE
+        | frame::GOAWAY_FRAMETYPE
+        | frame::PRIORITY_FRAMETYPE => Some(AtLeast(2)),
+        // TubeId(varint) + Code(u16) + HeaderBlock(*)
+        frame::NEWTUBE_REJECTED_FRAMETYPE => Some(AtLeast(3)),
+        // TubeId(varint) + Credit(u32)
+        frame::WINDOW_UPDATE_FRAMETYPE => Some(AtLeast(5)),
+        // TubeId(varint) + Sequence(u64)
+        frame::PAYLOAD_SEQUENCE_FRAMETYPE => Some(AtLeast(9)),
+        // TubeId(varint) + Code(u32) + Utf8Message(*)
+        frame::ABORT_WITH_CODE_FRAMETYPE => Some(AtLeast(5)),
+        _ => None,
+    }
+}
+
+/**
+ * Ensures that a frame's body is the right size for its FrameType before 
+ * parse_frame_body() indexes into it.
+ */
+fn validate_body_len(frame_type: u8, actual: usize) -> Result<(), FrameParseError> {
+    match expected_body_len(frame_type) {
+        None => Err(FrameParseError::UnknownFrameType(frame_type)),
+        Some(ExpectedBodyLen::AtLeast(expected)) | Some(ExpectedBodyLen::Exactly(expected))
+            if actual < expected => 
+            Err(FrameParseError::BodyTooShort { frame_type, expected, actual }),
+        Some(ExpectedBodyLen::Exactly(expected)) if actual > expected => 
+            Err(FrameParseError::BodyTooLong { frame_type, expected, actual }),
+        Some(_) => Ok(()),
+    }
 }
 
 fn double_u8_to_u16(left_byte: u8, right_byte: u8) -> u16 {
@@ -31,90 +305,215 @@ fn double_u8_to_u16(left_byte: u8, right_byte: u8) -> u16 {
     ((left_byte as u16) << 8) | (right_byte as u16)
 }
 
-fn parse_frame_body(frame_type: u8, mut frame_body_data: VecDeque<u8>) 
+/**
+ * Builds the Frame for any of the (fixed-width) FrameTypes that share the 
+ * Payload layout.
+ */
+fn payload_frame(
+    frame_type: u8, 
+    tube_id: u64, 
+    ack_id: Option<u64>, 
+    data: Bytes,
+) -> frame::Frame {
+    let processing_ack = matches!(
+        frame_type, 
+        frame::PROCESSING_ACK_PAYLOAD_FRAMETYPE 
+        | frame::PROCESSING_ACK_COMPRESSED_PAYLOAD_FRAMETYPE
+    );
+    match frame_type {
+        frame::COMPRESSED_PAYLOAD_FRAMETYPE 
+        | frame::PROCESSING_ACK_COMPRESSED_PAYLOAD_FRAMETYPE => 
+            frame::Frame::CompressedPayload { tube_id, ack_id, processing_ack, data },
+        _ => frame::Frame::Payload { tube_id, ack_id, processing_ack, data },
+    }
+}
+
+/**
+ * Parses the UpTo and Ranges fields that follow the TubeId of a 
+ * CumulativePayloadAck frame (in either of its forms).
+ */
+fn parse_cumulative_payload_ack(
+    frame_type: u8,
+    tube_id: u64,
+    mut body: &[u8],
+) -> Result<frame::Frame, FrameParseError> {
+    // UpTo + 1, or 0 if no ack ids are acked cumulatively
+    let up_to = get_varint_id(frame_type, &mut body)?.checked_sub(1);
+    let mut ranges = vec![];
+    while !body.is_empty() {
+        let start = get_varint_id(frame_type, &mut body)?;
+        let len = get_varint_id(frame_type, &mut body)?;
+        match start.checked_add(len) {
+            Some(end) => ranges.push(start..=end),
+            None => return Err(FrameParseError::AckRangeOverflow { start, len }),
+        }
+    }
+    Ok(frame::Frame::CumulativePayloadAck { tube_id, up_to, ranges })
+}
+
+/**
+ * Parses the Code and Utf8Message fields that follow the TubeId of an Abort 
+ * frame carrying an AbortReason::Application. The caller ensures that `body`
+ * holds at least the Code.
+ */
+fn parse_application_abort_reason(
+    mut body: &[u8],
+) -> Result<frame::AbortReason, FrameParseError> {
+    let code = body.get_u32();
+    let message = match std::str::from_utf8(body) {
+        Ok("") => None,
+        Ok(str) => Some(str.to_string()),
+        Err(utf8_err) => return Err(FrameParseError::AbortMessageUtf8Error(utf8_err)),
+    };
+    Ok(frame::AbortReason::Application { code, message })
+}
+
+fn parse_frame_body(frame_type: u8, frame_body_data: Bytes) 
         -> Result<frame::Frame, FrameParseError> {
+    validate_body_len(frame_type, frame_body_data.len())?;
+
+    if frame::VARINT_IDS_FRAMETYPES.contains(&frame_type) {
+        return parse_varint_ids_frame_body(frame_type, frame_body_data);
+    }
+
     match frame_type {
         frame::CLIENT_HAS_FINISHED_SENDING_FRAMETYPE => {
-            let tube_id = double_u8_to_u16(
+            let tube_id = u64::from(double_u8_to_u16(
+                frame_body_data[0],
+                frame_body_data[1],
+            ));
+            Ok(frame::Frame::ClientHasFinishedSending { tube_id, trailers: None })
+        },
+
+        frame::CLIENT_HAS_FINISHED_SENDING_WITH_TRAILERS_FRAMETYPE => {
+            let tube_id = u64::from(double_u8_to_u16(
                 frame_body_data[0],
                 frame_body_data[1],
-            );
-            Ok(frame::Frame::ClientHasFinishedSending { tube_id })
+            ));
+            let trailers = Some(header_block::parse_header_block(&frame_body_data[2..])?);
+            Ok(frame::Frame::ClientHasFinishedSending { tube_id, trailers })
         },
 
        frame::DRAIN_FRAMETYPE => {
-            Ok(frame::Frame::Drain)
+            let reason = match frame_body_data.first() {
+                Some(reason) => frame::DrainReason::from(*reason),
+                None => frame::DrainReason::Unspecified,
+            };
+            Ok(frame::Frame::Drain { reason })
         },
 
         frame::NEWTUBE_FRAMETYPE => {
-            let mut header_bytes = frame_body_data.split_off(2);
-            let tube_id = double_u8_to_u16(
+            let tube_id = u64::from(double_u8_to_u16(
                 frame_body_data[0],
                 frame_body_data[1],
-            );
-            let headers_str = match std::str::from_utf8(header_bytes.make_contiguous()) {
+            ));
+            let headers_str = match std::str::from_utf8(&frame_body_data[2..]) {
                 Ok(str) => str,
                 Err(utf8_err) => return Err(FrameParseError::HeaderUtf8Error(utf8_err))
             };
-            let headers = match serde_json::from_str::<HashMap<String, String>>(&headers_str) {
+            let headers = match serde_json::from_str::<HashMap<String, String>>(headers_str) {
                 Ok(headers) => headers,
                 Err(json_err) => return Err(FrameParseError::HeaderJsonDecodeError(json_err))
             };
             Ok(frame::Frame::NewTube { tube_id, headers })
         },
 
-        frame::PAYLOAD_FRAMETYPE => {
-            let data = frame_body_data.split_off(4).make_contiguous().to_vec();
-            let tube_id = double_u8_to_u16(
+        frame::NEWTUBE_BINARY_FRAMETYPE => {
+            let tube_id = u64::from(double_u8_to_u16(
+                frame_body_data[0],
+                frame_body_data[1],
+            ));
+            let headers = header_block::parse_header_block(&frame_body_data[2..])?;
+            Ok(frame::Frame::NewTube { tube_id, headers })
+        },
+
+        frame::NEWTUBE_ACCEPTED_FRAMETYPE => {
+            let tube_id = u64::from(double_u8_to_u16(
                 frame_body_data[0],
                 frame_body_data[1],
-            );
+            ));
+            let headers = header_block::parse_header_block(&frame_body_data[2..])?;
+            Ok(frame::Frame::NewTubeAccepted { tube_id, headers })
+        },
+
+        frame::NEWTUBE_REJECTED_FRAMETYPE => {
+            let mut body = &frame_body_data[..];
+            let tube_id = u64::from(body.get_u16());
+            let code = body.get_u16();
+            let headers = header_block::parse_header_block(body)?;
+            Ok(frame::Frame::NewTubeRejected { tube_id, code, headers })
+        },
+
+        frame::PAYLOAD_FRAMETYPE 
+        | frame::COMPRESSED_PAYLOAD_FRAMETYPE 
+        | frame::PROCESSING_ACK_PAYLOAD_FRAMETYPE 
+        | frame::PROCESSING_ACK_COMPRESSED_PAYLOAD_FRAMETYPE => {
+            let data = frame_body_data.slice(4..);
+            let tube_id = u64::from(double_u8_to_u16(
+                frame_body_data[0],
+                frame_body_data[1],
+            ));
             let ack_id = 
                 // First bit of ack_id indicates whether an ACK is expected for 
                 // this payload and should not be considered when interpreting 
                 // the ack_id value.
                 if (0b1000_0000 & frame_body_data[2]) > 0 {
-                    Some(double_u8_to_u16(
+                    Some(u64::from(double_u8_to_u16(
                         0b0111_1111 & frame_body_data[2],
                         frame_body_data[3]
-                    ))
+                    )))
                 } else {
                     None
                 };
-            Ok(frame::Frame::Payload { tube_id, ack_id, data })
+            Ok(payload_frame(frame_type, tube_id, ack_id, data))
         },
 
         frame::PAYLOAD_ACK_FRAMETYPE => {
-            let tube_id = double_u8_to_u16(
+            let tube_id = u64::from(double_u8_to_u16(
                 frame_body_data[0],
                 frame_body_data[1],
-            );
-            let ack_id = double_u8_to_u16(
-                // ack_ids are always 15 bits. The 16th/MSB here is only used in
-                // Payload frames to indicate if an ack is actually requested.
-                //
-                // For now we zero-out this bit in PayloadAck frames to reserve
-                // this bit for other things in the future (e.g. a feature to 
-                // enable an ack to indicate "received" vs "processed", etc).
+            ));
+            let ack_id = u64::from(double_u8_to_u16(
+                // ack_ids are always 15 bits. The 16th/MSB here indicates 
+                // whether the payload was processed by the application rather
+                // than just received.
                 127 & frame_body_data[2],
                 frame_body_data[3],
-            );
-            Ok(frame::Frame::PayloadAck { tube_id, ack_id })
+            ));
+            let processed = (0b1000_0000 & frame_body_data[2]) > 0;
+            Ok(frame::Frame::PayloadAck { tube_id, ack_id, processed })
+        },
+
+        frame::CUMULATIVE_PAYLOAD_ACK_FRAMETYPE => {
+            let tube_id = u64::from(double_u8_to_u16(
+                frame_body_data[0],
+                frame_body_data[1],
+            ));
+            parse_cumulative_payload_ack(frame_type, tube_id, &frame_body_data[2..])
         },
 
         frame::SERVER_HAS_FINISHED_SENDING_FRAMETYPE => {
-            let tube_id = double_u8_to_u16(
+            let tube_id = u64::from(double_u8_to_u16(
                 frame_body_data[0],
                 frame_body_data[1],
-            );
-            Ok(frame::Frame::ServerHasFinishedSending { tube_id })
+            ));
+            Ok(frame::Frame::ServerHasFinishedSending { tube_id, trailers: None })
+        },
+
+        frame::SERVER_HAS_FINISHED_SENDING_WITH_TRAILERS_FRAMETYPE => {
+            let tube_id = u64::from(double_u8_to_u16(
+                frame_body_data[0],
+                frame_body_data[1],
+            ));
+            let trailers = Some(header_block::parse_header_block(&frame_body_data[2..])?);
+            Ok(frame::Frame::ServerHasFinishedSending { tube_id, trailers })
         },
 
         frame::ABORT_FRAMETYPE => {
-            let tube_id = double_u8_to_u16(
+            let tube_id = u64::from(double_u8_to_u16(
                 frame_body_data[0],
                 frame_body_data[1],
-            );
+            ));
             let reason = frame::AbortReason::from(frame_body_data[2]);
             Ok(frame::Frame::Abort {
                 tube_id,
@@ -122,83 +521,486 @@ fn parse_frame_body(frame_type: u8, mut frame_body_data: VecDeque<u8>)
             })
         },
 
+        frame::ABORT_WITH_CODE_FRAMETYPE => {
+            let tube_id = u64::from(double_u8_to_u16(
+                frame_body_data[0],
+                frame_body_data[1],
+            ));
+            let reason = parse_application_abort_reason(&frame_body_data[2..])?;
+            Ok(frame::Frame::Abort {
+                tube_id,
+                reason,
+            })
+        },
+
         frame::ABORTACK_FRAMETYPE => {
-            let tube_id = double_u8_to_u16(
+            let tube_id = u64::from(double_u8_to_u16(
                 frame_body_data[0],
                 frame_body_data[1],
-            );
+            ));
             Ok(frame::Frame::AbortAck {
                 tube_id,
             })
         },
 
+        frame::PAYLOAD_FRAGMENT_FRAMETYPE => {
+            let data = frame_body_data.slice(2..);
+            let tube_id = u64::from(double_u8_to_u16(
+                frame_body_data[0],
+                frame_body_data[1],
+            ));
+            Ok(frame::Frame::PayloadFragment { tube_id, data })
+        },
+
+        frame::GOAWAY_FRAMETYPE => {
+            let last_tube_id = u64::from(double_u8_to_u16(
+                frame_body_data[0],
+                frame_body_data[1],
+            ));
+            let error_code = frame::GoAwayErrorCode::from(frame_body_data[2]);
+            let message = match std::str::from_utf8(&frame_body_data[3..]) {
+                Ok(str) => str.to_string(),
+                Err(utf8_err) => return Err(FrameParseError::GoAwayMessageUtf8Error(utf8_err))
+            };
+            Ok(frame::Frame::GoAway {
+                last_tube_id,
+                error_code,
+                message,
+            })
+        },
+
+        frame::PING_FRAMETYPE => {
+            let opaque = (&frame_body_data[..]).get_u64();
+            Ok(frame::Frame::Ping { opaque })
+        },
+
+        frame::PONG_FRAMETYPE => {
+            let opaque = (&frame_body_data[..]).get_u64();
+            Ok(frame::Frame::Pong { opaque })
+        },
+
+        frame::SETTINGS_FRAMETYPE => {
+            let mut body = &frame_body_data[..];
+            let version = body.get_u8();
+            let features = frame::Features::from_bits(body.get_u32());
+            let max_frame_size = body.get_u32();
+            let max_concurrent_tubes = body.get_u16();
+            let initial_window_size = 
+                if body.remaining() >= 4 { body.get_u32() } else { 0 };
+            Ok(frame::Frame::Settings {
+                version,
+                features,
+                max_frame_size,
+                max_concurrent_tubes,
+                initial_window_size,
+            })
+        },
+
+        frame::SESSION_FRAMETYPE => {
+            let token = (&frame_body_data[..]).get_u128();
+            Ok(frame::Frame::Session { token })
+        },
+
+        frame::PAYLOAD_SEQUENCE_FRAMETYPE => {
+            let mut body = &frame_body_data[..];
+            let tube_id = u64::from(body.get_u16());
+            let sequence = body.get_u64();
+            Ok(frame::Frame::PayloadSequence { tube_id, sequence })
+        },
+
+        frame::WINDOW_UPDATE_FRAMETYPE => {
+            let mut body = &frame_body_data[..];
+            let tube_id = u64::from(body.get_u16());
+            let credit = body.get_u32();
+            Ok(frame::Frame::WindowUpdate { tube_id, credit })
+        },
+
+        frame::PRIORITY_FRAMETYPE => {
+            let mut body = &frame_body_data[..];
+            let tube_id = u64::from(body.get_u16());
+            let weight = body.get_u8();
+            Ok(frame::Frame::Priority { tube_id, weight })
+        },
+
+        frame_type if frame::APPLICATION_EXTENSION_FRAMETYPES.contains(&frame_type) => {
+            let data = frame_body_data.slice(2..);
+            let tube_id = u64::from(double_u8_to_u16(
+                frame_body_data[0],
+                frame_body_data[1],
+            ));
+            let ext_type = frame_type - frame::APPLICATION_EXTENSION_FRAMETYPES.start();
+            Ok(frame::Frame::Extension { tube_id, ext_type, data })
+        },
+
         _ => Err(FrameParseError::UnknownFrameType(frame_type)),
     }
 }
 
+fn get_varint_id(frame_type: u8, body: &mut &[u8]) -> Result<u64, FrameParseError> {
+    varint::get_varint(body).ok_or(FrameParseError::VarintTruncated(frame_type))
+}
+
+/**
+ * Parses the varint-id form of a frame (see frame::VARINT_IDS_FRAMETYPE_FLAG).
+ * Since ids vary in length, the remaining fields are bounds-checked as they 
+ * are read rather than up front.
+ */
+fn parse_varint_ids_frame_body(frame_type: u8, frame_body_data: Bytes) 
+        -> Result<frame::Frame, FrameParseError> {
+    let body_len = frame_body_data.len();
+    let mut body = &frame_body_data[..];
+    let ensure_remaining = |body: &[u8], len: usize| {
+        if body.len() < len {
+            Err(FrameParseError::BodyTooShort { 
+                frame_type, 
+                expected: body_len - body.len() + len, 
+                actual: body_len,
+            })
+        } else {
+            Ok(())
+        }
+    };
+    let rest = |body: &[u8]| frame_body_data.slice(body_len - body.len()..);
+
+    let frame = 
+        if frame_type == frame::VARINT_IDS_EXTENSION_FRAMETYPE {
+            let ext_type = body.get_u8();
+            let max_ext_type = 
+                frame::APPLICATION_EXTENSION_FRAMETYPES.end() 
+                - frame::APPLICATION_EXTENSION_FRAMETYPES.start();
+            if ext_type > max_ext_type {
+                return Err(FrameParseError::ExtTypeOutOfRange(ext_type));
+            }
+            let tube_id = get_varint_id(frame_type, &mut body)?;
+            let data = rest(body);
+            body = &[];
+            frame::Frame::Extension { tube_id, ext_type, data }
+        } else {
+            let tube_id = get_varint_id(frame_type, &mut body)?;
+            match frame_type & !frame::VARINT_IDS_FRAMETYPE_FLAG {
+                frame::CLIENT_HAS_FINISHED_SENDING_FRAMETYPE => 
+                    frame::Frame::ClientHasFinishedSending { tube_id, trailers: None },
+
+                frame::CLIENT_HAS_FINISHED_SENDING_WITH_TRAILERS_FRAMETYPE => {
+                    let trailers = Some(header_block::parse_header_block(body)?);
+                    body = &[];
+                    frame::Frame::ClientHasFinishedSending { tube_id, trailers }
+                },
+
+                frame::NEWTUBE_FRAMETYPE => {
+                    let headers_str = match std::str::from_utf8(body) {
+                        Ok(str) => str,
+                        Err(utf8_err) => return Err(FrameParseError::HeaderUtf8Error(utf8_err))
+                    };
+                    let headers = match serde_json::from_str(headers_str) {
+                        Ok(headers) => headers,
+                        Err(json_err) => 
+                            return Err(FrameParseError::HeaderJsonDecodeError(json_err))
+                    };
+                    body = &[];
+                    frame::Frame::NewTube { tube_id, headers }
+                },
+
+                frame::NEWTUBE_BINARY_FRAMETYPE => {
+                    let headers = header_block::parse_header_block(body)?;
+                    body = &[];
+                    frame::Frame::NewTube { tube_id, headers }
+                },
+
+                frame::NEWTUBE_ACCEPTED_FRAMETYPE => {
+                    let headers = header_block::parse_header_block(body)?;
+                    body = &[];
+                    frame::Frame::NewTubeAccepted { tube_id, headers }
+                },
+
+                frame::NEWTUBE_REJECTED_FRAMETYPE => {
+                    ensure_remaining(body, 2)?;
+                    let code = body.get_u16();
+                    let headers = header_block::parse_header_block(body)?;
+                    body = &[];
+                    frame::Frame::NewTubeRejected { tube_id, code, headers }
+                },
+
+                base_frame_type @ (
+                    frame::PAYLOAD_FRAMETYPE 
+                    | frame::COMPRESSED_PAYLOAD_FRAMETYPE
+                    | frame::PROCESSING_ACK_PAYLOAD_FRAMETYPE
+                    | frame::PROCESSING_ACK_COMPRESSED_PAYLOAD_FRAMETYPE
+                ) => {
+                    // AckId + 1, or 0 if no ack is requested
+                    let ack_id = get_varint_id(frame_type, &mut body)?.checked_sub(1);
+                    let data = rest(body);
+                    body = &[];
+                    payload_frame(base_frame_type, tube_id, ack_id, data)
+                },
+
+                frame::PAYLOAD_ACK_FRAMETYPE => {
+                    // (AckId << 1) | Processed
+                    let ack_varint = get_varint_id(frame_type, &mut body)?;
+                    frame::Frame::PayloadAck { 
+                        tube_id, 
+                        ack_id: ack_varint >> 1, 
+                        processed: (ack_varint & 1) == 1,
+                    }
+                },
+
+                frame::CUMULATIVE_PAYLOAD_ACK_FRAMETYPE => {
+                    let frame = parse_cumulative_payload_ack(frame_type, tube_id, body)?;
+                    body = &[];
+                    frame
+                },
+
+                frame::SERVER_HAS_FINISHED_SENDING_FRAMETYPE => 
+                    frame::Frame::ServerHasFinishedSending { tube_id, trailers: None },
+
+                frame::SERVER_HAS_FINISHED_SENDING_WITH_TRAILERS_FRAMETYPE => {
+                    let trailers = Some(header_block::parse_header_block(body)?);
+                    body = &[];
+                    frame::Frame::ServerHasFinishedSending { tube_id, trailers }
+                },
+
+                frame::ABORT_FRAMETYPE => {
+                    ensure_remaining(body, 1)?;
+                    let reason = frame::AbortReason::from(body.get_u8());
+                    frame::Frame::Abort { tube_id, reason }
+                },
+
+                frame::ABORT_WITH_CODE_FRAMETYPE => {
+                    ensure_remaining(body, 4)?;
+                    let reason = parse_application_abort_reason(body)?;
+                    body = &[];
+                    frame::Frame::Abort { tube_id, reason }
+                },
+
+                frame::ABORTACK_FRAMETYPE => 
+                    frame::Frame::AbortAck { tube_id },
+
+                frame::PAYLOAD_FRAGMENT_FRAMETYPE => {
+                    let data = rest(body);
+                    body = &[];
+                    frame::Frame::PayloadFragment { tube_id, data }
+                },
+
+                frame::GOAWAY_FRAMETYPE => {
+                    ensure_remaining(body, 1)?;
+                    let error_code = frame::GoAwayErrorCode::from(body.get_u8());
+                    let message = match std::str::from_utf8(body) {
+                        Ok(str) => str.to_string(),
+                        Err(utf8_err) => 
+                            return Err(FrameParseError::GoAwayMessageUtf8Error(utf8_err))
+                    };
+                    body = &[];
+                    frame::Frame::GoAway { last_tube_id: tube_id, error_code, message }
+                },
+
+                frame::PAYLOAD_SEQUENCE_FRAMETYPE => {
+                    ensure_remaining(body, 8)?;
+                    let sequence = body.get_u64();
+                    frame::Frame::PayloadSequence { tube_id, sequence }
+                },
+
+                frame::WINDOW_UPDATE_FRAMETYPE => {
+                    ensure_remaining(body, 4)?;
+                    let credit = body.get_u32();
+                    frame::Frame::WindowUpdate { tube_id, credit }
+                },
+
+                frame::PRIORITY_FRAMETYPE => {
+                    ensure_remaining(body, 1)?;
+                    let weight = body.get_u8();
+                    frame::Frame::Priority { tube_id, weight }
+                },
+
+                _ => return Err(FrameParseError::UnknownFrameType(frame_type)),
+            }
+        };
+
+    // Frames that don't end in variable-length data must be fully consumed by 
+    // their fields.
+    if !body.is_empty() {
+        return Err(FrameParseError::BodyTooLong { 
+            frame_type, 
+            expected: body_len - body.len(), 
+            actual: body_len,
+        });
+    }
+    Ok(frame)
+}
+
+/**
+ * If `data` begins with at least one full frame, returns the number of bytes 
+ * occupied by that frame (including its header). Returns None if more data is
+ * needed.
+ *
+ * Errors if the (possibly partial) frame at the start of `data` violates any 
+ * of the limits in `config`.
+ */
+pub(in super) fn full_frame_len(
+    data: &[u8],
+    config: &DecoderConfig,
+) -> Result<Option<usize>, FrameParseError> {
+    config.check_frame_header(data)?;
+
+    if data.len() < 3 {
+        return Ok(None);
+    }
+    let body_len: usize = double_u8_to_u16(data[1], data[2]).into();
+    if data.len() < 3 + body_len {
+        config.check_buffered_bytes(data.len())?;
+        return Ok(None);
+    }
+    Ok(Some(3 + body_len))
+}
+
+/**
+ * Frames whose FrameType falls in frame::RESERVED_EXTENSION_FRAMETYPES (and 
+ * which this version of the protocol doesn't understand) are skipped rather 
+ * than parsed.
+ */
+pub(in super) fn is_skippable_frame(frame_data: &[u8]) -> bool {
+    match frame_data.first() {
+        Some(frame_type) => {
+            let is_skippable = 
+                frame::RESERVED_EXTENSION_FRAMETYPES.contains(frame_type)
+                && expected_body_len(*frame_type).is_none();
+            if is_skippable {
+                log::trace!(
+                    "Skipping frame with unrecognized extension FrameType({})",
+                    frame_type,
+                );
+            }
+            is_skippable
+        },
+        None => false,
+    }
+}
+
+/**
+ * Parses a single, complete frame (including its header) as delimited by 
+ * full_frame_len().
+ */
+pub(in super) fn parse_frame(mut frame_data: Bytes) -> Result<frame::Frame, FrameParseError> {
+    let frame_type = frame_data.get_u8();
+    frame_data.advance(2); // FrameBodyByteLength
+    parse_frame_body(frame_type, frame_data)
+}
+
+/**
+ * Decodes a stream of bytes (delivered in arbitrarily-sized chunks) into 
+ * Frames.
+ *
+ * Frames are sliced out of the received chunks without copying, so the data 
+ * carried by a decoded Payload frame shares its allocation with the chunk it 
+ * arrived in. The only time bytes are copied is when a frame straddles two or 
+ * more chunks and must be stitched back together.
+ */
+#[derive(Debug,Default)]
 pub struct Decoder {
-    partial_data: VecDeque<u8>,
+    config: DecoderConfig,
+    partial_data: BytesMut,
 }
 impl Decoder {
     pub fn new() -> Self {
+        Self::new_with_config(DecoderConfig::default())
+    }
+
+    pub fn new_with_config(config: DecoderConfig) -> Self {
         Decoder {
-            partial_data: VecDeque::new(),
+            config,
+            partial_data: BytesMut::new(),
         }
     }
 
     pub fn decode(
         &mut self, 
-        data: Vec<u8>,
+        data: Bytes,
     ) -> Result<VecDeque<frame::Frame>, FrameDecodeError> {
-        self.partial_data.append(&mut VecDeque::from(data));
-
         let mut decoded_frames = VecDeque::new();
-        while self.partial_data.len() >= 3 {
-            let body_len = double_u8_to_u16(
-                self.partial_data[1], 
-                self.partial_data[2],
-            );
-
-            // If we have at least a full frame, parse it
-            if (self.partial_data.len() as u16) >= 3 + body_len {
-                let index_after_last_frame_byte: usize = (3 + body_len).into();
-                let mut frame_data = 
-                    self.partial_data
-                        .drain(0..index_after_last_frame_byte)
-                        .collect::<VecDeque<_>>();
-
-                let frame_type = match frame_data.pop_front() {
-                    Some(ft) => ft,
-                    None => {
-                        let logic_error_str = concat!(
-                            "Although partial_data size was big enough, ",
-                            "pop_front returned None??"
-                        );
-                        return Err(FrameDecodeError {
-                            parse_error: 
-                                FrameParseError::InternalByteOffsetLogicError(
-                                    logic_error_str.to_string()
-                                ),
-                            num_frames_parsed_successfully: decoded_frames.len(),
-                        });
+        let mut data = data;
+        if !self.partial_data.is_empty() {
+            match self.complete_partial_frame(&mut data) {
+                Ok(Some(frame_data)) => {
+                    if !is_skippable_frame(&frame_data) {
+                        match parse_frame(frame_data) {
+                            Ok(frame) => decoded_frames.push_back(frame),
+                            Err(parse_error) => return Err(FrameDecodeError {
+                                parse_error,
+                                num_frames_parsed_successfully: 0,
+                                decoded_frames,
+                            }),
+                        }
                     }
-                };
-                frame_data.pop_front(); // FrameBodyByteLength[0]
-                frame_data.pop_front(); // FrameBodyByteLength[1]
-                match parse_frame_body(frame_type, frame_data) {
-                    Ok(frame) => decoded_frames.push_back(frame),
-                    Err(decode_error) => return Err(FrameDecodeError {
-                        parse_error: decode_error,
-                        num_frames_parsed_successfully: decoded_frames.len(),
-                    })
-                }
-            } else {
-                break;
+                },
+                Ok(None) => return Ok(decoded_frames),
+                Err(parse_error) => return Err(FrameDecodeError {
+                    parse_error,
+                    num_frames_parsed_successfully: 0,
+                    decoded_frames,
+                }),
             }
         }
-        
+
+        loop {
+            let frame_len = match full_frame_len(&data, &self.config) {
+                Ok(Some(frame_len)) => frame_len,
+                Ok(None) => break,
+                Err(parse_error) => return Err(FrameDecodeError {
+                    parse_error,
+                    num_frames_parsed_successfully: decoded_frames.len(),
+                    decoded_frames,
+                }),
+            };
+            let frame_data = data.split_to(frame_len);
+            if is_skippable_frame(&frame_data) {
+                continue;
+            }
+            match parse_frame(frame_data) {
+                Ok(frame) => decoded_frames.push_back(frame),
+                Err(decode_error) => return Err(FrameDecodeError {
+                    parse_error: decode_error,
+                    num_frames_parsed_successfully: decoded_frames.len(),
+                    decoded_frames,
+                })
+            }
+        }
+
+        self.partial_data.extend_from_slice(&data);
         Ok(decoded_frames)
     }
+
+    /**
+     * Moves only as much of `data` into self.partial_data as it takes to 
+     * complete the frame that is already partially buffered there, checking 
+     * the configured limits before anything is copied. Returns the completed 
+     * frame's data, or None if `data` ran out before the frame was complete.
+     */
+    fn complete_partial_frame(
+        &mut self,
+        data: &mut Bytes,
+    ) -> Result<Option<Bytes>, FrameParseError> {
+        if self.partial_data.len() < 3 {
+            let header_len = data.len().min(3 - self.partial_data.len());
+            let mut header = self.partial_data.to_vec();
+            header.extend_from_slice(&data[..header_len]);
+            self.config.check_frame_header(&header)?;
+            self.partial_data.extend_from_slice(&data.split_to(header_len));
+            if self.partial_data.len() < 3 {
+                return Ok(None);
+            }
+        }
+
+        let body_len: usize = 
+            double_u8_to_u16(self.partial_data[1], self.partial_data[2]).into();
+        let missing_len = 3 + body_len - self.partial_data.len();
+        if data.len() < missing_len {
+            self.config.check_buffered_bytes(self.partial_data.len() + data.len())?;
+            self.partial_data.extend_from_slice(data);
+            data.clear();
+            return Ok(None);
+        }
+        self.partial_data.extend_from_slice(&data.split_to(missing_len));
+        Ok(Some(self.partial_data.split().freeze()))
+    }
 }
 
 #[cfg(test)]
@@ -211,7 +1013,7 @@ mod decoder_tests {
     #[test]
     fn empty_data_yields_empty_vec() {
         let mut decoder = Decoder::new();
-        let data = vec![];
+        let data = Bytes::new();
         let decoded_frames = decoder.decode(data).unwrap();
         assert_eq!(decoded_frames.len(), 0);
     }
@@ -221,43 +1023,62 @@ mod decoder_tests {
         let mut decoder = Decoder::new();
         let mut data = encode::client_has_finished_sending_frame(43).unwrap();
 
-        let final_byte = data.pop().unwrap();
+        let final_byte = data.split_off(data.len() - 1);
         let decoded_frames = &decoder.decode(data).unwrap();
         assert_eq!(decoded_frames.len(), 0);
 
-        let decoded_frames = &decoder.decode(vec![final_byte]).unwrap();
+        let decoded_frames = &decoder.decode(final_byte).unwrap();
         assert_eq!(decoded_frames.len(), 1);
-        assert_eq!(decoded_frames[0], frame::Frame::ClientHasFinishedSending { tube_id: 43 });
+        assert_eq!(decoded_frames[0], frame::Frame::ClientHasFinishedSending { tube_id: 43, trailers: None });
     }
 
     #[test]
     fn data_for_two_full_frames_yield_two_frames() {
         let mut decoder = Decoder::new();
 
-        let mut data = encode::client_has_finished_sending_frame(43).unwrap();
-        data.append(&mut encode::server_has_finished_sending_frame(42).unwrap());
+        let mut data = BytesMut::new();
+        encode::client_has_finished_sending_frame_into(&mut data, 43).unwrap();
+        encode::server_has_finished_sending_frame_into(&mut data, 42).unwrap();
 
-        let decoded_frames = &decoder.decode(data).unwrap();
+        let decoded_frames = &decoder.decode(data.freeze()).unwrap();
         assert_eq!(decoded_frames.len(), 2);
-        assert_eq!(decoded_frames[0], frame::Frame::ClientHasFinishedSending { tube_id: 43 });
-        assert_eq!(decoded_frames[1], frame::Frame::ServerHasFinishedSending { tube_id: 42 });
+        assert_eq!(decoded_frames[0], frame::Frame::ClientHasFinishedSending { tube_id: 43, trailers: None });
+        assert_eq!(decoded_frames[1], frame::Frame::ServerHasFinishedSending { tube_id: 42, trailers: None });
     }
 
     #[test]
     fn full_frame_plus_partial_frame_yields_single_frame_until_rest_of_second_frame_provided() {
         let mut decoder = Decoder::new();
 
-        let mut data = encode::client_has_finished_sending_frame(43).unwrap();
-        data.append(&mut encode::server_has_finished_sending_frame(42).unwrap());
-        let final_byte = data.pop().unwrap();
+        let mut data = BytesMut::new();
+        encode::client_has_finished_sending_frame_into(&mut data, 43).unwrap();
+        encode::server_has_finished_sending_frame_into(&mut data, 42).unwrap();
+        let final_byte = data.split_off(data.len() - 1).freeze();
+        let data = data.freeze();
 
         let decoded_frames = &decoder.decode(data).unwrap();
         assert_eq!(decoded_frames.len(), 1);
-        assert_eq!(decoded_frames[0], frame::Frame::ClientHasFinishedSending { tube_id: 43 });
+        assert_eq!(decoded_frames[0], frame::Frame::ClientHasFinishedSending { tube_id: 43, trailers: None });
 
-        let decoded_frames = &decoder.decode(vec![final_byte]).unwrap();
+        let decoded_frames = &decoder.decode(final_byte).unwrap();
         assert_eq!(decoded_frames.len(), 1);
-        assert_eq!(decoded_frames[0], frame::Frame::ServerHasFinishedSending { tube_id: 42 });
+        assert_eq!(decoded_frames[0], frame::Frame::ServerHasFinishedSending { tube_id: 42, trailers: None });
+    }
+
+    #[test]
+    fn payload_data_is_sliced_from_received_chunk_without_copying() {
+        let mut decoder = Decoder::new();
+        let data = encode::payload_frame(42, None, &[0, 1, 42, 255]).unwrap();
+        let data_range = data.as_ptr_range();
+
+        let decoded_frames = decoder.decode(data.clone()).unwrap();
+        match &decoded_frames[0] {
+            frame::Frame::Payload { data: payload_data, .. } => {
+                assert_eq!(&payload_data[..], &[0, 1, 42, 255]);
+                assert!(data_range.contains(&payload_data.as_ptr()));
+            },
+            unexpected => panic!("Expected a Payload frame, got {:?}", unexpected),
+        }
     }
 
     #[test]
@@ -268,14 +1089,16 @@ mod decoder_tests {
           ("header1".to_string(), "value1".to_string()),
           ("header2".to_string(), "value2".to_string()),
         ]);
-        let mut data = encode::newtube_frame(42, headers).unwrap();
+        let mut data = BytesMut::new();
+        encode::newtube_frame_into(&mut data, 42, &headers).unwrap();
+        let mut data = data.to_vec();
 
         // Tweak encoded data to insert an invalid utf8 byte into the encoded 
         // headers region of the frame.
         data.pop();
         data.push(159);
 
-        match &decoder.decode(data) {
+        match &decoder.decode(data.into()) {
             Ok(_frames) => panic!(concat!(
                 "Successfully decoded a NewTube frame that contains ",
                 "invalid utf8!"
@@ -301,7 +1124,8 @@ mod decoder_tests {
         let mut decoder = Decoder::new();
 
         let headers = HashMap::from([]);
-        let correct_data = encode::newtube_frame(42, headers).unwrap();
+        let mut correct_data = BytesMut::new();
+        encode::newtube_frame_into(&mut correct_data, 42, &headers).unwrap();
 
         // Tweak encoded data to insert invalid json into the headers portion 
         // of the frame.
@@ -321,7 +1145,7 @@ mod decoder_tests {
         let mut invalid_json_bytes = "{]".to_string().into_bytes();
         bad_data.append(&mut invalid_json_bytes);
 
-        match &decoder.decode(bad_data) {
+        match &decoder.decode(bad_data.into()) {
             Ok(_frames) => panic!(concat!(
                 "Successfully decoded a NewTube frame that contains ",
                 "an invalid json encoding of it's headers!"
@@ -342,16 +1166,243 @@ mod decoder_tests {
         };
     }
 
+    #[test]
+    fn errors_if_payload_body_is_too_short() {
+        let mut decoder = Decoder::new();
+
+        // Payload frame with a 1-byte body (TubeId and AckId need 4 bytes)
+        let data = Bytes::from(vec![frame::PAYLOAD_FRAMETYPE, 0, 1, 42]);
+
+        match &decoder.decode(data) {
+            Err(FrameDecodeError {
+              parse_error: FrameParseError::BodyTooShort { frame_type, expected, actual },
+              ..
+            }) => {
+                assert_eq!(*frame_type, frame::PAYLOAD_FRAMETYPE);
+                assert_eq!(*expected, 4);
+                assert_eq!(*actual, 1);
+            },
+
+            unexpected => panic!(
+                "Unexpected result when decoding a truncated Payload frame: {:?}",
+                unexpected,
+            ),
+        };
+    }
+
+    #[test]
+    fn errors_if_abort_body_is_too_short() {
+        let mut decoder = Decoder::new();
+
+        // Abort frame with a 2-byte body (missing the AbortReason)
+        let data = Bytes::from(vec![frame::ABORT_FRAMETYPE, 0, 2, 0, 42]);
+
+        match &decoder.decode(data) {
+            Err(FrameDecodeError {
+              parse_error: FrameParseError::BodyTooShort { frame_type, expected, actual },
+              ..
+            }) => {
+                assert_eq!(*frame_type, frame::ABORT_FRAMETYPE);
+                assert_eq!(*expected, 3);
+                assert_eq!(*actual, 2);
+            },
+
+            unexpected => panic!(
+                "Unexpected result when decoding a truncated Abort frame: {:?}",
+                unexpected,
+            ),
+        };
+    }
+
+    #[test]
+    fn decodes_abort_with_code() {
+        let mut decoder = Decoder::new();
+
+        // Abort frame with Code 4021 and no message
+        let data = Bytes::from(vec![
+            frame::ABORT_WITH_CODE_FRAMETYPE, 0, 6, 0, 42, 0, 0, 0x0F, 0xB5,
+        ]);
+        let frames = decoder.decode(data).unwrap();
+        assert_eq!(Vec::from(frames), vec![frame::Frame::Abort {
+            tube_id: 42,
+            reason: frame::AbortReason::Application { code: 4021, message: None },
+        }]);
+
+        // Abort frame with a message that isn't valid UTF-8
+        let data = Bytes::from(vec![
+            frame::ABORT_WITH_CODE_FRAMETYPE, 0, 7, 0, 42, 0, 0, 0x0F, 0xB5, 0xFF,
+        ]);
+        match decoder.decode(data) {
+            Err(FrameDecodeError { parse_error: FrameParseError::AbortMessageUtf8Error(_), .. }) => (),
+            unexpected => panic!(
+                "Unexpected result when decoding an Abort frame with a bad message: {:?}",
+                unexpected,
+            ),
+        };
+    }
+
+    #[test]
+    fn errors_if_fixed_size_body_is_too_long() {
+        let mut decoder = Decoder::new();
+
+        // AbortAck frame with a 3-byte body (only a TubeId is expected)
+        let data = Bytes::from(vec![frame::ABORTACK_FRAMETYPE, 0, 3, 0, 42, 0]);
+
+        match &decoder.decode(data) {
+            Err(FrameDecodeError {
+              parse_error: FrameParseError::BodyTooLong { frame_type, expected, actual },
+              ..
+            }) => {
+                assert_eq!(*frame_type, frame::ABORTACK_FRAMETYPE);
+                assert_eq!(*expected, 2);
+                assert_eq!(*actual, 3);
+            },
+
+            unexpected => panic!(
+                "Unexpected result when decoding an oversized AbortAck frame: {:?}",
+                unexpected,
+            ),
+        };
+    }
+
+    #[test]
+    fn errors_if_frame_exceeds_max_frame_size_before_body_arrives() {
+        let mut decoder = Decoder::new_with_config(DecoderConfig {
+            max_frame_size: 16,
+            ..DecoderConfig::default()
+        });
+
+        // Only the header of a Payload frame with a 20-byte body
+        let data = Bytes::from(vec![frame::PAYLOAD_FRAMETYPE, 0, 20]);
+
+        match &decoder.decode(data) {
+            Err(FrameDecodeError {
+              parse_error: FrameParseError::LimitExceeded { limit, max, actual },
+              ..
+            }) => {
+                assert_eq!(*limit, DecoderLimit::MaxFrameSize);
+                assert_eq!(*max, 16);
+                assert_eq!(*actual, 23);
+            },
+
+            unexpected => panic!(
+                "Unexpected result when decoding an oversized frame: {:?}",
+                unexpected,
+            ),
+        };
+    }
+
+    #[test]
+    fn errors_if_partial_data_exceeds_max_buffered_bytes() {
+        let mut decoder = Decoder::new_with_config(DecoderConfig {
+            max_buffered_bytes: 8,
+            ..DecoderConfig::default()
+        });
+
+        let data = encode::payload_frame(42, None, &[0; 16]).unwrap();
+        assert_eq!(decoder.decode(data.slice(0..8)).unwrap().len(), 0);
+
+        match &decoder.decode(data.slice(8..9)) {
+            Err(FrameDecodeError {
+              parse_error: FrameParseError::LimitExceeded { limit, max, actual },
+              ..
+            }) => {
+                assert_eq!(*limit, DecoderLimit::MaxBufferedBytes);
+                assert_eq!(*max, 8);
+                assert_eq!(*actual, 9);
+            },
+
+            unexpected => panic!(
+                "Unexpected result when buffering too much partial data: {:?}",
+                unexpected,
+            ),
+        };
+    }
+
+    #[test]
+    fn checks_max_buffered_bytes_before_buffering_more_data() {
+        let mut decoder = Decoder::new_with_config(DecoderConfig {
+            max_buffered_bytes: 8,
+            ..DecoderConfig::default()
+        });
+
+        let data = encode::payload_frame(42, None, &[0; 1024]).unwrap();
+        assert_eq!(decoder.decode(data.slice(0..2)).unwrap().len(), 0);
+        match &decoder.decode(data.slice(2..1000)) {
+            Err(FrameDecodeError {
+              parse_error: FrameParseError::LimitExceeded { actual, .. },
+              ..
+            }) => assert_eq!(*actual, 1000),
+            unexpected => panic!(
+                "Unexpected result when buffering too much partial data: {:?}",
+                unexpected,
+            ),
+        };
+        assert!(decoder.partial_data.len() <= 8);
+    }
+
+    #[test]
+    fn completes_a_partial_frame_then_decodes_the_rest_of_the_chunk() {
+        let mut decoder = Decoder::new_with_config(DecoderConfig {
+            max_buffered_bytes: 8,
+            ..DecoderConfig::default()
+        });
+
+        let mut data = BytesMut::new();
+        encode::payload_frame_into(&mut data, 42, None, &[0; 16]).unwrap();
+        encode::payload_frame_into(&mut data, 43, None, &[1; 16]).unwrap();
+        let data = data.freeze();
+
+        assert_eq!(decoder.decode(data.slice(0..1)).unwrap().len(), 0);
+        let decoded_frames = decoder.decode(data.slice(1..)).unwrap();
+        assert_eq!(decoded_frames.len(), 2);
+        assert!(matches!(&decoded_frames[1], frame::Frame::Payload { tube_id: 43, .. }));
+        assert!(decoder.partial_data.is_empty());
+    }
+
+    #[test]
+    fn errors_if_frametype_is_not_in_allowlist() {
+        let mut decoder = Decoder::new_with_config(DecoderConfig {
+            allowed_frame_types: Some(HashSet::from([frame::FrameTag::Payload])),
+            ..DecoderConfig::default()
+        });
+
+        let mut data = BytesMut::new();
+        encode::payload_frame_into(&mut data, 42, None, &[0, 1]).unwrap();
+        encode::drain_frame_into(&mut data, frame::DrainReason::Shutdown).unwrap();
+
+        match &decoder.decode(data.freeze()) {
+            Err(FrameDecodeError {
+              parse_error: FrameParseError::DisallowedFrameType(frame_type),
+              num_frames_parsed_successfully,
+              decoded_frames,
+            }) => {
+                assert_eq!(*frame_type, frame::DRAIN_FRAMETYPE);
+                assert_eq!(*num_frames_parsed_successfully, 1);
+                assert_eq!(decoded_frames.len(), 1);
+                assert!(matches!(
+                    &decoded_frames[0], 
+                    frame::Frame::Payload { tube_id: 42, .. },
+                ));
+            },
+
+            unexpected => panic!(
+                "Unexpected result when decoding a disallowed frame: {:?}",
+                unexpected,
+            ),
+        };
+    }
+
     #[test]
     fn errors_if_frametype_value_is_unknown() {
         let mut decoder = Decoder::new();
 
-        let mut data = encode::client_has_finished_sending_frame(43).unwrap();
+        let mut data = encode::client_has_finished_sending_frame(43).unwrap().to_vec();
 
         // Tweak encoded data to use an invalid FrameType value
         data[0] = 255;
 
-        match &decoder.decode(data) {
+        match &decoder.decode(data.into()) {
             Ok(_frames) => panic!("Somehow decoded a frame with an invalid FrameType!?"),
 
             Err(FrameDecodeError{
@@ -368,5 +1419,18 @@ mod decoder_tests {
             )
         };
     }
-}
 
+    #[test]
+    fn skips_unknown_frames_in_reserved_extension_range() {
+        let mut decoder = Decoder::new();
+
+        let mut data = BytesMut::from(&[
+            *frame::RESERVED_EXTENSION_FRAMETYPES.start(), 0, 3, 1, 2, 3,
+        ][..]);
+        encode::client_has_finished_sending_frame_into(&mut data, 43).unwrap();
+
+        let decoded_frames = decoder.decode(data.freeze()).unwrap();
+        assert_eq!(decoded_frames.len(), 1);
+        assert_eq!(decoded_frames[0], frame::Frame::ClientHasFinishedSending { tube_id: 43, trailers: None });
+    }
+}
@@ -1,150 +1,1018 @@
 use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+use bytes::BufMut;
+use bytes::Bytes;
+use bytes::BytesMut;
 
 use super::frame;
+use super::header_block;
+use super::varint;
 
 #[derive(Debug)]
 pub enum FrameEncodeError {
-    AckIdTooLarge(u16),
+    AckIdTooLarge(u64),
     DataTooLarge(usize),
+    ExtTypeOutOfRange(u8),
     HeaderJsonEncodeError(serde_json::error::Error),
+    HeadersTooLarge(usize),
+    TubeIdTooLarge(u64),
+}
+
+/**
+ * Every frame-specific encoding function comes in two flavors:
+ *
+ *   * `*_frame_into(buf, ...)` appends the encoded frame to a caller-provided 
+ *     BytesMut so that several frames can be built up in a single buffer 
+ *     without any intermediate allocations.
+ *   * `*_frame(...)` is a convenience wrapper that encodes the frame into a 
+ *     fresh buffer and returns it as a Bytes.
+ *
+ * On error, nothing is written to `buf`.
+ */
+fn encode_with(
+    encode_into: impl FnOnce(&mut BytesMut) -> Result<(), FrameEncodeError>,
+) -> Result<Bytes, FrameEncodeError> {
+    let mut buf = BytesMut::new();
+    encode_into(&mut buf)?;
+    Ok(buf.freeze())
+}
+
+fn put_frame_header(
+    buf: &mut BytesMut, 
+    frame_type: u8, 
+    body_len: usize,
+) -> Result<(), FrameEncodeError> {
+    if body_len > frame::MAX_FRAME_BODY_LEN {
+        return Err(FrameEncodeError::DataTooLarge(body_len));
+    }
+    buf.reserve(3 + body_len);
+    buf.put_u8(frame_type);
+    buf.put_u16(body_len as u16);
+    Ok(())
+}
+
+/**
+ * Frames are encoded with fixed-width ids unless one of their ids doesn't 
+ * fit, in which case every id in the frame is encoded as a varint and the 
+ * frame is sent with frame::VARINT_IDS_FRAMETYPE_FLAG set.
+ */
+#[derive(Clone,Copy,Debug,PartialEq)]
+enum IdWidth {
+    Fixed,
+    Varint,
+}
+impl IdWidth {
+    fn for_tube_id(tube_id: u64) -> Result<Self, FrameEncodeError> {
+        if tube_id <= frame::MAX_FIXED_WIDTH_TUBE_ID {
+            Ok(IdWidth::Fixed)
+        } else if tube_id <= frame::MAX_TUBE_ID {
+            Ok(IdWidth::Varint)
+        } else {
+            Err(FrameEncodeError::TubeIdTooLarge(tube_id))
+        }
+    }
+
+    fn for_ids(tube_id: u64, ack_id: Option<u64>) -> Result<Self, FrameEncodeError> {
+        let id_width = IdWidth::for_tube_id(tube_id)?;
+        match ack_id {
+            Some(ack_id) if ack_id > frame::MAX_ACK_ID => 
+                Err(FrameEncodeError::AckIdTooLarge(ack_id)),
+            Some(ack_id) if ack_id > frame::MAX_FIXED_WIDTH_ACK_ID => 
+                Ok(IdWidth::Varint),
+            _ => Ok(id_width),
+        }
+    }
+
+    fn frame_type(self, frame_type: u8) -> u8 {
+        match self {
+            IdWidth::Fixed => frame_type,
+            IdWidth::Varint => frame_type | frame::VARINT_IDS_FRAMETYPE_FLAG,
+        }
+    }
+
+    fn id_len(self, id: u64) -> usize {
+        match self {
+            IdWidth::Fixed => 2,
+            IdWidth::Varint => varint::varint_len(id),
+        }
+    }
+
+    fn put_id(self, buf: &mut BytesMut, id: u64) {
+        match self {
+            IdWidth::Fixed => buf.put_u16(id as u16),
+            IdWidth::Varint => varint::put_varint(buf, id),
+        }
+    }
+
+    /**
+     * The AckRequested/AckId fields of Payload and CompressedPayload frames.
+     */
+    fn payload_ack_id_len(self, ack_id: Option<u64>) -> usize {
+        self.id_len(payload_ack_id_varint(ack_id))
+    }
+
+    fn put_payload_ack_id(self, buf: &mut BytesMut, ack_id: Option<u64>) {
+        match self {
+            IdWidth::Fixed => match ack_id {
+                Some(ack_id) => buf.put_u16((0b1000_0000 << 8) | ack_id as u16),
+                None => buf.put_u16(0),
+            },
+            IdWidth::Varint => varint::put_varint(buf, payload_ack_id_varint(ack_id)),
+        }
+    }
+}
+
+fn payload_ack_id_varint(ack_id: Option<u64>) -> u64 {
+    ack_id.map_or(0, |ack_id| ack_id.saturating_add(1))
+}
+
+/**
+ * Frames that carry nothing but a TubeId.
+ */
+fn put_tube_id_frame(
+    buf: &mut BytesMut,
+    frame_type: u8,
+    tube_id: u64,
+) -> Result<(), FrameEncodeError> {
+    let id_width = IdWidth::for_tube_id(tube_id)?;
+    put_frame_header(buf, id_width.frame_type(frame_type), id_width.id_len(tube_id))?;
+    id_width.put_id(buf, tube_id);
+    Ok(())
+}
+
+/**
+ * The most data that fits in a single Payload (or CompressedPayload) frame 
+ * with the given ids.
+ */
+pub fn payload_frame_data_capacity(tube_id: u64, ack_id: Option<u64>) -> usize {
+    let id_width = IdWidth::for_ids(tube_id, ack_id).unwrap_or(IdWidth::Varint);
+    frame::MAX_FRAME_BODY_LEN 
+        - id_width.id_len(tube_id) 
+        - id_width.payload_ack_id_len(ack_id)
+}
+
+/**
+ * The longest message (in bytes) that fits in a single Abort frame with an 
+ * AbortReason::Application for the given Tube.
+ */
+pub fn abort_message_capacity(tube_id: u64) -> usize {
+    let id_width = IdWidth::for_tube_id(tube_id).unwrap_or(IdWidth::Varint);
+    frame::MAX_FRAME_BODY_LEN - id_width.id_len(tube_id) - 4
+}
+
+/**
+ * The longest message (in bytes) that fits in a single GoAway frame naming 
+ * `last_tube_id`.
+ */
+pub fn goaway_message_capacity(last_tube_id: u64) -> usize {
+    let id_width = IdWidth::for_tube_id(last_tube_id).unwrap_or(IdWidth::Varint);
+    frame::MAX_FRAME_BODY_LEN - id_width.id_len(last_tube_id) - 1
+}
+
+/**
+ * The longest prefix of `message` that is at most `max_len` bytes long and 
+ * doesn't split a UTF-8 character.
+ */
+pub fn truncate_on_char_boundary(message: &str, max_len: usize) -> &str {
+    if message.len() <= max_len {
+        return message;
+    }
+    let len = (0..=max_len).rev()
+        .find(|len| message.is_char_boundary(*len))
+        .unwrap_or(0);
+    &message[..len]
+}
+
+/**
+ * The most data that fits in a single PayloadFragment frame for the given 
+ * Tube.
+ */
+pub fn payload_fragment_frame_data_capacity(tube_id: u64) -> usize {
+    let id_width = IdWidth::for_tube_id(tube_id).unwrap_or(IdWidth::Varint);
+    frame::MAX_FRAME_BODY_LEN - id_width.id_len(tube_id)
+}
+
+/**
+ * Encodes any Frame by dispatching to the appropriate frame-specific 
+ * `*_frame_into()` function.
+ *
+ * NewTube frames are encoded with JSON headers, which every peer understands. 
+ * Use newtube_frame_with_encoding_into() to encode them with a negotiated 
+ * HeaderEncoding instead.
+ */
+pub fn frame_into(
+    buf: &mut BytesMut,
+    frame: &frame::Frame,
+) -> Result<(), FrameEncodeError> {
+    use frame::Frame::*;
+    match frame {
+        ClientHasFinishedSending { tube_id, trailers } => 
+            put_has_finished_sending_frame(
+                buf, 
+                frame::CLIENT_HAS_FINISHED_SENDING_FRAMETYPE, 
+                *tube_id, 
+                trailers.as_ref(),
+            ),
+        Drain { reason } => 
+            drain_frame_into(buf, *reason),
+        NewTube { tube_id, headers } => 
+            newtube_frame_into(buf, *tube_id, headers),
+        NewTubeAccepted { tube_id, headers } => 
+            newtube_accepted_frame_into(buf, *tube_id, headers),
+        NewTubeRejected { tube_id, code, headers } => 
+            newtube_rejected_frame_into(buf, *tube_id, *code, headers),
+        Payload { tube_id, ack_id, processing_ack, data } => {
+            let frame_type = 
+                if *processing_ack {
+                    frame::PROCESSING_ACK_PAYLOAD_FRAMETYPE
+                } else {
+                    frame::PAYLOAD_FRAMETYPE
+                };
+            put_payload_frame(buf, frame_type, *tube_id, *ack_id, data)
+        },
+        PayloadAck { tube_id, ack_id, processed } => 
+            payload_ack_frame_into(buf, *tube_id, *ack_id, *processed),
+        PayloadSequence { tube_id, sequence } => 
+            payload_sequence_frame_into(buf, *tube_id, *sequence),
+        CumulativePayloadAck { tube_id, up_to, ranges } => 
+            cumulative_payload_ack_frame_into(buf, *tube_id, *up_to, ranges),
+        ServerHasFinishedSending { tube_id, trailers } => 
+            put_has_finished_sending_frame(
+                buf, 
+                frame::SERVER_HAS_FINISHED_SENDING_FRAMETYPE, 
+                *tube_id, 
+                trailers.as_ref(),
+            ),
+        Abort { tube_id, reason } => 
+            abort_frame_into(buf, *tube_id, reason.clone()),
+        AbortAck { tube_id } => 
+            abort_ack_frame_into(buf, *tube_id),
+        PayloadFragment { tube_id, data } => 
+            payload_fragment_frame_into(buf, *tube_id, data),
+        CompressedPayload { tube_id, ack_id, processing_ack, data } => {
+            let frame_type = 
+                if *processing_ack {
+                    frame::PROCESSING_ACK_COMPRESSED_PAYLOAD_FRAMETYPE
+                } else {
+                    frame::COMPRESSED_PAYLOAD_FRAMETYPE
+                };
+            put_payload_frame(buf, frame_type, *tube_id, *ack_id, data)
+        },
+        GoAway { last_tube_id, error_code, message } => 
+            goaway_frame_into(buf, *last_tube_id, *error_code, message),
+        Ping { opaque } => 
+            ping_frame_into(buf, *opaque),
+        Pong { opaque } => 
+            pong_frame_into(buf, *opaque),
+        Extension { tube_id, ext_type, data } => 
+            extension_frame_into(buf, *tube_id, *ext_type, data),
+        Settings { 
+            version, 
+            features, 
+            max_frame_size, 
+            max_concurrent_tubes, 
+            initial_window_size,
+        } => 
+            settings_frame_into(
+                buf, 
+                *version, 
+                *features, 
+                *max_frame_size, 
+                *max_concurrent_tubes,
+                *initial_window_size,
+            ),
+        Session { token } => 
+            session_frame_into(buf, *token),
+        WindowUpdate { tube_id, credit } => 
+            window_update_frame_into(buf, *tube_id, *credit),
+        Priority { tube_id, weight } => 
+            priority_frame_into(buf, *tube_id, *weight),
+    }
 }
 
 pub fn abort_frame(
-    tube_id: u16,
+    tube_id: u64,
     reason: frame::AbortReason,
-) -> Result<Vec<u8>, FrameEncodeError> {
-    let tubeid_bytes = tube_id.to_be_bytes();
-    let reason_u8: u8 = reason.into();
-    Ok(vec![
-       frame::ABORT_FRAMETYPE,
-       0, 3,
-       tubeid_bytes[0],
-       tubeid_bytes[1],
-       reason_u8,
-    ])
+) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| abort_frame_into(buf, tube_id, reason))
+}
+
+pub fn abort_frame_into(
+    buf: &mut BytesMut,
+    tube_id: u64,
+    reason: frame::AbortReason,
+) -> Result<(), FrameEncodeError> {
+    let id_width = IdWidth::for_tube_id(tube_id)?;
+    if let frame::AbortReason::Application { code, message } = &reason {
+        let message = message.as_deref().unwrap_or("");
+        put_frame_header(
+            buf, 
+            id_width.frame_type(frame::ABORT_WITH_CODE_FRAMETYPE), 
+            id_width.id_len(tube_id) + 4 + message.len(),
+        )?;
+        id_width.put_id(buf, tube_id);
+        buf.put_u32(*code);
+        buf.put_slice(message.as_bytes());
+        return Ok(());
+    }
+
+    put_frame_header(
+        buf, 
+        id_width.frame_type(frame::ABORT_FRAMETYPE), 
+        id_width.id_len(tube_id) + 1,
+    )?;
+    id_width.put_id(buf, tube_id);
+    buf.put_u8(reason.into());
+    Ok(())
 }
 
 pub fn abort_ack_frame(
-    tube_id: u16,
-) -> Result<Vec<u8>, FrameEncodeError> {
-    let tubeid_bytes = tube_id.to_be_bytes();
-    Ok(vec![
-       frame::ABORTACK_FRAMETYPE,
-       0, 3,
-       tubeid_bytes[0],
-       tubeid_bytes[1],
-    ])
+    tube_id: u64,
+) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| abort_ack_frame_into(buf, tube_id))
+}
+
+pub fn abort_ack_frame_into(
+    buf: &mut BytesMut,
+    tube_id: u64,
+) -> Result<(), FrameEncodeError> {
+    put_tube_id_frame(buf, frame::ABORTACK_FRAMETYPE, tube_id)
 }
 
 pub fn client_has_finished_sending_frame(
-    tube_id: u16,
-) -> Result<Vec<u8>, FrameEncodeError> {
-    let tubeid_bytes = tube_id.to_be_bytes();
-    Ok(vec![
+    tube_id: u64,
+) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| client_has_finished_sending_frame_into(buf, tube_id))
+}
+
+pub fn client_has_finished_sending_frame_into(
+    buf: &mut BytesMut,
+    tube_id: u64,
+) -> Result<(), FrameEncodeError> {
+    put_tube_id_frame(buf, frame::CLIENT_HAS_FINISHED_SENDING_FRAMETYPE, tube_id)
+}
+
+pub fn client_has_finished_sending_with_trailers_frame(
+    tube_id: u64,
+    trailers: &HashMap<String, String>,
+) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| client_has_finished_sending_with_trailers_frame_into(buf, tube_id, trailers))
+}
+
+pub fn client_has_finished_sending_with_trailers_frame_into(
+    buf: &mut BytesMut,
+    tube_id: u64,
+    trailers: &HashMap<String, String>,
+) -> Result<(), FrameEncodeError> {
+    put_has_finished_sending_frame(
+        buf, 
         frame::CLIENT_HAS_FINISHED_SENDING_FRAMETYPE, 
-        0, 2,
-        tubeid_bytes[0], 
-        tubeid_bytes[1],
-    ])
-}
-
-pub fn drain_frame() -> Result<Vec<u8>, FrameEncodeError> {
-    Ok(vec![
-        frame::DRAIN_FRAMETYPE,
-        // bodylen=0
-        0, 0
-    ])
-}
-
-pub fn newtube_frame(
-    tube_id: u16, 
-    headers: HashMap<String, String>
-) -> Result<Vec<u8>, FrameEncodeError> {
-    let tubeid_bytes = tube_id.to_be_bytes();
-    let mut headers_json_str_bytes = match serde_json::to_string(&headers) {
-        Ok(json_str) => json_str.into_bytes(),
+        tube_id, 
+        Some(trailers),
+    )
+}
+
+/**
+ * ClientHasFinishedSending and ServerHasFinishedSending frames share the same
+ * layout. `frame_type` is the FrameType to use when there are no trailers.
+ */
+fn put_has_finished_sending_frame(
+    buf: &mut BytesMut,
+    frame_type: u8,
+    tube_id: u64,
+    trailers: Option<&HashMap<String, String>>,
+) -> Result<(), FrameEncodeError> {
+    let trailers = match trailers {
+        Some(trailers) => trailers,
+        None => return put_tube_id_frame(buf, frame_type, tube_id),
+    };
+    let frame_type = match frame_type {
+        frame::CLIENT_HAS_FINISHED_SENDING_FRAMETYPE => 
+            frame::CLIENT_HAS_FINISHED_SENDING_WITH_TRAILERS_FRAMETYPE,
+        _ => frame::SERVER_HAS_FINISHED_SENDING_WITH_TRAILERS_FRAMETYPE,
+    };
+
+    let id_width = IdWidth::for_tube_id(tube_id)?;
+    let body_len = id_width.id_len(tube_id) + header_block::encoded_len(trailers)?;
+    if body_len > frame::MAX_FRAME_BODY_LEN {
+        return Err(FrameEncodeError::HeadersTooLarge(body_len));
+    }
+    put_frame_header(buf, id_width.frame_type(frame_type), body_len)?;
+    id_width.put_id(buf, tube_id);
+    header_block::put_header_block(buf, trailers);
+    Ok(())
+}
+
+pub fn drain_frame(reason: frame::DrainReason) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| drain_frame_into(buf, reason))
+}
+
+pub fn drain_frame_into(
+    buf: &mut BytesMut,
+    reason: frame::DrainReason,
+) -> Result<(), FrameEncodeError> {
+    put_frame_header(buf, frame::DRAIN_FRAMETYPE, 1)?;
+    buf.put_u8(reason.into());
+    Ok(())
+}
+
+pub fn extension_frame(
+    tube_id: u64,
+    ext_type: u8,
+    data: &[u8],
+) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| extension_frame_into(buf, tube_id, ext_type, data))
+}
+
+pub fn extension_frame_into(
+    buf: &mut BytesMut,
+    tube_id: u64,
+    ext_type: u8,
+    data: &[u8],
+) -> Result<(), FrameEncodeError> {
+    let frame_type = match frame::APPLICATION_EXTENSION_FRAMETYPES.start().checked_add(ext_type) {
+        Some(frame_type) if frame::APPLICATION_EXTENSION_FRAMETYPES.contains(&frame_type) => 
+            frame_type,
+        _ => return Err(FrameEncodeError::ExtTypeOutOfRange(ext_type)),
+    };
+    match IdWidth::for_tube_id(tube_id)? {
+        IdWidth::Fixed => {
+            put_frame_header(buf, frame_type, 2 + data.len())?;
+            buf.put_u16(tube_id as u16);
+        },
+        IdWidth::Varint => {
+            put_frame_header(
+                buf, 
+                frame::VARINT_IDS_EXTENSION_FRAMETYPE, 
+                1 + varint::varint_len(tube_id) + data.len(),
+            )?;
+            buf.put_u8(ext_type);
+            varint::put_varint(buf, tube_id);
+        },
+    }
+    buf.put_slice(data);
+    Ok(())
+}
+
+pub fn goaway_frame(
+    last_tube_id: u64,
+    error_code: frame::GoAwayErrorCode,
+    message: &str,
+) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| goaway_frame_into(buf, last_tube_id, error_code, message))
+}
+
+/**
+ * Encodes a GoAway frame, cutting `message` short (on a character boundary) if
+ * it doesn't fit in the frame.
+ */
+pub fn goaway_frame_into(
+    buf: &mut BytesMut,
+    last_tube_id: u64,
+    error_code: frame::GoAwayErrorCode,
+    message: &str,
+) -> Result<(), FrameEncodeError> {
+    let id_width = IdWidth::for_tube_id(last_tube_id)?;
+    let message = truncate_on_char_boundary(message, goaway_message_capacity(last_tube_id));
+    put_frame_header(
+        buf, 
+        id_width.frame_type(frame::GOAWAY_FRAMETYPE), 
+        id_width.id_len(last_tube_id) + 1 + message.len(),
+    )?;
+    id_width.put_id(buf, last_tube_id);
+    buf.put_u8(error_code.into());
+    buf.put_slice(message.as_bytes());
+    Ok(())
+}
+
+pub fn newtube_frame_into(
+    buf: &mut BytesMut,
+    tube_id: u64, 
+    headers: &HashMap<String, String>
+) -> Result<(), FrameEncodeError> {
+    let headers_json_str_bytes = match serde_json::to_vec(headers) {
+        Ok(json_bytes) => json_bytes,
         Err(json_err) => return Err(FrameEncodeError::HeaderJsonEncodeError(json_err))
     };
-    let body_len: u16 = 2 + (headers_json_str_bytes.len() as u16);
-    let body_len_bytes = body_len.to_be_bytes();
-    let mut bytes = vec![
-        frame::NEWTUBE_FRAMETYPE, 
-        body_len_bytes[0],
-        body_len_bytes[1],
-        tubeid_bytes[0], 
-        tubeid_bytes[1],
-    ];
-    bytes.append(&mut headers_json_str_bytes);
-    Ok(bytes)
+    let id_width = IdWidth::for_tube_id(tube_id)?;
+    let body_len = id_width.id_len(tube_id) + headers_json_str_bytes.len();
+    if body_len > frame::MAX_FRAME_BODY_LEN {
+        return Err(FrameEncodeError::HeadersTooLarge(body_len));
+    }
+    put_frame_header(buf, id_width.frame_type(frame::NEWTUBE_FRAMETYPE), body_len)?;
+    id_width.put_id(buf, tube_id);
+    buf.put_slice(&headers_json_str_bytes);
+    Ok(())
+}
+
+#[cfg(feature = "client")]
+pub fn newtube_binary_frame_into(
+    buf: &mut BytesMut,
+    tube_id: u64, 
+    headers: &HashMap<String, String>
+) -> Result<(), FrameEncodeError> {
+    let id_width = IdWidth::for_tube_id(tube_id)?;
+    let body_len = id_width.id_len(tube_id) + header_block::encoded_len(headers)?;
+    if body_len > frame::MAX_FRAME_BODY_LEN {
+        return Err(FrameEncodeError::HeadersTooLarge(body_len));
+    }
+    put_frame_header(buf, id_width.frame_type(frame::NEWTUBE_BINARY_FRAMETYPE), body_len)?;
+    id_width.put_id(buf, tube_id);
+    header_block::put_header_block(buf, headers);
+    Ok(())
+}
+
+/**
+ * Encodes a NewTube frame using whichever HeaderEncoding was negotiated for 
+ * the channel.
+ */
+#[cfg(feature = "client")]
+pub fn newtube_frame_with_encoding_into(
+    buf: &mut BytesMut,
+    tube_id: u64, 
+    headers: &HashMap<String, String>,
+    header_encoding: frame::HeaderEncoding,
+) -> Result<(), FrameEncodeError> {
+    match header_encoding {
+        frame::HeaderEncoding::Binary => newtube_binary_frame_into(buf, tube_id, headers),
+        frame::HeaderEncoding::Json => newtube_frame_into(buf, tube_id, headers),
+    }
+}
+
+#[cfg(feature = "client")]
+pub fn newtube_frame_with_encoding(
+    tube_id: u64, 
+    headers: &HashMap<String, String>,
+    header_encoding: frame::HeaderEncoding,
+) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| newtube_frame_with_encoding_into(
+        buf, 
+        tube_id, 
+        headers, 
+        header_encoding,
+    ))
+}
+
+pub fn newtube_accepted_frame(
+    tube_id: u64, 
+    headers: &HashMap<String, String>
+) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| newtube_accepted_frame_into(buf, tube_id, headers))
+}
+
+pub fn newtube_accepted_frame_into(
+    buf: &mut BytesMut,
+    tube_id: u64, 
+    headers: &HashMap<String, String>
+) -> Result<(), FrameEncodeError> {
+    put_newtube_response_frame(buf, frame::NEWTUBE_ACCEPTED_FRAMETYPE, tube_id, None, headers)
+}
+
+pub fn newtube_rejected_frame(
+    tube_id: u64, 
+    code: u16,
+    headers: &HashMap<String, String>
+) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| newtube_rejected_frame_into(buf, tube_id, code, headers))
+}
+
+pub fn newtube_rejected_frame_into(
+    buf: &mut BytesMut,
+    tube_id: u64, 
+    code: u16,
+    headers: &HashMap<String, String>
+) -> Result<(), FrameEncodeError> {
+    put_newtube_response_frame(
+        buf, 
+        frame::NEWTUBE_REJECTED_FRAMETYPE, 
+        tube_id, 
+        Some(code), 
+        headers,
+    )
+}
+
+/**
+ * NewTubeAccepted and NewTubeRejected frames share the same layout, except 
+ * that only the latter has a Code.
+ */
+fn put_newtube_response_frame(
+    buf: &mut BytesMut,
+    frame_type: u8,
+    tube_id: u64, 
+    code: Option<u16>,
+    headers: &HashMap<String, String>
+) -> Result<(), FrameEncodeError> {
+    let id_width = IdWidth::for_tube_id(tube_id)?;
+    let code_len = if code.is_some() { 2 } else { 0 };
+    let body_len = id_width.id_len(tube_id) + code_len + header_block::encoded_len(headers)?;
+    if body_len > frame::MAX_FRAME_BODY_LEN {
+        return Err(FrameEncodeError::HeadersTooLarge(body_len));
+    }
+    put_frame_header(buf, id_width.frame_type(frame_type), body_len)?;
+    id_width.put_id(buf, tube_id);
+    if let Some(code) = code {
+        buf.put_u16(code);
+    }
+    header_block::put_header_block(buf, headers);
+    Ok(())
 }
 
 pub fn payload_frame(
-    tube_id: u16,
-    ack_id: Option<u16>,
-    mut data: Vec<u8>,
-) -> Result<Vec<u8>, FrameEncodeError> {
-    // BodyLenBytes maxes out at 2^16, so ensure that the size of data fits into
-    // that limit
-    if data.len() > 2 + 2 + (2^16) {
+    tube_id: u64,
+    ack_id: Option<u64>,
+    data: &[u8],
+) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| payload_frame_into(buf, tube_id, ack_id, data))
+}
+
+pub fn payload_frame_into(
+    buf: &mut BytesMut,
+    tube_id: u64,
+    ack_id: Option<u64>,
+    data: &[u8],
+) -> Result<(), FrameEncodeError> {
+    put_payload_frame(buf, frame::PAYLOAD_FRAMETYPE, tube_id, ack_id, data)
+}
+
+/**
+ * Like payload_frame(), but asks the receiving peer to hold the ack until 
+ * its application has processed the payload.
+ */
+pub fn processing_ack_payload_frame(
+    tube_id: u64,
+    ack_id: u64,
+    data: &[u8],
+) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| processing_ack_payload_frame_into(buf, tube_id, ack_id, data))
+}
+
+pub fn processing_ack_payload_frame_into(
+    buf: &mut BytesMut,
+    tube_id: u64,
+    ack_id: u64,
+    data: &[u8],
+) -> Result<(), FrameEncodeError> {
+    put_payload_frame(buf, frame::PROCESSING_ACK_PAYLOAD_FRAMETYPE, tube_id, Some(ack_id), data)
+}
+
+/**
+ * Payload and CompressedPayload frames (with or without a processing ack) 
+ * share the same layout.
+ */
+fn put_payload_frame(
+    buf: &mut BytesMut,
+    frame_type: u8,
+    tube_id: u64,
+    ack_id: Option<u64>,
+    data: &[u8],
+) -> Result<(), FrameEncodeError> {
+    // BodyLenBytes maxes out at 2^16-1, so ensure that the size of data (plus
+    // the TubeId and AckId fields) fits into that limit
+    let id_width = IdWidth::for_ids(tube_id, ack_id)?;
+    if data.len() > payload_frame_data_capacity(tube_id, ack_id) {
         return Err(FrameEncodeError::DataTooLarge(data.len()))
     }
 
-    let tubeid_bytes = tube_id.to_be_bytes();
-    let ack_bytes = match ack_id {
-        Some(ack_id) => {
-            if ((0b1000_0000 << 8) & ack_id) > 0 {
-                return Err(FrameEncodeError::AckIdTooLarge(ack_id));
-            } else {
-                ((0b1000_0000 << 8) | ack_id).to_be_bytes()
-            }
-        },
-        None => [0, 0]
-    };
-    let body_len = 2 + 2 + (data.len() as u16);
-    let body_len_bytes = body_len.to_be_bytes();
-    let mut bytes = vec![
-        frame::PAYLOAD_FRAMETYPE,
-        body_len_bytes[0],
-        body_len_bytes[1],
-        tubeid_bytes[0],
-        tubeid_bytes[1],
-        ack_bytes[0],
-        ack_bytes[1],
-    ];
-    bytes.append(&mut data);
-    Ok(bytes)
+    let ids_len = id_width.id_len(tube_id) + id_width.payload_ack_id_len(ack_id);
+    put_frame_header(buf, id_width.frame_type(frame_type), ids_len + data.len())?;
+    id_width.put_id(buf, tube_id);
+    id_width.put_payload_ack_id(buf, ack_id);
+    buf.put_slice(data);
+    Ok(())
+}
+
+pub fn compressed_payload_frame(
+    tube_id: u64,
+    ack_id: Option<u64>,
+    data: &[u8],
+) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| compressed_payload_frame_into(buf, tube_id, ack_id, data))
+}
+
+pub fn compressed_payload_frame_into(
+    buf: &mut BytesMut,
+    tube_id: u64,
+    ack_id: Option<u64>,
+    data: &[u8],
+) -> Result<(), FrameEncodeError> {
+    put_payload_frame(buf, frame::COMPRESSED_PAYLOAD_FRAMETYPE, tube_id, ack_id, data)
+}
+
+pub fn processing_ack_compressed_payload_frame(
+    tube_id: u64,
+    ack_id: u64,
+    data: &[u8],
+) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| processing_ack_compressed_payload_frame_into(buf, tube_id, ack_id, data))
+}
+
+pub fn processing_ack_compressed_payload_frame_into(
+    buf: &mut BytesMut,
+    tube_id: u64,
+    ack_id: u64,
+    data: &[u8],
+) -> Result<(), FrameEncodeError> {
+    put_payload_frame(
+        buf, 
+        frame::PROCESSING_ACK_COMPRESSED_PAYLOAD_FRAMETYPE, 
+        tube_id, 
+        Some(ack_id), 
+        data,
+    )
+}
+
+pub fn payload_fragment_frame(
+    tube_id: u64,
+    data: &[u8],
+) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| payload_fragment_frame_into(buf, tube_id, data))
+}
+
+pub fn payload_fragment_frame_into(
+    buf: &mut BytesMut,
+    tube_id: u64,
+    data: &[u8],
+) -> Result<(), FrameEncodeError> {
+    let id_width = IdWidth::for_tube_id(tube_id)?;
+    if data.len() > payload_fragment_frame_data_capacity(tube_id) {
+        return Err(FrameEncodeError::DataTooLarge(data.len()))
+    }
+
+    put_frame_header(
+        buf, 
+        id_width.frame_type(frame::PAYLOAD_FRAGMENT_FRAMETYPE), 
+        id_width.id_len(tube_id) + data.len(),
+    )?;
+    id_width.put_id(buf, tube_id);
+    buf.put_slice(data);
+    Ok(())
 }
 
 pub fn payload_ack_frame(
-    tube_id: u16,
-    ack_id: u16,
-) -> Result<Vec<u8>, FrameEncodeError> {
-    let tubeid_bytes = tube_id.to_be_bytes();
-    let ack_id_bytes = if ((0b1000_0000 << 8) & ack_id) > 0 {
+    tube_id: u64,
+    ack_id: u64,
+    processed: bool,
+) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| payload_ack_frame_into(buf, tube_id, ack_id, processed))
+}
+
+pub fn payload_ack_frame_into(
+    buf: &mut BytesMut,
+    tube_id: u64,
+    ack_id: u64,
+    processed: bool,
+) -> Result<(), FrameEncodeError> {
+    let id_width = IdWidth::for_ids(tube_id, Some(ack_id))?;
+    let processed_bit = u64::from(processed);
+    match id_width {
+        IdWidth::Fixed => {
+            put_frame_header(buf, frame::PAYLOAD_ACK_FRAMETYPE, 2 + 2)?;
+            buf.put_u16(tube_id as u16);
+            buf.put_u16(((processed_bit as u16) << 15) | ack_id as u16);
+        },
+        IdWidth::Varint => {
+            let ack_varint = (ack_id << 1) | processed_bit;
+            put_frame_header(
+                buf, 
+                id_width.frame_type(frame::PAYLOAD_ACK_FRAMETYPE), 
+                varint::varint_len(tube_id) + varint::varint_len(ack_varint),
+            )?;
+            varint::put_varint(buf, tube_id);
+            varint::put_varint(buf, ack_varint);
+        },
+    }
+    Ok(())
+}
+
+pub fn payload_sequence_frame(
+    tube_id: u64,
+    sequence: u64,
+) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| payload_sequence_frame_into(buf, tube_id, sequence))
+}
+
+pub fn payload_sequence_frame_into(
+    buf: &mut BytesMut,
+    tube_id: u64,
+    sequence: u64,
+) -> Result<(), FrameEncodeError> {
+    let id_width = IdWidth::for_tube_id(tube_id)?;
+    put_frame_header(
+        buf, 
+        id_width.frame_type(frame::PAYLOAD_SEQUENCE_FRAMETYPE), 
+        id_width.id_len(tube_id) + 8,
+    )?;
+    id_width.put_id(buf, tube_id);
+    buf.put_u64(sequence);
+    Ok(())
+}
+
+/**
+ * Acknowledges every ack id from 0 through `up_to` (if any) plus every ack id
+ * in `ranges`. Empty ranges cover no ack ids and are left out.
+ */
+pub fn cumulative_payload_ack_frame(
+    tube_id: u64,
+    up_to: Option<u64>,
+    ranges: &[RangeInclusive<u64>],
+) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| cumulative_payload_ack_frame_into(buf, tube_id, up_to, ranges))
+}
+
+pub fn cumulative_payload_ack_frame_into(
+    buf: &mut BytesMut,
+    tube_id: u64,
+    up_to: Option<u64>,
+    ranges: &[RangeInclusive<u64>],
+) -> Result<(), FrameEncodeError> {
+    // Only the TubeId has a fixed-width form; the ack id fields are always 
+    // varints.
+    let id_width = IdWidth::for_tube_id(tube_id)?;
+    let ranges = ranges.iter().filter(|range| !range.is_empty());
+    let mut ack_ids = up_to.into_iter().chain(ranges.clone().map(|range| *range.end()));
+    if let Some(ack_id) = ack_ids.find(|ack_id| *ack_id > frame::MAX_ACK_ID) {
         return Err(FrameEncodeError::AckIdTooLarge(ack_id));
-    } else {
-        ack_id.to_be_bytes()
-    };
-    Ok(vec![
-       frame::PAYLOAD_ACK_FRAMETYPE,
-       0, 4,
-       tubeid_bytes[0],
-       tubeid_bytes[1],
-       ack_id_bytes[0],
-       ack_id_bytes[1],
-    ])
+    }
+
+    let body_len = 
+        id_width.id_len(tube_id) 
+        + varint::varint_len(payload_ack_id_varint(up_to))
+        + ranges.clone()
+            .map(|range| {
+                varint::varint_len(*range.start()) 
+                    + varint::varint_len(range.end() - range.start())
+            })
+            .sum::<usize>();
+    put_frame_header(
+        buf, 
+        id_width.frame_type(frame::CUMULATIVE_PAYLOAD_ACK_FRAMETYPE), 
+        body_len,
+    )?;
+    id_width.put_id(buf, tube_id);
+    varint::put_varint(buf, payload_ack_id_varint(up_to));
+    for range in ranges {
+        varint::put_varint(buf, *range.start());
+        varint::put_varint(buf, range.end() - range.start());
+    }
+    Ok(())
+}
+
+pub fn ping_frame(
+    opaque: u64,
+) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| ping_frame_into(buf, opaque))
+}
+
+pub fn ping_frame_into(
+    buf: &mut BytesMut,
+    opaque: u64,
+) -> Result<(), FrameEncodeError> {
+    put_frame_header(buf, frame::PING_FRAMETYPE, 8)?;
+    buf.put_u64(opaque);
+    Ok(())
+}
+
+pub fn pong_frame(
+    opaque: u64,
+) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| pong_frame_into(buf, opaque))
+}
+
+pub fn pong_frame_into(
+    buf: &mut BytesMut,
+    opaque: u64,
+) -> Result<(), FrameEncodeError> {
+    put_frame_header(buf, frame::PONG_FRAMETYPE, 8)?;
+    buf.put_u64(opaque);
+    Ok(())
+}
+
+pub fn settings_frame(
+    version: u8,
+    features: frame::Features,
+    max_frame_size: u32,
+    max_concurrent_tubes: u16,
+    initial_window_size: u32,
+) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| settings_frame_into(
+        buf, 
+        version, 
+        features, 
+        max_frame_size, 
+        max_concurrent_tubes,
+        initial_window_size,
+    ))
+}
+
+pub fn settings_frame_into(
+    buf: &mut BytesMut,
+    version: u8,
+    features: frame::Features,
+    max_frame_size: u32,
+    max_concurrent_tubes: u16,
+    initial_window_size: u32,
+) -> Result<(), FrameEncodeError> {
+    put_frame_header(buf, frame::SETTINGS_FRAMETYPE, 1 + 4 + 4 + 2 + 4)?;
+    buf.put_u8(version);
+    buf.put_u32(features.bits());
+    buf.put_u32(max_frame_size);
+    buf.put_u16(max_concurrent_tubes);
+    buf.put_u32(initial_window_size);
+    Ok(())
+}
+
+#[cfg(feature = "server")]
+pub fn session_frame(token: u128) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| session_frame_into(buf, token))
+}
+
+pub fn session_frame_into(
+    buf: &mut BytesMut,
+    token: u128,
+) -> Result<(), FrameEncodeError> {
+    put_frame_header(buf, frame::SESSION_FRAMETYPE, 16)?;
+    buf.put_u128(token);
+    Ok(())
+}
+
+pub fn window_update_frame(
+    tube_id: u64,
+    credit: u32,
+) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| window_update_frame_into(buf, tube_id, credit))
+}
+
+pub fn window_update_frame_into(
+    buf: &mut BytesMut,
+    tube_id: u64,
+    credit: u32,
+) -> Result<(), FrameEncodeError> {
+    let id_width = IdWidth::for_tube_id(tube_id)?;
+    put_frame_header(
+        buf, 
+        id_width.frame_type(frame::WINDOW_UPDATE_FRAMETYPE), 
+        id_width.id_len(tube_id) + 4,
+    )?;
+    id_width.put_id(buf, tube_id);
+    buf.put_u32(credit);
+    Ok(())
+}
+
+pub fn priority_frame(
+    tube_id: u64,
+    weight: u8,
+) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| priority_frame_into(buf, tube_id, weight))
+}
+
+pub fn priority_frame_into(
+    buf: &mut BytesMut,
+    tube_id: u64,
+    weight: u8,
+) -> Result<(), FrameEncodeError> {
+    let id_width = IdWidth::for_tube_id(tube_id)?;
+    put_frame_header(
+        buf, 
+        id_width.frame_type(frame::PRIORITY_FRAMETYPE), 
+        id_width.id_len(tube_id) + 1,
+    )?;
+    id_width.put_id(buf, tube_id);
+    buf.put_u8(weight);
+    Ok(())
 }
 
 pub fn server_has_finished_sending_frame(
-    tube_id: u16,
-) -> Result<Vec<u8>, FrameEncodeError> {
-    let tubeid_bytes = tube_id.to_be_bytes();
-    Ok(vec![
+    tube_id: u64,
+) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| server_has_finished_sending_frame_into(buf, tube_id))
+}
+
+pub fn server_has_finished_sending_frame_into(
+    buf: &mut BytesMut,
+    tube_id: u64,
+) -> Result<(), FrameEncodeError> {
+    put_tube_id_frame(buf, frame::SERVER_HAS_FINISHED_SENDING_FRAMETYPE, tube_id)
+}
+
+pub fn server_has_finished_sending_with_trailers_frame(
+    tube_id: u64,
+    trailers: &HashMap<String, String>,
+) -> Result<Bytes, FrameEncodeError> {
+    encode_with(|buf| server_has_finished_sending_with_trailers_frame_into(buf, tube_id, trailers))
+}
+
+pub fn server_has_finished_sending_with_trailers_frame_into(
+    buf: &mut BytesMut,
+    tube_id: u64,
+    trailers: &HashMap<String, String>,
+) -> Result<(), FrameEncodeError> {
+    put_has_finished_sending_frame(
+        buf, 
         frame::SERVER_HAS_FINISHED_SENDING_FRAMETYPE, 
-        0, 2,
-        tubeid_bytes[0], 
-        tubeid_bytes[1],
-    ])
+        tube_id, 
+        Some(trailers),
+    )
 }
 
 #[cfg(test)]
@@ -167,7 +1035,7 @@ mod encode_payload_tests {
             }
         }
 
-        match encode::payload_frame(42, Some(42), data) {
+        match encode::payload_frame(42, Some(42), &data) {
             Err(FrameEncodeError::DataTooLarge(size)) => assert_eq!(size, 66000),
             Err(err) => panic!(concat!(
                 "Received the wrong error when passing too much data to ",
@@ -183,8 +1051,8 @@ mod encode_payload_tests {
 
     #[test]
     fn errors_on_oversized_ackid() {
-        match encode::payload_frame(42, Some(65000), vec![]) {
-            Err(FrameEncodeError::AckIdTooLarge(size)) => assert_eq!(size, 65000),
+        match encode::payload_frame(42, Some(u64::MAX), &[]) {
+            Err(FrameEncodeError::AckIdTooLarge(size)) => assert_eq!(size, u64::MAX),
             Err(err) => panic!(
                 "Received the wrong error when passing an oversized ack_id: {:?}",
                 err
@@ -197,6 +1065,109 @@ mod encode_payload_tests {
     }
 }
 
+#[cfg(test)]
+mod encode_payload_capacity_tests {
+    // Hacky aesthetic workaround for `use super as encode`
+    mod encode { pub use super::super::*; }
+
+    use super::super::frame;
+    use super::FrameEncodeError;
+
+    #[test]
+    fn data_at_capacity_fills_a_frame() {
+        let capacity = encode::payload_frame_data_capacity(42, Some(42));
+        let bytes = encode::payload_frame(42, Some(42), &vec![7; capacity]).unwrap();
+        assert_eq!(bytes.len(), 3 + frame::MAX_FRAME_BODY_LEN);
+
+        match encode::payload_frame(42, Some(42), &vec![7; capacity + 1]) {
+            Err(FrameEncodeError::DataTooLarge(size)) => assert_eq!(size, capacity + 1),
+            res => panic!("Expected a DataTooLarge error, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn large_ids_reduce_capacity() {
+        let tube_id = 70000;
+        let capacity = encode::payload_fragment_frame_data_capacity(tube_id);
+        assert!(capacity < encode::payload_fragment_frame_data_capacity(42));
+
+        // TubeId 70000 takes up 4 bytes as a varint.
+        let bytes = encode::payload_fragment_frame(tube_id, &vec![7; capacity]).unwrap();
+        assert_eq!(
+            bytes[0], 
+            frame::PAYLOAD_FRAGMENT_FRAMETYPE | frame::VARINT_IDS_FRAMETYPE_FLAG,
+        );
+        assert_eq!(&bytes[1..3], &u16::MAX.to_be_bytes());
+        assert!(encode::payload_fragment_frame(tube_id, &vec![7; capacity + 1]).is_err());
+    }
+}
+
+#[cfg(test)]
+mod encode_into_tests {
+    use bytes::BytesMut;
+
+    // Hacky aesthetic workaround for `use super as encode`
+    mod encode { pub use super::super::*; }
+
+    use super::super::frame;
+
+    #[test]
+    fn appends_multiple_frames_to_one_buffer() {
+        let mut buf = BytesMut::new();
+        encode::client_has_finished_sending_frame_into(&mut buf, 43).unwrap();
+        encode::frame_into(&mut buf, &frame::Frame::AbortAck { tube_id: 42 }).unwrap();
+
+        let mut expected = BytesMut::new();
+        expected.extend_from_slice(&encode::client_has_finished_sending_frame(43).unwrap());
+        expected.extend_from_slice(&encode::abort_ack_frame(42).unwrap());
+        assert_eq!(buf, expected);
+    }
+
+    #[test]
+    fn writes_nothing_on_error() {
+        let mut buf = BytesMut::new();
+        assert!(encode::payload_ack_frame_into(&mut buf, 42, u64::MAX, false).is_err());
+        assert!(encode::payload_frame_into(&mut buf, 42, Some(u64::MAX), &[]).is_err());
+        assert!(encode::payload_fragment_frame_into(
+            &mut buf, 
+            42, 
+            &vec![0; encode::payload_fragment_frame_data_capacity(42) + 1],
+        ).is_err());
+        assert!(buf.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod encode_goaway_tests {
+    // Hacky aesthetic workaround for `use super as encode`
+    mod encode { pub use super::super::*; }
+
+    use super::frame;
+
+    #[test]
+    fn truncation_never_splits_a_character() {
+        assert_eq!(encode::truncate_on_char_boundary("héllo", 10), "héllo");
+        assert_eq!(encode::truncate_on_char_boundary("héllo", 2), "h");
+        assert_eq!(encode::truncate_on_char_boundary("héllo", 3), "hé");
+        assert_eq!(encode::truncate_on_char_boundary("héllo", 0), "");
+    }
+
+    #[test]
+    fn goaway_message_is_truncated_to_fit_the_frame() {
+        let message = "é".repeat(frame::MAX_FRAME_BODY_LEN);
+        let encoded_bytes = 
+            encode::goaway_frame(3, frame::GoAwayErrorCode::InternalError, &message).unwrap();
+        let frames = crate::common::frame::Decoder::new().decode(encoded_bytes).unwrap();
+        match &frames[0] {
+            frame::Frame::GoAway { last_tube_id: 3, message: sent_message, .. } => {
+                assert_eq!(sent_message.len(), encode::goaway_message_capacity(3) & !1);
+                assert!(message.starts_with(sent_message.as_str()));
+            },
+            unexpected => panic!("Expected a GoAway frame, got {:?}", unexpected),
+        }
+    }
+}
+
 #[cfg(test)]
 mod encode_payload_ack_tests {
     // Hacky aesthetic workaround for `use super as encode`
@@ -206,8 +1177,8 @@ mod encode_payload_ack_tests {
 
     #[test]
     fn errors_on_oversized_ackid() {
-        match encode::payload_ack_frame(42, 65000) {
-            Err(FrameEncodeError::AckIdTooLarge(size)) => assert_eq!(size, 65000),
+        match encode::payload_ack_frame(42, u64::MAX, false) {
+            Err(FrameEncodeError::AckIdTooLarge(size)) => assert_eq!(size, u64::MAX),
             Err(err) => panic!(
                 "Received the wrong error when passing an oversized ack_id: {:?}",
                 err
@@ -219,3 +1190,32 @@ mod encode_payload_ack_tests {
         }
     }
 }
+
+#[cfg(all(test, feature = "client"))]
+mod encode_newtube_tests {
+    use std::collections::HashMap;
+
+    // Hacky aesthetic workaround for `use super as encode`
+    mod encode { pub use super::super::*; }
+
+    use super::FrameEncodeError;
+    use super::frame::HeaderEncoding;
+
+    #[test]
+    fn errors_on_oversized_headers() {
+        let headers = HashMap::from([
+            ("x-big".to_string(), "a".repeat(60000)),
+            ("x-bigger".to_string(), "b".repeat(60000)),
+        ]);
+        for header_encoding in [HeaderEncoding::Binary, HeaderEncoding::Json] {
+            match encode::newtube_frame_with_encoding(1, &headers, header_encoding) {
+                Err(FrameEncodeError::HeadersTooLarge(_)) => (),
+                unexpected => panic!(
+                    "Unexpected result encoding oversized {:?} headers: {:?}",
+                    header_encoding,
+                    unexpected,
+                ),
+            }
+        }
+    }
+}
@@ -1,4 +1,7 @@
 use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+use bytes::Bytes;
 
 pub(in super) const CLIENT_HAS_FINISHED_SENDING_FRAMETYPE: u8 = 0x0;
 pub(in super) const DRAIN_FRAMETYPE: u8 = 0x1;
@@ -8,6 +11,86 @@ pub(in super) const PAYLOAD_ACK_FRAMETYPE: u8 = 0x4;
 pub(in super) const SERVER_HAS_FINISHED_SENDING_FRAMETYPE: u8 = 0x5;
 pub(in super) const ABORT_FRAMETYPE: u8 = 0x6;
 pub(in super) const ABORTACK_FRAMETYPE: u8 = 0x7;
+pub(in super) const PAYLOAD_FRAGMENT_FRAMETYPE: u8 = 0x8;
+pub(in super) const GOAWAY_FRAMETYPE: u8 = 0x9;
+pub(in super) const PING_FRAMETYPE: u8 = 0xA;
+pub(in super) const PONG_FRAMETYPE: u8 = 0xB;
+pub(in super) const SETTINGS_FRAMETYPE: u8 = 0xC;
+pub(in super) const NEWTUBE_BINARY_FRAMETYPE: u8 = 0xD;
+pub(in super) const COMPRESSED_PAYLOAD_FRAMETYPE: u8 = 0xE;
+pub(in super) const WINDOW_UPDATE_FRAMETYPE: u8 = 0xF;
+pub(in super) const PRIORITY_FRAMETYPE: u8 = 0x10;
+pub(in super) const PROCESSING_ACK_PAYLOAD_FRAMETYPE: u8 = 0x11;
+pub(in super) const PROCESSING_ACK_COMPRESSED_PAYLOAD_FRAMETYPE: u8 = 0x12;
+pub(in super) const CUMULATIVE_PAYLOAD_ACK_FRAMETYPE: u8 = 0x13;
+pub(in super) const CLIENT_HAS_FINISHED_SENDING_WITH_TRAILERS_FRAMETYPE: u8 = 0x14;
+pub(in super) const SERVER_HAS_FINISHED_SENDING_WITH_TRAILERS_FRAMETYPE: u8 = 0x15;
+pub(in super) const NEWTUBE_ACCEPTED_FRAMETYPE: u8 = 0x16;
+pub(in super) const NEWTUBE_REJECTED_FRAMETYPE: u8 = 0x17;
+pub(in super) const ABORT_WITH_CODE_FRAMETYPE: u8 = 0x18;
+pub(in super) const SESSION_FRAMETYPE: u8 = 0x19;
+pub(in super) const PAYLOAD_SEQUENCE_FRAMETYPE: u8 = 0x1A;
+
+/**
+ * Setting this bit in the FrameType of a frame that carries a TubeId (or an 
+ * AckId) indicates that its ids are encoded as varints rather than as 
+ * fixed-width fields (see Features::VARINT_IDS).
+ */
+pub(in super) const VARINT_IDS_FRAMETYPE_FLAG: u8 = 0x40;
+
+/**
+ * FrameTypes in this range are the varint-id forms of the frames above.
+ */
+pub(in super) const VARINT_IDS_FRAMETYPES: RangeInclusive<u8> = 0x40..=0x7F;
+
+/**
+ * An Extension frame's FrameType is taken up by its ExtType, so the 
+ * varint-id form of an Extension frame uses this FrameType and carries its 
+ * ExtType in the body instead.
+ */
+pub(in super) const VARINT_IDS_EXTENSION_FRAMETYPE: u8 = 0x7F;
+
+/**
+ * FrameTypes in this range are reserved for future extensions to the 
+ * protocol. A peer that receives a frame with one of these FrameTypes that it 
+ * doesn't understand skips over it rather than treating it as a protocol 
+ * violation.
+ */
+pub const RESERVED_EXTENSION_FRAMETYPES: RangeInclusive<u8> = 0x80..=0xBF;
+
+/**
+ * FrameTypes in this range are reserved for application-defined Extension 
+ * frames. An Extension frame's ExtType is its offset into this range, so 
+ * applications have 48 ExtTypes (0x00 through 0x2F) at their disposal.
+ */
+pub const APPLICATION_EXTENSION_FRAMETYPES: RangeInclusive<u8> = 0xC0..=0xEF;
+
+/**
+ * The version of the framing protocol implemented by this crate. Sent in the 
+ * Settings frame at the start of every channel.
+ */
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/**
+ * The largest body a single frame can carry (FrameBodyByteLength is a u16).
+ */
+pub(in crate) const MAX_FRAME_BODY_LEN: usize = u16::MAX as usize;
+
+/**
+ * The largest TubeId and AckId that fit in the fixed-width forms of each 
+ * frame. Larger ids may only be used once both peers have advertised 
+ * Features::VARINT_IDS.
+ */
+pub const MAX_FIXED_WIDTH_TUBE_ID: u64 = u16::MAX as u64;
+pub const MAX_FIXED_WIDTH_ACK_ID: u64 = 0x7FFF;
+
+/**
+ * The largest TubeId and AckId that can be encoded at all. AckIds top out at 
+ * half of MAX_VARINT because PayloadAck frames share their AckId varint with 
+ * the Processed bit.
+ */
+pub const MAX_TUBE_ID: u64 = super::varint::MAX_VARINT;
+pub const MAX_ACK_ID: u64 = super::varint::MAX_VARINT >> 1;
 
 /**
  * Each encoded Tube frame specifies its own structure, but all frames begin 
@@ -29,6 +112,18 @@ pub enum AbortReason {
     ApplicationAbort,
     ApplicationError,
     TransportErrorWhileSynchronizingTubeState,
+    ProtocolViolation,
+    ChannelGoingAway,
+    KeepaliveTimeout,
+    /**
+     * An application-defined code and optional message explaining why the 
+     * Tube was aborted (see Tube::abort_with()). Only sent if both peers 
+     * advertised Features::ABORT_CODES.
+     */
+    Application {
+        code: u32,
+        message: Option<String>,
+    },
     Unknown,
 }
 impl From<u8> for AbortReason {
@@ -37,6 +132,9 @@ impl From<u8> for AbortReason {
             0x0 => AbortReason::ApplicationAbort,
             0x1 => AbortReason::ApplicationError,
             0x2 => AbortReason::TransportErrorWhileSynchronizingTubeState,
+            0x3 => AbortReason::ProtocolViolation,
+            0x4 => AbortReason::ChannelGoingAway,
+            0x5 => AbortReason::KeepaliveTimeout,
             _   => AbortReason::Unknown,
         }
     }
@@ -47,11 +145,206 @@ impl Into<u8> for AbortReason {
             AbortReason::ApplicationAbort                          => 0x00,
             AbortReason::ApplicationError                          => 0x01,
             AbortReason::TransportErrorWhileSynchronizingTubeState => 0x02,
+            AbortReason::ProtocolViolation                         => 0x03,
+            AbortReason::ChannelGoingAway                          => 0x04,
+            AbortReason::KeepaliveTimeout                          => 0x05,
+            // Peers that can't decode the code and message are told the 
+            // application aborted the Tube due to an error.
+            AbortReason::Application { .. }                        => 0x01,
             AbortReason::Unknown                                   => 0xFF,
         }
     }
 }
 
+/**
+ * Explains why a peer sent a GoAway frame. Aside from NoError, each code 
+ * corresponds to a category of FrameParseError or FrameHandlerError.
+ */
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum GoAwayErrorCode {
+    NoError,
+    ProtocolError,
+    TubeStateError,
+    LimitExceeded,
+    InternalError,
+    Unknown,
+}
+impl From<u8> for GoAwayErrorCode {
+    fn from(code: u8) -> Self {
+        match code {
+            0x0 => GoAwayErrorCode::NoError,
+            0x1 => GoAwayErrorCode::ProtocolError,
+            0x2 => GoAwayErrorCode::TubeStateError,
+            0x3 => GoAwayErrorCode::LimitExceeded,
+            0x4 => GoAwayErrorCode::InternalError,
+            _   => GoAwayErrorCode::Unknown,
+        }
+    }
+}
+impl From<GoAwayErrorCode> for u8 {
+    fn from(code: GoAwayErrorCode) -> Self {
+        match code {
+            GoAwayErrorCode::NoError        => 0x00,
+            GoAwayErrorCode::ProtocolError  => 0x01,
+            GoAwayErrorCode::TubeStateError => 0x02,
+            GoAwayErrorCode::LimitExceeded  => 0x03,
+            GoAwayErrorCode::InternalError  => 0x04,
+            GoAwayErrorCode::Unknown        => 0xFF,
+        }
+    }
+}
+
+/**
+ * Explains why a peer sent a Drain frame (see Channel::drain()).
+ */
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum DrainReason {
+    Unspecified,
+    Shutdown,
+    Restart,
+    Overloaded,
+    Unknown,
+}
+impl From<u8> for DrainReason {
+    fn from(reason: u8) -> Self {
+        match reason {
+            0x0 => DrainReason::Unspecified,
+            0x1 => DrainReason::Shutdown,
+            0x2 => DrainReason::Restart,
+            0x3 => DrainReason::Overloaded,
+            _   => DrainReason::Unknown,
+        }
+    }
+}
+impl From<DrainReason> for u8 {
+    fn from(reason: DrainReason) -> Self {
+        match reason {
+            DrainReason::Unspecified => 0x00,
+            DrainReason::Shutdown    => 0x01,
+            DrainReason::Restart     => 0x02,
+            DrainReason::Overloaded  => 0x03,
+            DrainReason::Unknown     => 0xFF,
+        }
+    }
+}
+
+/**
+ * A set of optional protocol features, advertised by each peer in its 
+ * Settings frame. Only the features supported by both peers may be used on a 
+ * channel.
+ */
+#[derive(Clone,Copy,Debug,Default,Eq,Hash,PartialEq)]
+pub struct Features(u32);
+impl Features {
+    pub const PAYLOAD_FRAGMENTATION: Features = Features(1 << 0);
+    pub const KEEPALIVE: Features = Features(1 << 1);
+    pub const BINARY_HEADERS: Features = Features(1 << 2);
+    pub const COMPRESSION: Features = Features(1 << 3);
+    pub const FLOW_CONTROL: Features = Features(1 << 4);
+    pub const PRIORITY: Features = Features(1 << 5);
+    pub const VARINT_IDS: Features = Features(1 << 6);
+    pub const PROCESSING_ACKS: Features = Features(1 << 7);
+    pub const CUMULATIVE_ACKS: Features = Features(1 << 8);
+    pub const TRAILERS: Features = Features(1 << 9);
+    pub const NEWTUBE_HANDSHAKE: Features = Features(1 << 10);
+    pub const ABORT_CODES: Features = Features(1 << 11);
+    pub const DRAIN: Features = Features(1 << 12);
+    pub const SESSION_RESUMPTION: Features = Features(1 << 13);
+    pub const PAYLOAD_SEQUENCES: Features = Features(1 << 14);
+
+    pub const fn empty() -> Self {
+        Features(0)
+    }
+
+    /**
+     * Every feature implemented by this crate.
+     */
+    pub const fn supported() -> Self {
+        Features(
+            Features::PAYLOAD_FRAGMENTATION.0 
+            | Features::KEEPALIVE.0
+            | Features::BINARY_HEADERS.0
+            | Features::COMPRESSION.0
+            | Features::FLOW_CONTROL.0
+            | Features::PRIORITY.0
+            | Features::VARINT_IDS.0
+            | Features::PROCESSING_ACKS.0
+            | Features::CUMULATIVE_ACKS.0
+            | Features::TRAILERS.0
+            | Features::NEWTUBE_HANDSHAKE.0
+            | Features::ABORT_CODES.0
+            | Features::DRAIN.0
+            | Features::SESSION_RESUMPTION.0
+            | Features::PAYLOAD_SEQUENCES.0
+        )
+    }
+
+    pub const fn from_bits(bits: u32) -> Self {
+        Features(bits)
+    }
+
+    pub const fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub const fn contains(&self, other: Features) -> bool {
+        (self.0 & other.0) == other.0
+    }
+
+    pub const fn difference(&self, other: Features) -> Self {
+        Features(self.0 & !other.0)
+    }
+
+    pub const fn intersection(&self, other: Features) -> Self {
+        Features(self.0 & other.0)
+    }
+
+    pub const fn union(&self, other: Features) -> Self {
+        Features(self.0 | other.0)
+    }
+
+    /**
+     * The features used on a channel where one peer advertised `self` and
+     * the other advertised `peer`: every feature both advertised, except
+     * that
+     *
+     *   * SESSION_RESUMPTION is only used along with PAYLOAD_SEQUENCES, so
+     *     the frames replayed on a resumed channel are delivered only once.
+     *   * FLOW_CONTROL is never used along with SESSION_RESUMPTION, since
+     *     frames replayed on a resumed channel would be charged against
+     *     windows that were already credited for them.
+     */
+    pub const fn negotiate(&self, peer: Features) -> Self {
+        let mut negotiated = self.intersection(peer);
+        if !negotiated.contains(Features::PAYLOAD_SEQUENCES) {
+            negotiated = negotiated.difference(Features::SESSION_RESUMPTION);
+        }
+        if negotiated.contains(Features::SESSION_RESUMPTION) {
+            negotiated = negotiated.difference(Features::FLOW_CONTROL);
+        }
+        negotiated
+    }
+}
+
+/**
+ * The layouts below show each frame's ids in their original fixed-width 
+ * form: TubeId(u16) and AckId(15). Ids that don't fit in those fields are 
+ * encoded as varints (see varint::put_varint()) instead, in which case the 
+ * frame is sent with VARINT_IDS_FRAMETYPE_FLAG set in its FrameType and:
+ *
+ *   * Each TubeId (and LastTubeId) field is a varint.
+ *   * The AckRequested and AckId fields of Payload and CompressedPayload 
+ *     frames are replaced by a single varint holding AckId + 1, or 0 if no 
+ *     ack is requested.
+ *   * The Processed and AckId fields of PayloadAck frames are replaced by a 
+ *     single varint holding (AckId << 1) | Processed.
+ *   * Extension frames are sent with VARINT_IDS_EXTENSION_FRAMETYPE and an 
+ *     ExtType(u8) field ahead of the TubeId.
+ *
+ * A peer only hands out ids that need the varint form once both peers have 
+ * advertised Features::VARINT_IDS, but every peer that supports the feature 
+ * accepts either form at any time.
+ */
 #[derive(Clone,Debug,PartialEq)]
 pub enum Frame {
     /**
@@ -61,9 +354,18 @@ pub enum Frame {
      *   +---------------+
      *   |  TubeId(u16)  |
      *   +---------------+
+     *
+     * If the client has trailers to send (e.g. a final status or a checksum 
+     * of what it sent), they are carried in a header block under a separate 
+     * FrameType (see Features::TRAILERS):
+     *
+     *   +---------------+-------------------+
+     *   |  TubeId(u16)  |  HeaderBlock(*)   |
+     *   +---------------+-------------------+
      */
     ClientHasFinishedSending {
-        tube_id: u16,
+        tube_id: u64,
+        trailers: Option<HashMap<String, String>>,
     },
 
     /**
@@ -72,8 +374,17 @@ pub enum Frame {
      * application running on each peer to use this signal to coordinate the 
      * graceful shutdown of all Tubes hosted by the TubeTransport this 
      * frame arrived on.
+     *
+     *   +-------------------+
+     *   |  DrainReason(u8)  |
+     *   +-------------------+
+     *
+     * A Drain frame with an empty body has DrainReason::Unspecified. It is 
+     * only sent if both peers advertised Features::DRAIN.
      */
-    Drain,
+    Drain {
+        reason: DrainReason,
+    },
 
     /**
      * This frame is sent by either peer to indicate the creation of a new 
@@ -83,50 +394,137 @@ pub enum Frame {
      *   +---------------+-----------------------------+
      *   |  TubeId(u16)  |  Utf8EncodedJSONHeaders(*)  |
      *   +---------------+-----------------------------+
+     *
+     * When both peers advertise Features::BINARY_HEADERS, the headers are 
+     * instead sent as a compact binary header block (see 
+     * header_block::put_header_block()) under a separate FrameType:
+     *
+     *   +---------------+-------------------+
+     *   |  TubeId(u16)  |  HeaderBlock(*)   |
+     *   +---------------+-------------------+
      */
     NewTube {
-        tube_id: u16,
+        tube_id: u64,
+        headers: HashMap<String, String>,
+    },
+
+    /**
+     * This frame is sent by the peer that receives a NewTube frame once its 
+     * application accepts the Tube (see Features::NEWTUBE_HANDSHAKE). The 
+     * headers are the application's response to the NewTube headers.
+     *
+     *   +---------------+-------------------+
+     *   |  TubeId(u16)  |  HeaderBlock(*)   |
+     *   +---------------+-------------------+
+     */
+    NewTubeAccepted {
+        tube_id: u64,
+        headers: HashMap<String, String>,
+    },
+
+    /**
+     * This frame is sent instead of NewTubeAccepted if the application 
+     * refuses the Tube. Code is application-defined. Both peers stop tracking
+     * the Tube, so its TubeId may be re-used as soon as this frame arrives.
+     *
+     *   +---------------+-------------+-------------------+
+     *   |  TubeId(u16)  |  Code(u16)  |  HeaderBlock(*)   |
+     *   +---------------+-------------+-------------------+
+     */
+    NewTubeRejected {
+        tube_id: u64,
+        code: u16,
         headers: HashMap<String, String>,
     },
 
     /**
      * This frame is sent by either peer to transmit data.
      *
+     * If `processing_ack` is set, the sender wants its ack once the receiving 
+     * application has processed the payload rather than as soon as it 
+     * arrives (see Features::PROCESSING_ACKS). Such frames are sent with 
+     * PROCESSING_ACK_PAYLOAD_FRAMETYPE but are otherwise identical.
+     *
      *   +---------------+-------------------+-------------+-----------+
      *   |  TubeId(u16)  |  AckRequested(1)  |  AckId(15)  |  Data(*)  |
      *   +---------------+-------------------+-------------+-----------+
      */
     Payload {
-        tube_id: u16,
-        ack_id: Option<u16>,
-        data: Vec<u8>,
+        tube_id: u64,
+        ack_id: Option<u64>,
+        processing_ack: bool,
+        data: Bytes,
     },
 
     /**
      * This frame is sent by either peer when it receives a Payload frame that 
-     * specifies an ack_id. Note that receipt of a PayloadAck frame only means 
-     * the other peer received a Payload, it does not necessarily mean that the 
-     * application successfully processed the payload.
+     * specifies an ack_id. Unless Processed is set, receipt of a PayloadAck 
+     * frame only means the other peer received a Payload, it does not 
+     * necessarily mean that the application successfully processed the 
+     * payload.
      *
-     *   +---------------+---------------+-------------+
-     *   |  TubeId(u16)  |  RESERVED(1)  |  AckId(15)  |
-     *   +---------------+---------------+-------------+
+     * Processed is set on the ack for a Payload that requested a processing 
+     * ack, which is only sent once the receiving application acks the payload 
+     * itself.
+     *
+     *   +---------------+----------------+-------------+
+     *   |  TubeId(u16)  |  Processed(1)  |  AckId(15)  |
+     *   +---------------+----------------+-------------+
      */
     PayloadAck {
-        tube_id: u16,
-        ack_id: u16,
+        tube_id: u64,
+        ack_id: u64,
+        processed: bool,
+    },
+
+    /**
+     * This frame is sent immediately before the Payload frame it numbers,
+     * once both peers have advertised Features::PAYLOAD_SEQUENCES. A sender
+     * that resends a payload whose ack never arrived gives it the same
+     * Sequence, so the receiver can recognize (and drop) a payload it has
+     * already delivered. Sequences increase with each payload sent on a Tube.
+     *
+     *   +---------------+-----------------+
+     *   |  TubeId(u16)  |  Sequence(u64)  |
+     *   +---------------+-----------------+
+     */
+    PayloadSequence {
+        tube_id: u64,
+        sequence: u64,
+    },
+
+    /**
+     * This frame acknowledges several Payload frames at once and is 
+     * equivalent to a (non-Processed) PayloadAck frame for each of the ack 
+     * ids it covers (see Features::CUMULATIVE_ACKS). It covers every ack id 
+     * from 0 through UpTo - 1 (none if UpTo is 0), plus every ack id in each 
+     * of the ranges that follow it: RangeStart through RangeStart + RangeLen.
+     *
+     * Unlike the other fixed-width frames, the ack id fields of this frame 
+     * are always varints.
+     *
+     *   +---------------+----------------+----------------------------------------------+
+     *   |  TubeId(u16)  |  UpTo(varint)  |  [RangeStart(varint) + RangeLen(varint)](*)  |
+     *   +---------------+----------------+----------------------------------------------+
+     */
+    CumulativePayloadAck {
+        tube_id: u64,
+        up_to: Option<u64>,
+        ranges: Vec<RangeInclusive<u64>>,
     },
 
     /**
      * This frame is sent by the server when it will send no further Payload 
-     * frames for a given Tube.
+     * frames for a given Tube. Like ClientHasFinishedSending, it has a second
+     * FrameType for when it carries trailers.
      *
      *   +---------------+
      *   |  TubeId(u16)  |
      *   +---------------+
      */
     ServerHasFinishedSending {
-        tube_id: u16,
+        tube_id: u64,
+        trailers: Option<HashMap<String, String>>,
     },
 
     /**
@@ -137,9 +535,15 @@ pub enum Frame {
      *   +-----------------------------------+
      *   |  TubeId(u16)  |  AbortReason(u8)  |
      *   +-----------------------------------+
+     *
+     * An AbortReason::Application is sent with its own FrameType instead:
+     *
+     *   +-----------------------------------------------+
+     *   |  TubeId(u16)  |  Code(u32)  |  Utf8Message(*)  |
+     *   +-----------------------------------------------+
      */
     Abort {
-        tube_id: u16,
+        tube_id: u64,
         reason: AbortReason,
     },
 
@@ -161,6 +565,331 @@ pub enum Frame {
      *   +---------------+
      */
     AbortAck {
-        tube_id: u16,
+        tube_id: u64,
+    },
+
+    /**
+     * This frame is sent by either peer to transmit a leading chunk of a 
+     * payload that is too large to fit in a single Payload frame. The 
+     * receiving peer buffers PayloadFragment data for a Tube until the next 
+     * Payload frame arrives for that Tube, at which point the buffered 
+     * fragments and the Payload frame's data are reassembled into a single 
+     * payload.
+     *
+     * Only the terminating Payload frame carries an AckId, so a fragmented 
+     * payload is acknowledged exactly once.
+     *
+     *   +---------------+-----------+
+     *   |  TubeId(u16)  |  Data(*)  |
+     *   +---------------+-----------+
+     */
+    PayloadFragment {
+        tube_id: u64,
+        data: Bytes,
+    },
+
+    /**
+     * This frame is identical to a Payload frame except that the (possibly 
+     * reassembled) data it terminates was compressed with the algorithm 
+     * requested in the Tube's NewTube headers. The receiving peer decompresses
+     * the data before emitting it as a TubeEvent::Payload.
+     *
+     * Like Payload frames, CompressedPayload frames that request a processing
+     * ack are sent with their own FrameType 
+     * (PROCESSING_ACK_COMPRESSED_PAYLOAD_FRAMETYPE).
+     *
+     *   +---------------+-------------------+-------------+-----------+
+     *   |  TubeId(u16)  |  AckRequested(1)  |  AckId(15)  |  Data(*)  |
+     *   +---------------+-------------------+-------------+-----------+
+     */
+    CompressedPayload {
+        tube_id: u64,
+        ack_id: Option<u64>,
+        processing_ack: bool,
+        data: Bytes,
+    },
+
+    /**
+     * This frame is sent by either peer immediately before it tears down the 
+     * channel (e.g. because the other peer violated the framing protocol). 
+     * Every Tube on the channel is terminated when this frame is received.
+     *
+     * LastTubeId is the id of the most recent peer-initiated Tube that the 
+     * sender processed.
+     *
+     *   +-------------------+-----------------+--------------------+
+     *   |  LastTubeId(u16)  |  ErrorCode(u8)  |  Utf8Message(*)    |
+     *   +-------------------+-----------------+--------------------+
+     */
+    GoAway {
+        last_tube_id: u64,
+        error_code: GoAwayErrorCode,
+        message: String,
+    },
+
+    /**
+     * This frame is sent periodically by either peer to check that the 
+     * channel is still alive. The receiving peer must promptly respond with a 
+     * Pong frame carrying the same Opaque value.
+     *
+     *   +---------------+
+     *   |  Opaque(u64)  |
+     *   +---------------+
+     */
+    Ping {
+        opaque: u64,
+    },
+
+    /**
+     * This frame is sent in response to a Ping frame and echoes back the 
+     * Ping's Opaque value. The time between sending a Ping and receiving its 
+     * Pong is the channel's round-trip time.
+     *
+     *   +---------------+
+     *   |  Opaque(u64)  |
+     *   +---------------+
+     */
+    Pong {
+        opaque: u64,
+    },
+
+    /**
+     * This frame is sent by each peer as the very first frame on a channel. 
+     * It advertises the sender's protocol version, the optional Features it 
+     * supports, the largest frame (including its header) it will accept, the 
+     * most Tubes it will allow to be open on the channel at once, and the 
+     * receive window it grants each flow-controlled Tube.
+     *
+     * InitialWindowSize is only meaningful if both peers advertise 
+     * Features::FLOW_CONTROL, and may be omitted by peers that don't (in which
+     * case it decodes as 0). Any bytes that follow it are reserved for future
+     * settings and are ignored.
+     *
+     *   +---------------+----------------+--------------------+--------------------------+----------------------------+
+     *   |  Version(u8)  |  Features(u32) |  MaxFrameSize(u32) |  MaxConcurrentTubes(u16) |  [InitialWindowSize(u32)]  |
+     *   +---------------+----------------+--------------------+--------------------------+----------------------------+
+     */
+    Settings {
+        version: u8,
+        features: Features,
+        max_frame_size: u32,
+        max_concurrent_tubes: u16,
+        initial_window_size: u32,
+    },
+
+    /**
+     * This frame is sent by the server once both peers have advertised 
+     * Features::SESSION_RESUMPTION. If the channel's transport is lost, the 
+     * client may present Token (see SESSION_HEADER) on a new transport to 
+     * resume the channel along with all of its Tubes.
+     *
+     *   +---------------+
+     *   |  Token(u128)  |
+     *   +---------------+
+     */
+    Session {
+        token: u128,
     },
+
+    /**
+     * This frame is sent by either peer to grant the other peer Credit more 
+     * bytes of payload data on a flow-controlled Tube (see 
+     * Features::FLOW_CONTROL). It is sent as the receiving application 
+     * consumes the Tube's payloads.
+     *
+     *   +---------------+---------------+
+     *   |  TubeId(u16)  |  Credit(u32)  |
+     *   +---------------+---------------+
+     */
+    WindowUpdate {
+        tube_id: u64,
+        credit: u32,
+    },
+
+    /**
+     * This frame is sent by either peer when the application changes the 
+     * Weight of a Tube (see Features::PRIORITY). Both peers schedule the 
+     * payload frames they send on a Tube according to its Weight, so a Tube 
+     * with twice the Weight of another gets roughly twice the share of the 
+     * channel when both have data waiting to be sent.
+     *
+     *   +---------------+--------------+
+     *   |  TubeId(u16)  |  Weight(u8)  |
+     *   +---------------+--------------+
+     */
+    Priority {
+        tube_id: u64,
+        weight: u8,
+    },
+
+    /**
+     * This frame is sent by either peer to deliver an application-defined 
+     * control message (e.g. a cursor update or a cancellation hint) on a Tube
+     * without going through the Tube's stream of Payloads. Its FrameType is 
+     * APPLICATION_EXTENSION_FRAMETYPES.start() + ExtType; the tubez protocol 
+     * itself attaches no meaning to the ExtType or the data.
+     *
+     *   +---------------+-----------+
+     *   |  TubeId(u16)  |  Data(*)  |
+     *   +---------------+-----------+
+     */
+    Extension {
+        tube_id: u64,
+        ext_type: u8,
+        data: Bytes,
+    },
+}
+
+/**
+ * Specifies how the headers in a NewTube frame are encoded on the wire.
+ */
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum HeaderEncoding {
+    Binary,
+    Json,
+}
+
+/**
+ * Identifies a kind of Frame independent of its contents (e.g. to configure 
+ * which frames a Decoder will accept).
+ */
+#[derive(Clone,Copy,Debug,Eq,Hash,PartialEq)]
+pub enum FrameTag {
+    ClientHasFinishedSending,
+    Drain,
+    NewTube,
+    NewTubeAccepted,
+    NewTubeRejected,
+    Payload,
+    PayloadAck,
+    PayloadSequence,
+    CumulativePayloadAck,
+    ServerHasFinishedSending,
+    Abort,
+    AbortAck,
+    PayloadFragment,
+    CompressedPayload,
+    GoAway,
+    Ping,
+    Pong,
+    Settings,
+    Session,
+    WindowUpdate,
+    Priority,
+    Extension,
+}
+impl From<&Frame> for FrameTag {
+    fn from(frame: &Frame) -> Self {
+        match frame {
+            Frame::ClientHasFinishedSending { .. } => FrameTag::ClientHasFinishedSending,
+            Frame::Drain { .. } => FrameTag::Drain,
+            Frame::NewTube { .. } => FrameTag::NewTube,
+            Frame::NewTubeAccepted { .. } => FrameTag::NewTubeAccepted,
+            Frame::NewTubeRejected { .. } => FrameTag::NewTubeRejected,
+            Frame::Payload { .. } => FrameTag::Payload,
+            Frame::PayloadAck { .. } => FrameTag::PayloadAck,
+            Frame::PayloadSequence { .. } => FrameTag::PayloadSequence,
+            Frame::CumulativePayloadAck { .. } => FrameTag::CumulativePayloadAck,
+            Frame::ServerHasFinishedSending { .. } => FrameTag::ServerHasFinishedSending,
+            Frame::Abort { .. } => FrameTag::Abort,
+            Frame::AbortAck { .. } => FrameTag::AbortAck,
+            Frame::PayloadFragment { .. } => FrameTag::PayloadFragment,
+            Frame::CompressedPayload { .. } => FrameTag::CompressedPayload,
+            Frame::GoAway { .. } => FrameTag::GoAway,
+            Frame::Ping { .. } => FrameTag::Ping,
+            Frame::Pong { .. } => FrameTag::Pong,
+            Frame::Settings { .. } => FrameTag::Settings,
+            Frame::Session { .. } => FrameTag::Session,
+            Frame::WindowUpdate { .. } => FrameTag::WindowUpdate,
+            Frame::Priority { .. } => FrameTag::Priority,
+            Frame::Extension { .. } => FrameTag::Extension,
+        }
+    }
+}
+impl FrameTag {
+    /**
+     * Whether frames of this kind carry a TubeId (and so have a varint-id 
+     * form).
+     */
+    pub(in super) fn has_ids(&self) -> bool {
+        !matches!(
+            self, 
+            FrameTag::Drain 
+            | FrameTag::Ping 
+            | FrameTag::Pong 
+            | FrameTag::Settings 
+            | FrameTag::Session
+        )
+    }
+
+    /**
+     * The kind of Frame that a FrameType value decodes to, if any.
+     */
+    pub(in super) fn from_frametype(frame_type: u8) -> Option<Self> {
+        match frame_type {
+            CLIENT_HAS_FINISHED_SENDING_FRAMETYPE => Some(FrameTag::ClientHasFinishedSending),
+            DRAIN_FRAMETYPE => Some(FrameTag::Drain),
+            NEWTUBE_FRAMETYPE | NEWTUBE_BINARY_FRAMETYPE => Some(FrameTag::NewTube),
+            PAYLOAD_FRAMETYPE => Some(FrameTag::Payload),
+            PAYLOAD_ACK_FRAMETYPE => Some(FrameTag::PayloadAck),
+            SERVER_HAS_FINISHED_SENDING_FRAMETYPE => Some(FrameTag::ServerHasFinishedSending),
+            ABORT_FRAMETYPE | ABORT_WITH_CODE_FRAMETYPE => Some(FrameTag::Abort),
+            ABORTACK_FRAMETYPE => Some(FrameTag::AbortAck),
+            PAYLOAD_FRAGMENT_FRAMETYPE => Some(FrameTag::PayloadFragment),
+            COMPRESSED_PAYLOAD_FRAMETYPE => Some(FrameTag::CompressedPayload),
+            GOAWAY_FRAMETYPE => Some(FrameTag::GoAway),
+            PING_FRAMETYPE => Some(FrameTag::Ping),
+            PONG_FRAMETYPE => Some(FrameTag::Pong),
+            SETTINGS_FRAMETYPE => Some(FrameTag::Settings),
+            WINDOW_UPDATE_FRAMETYPE => Some(FrameTag::WindowUpdate),
+            PRIORITY_FRAMETYPE => Some(FrameTag::Priority),
+            PROCESSING_ACK_PAYLOAD_FRAMETYPE => Some(FrameTag::Payload),
+            PROCESSING_ACK_COMPRESSED_PAYLOAD_FRAMETYPE => Some(FrameTag::CompressedPayload),
+            CUMULATIVE_PAYLOAD_ACK_FRAMETYPE => Some(FrameTag::CumulativePayloadAck),
+            CLIENT_HAS_FINISHED_SENDING_WITH_TRAILERS_FRAMETYPE => 
+                Some(FrameTag::ClientHasFinishedSending),
+            SERVER_HAS_FINISHED_SENDING_WITH_TRAILERS_FRAMETYPE => 
+                Some(FrameTag::ServerHasFinishedSending),
+            NEWTUBE_ACCEPTED_FRAMETYPE => Some(FrameTag::NewTubeAccepted),
+            NEWTUBE_REJECTED_FRAMETYPE => Some(FrameTag::NewTubeRejected),
+            SESSION_FRAMETYPE => Some(FrameTag::Session),
+            PAYLOAD_SEQUENCE_FRAMETYPE => Some(FrameTag::PayloadSequence),
+            VARINT_IDS_EXTENSION_FRAMETYPE => Some(FrameTag::Extension),
+            frame_type if VARINT_IDS_FRAMETYPES.contains(&frame_type) => {
+                let tag = FrameTag::from_frametype(frame_type & !VARINT_IDS_FRAMETYPE_FLAG)?;
+                if tag.has_ids() { Some(tag) } else { None }
+            },
+            frame_type if APPLICATION_EXTENSION_FRAMETYPES.contains(&frame_type) => 
+                Some(FrameTag::Extension),
+            _ => None,
+        }
+    }
+}
+impl From<FrameTag> for u8 {
+    fn from(tag: FrameTag) -> Self {
+        match tag {
+            FrameTag::ClientHasFinishedSending => CLIENT_HAS_FINISHED_SENDING_FRAMETYPE,
+            FrameTag::Drain => DRAIN_FRAMETYPE,
+            FrameTag::NewTube => NEWTUBE_FRAMETYPE,
+            FrameTag::NewTubeAccepted => NEWTUBE_ACCEPTED_FRAMETYPE,
+            FrameTag::NewTubeRejected => NEWTUBE_REJECTED_FRAMETYPE,
+            FrameTag::Payload => PAYLOAD_FRAMETYPE,
+            FrameTag::PayloadAck => PAYLOAD_ACK_FRAMETYPE,
+            FrameTag::PayloadSequence => PAYLOAD_SEQUENCE_FRAMETYPE,
+            FrameTag::CumulativePayloadAck => CUMULATIVE_PAYLOAD_ACK_FRAMETYPE,
+            FrameTag::ServerHasFinishedSending => SERVER_HAS_FINISHED_SENDING_FRAMETYPE,
+            FrameTag::Abort => ABORT_FRAMETYPE,
+            FrameTag::AbortAck => ABORTACK_FRAMETYPE,
+            FrameTag::PayloadFragment => PAYLOAD_FRAGMENT_FRAMETYPE,
+            FrameTag::CompressedPayload => COMPRESSED_PAYLOAD_FRAMETYPE,
+            FrameTag::GoAway => GOAWAY_FRAMETYPE,
+            FrameTag::Ping => PING_FRAMETYPE,
+            FrameTag::Pong => PONG_FRAMETYPE,
+            FrameTag::Settings => SETTINGS_FRAMETYPE,
+            FrameTag::Session => SESSION_FRAMETYPE,
+            FrameTag::WindowUpdate => WINDOW_UPDATE_FRAMETYPE,
+            FrameTag::Priority => PRIORITY_FRAMETYPE,
+            FrameTag::Extension => *APPLICATION_EXTENSION_FRAMETYPES.start(),
+        }
+    }
 }
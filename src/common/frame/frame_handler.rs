@@ -2,7 +2,13 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::Mutex;
 
+use crate::common::ChannelConfig;
+use crate::common::Compression;
+use crate::common::DecompressError;
+use crate::common::FlowControl;
+use crate::common::NegotiatedSettings;
 use crate::common::PeerType;
+use crate::common::SendScheduler;
 use crate::common::tube;
 use crate::common::tube::TubeCompletionState;
 use crate::common::UniqueId;
@@ -13,52 +19,296 @@ use super::frame;
 pub enum FrameHandlerError {
     AbortAckFrameEncodingError(encode::FrameEncodeError),
     AbortAckTransmitError(hyper::Error),
-    DuplicateAbortFrame { tube_id: u16 },
-    DuplicateHasFinishedSendingFrame { tube_id: u16 },
+    CompressedPayloadOnUncompressedTube { tube_id: u64 },
+    DecompressError {
+        tube_id: u64,
+        error: DecompressError,
+    },
+    DuplicateAbortFrame { tube_id: u64 },
+    DuplicateHasFinishedSendingFrame { tube_id: u64 },
+    FlowControlWindowExceeded { tube_id: u64 },
     InappropriateHasFinishedSendingFrameFromPeer,
+    InappropriateNewTubeResponseFromPeer,
+    InappropriateSessionFrameFromPeer,
+    MaxConcurrentTubesRejectionEncodingError(encode::FrameEncodeError),
+    MaxConcurrentTubesRejectionTransmitError(hyper::Error),
+    MessageTooLarge {
+        tube_id: u64,
+        size: usize,
+    },
     PayloadAckFrameEncodingError(encode::FrameEncodeError),
     PayloadAckTransmitError(hyper::Error),
-    ReceivedHasFinishedSendingAfterRemoteAbort { tube_id: u16 },
+    PongFrameEncodingError(encode::FrameEncodeError),
+    PongTransmitError(hyper::Error),
+    ReceivedHasFinishedSendingAfterRemoteAbort { tube_id: u64 },
     ServerInitiatedTubesNotImplemented,
-    TubeManagerInsertionError { tube_id: u16 },
+    TubeManagerInsertionError { tube_id: u64 },
     UntrackedAckId {
-        tube_id: u16,
-        ack_id: u16,
+        tube_id: u64,
+        ack_id: u64,
     },
     UntrackedTubeId(frame::Frame),
 }
+impl FrameHandlerError {
+    /**
+     * Whether this error means the peer has violated the protocol in a way 
+     * that leaves the channel's state untrustworthy. The channel should send 
+     * a GoAway (with GoAwayErrorCode::from(&error)) and tear down every Tube 
+     * rather than carry on.
+     *
+     * Errors that only affect a single payload or Tube (or that can arise 
+     * from benign races, such as frames for a Tube that was just aborted) 
+     * are not fatal.
+     */
+    pub fn is_fatal(&self) -> bool {
+        use FrameHandlerError::*;
+        match self {
+            CompressedPayloadOnUncompressedTube { .. }
+            | FlowControlWindowExceeded { .. }
+            | InappropriateHasFinishedSendingFrameFromPeer
+            | InappropriateNewTubeResponseFromPeer
+            | InappropriateSessionFrameFromPeer => true,
+            AbortAckFrameEncodingError(_)
+            | AbortAckTransmitError(_)
+            | DecompressError { .. }
+            | DuplicateAbortFrame { .. }
+            | DuplicateHasFinishedSendingFrame { .. }
+            | MaxConcurrentTubesRejectionEncodingError(_)
+            | MaxConcurrentTubesRejectionTransmitError(_)
+            | MessageTooLarge { .. }
+            | PayloadAckFrameEncodingError(_)
+            | PayloadAckTransmitError(_)
+            | PongFrameEncodingError(_)
+            | PongTransmitError(_)
+            | ReceivedHasFinishedSendingAfterRemoteAbort { .. }
+            | ServerInitiatedTubesNotImplemented
+            | TubeManagerInsertionError { .. }
+            | UntrackedAckId { .. }
+            | UntrackedTubeId(_) => false,
+        }
+    }
+}
+impl From<&FrameHandlerError> for frame::GoAwayErrorCode {
+    fn from(err: &FrameHandlerError) -> Self {
+        use FrameHandlerError::*;
+        match err {
+            DuplicateAbortFrame { .. }
+            | DuplicateHasFinishedSendingFrame { .. }
+            | ReceivedHasFinishedSendingAfterRemoteAbort { .. }
+            | TubeManagerInsertionError { .. }
+            | UntrackedAckId { .. }
+            | UntrackedTubeId(_) => frame::GoAwayErrorCode::TubeStateError,
+            CompressedPayloadOnUncompressedTube { .. }
+            | DecompressError { error: crate::common::DecompressError::IoError(_), .. }
+            | FlowControlWindowExceeded { .. }
+            | InappropriateHasFinishedSendingFrameFromPeer
+            | InappropriateNewTubeResponseFromPeer
+            | InappropriateSessionFrameFromPeer
+            | ServerInitiatedTubesNotImplemented => frame::GoAwayErrorCode::ProtocolError,
+            DecompressError { error: crate::common::DecompressError::MessageTooLarge { .. }, .. }
+            | MessageTooLarge { .. } => frame::GoAwayErrorCode::LimitExceeded,
+            AbortAckFrameEncodingError(_)
+            | AbortAckTransmitError(_)
+            | MaxConcurrentTubesRejectionEncodingError(_)
+            | MaxConcurrentTubesRejectionTransmitError(_)
+            | PayloadAckFrameEncodingError(_)
+            | PayloadAckTransmitError(_)
+            | PongFrameEncodingError(_)
+            | PongTransmitError(_) => frame::GoAwayErrorCode::InternalError,
+        }
+    }
+}
 
 // TODO: When server-initiated tubes are implemented, can we generalize 
 //       server_ctx into channel_ctx, pass in channel_ctx from both server and 
 //       client code, and then handle NewTube event-publishing entirely here? If
 //       so we could eliminate FrameHandlerResult whose sole purpose is to host 
 //       FrameHandlerResult::NewTube...
+#[derive(Debug)]
 pub enum FrameHandlerResult {
+    Drain(frame::DrainReason),
     FullyHandled,
-    NewTube(tube::Tube),
+    GoAway {
+        last_tube_id: u64,
+        error_code: frame::GoAwayErrorCode,
+        message: String,
+    },
+    NewTube(tube::PendingTube),
+    Pong { opaque: u64 },
+    Session { token: u128 },
+    Settings(NegotiatedSettings),
+}
+
+/**
+ * Gives back the flow control credit consumed by a payload that is dropped 
+ * rather than emitted to the application, so the peer isn't left waiting on 
+ * credit that would otherwise never be returned.
+ */
+fn release_dropped_payload_credit(
+    tube_mgr: &mut tube::TubeManager,
+    tube_id: u64,
+    data_sender: &Arc<tokio::sync::Mutex<hyper::body::Sender>>,
+) {
+    let credit = tube_mgr.flow_control.as_mut()
+        .and_then(|flow_control| flow_control.drop_payload());
+    if let Some(credit) = credit {
+        crate::common::spawn_window_update(data_sender.clone(), tube_id, credit);
+    }
 }
 
+/**
+ * The code a NewTube that would exceed max_concurrent_tubes is rejected with 
+ * (429 Too Many Requests).
+ */
+pub const MAX_CONCURRENT_TUBES_REJECTION_CODE: u16 = 429;
+
 pub struct FrameHandler<'a> {
+    ack_batch_delay: std::time::Duration,
+    features: frame::Features,
+    initial_window_size: u32,
+    last_peer_tube_id: u64,
+    max_concurrent_tubes: u16,
+    max_message_size: usize,
+    peer_initial_window_size: Option<u32>,
+    peer_max_frame_size: Option<u32>,
     peer_type: PeerType,
-    tube_managers: &'a mut Arc<Mutex<HashMap<u16, Arc<Mutex<tube::TubeManager>>>>>,
+    /**
+     * The Features advertised by both peers, once the peer's Settings frame 
+     * has arrived.
+     */
+    negotiated_features: frame::Features,
+    send_scheduler: Arc<SendScheduler>,
+    tube_managers: &'a mut Arc<Mutex<HashMap<u64, Arc<Mutex<tube::TubeManager>>>>>,
 }
 impl<'a> FrameHandler<'a> {
     pub fn new(
         peer_type: PeerType,
-        tube_managers: &'a mut Arc<Mutex<HashMap<u16, Arc<Mutex<tube::TubeManager>>>>>,
+        tube_managers: &'a mut Arc<Mutex<HashMap<u64, Arc<Mutex<tube::TubeManager>>>>>,
+        config: &ChannelConfig,
+    ) -> Self {
+        Self::new_with_send_scheduler(
+            peer_type,
+            tube_managers,
+            config,
+            Arc::new(SendScheduler::new()),
+        )
+    }
+
+    /**
+     * Like new(), but Tubes created by the peer share `send_scheduler` with 
+     * the Tubes created locally on the same channel.
+     */
+    pub fn new_with_send_scheduler(
+        peer_type: PeerType,
+        tube_managers: &'a mut Arc<Mutex<HashMap<u64, Arc<Mutex<tube::TubeManager>>>>>,
+        config: &ChannelConfig,
+        send_scheduler: Arc<SendScheduler>,
     ) -> Self {
         FrameHandler {
+            ack_batch_delay: config.ack_batch_delay,
+            features: config.advertised_features(),
+            initial_window_size: config.initial_window_size,
+            last_peer_tube_id: 0,
+            max_concurrent_tubes: config.max_concurrent_tubes,
+            max_message_size: config.max_message_size,
+            peer_initial_window_size: None,
+            peer_max_frame_size: None,
             peer_type,
+            send_scheduler,
             tube_managers,
+            negotiated_features: frame::Features::empty(),
         }
     }
 
-    fn get_tube_mgr(&mut self, tube_id: &u16) -> Option<Arc<Mutex<tube::TubeManager>>> {
-        let tube_mgrs = self.tube_managers.lock().unwrap();
-        match tube_mgrs.get(tube_id) {
-            Some(tm) => Some(tm.clone()),
-            None => None,
+    fn get_tube_mgr(&mut self, tube_id: &u64) -> Option<Arc<Mutex<tube::TubeManager>>> {
+        let tube_mgr = self.tube_managers.lock().unwrap().get(tube_id)?.clone();
+        // Any frame from the peer on a Tube means it has seen the NewTube.
+        tube_mgr.lock().unwrap().replay_log
+            .retain(|entry| !matches!(entry, tube::ReplayEntry::NewTube(_)));
+        Some(tube_mgr)
+    }
+
+    /**
+     * Whether both peers advertised Features::SESSION_RESUMPTION, in which 
+     * case the peer may send frames again that already arrived over an 
+     * earlier transport.
+     */
+    fn resumable(&self) -> bool {
+        self.negotiated_features.contains(frame::Features::SESSION_RESUMPTION)
+    }
+
+    /**
+     * Turns away a NewTube that would exceed our max_concurrent_tubes. The 
+     * peer is sent a NewTubeRejected with MAX_CONCURRENT_TUBES_REJECTION_CODE
+     * if both peers advertised Features::NEWTUBE_HANDSHAKE, and an Abort 
+     * otherwise.
+     */
+    async fn reject_excess_tube(
+        &self,
+        tube_id: u64,
+        data_sender: &Arc<tokio::sync::Mutex<hyper::body::Sender>>,
+    ) -> Result<FrameHandlerResult, FrameHandlerError> {
+        let rejection_frame_data = 
+            if self.negotiated_features.contains(frame::Features::NEWTUBE_HANDSHAKE) {
+                encode::newtube_rejected_frame(
+                    tube_id,
+                    MAX_CONCURRENT_TUBES_REJECTION_CODE,
+                    &HashMap::new(),
+                )
+            } else {
+                encode::abort_frame(tube_id, frame::AbortReason::ProtocolViolation)
+            };
+        let rejection_frame_data = match rejection_frame_data {
+            Ok(data) => data,
+            Err(e) => return Err(
+                FrameHandlerError::MaxConcurrentTubesRejectionEncodingError(e)
+            ),
+        };
+        let mut sender = data_sender.lock().await;
+        if let Err(e) = sender.send_data(rejection_frame_data).await {
+            return Err(FrameHandlerError::MaxConcurrentTubesRejectionTransmitError(e));
         }
+        Ok(FrameHandlerResult::FullyHandled)
+    }
+
+    /**
+     * Called when the peer has violated the framing protocol (e.g. by sending
+     * a malformed frame). The stream of frames from the peer can no longer be
+     * trusted, so every Tube on the channel is terminated with an 
+     * Abort(reason) event and stops tracking state.
+     */
+    pub fn abort_all_tubes(&mut self, reason: frame::AbortReason) {
+        tube::abort_all_tube_managers(
+            self.tube_managers,
+            reason.clone(),
+            tube::TubeEvent::Abort(reason),
+        );
+    }
+
+    /**
+     * The id of the most recent Tube initiated by the peer. This is the 
+     * LastTubeId sent in a GoAway frame.
+     */
+    pub fn last_peer_tube_id(&self) -> u64 {
+        self.last_peer_tube_id
+    }
+
+    /**
+     * The largest frame the peer accepts, once its Settings frame has 
+     * arrived.
+     */
+    pub fn peer_max_frame_size(&self) -> Option<u32> {
+        self.peer_max_frame_size
+    }
+
+    /**
+     * Picks up where the FrameHandler for a channel's previous transport left
+     * off, so that a resumed channel's GoAway still names the last Tube the 
+     * peer initiated over any of its transports.
+     */
+    #[cfg(feature = "server")]
+    pub fn set_last_peer_tube_id(&mut self, tube_id: u64) {
+        self.last_peer_tube_id = tube_id;
     }
 
     pub async fn handle_frame(
@@ -67,13 +317,15 @@ impl<'a> FrameHandler<'a> {
         data_sender: &mut Arc<tokio::sync::Mutex<hyper::body::Sender>>,
     ) -> Result<FrameHandlerResult, FrameHandlerError> {
         match frame {
-            frame::Frame::ClientHasFinishedSending { tube_id } => {
+            frame::Frame::ClientHasFinishedSending { tube_id, ref trailers } => {
                 if let PeerType::Client = self.peer_type {
                     return Err(FrameHandlerError::InappropriateHasFinishedSendingFrameFromPeer);
                 }
 
                 let tube_mgr = match self.get_tube_mgr(&tube_id) {
                     Some(tm) => tm,
+                    // The Tube may have closed before the transport was lost.
+                    None if self.resumable() => return Ok(FrameHandlerResult::FullyHandled),
                     None => return Err(FrameHandlerError::UntrackedTubeId(frame)),
                 };
 
@@ -86,7 +338,9 @@ impl<'a> FrameHandler<'a> {
                                 ClientHasFinishedSending,
                             ServerHasFinishedSending => 
                                 Closed,
-                            ClientHasFinishedSending | Closed => 
+                            ClientHasFinishedSending | Closed if tube_mgr.resumable =>
+                                return Ok(FrameHandlerResult::FullyHandled),
+                            ClientHasFinishedSending | Closed =>
                                 return Err(FrameHandlerError::DuplicateHasFinishedSendingFrame {
                                     tube_id,
                                 }),
@@ -102,7 +356,11 @@ impl<'a> FrameHandler<'a> {
                     if tube_mgr.completion_state != new_state {
                         tube_mgr.completion_state = new_state.clone();
                         if tube_mgr.completion_state == tube::TubeCompletionState::ClientHasFinishedSending {
-                            tube_mgr.pending_events.push_back(tube::TubeEvent::ClientHasFinishedSending);
+                            tube_mgr.pending_events.push_back(
+                                tube::TubeEvent::ClientHasFinishedSending(
+                                    trailers.clone().unwrap_or_default()
+                                )
+                            );
                         }
                         if let Some(waker) = tube_mgr.waker.take() {
                           waker.wake();
@@ -117,22 +375,89 @@ impl<'a> FrameHandler<'a> {
                 }
             },
 
-            frame::Frame::Drain => {
-                // TODO
+            frame::Frame::Drain { reason } => {
+                tube::drain_all_tube_managers(self.tube_managers, reason);
+                return Ok(FrameHandlerResult::Drain(reason))
             },
 
-            // TODO: Handle NewTube headers
-            frame::Frame::NewTube { tube_id, headers: _ } => {
+            // TODO: Handle the rest of the NewTube headers
+            frame::Frame::NewTube { tube_id, ref headers } => {
                 if let PeerType::Client = self.peer_type {
                     return Err(FrameHandlerError::ServerInitiatedTubesNotImplemented);
                 }
 
-                let tube_mgr = Arc::new(Mutex::new(tube::TubeManager::new()));
-                if let Err(_) = self.tube_managers.lock().unwrap().try_insert(tube_id, tube_mgr.clone()) {
+                // A resumed client sends the NewTube again if it can't be sure
+                // that it arrived.
+                let is_redelivery = self.get_tube_mgr(&tube_id)
+                    .is_some_and(|tube_mgr| tube_mgr.lock().unwrap().resumable);
+                if is_redelivery {
+                    log::trace!("Ignoring a redelivered NewTube(tube_id={}).", tube_id);
+                    return Ok(FrameHandlerResult::FullyHandled);
+                }
+
+                let open_tubes = self.tube_managers.lock().unwrap().len();
+                if open_tubes >= usize::from(self.max_concurrent_tubes) {
+                    log::trace!(
+                        "Rejecting NewTube(tube_id={}) because {} Tubes are \
+                         already open.",
+                        tube_id,
+                        open_tubes,
+                    );
+                    return self.reject_excess_tube(tube_id, data_sender).await;
+                }
+
+                let mut tube_mgr = tube::TubeManager::new();
+                if self.features.contains(frame::Features::COMPRESSION) {
+                    // An algorithm we don't support goes unconfirmed, so the 
+                    // client never compresses with it.
+                    tube_mgr.compression = headers.get(crate::common::COMPRESSION_HEADER)
+                        .and_then(|value| Compression::from_header_value(value));
+                    tube_mgr.compression_confirmed = tube_mgr.compression.is_some();
+                }
+                if self.negotiated_features.contains(frame::Features::FLOW_CONTROL)
+                    && headers.contains_key(crate::common::FLOW_CONTROL_HEADER) {
+                    // The client only requests flow control once it has seen
+                    // our Settings, so the peer's Settings should have arrived
+                    // already.
+                    if let Some(peer_initial_window_size) = self.peer_initial_window_size {
+                        tube_mgr.flow_control = Some(FlowControl::new(
+                            peer_initial_window_size,
+                            self.initial_window_size,
+                        ));
+                    }
+                }
+                if let Some(value) = headers.get(crate::common::PRIORITY_HEADER) {
+                    if let Some(weight) = crate::common::weight_from_header_value(value) {
+                        tube_mgr.weight = weight;
+                    }
+                    tube_mgr.announces_priority = 
+                        self.features.contains(frame::Features::PRIORITY);
+                }
+                tube_mgr.abort_codes = 
+                    self.negotiated_features.contains(frame::Features::ABORT_CODES);
+                tube_mgr.payload_fragmentation = 
+                    self.negotiated_features.contains(frame::Features::PAYLOAD_FRAGMENTATION);
+                if let Some(peer_max_frame_size) = self.peer_max_frame_size {
+                    tube_mgr.peer_max_frame_size = 
+                        usize::try_from(peer_max_frame_size).unwrap_or(usize::MAX);
+                }
+                tube_mgr.payload_sequences = 
+                    self.negotiated_features.contains(frame::Features::PAYLOAD_SEQUENCES);
+                tube_mgr.processing_acks = 
+                    self.negotiated_features.contains(frame::Features::PROCESSING_ACKS);
+                tube_mgr.trailers = 
+                    self.negotiated_features.contains(frame::Features::TRAILERS);
+                tube_mgr.varint_ids = 
+                    self.negotiated_features.contains(frame::Features::VARINT_IDS);
+                tube_mgr.resumable = 
+                    self.negotiated_features.contains(frame::Features::SESSION_RESUMPTION);
+                let tube_mgr = Arc::new(Mutex::new(tube_mgr));
+                if self.tube_managers.lock().unwrap().try_insert(tube_id, tube_mgr.clone()).is_err() {
                     return Err(FrameHandlerError::TubeManagerInsertionError {
                         tube_id,
                     });
                 }
+                self.last_peer_tube_id = tube_id;
 
                 log::trace!("Emitting tube...");
                 let tube_id = UniqueId::new(tube_id, None);
@@ -140,7 +465,17 @@ impl<'a> FrameHandler<'a> {
                     self.peer_type,
                     tube_id,
                     data_sender.clone(),
+                    tube_mgr.clone(),
+                    self.send_scheduler.clone(),
+                    self.max_message_size,
+                );
+                let pending_tube = tube::PendingTube::new(
+                    tube,
+                    headers.clone(),
+                    self.negotiated_features.contains(frame::Features::NEWTUBE_HANDSHAKE),
+                    data_sender.clone(),
                     tube_mgr,
+                    self.tube_managers.clone(),
                 );
 
                 // TODO: When server-initiated tubes are implemented, can we 
@@ -149,7 +484,7 @@ impl<'a> FrameHandler<'a> {
                 //       handle NewTube event-publishing entirely here? If so 
                 //       we could eliminate FrameHandlerResult whose sole 
                 //       purpose is to host FrameHandlerResult::NewTube...
-                return Ok(FrameHandlerResult::NewTube(tube))
+                return Ok(FrameHandlerResult::NewTube(pending_tube))
                 /*
                 let mut server_ctx = server_ctx.lock().unwrap();
                 server_ctx.pending_events.push_back(ServerEvent::NewTube(tube));
@@ -159,41 +494,333 @@ impl<'a> FrameHandler<'a> {
                 */
             },
 
-            frame::Frame::Payload { tube_id, ack_id, ref data } => {
+            frame::Frame::NewTubeAccepted { tube_id, ref headers } => {
+                if let PeerType::Server = self.peer_type {
+                    return Err(FrameHandlerError::InappropriateNewTubeResponseFromPeer);
+                }
+
+                let tube_mgr = match self.get_tube_mgr(&tube_id) {
+                    Some(tm) => tm,
+                    None => return Err(FrameHandlerError::UntrackedTubeId(frame)),
+                };
+                let mut tube_mgr = tube_mgr.lock().unwrap();
+                let confirmed_compression = headers.get(crate::common::COMPRESSION_HEADER)
+                    .and_then(|value| Compression::from_header_value(value));
+                tube_mgr.compression_confirmed = 
+                    tube_mgr.compression.is_some() 
+                    && tube_mgr.compression == confirmed_compression;
+                if !tube_mgr.resolve_acceptance(tube::NewTubeResponse::Accepted(headers.clone())) {
+                    log::trace!(
+                        "Ignoring NewTubeAccepted for Tube(id={}) that isn't \
+                         awaiting acceptance.",
+                        tube_id,
+                    );
+                }
+            },
+
+            frame::Frame::NewTubeRejected { tube_id, code, ref headers } => {
+                if let PeerType::Server = self.peer_type {
+                    return Err(FrameHandlerError::InappropriateNewTubeResponseFromPeer);
+                }
+
+                let tube_mgr = match self.get_tube_mgr(&tube_id) {
+                    Some(tm) => tm,
+                    None => return Err(FrameHandlerError::UntrackedTubeId(frame)),
+                };
+                {
+                    let mut tube_mgr = tube_mgr.lock().unwrap();
+                    tube_mgr.completion_state = TubeCompletionState::AbortedFromRemote(
+                        frame::AbortReason::ApplicationAbort,
+                    );
+                    let response = tube::NewTubeResponse::Rejected {
+                        code,
+                        headers: headers.clone(),
+                    };
+                    if !tube_mgr.resolve_acceptance(response) {
+                        tube_mgr.pending_events.push_back(
+                            tube::TubeEvent::Rejected(code, headers.clone())
+                        );
+                        if let Some(waker) = tube_mgr.waker.take() {
+                            waker.wake();
+                        }
+                    }
+                    if let Some(flow_control) = &tube_mgr.flow_control {
+                        flow_control.wake_senders();
+                    }
+                }
+                self.tube_managers.lock().unwrap().remove(&tube_id);
+            },
+
+            frame::Frame::Payload { tube_id, ack_id, processing_ack, ref data } 
+            | frame::Frame::CompressedPayload { tube_id, ack_id, processing_ack, ref data } => {
+                let is_compressed = 
+                    matches!(frame, frame::Frame::CompressedPayload { .. });
                 let tube_mgr = match self.get_tube_mgr(&tube_id) {
                     Some(tm) => tm,
                     None => return Err(FrameHandlerError::UntrackedTubeId(frame)),
                 };
 
-                // If an ack was requested, send one...
-                if let Some(ack_id) = ack_id {
-                    let frame_data = match encode::payload_ack_frame(tube_id, ack_id) {
+                // If this Payload terminates a fragmented payload, reassemble 
+                // it with the data from the preceding PayloadFragment frames.
+                let (data, is_redelivery) = {
+                    let mut tube_mgr = tube_mgr.lock().unwrap();
+                    if let Some(flow_control) = &mut tube_mgr.flow_control {
+                        if !flow_control.receive(data.len()) {
+                            return Err(FrameHandlerError::FlowControlWindowExceeded {
+                                tube_id,
+                            });
+                        }
+                    }
+                    let sequence = tube_mgr.pending_payload_sequence.take();
+
+                    if tube_mgr.discarding_oversized_payload {
+                        log::trace!(
+                            "Dropping the final frame of an oversized payload \
+                             on Tube(id={}).",
+                            tube_id,
+                        );
+                        tube_mgr.discarding_oversized_payload = false;
+                        release_dropped_payload_credit(&mut tube_mgr, tube_id, data_sender);
+                        return Ok(FrameHandlerResult::FullyHandled);
+                    }
+
+                    let size = tube_mgr.partial_payload.len() + data.len();
+                    if size > self.max_message_size {
+                        tube_mgr.partial_payload.clear();
+                        release_dropped_payload_credit(&mut tube_mgr, tube_id, data_sender);
+                        return Err(FrameHandlerError::MessageTooLarge {
+                            tube_id,
+                            size,
+                        });
+                    }
+
+                    let data = if tube_mgr.partial_payload.is_empty() {
+                        data.clone()
+                    } else {
+                        tube_mgr.partial_payload.extend_from_slice(data);
+                        tube_mgr.partial_payload.split().freeze()
+                    };
+
+                    // A payload the peer sent again because its ack went 
+                    // missing is acked again, but only delivered once.
+                    let last_sequence = tube_mgr.last_payload_sequence;
+                    match sequence {
+                        Some(sequence) if last_sequence.is_some_and(|last| sequence <= last) =>
+                            (data, true),
+                        Some(sequence) => {
+                            tube_mgr.last_payload_sequence = Some(sequence);
+                            (data, false)
+                        },
+                        None => (data, false),
+                    }
+                };
+
+                let data = if is_compressed && !is_redelivery {
+                    let compression = tube_mgr.lock().unwrap().compression;
+                    let compression = match compression {
+                        Some(compression) => compression,
+                        None => {
+                            release_dropped_payload_credit(
+                                &mut tube_mgr.lock().unwrap(),
+                                tube_id,
+                                data_sender,
+                            );
+                            return Err(
+                                FrameHandlerError::CompressedPayloadOnUncompressedTube {
+                                    tube_id,
+                                }
+                            );
+                        },
+                    };
+                    match compression.decompress(&data, self.max_message_size) {
                         Ok(data) => data,
-                        Err(e) => return Err(FrameHandlerError::PayloadAckFrameEncodingError(e)),
+                        Err(error) => {
+                            release_dropped_payload_credit(
+                                &mut tube_mgr.lock().unwrap(),
+                                tube_id,
+                                data_sender,
+                            );
+                            return Err(FrameHandlerError::DecompressError {
+                                tube_id,
+                                error,
+                            });
+                        },
+                    }
+                } else {
+                    data
+                };
+
+                // If a processing ack was requested, leave it to the 
+                // application to send one...
+                let processing_ack = match ack_id {
+                    Some(ack_id) if processing_ack => Some(tube::ProcessingAck::new(
+                        tube_id,
+                        ack_id,
+                        data_sender.clone(),
+                        &tube_mgr,
+                    )),
+                    _ => None,
+                };
+
+                // ...otherwise if an ack was requested, send one now (or batch
+                // it up with the acks for the payloads that follow it).
+                if let (Some(ack_id), None) = (ack_id, &processing_ack) {
+                    let batches_acks = 
+                        !self.ack_batch_delay.is_zero()
+                        && self.negotiated_features.contains(frame::Features::CUMULATIVE_ACKS);
+                    let ack_ids = 
+                        if batches_acks {
+                            let mut tube_mgr_guard = tube_mgr.lock().unwrap();
+                            tube_mgr_guard.pending_acks.push(ack_id);
+                            if tube_mgr_guard.pending_acks.len() == 1 {
+                                crate::common::spawn_ack_batch_flush(
+                                    data_sender.clone(),
+                                    tube_mgr.clone(),
+                                    tube_id,
+                                    self.ack_batch_delay,
+                                );
+                            }
+                            if tube_mgr_guard.pending_acks.len() >= crate::common::MAX_BATCHED_ACK_IDS {
+                                std::mem::take(&mut tube_mgr_guard.pending_acks)
+                            } else {
+                                vec![]
+                            }
+                        } else {
+                            vec![ack_id]
+                        };
+
+                    if !ack_ids.is_empty() {
+                        let frame_data = match crate::common::ack_batch_frame(tube_id, ack_ids) {
+                            Ok(data) => data,
+                            Err(e) => return Err(FrameHandlerError::PayloadAckFrameEncodingError(e)),
+                        };
+                        let mut sender = data_sender.lock().await;
+                        match sender.send_data(frame_data).await {
+                            Ok(_) => (),
+                            Err(e) => return Err(FrameHandlerError::PayloadAckTransmitError(e)),
+                        }
+                    }
+                }
+
+                // A redelivered payload whose processing ack was lost is acked 
+                // again, as long as the application got around to acking it.
+                if let (Some(ack_id), Some(_)) = (ack_id, &processing_ack) {
+                    let was_processed = {
+                        let mut tube_mgr = tube_mgr.lock().unwrap();
+                        if is_redelivery {
+                            tube_mgr.processed_ack_ids.contains(&ack_id)
+                        } else {
+                            tube_mgr.processed_ack_ids.remove(&ack_id);
+                            false
+                        }
                     };
-                    let mut sender = data_sender.lock().await;
-                    match sender.send_data(frame_data.into()).await {
-                        Ok(_) => (),
-                        Err(e) => return Err(FrameHandlerError::PayloadAckTransmitError(e)),
+                    if was_processed {
+                        let frame_data = match encode::payload_ack_frame(tube_id, ack_id, true) {
+                            Ok(data) => data,
+                            Err(e) => return Err(FrameHandlerError::PayloadAckFrameEncodingError(e)),
+                        };
+                        let mut sender = data_sender.lock().await;
+                        if let Err(e) = sender.send_data(frame_data).await {
+                            return Err(FrameHandlerError::PayloadAckTransmitError(e));
+                        }
                     }
                 }
 
                 let mut tube_mgr = tube_mgr.lock().unwrap();
-                tube_mgr.pending_events.push_back(tube::TubeEvent::Payload(data.to_vec()));
+                if is_redelivery {
+                    log::trace!("Dropping a redelivered payload on Tube(id={}).", tube_id);
+                    release_dropped_payload_credit(&mut tube_mgr, tube_id, data_sender);
+                    return Ok(FrameHandlerResult::FullyHandled);
+                }
+                if let Some(flow_control) = &mut tube_mgr.flow_control {
+                    flow_control.finish_payload();
+                }
+                tube_mgr.pending_events.push_back(match processing_ack {
+                    Some(processing_ack) => 
+                        tube::TubeEvent::PayloadAwaitingAck(data, processing_ack),
+                    None => tube::TubeEvent::Payload(data),
+                });
                 if let Some(waker) = tube_mgr.waker.take() {
                     waker.wake();
                 }
             },
 
-            frame::Frame::PayloadAck { tube_id, ack_id } => {
+            frame::Frame::Extension { tube_id, ext_type, ref data } => {
                 let tube_mgr = match self.get_tube_mgr(&tube_id) {
                     Some(tm) => tm,
                     None => return Err(FrameHandlerError::UntrackedTubeId(frame)),
                 };
 
                 let mut tube_mgr = tube_mgr.lock().unwrap();
-                match tube_mgr.sendacks.get_mut(&ack_id) {
+                tube_mgr.pending_events.push_back(
+                    tube::TubeEvent::Extension(ext_type, data.clone())
+                );
+                if let Some(waker) = tube_mgr.waker.take() {
+                    waker.wake();
+                }
+            },
+
+            frame::Frame::PayloadFragment { tube_id, ref data } => {
+                let tube_mgr = match self.get_tube_mgr(&tube_id) {
+                    Some(tm) => tm,
+                    None => return Err(FrameHandlerError::UntrackedTubeId(frame)),
+                };
+
+                let mut tube_mgr = tube_mgr.lock().unwrap();
+                if let Some(flow_control) = &mut tube_mgr.flow_control {
+                    if !flow_control.receive(data.len()) {
+                        return Err(FrameHandlerError::FlowControlWindowExceeded {
+                            tube_id,
+                        });
+                    }
+                }
+
+                if tube_mgr.discarding_oversized_payload {
+                    release_dropped_payload_credit(&mut tube_mgr, tube_id, data_sender);
+                    return Ok(FrameHandlerResult::FullyHandled);
+                }
+
+                let size = tube_mgr.partial_payload.len() + data.len();
+                if size > self.max_message_size {
+                    tube_mgr.partial_payload.clear();
+                    tube_mgr.discarding_oversized_payload = true;
+                    release_dropped_payload_credit(&mut tube_mgr, tube_id, data_sender);
+                    return Err(FrameHandlerError::MessageTooLarge {
+                        tube_id,
+                        size,
+                    });
+                }
+                tube_mgr.partial_payload.extend_from_slice(data);
+                let credit = tube_mgr.flow_control.as_mut()
+                    .and_then(|flow_control| flow_control.buffer_fragment());
+                if let Some(credit) = credit {
+                    crate::common::spawn_window_update(data_sender.clone(), tube_id, credit);
+                }
+            },
+
+            frame::Frame::PayloadAck { tube_id, ack_id, processed } => {
+                let tube_mgr = match self.get_tube_mgr(&tube_id) {
+                    Some(tm) => tm,
+                    None => return Err(FrameHandlerError::UntrackedTubeId(frame)),
+                };
+
+                let mut tube_mgr = tube_mgr.lock().unwrap();
+                let was_settled = tube_mgr.recently_settled_ack_ids.contains(&ack_id);
+                // Payloads sent with Tube::send_and_forget() on a resumable 
+                // Tube are only tracked by the replay log.
+                let was_retained = tube_mgr.settle_payload(ack_id);
+                let sendacks = 
+                    if processed {
+                        &mut tube_mgr.processing_sendacks
+                    } else {
+                        &mut tube_mgr.sendacks
+                    };
+                match sendacks.get_mut(&ack_id) {
                     Some(res) => res.resolve(()),
+                    None if was_retained => (),
+                    // A payload that was sent more than once may be acked 
+                    // more than once, after its send() has already returned.
+                    None if was_settled => (),
                     None => return Err(FrameHandlerError::UntrackedAckId {
                         tube_id,
                         ack_id
@@ -201,13 +828,54 @@ impl<'a> FrameHandler<'a> {
                 };
             },
 
-            frame::Frame::ServerHasFinishedSending { tube_id } => {
+            frame::Frame::PayloadSequence { tube_id, sequence } => {
+                let tube_mgr = match self.get_tube_mgr(&tube_id) {
+                    Some(tm) => tm,
+                    None => return Err(FrameHandlerError::UntrackedTubeId(frame)),
+                };
+                tube_mgr.lock().unwrap().pending_payload_sequence = Some(sequence);
+            },
+
+            frame::Frame::CumulativePayloadAck { tube_id, up_to, ref ranges } => {
+                let tube_mgr = match self.get_tube_mgr(&tube_id) {
+                    Some(tm) => tm,
+                    None => return Err(FrameHandlerError::UntrackedTubeId(frame)),
+                };
+
+                // The frame may cover far more ack ids than are outstanding, 
+                // so check each outstanding ack id against it rather than the 
+                // other way around. Ack ids that aren't outstanding (e.g. 
+                // because their send() already timed out) are ignored.
+                let is_acked = |ack_id: &u64| 
+                    up_to.is_some_and(|up_to| *ack_id <= up_to)
+                    || ranges.iter().any(|range| range.contains(ack_id));
+                let mut tube_mgr = tube_mgr.lock().unwrap();
+                for (ack_id, res) in tube_mgr.sendacks.iter_mut() {
+                    if is_acked(ack_id) {
+                        res.resolve(());
+                    }
+                }
+                let settled_ack_ids: Vec<u64> = tube_mgr.replay_log.iter()
+                    .filter_map(|entry| match entry {
+                        tube::ReplayEntry::Payload { ack_id, .. } if is_acked(ack_id) =>
+                            Some(*ack_id),
+                        _ => None,
+                    })
+                    .collect();
+                for ack_id in settled_ack_ids {
+                    tube_mgr.settle_payload(ack_id);
+                }
+            },
+
+            frame::Frame::ServerHasFinishedSending { tube_id, ref trailers } => {
                 if let PeerType::Server = self.peer_type {
                     return Err(FrameHandlerError::InappropriateHasFinishedSendingFrameFromPeer);
                 }
 
                 let tube_mgr = match self.get_tube_mgr(&tube_id) {
                     Some(tm) => tm,
+                    // The Tube may have closed before the transport was lost.
+                    None if self.resumable() => return Ok(FrameHandlerResult::FullyHandled),
                     None => return Err(FrameHandlerError::UntrackedTubeId(frame)),
                 };
 
@@ -220,6 +888,8 @@ impl<'a> FrameHandler<'a> {
                                 ServerHasFinishedSending,
                             ClientHasFinishedSending => 
                                 Closed,
+                            ServerHasFinishedSending | Closed if tube_mgr.resumable =>
+                                return Ok(FrameHandlerResult::FullyHandled),
                             ServerHasFinishedSending | Closed =>
                                 return Err(FrameHandlerError::DuplicateHasFinishedSendingFrame {
                                     tube_id,
@@ -236,7 +906,11 @@ impl<'a> FrameHandler<'a> {
                     if tube_mgr.completion_state != new_state {
                         tube_mgr.completion_state = new_state.clone();
                         if tube_mgr.completion_state == tube::TubeCompletionState::ServerHasFinishedSending {
-                            tube_mgr.pending_events.push_back(tube::TubeEvent::ServerHasFinishedSending);
+                            tube_mgr.pending_events.push_back(
+                                tube::TubeEvent::ServerHasFinishedSending(
+                                    trailers.clone().unwrap_or_default()
+                                )
+                            );
                         }
                         if let Some(waker) = tube_mgr.waker.take() {
                             waker.wake();
@@ -252,14 +926,19 @@ impl<'a> FrameHandler<'a> {
             },
 
             frame::Frame::Abort { tube_id, ref reason } => {
+                // A resumed peer sends its Abort again until it is acked, even
+                // if the Tube was already aborted (or never opened) here.
                 let tube_mgr = match self.get_tube_mgr(&tube_id) {
-                    Some(tm) => tm,
+                    Some(tm) => Some(tm),
+                    None if self.resumable() => None,
                     None => return Err(FrameHandlerError::UntrackedTubeId(frame)),
                 };
 
-                {
+                if let Some(tube_mgr) = tube_mgr {
                     let mut tube_mgr = tube_mgr.lock().unwrap();
                     match tube_mgr.completion_state {
+                        TubeCompletionState::AbortedFromRemote(_) if tube_mgr.resumable => (),
+
                         TubeCompletionState::AbortedFromRemote(_) =>
                             return Err(FrameHandlerError::DuplicateAbortFrame {
                                 tube_id,
@@ -270,10 +949,16 @@ impl<'a> FrameHandler<'a> {
                         _ => {
                             tube_mgr.completion_state = 
                                 TubeCompletionState::AbortedFromLocal(reason.clone());
+                            tube_mgr.resolve_acceptance(
+                                tube::NewTubeResponse::Aborted(reason.clone())
+                            );
                             tube_mgr.pending_events.push_back(tube::TubeEvent::Abort(reason.clone()));
                             if let Some(waker) = tube_mgr.waker.take() {
                                 waker.wake();
                             }
+                            if let Some(flow_control) = &tube_mgr.flow_control {
+                                flow_control.wake_senders();
+                            }
                         },
                     }
                 };
@@ -288,11 +973,96 @@ impl<'a> FrameHandler<'a> {
                 };
                 let mut sender = data_sender.lock().await;
                 log::trace!("Sending AbortAck(tube_id={})...", tube_id);
-                if let Err(e) = sender.send_data(abortack_frame_data.into()).await {
+                if let Err(e) = sender.send_data(abortack_frame_data).await {
                     return Err(FrameHandlerError::AbortAckTransmitError(e));
                 }
             },
 
+            frame::Frame::WindowUpdate { tube_id, credit } => {
+                // A WindowUpdate may cross paths with an Abort, so updates for
+                // Tubes that are no longer tracked are ignored.
+                if let Some(tube_mgr) = self.get_tube_mgr(&tube_id) {
+                    let mut tube_mgr = tube_mgr.lock().unwrap();
+                    if let Some(flow_control) = &mut tube_mgr.flow_control {
+                        flow_control.add_send_credit(credit);
+                    }
+                }
+            },
+
+            frame::Frame::Priority { tube_id, weight } => {
+                // Like a WindowUpdate, a Priority frame may cross paths with 
+                // an Abort.
+                if let Some(tube_mgr) = self.get_tube_mgr(&tube_id) {
+                    tube_mgr.lock().unwrap().weight = weight.max(1);
+                }
+            },
+
+            frame::Frame::GoAway { last_tube_id, error_code, message } => {
+                log::trace!(
+                    "Peer is going away (last_tube_id={}, error_code={:?}): {}",
+                    last_tube_id,
+                    error_code,
+                    message,
+                );
+                tube::abort_all_tube_managers(
+                    self.tube_managers,
+                    frame::AbortReason::ChannelGoingAway,
+                    tube::TubeEvent::GoAway(error_code, message.clone()),
+                );
+                return Ok(FrameHandlerResult::GoAway {
+                    last_tube_id,
+                    error_code,
+                    message,
+                });
+            },
+
+            frame::Frame::Ping { opaque } => {
+                let pong_frame_data = match encode::pong_frame(opaque) {
+                    Ok(data) => data,
+                    Err(e) => return Err(
+                        FrameHandlerError::PongFrameEncodingError(e)
+                    ),
+                };
+                let mut sender = data_sender.lock().await;
+                log::trace!("Sending Pong(opaque={})...", opaque);
+                if let Err(e) = sender.send_data(pong_frame_data).await {
+                    return Err(FrameHandlerError::PongTransmitError(e));
+                }
+            },
+
+            frame::Frame::Pong { opaque } => {
+                return Ok(FrameHandlerResult::Pong { opaque });
+            },
+
+            frame::Frame::Settings {
+                version, 
+                features, 
+                max_frame_size, 
+                max_concurrent_tubes,
+                initial_window_size,
+            } => {
+                self.peer_initial_window_size = Some(initial_window_size);
+                self.peer_max_frame_size = Some(max_frame_size);
+                self.negotiated_features = self.features.negotiate(features);
+                let settings = NegotiatedSettings::new(
+                    self.features,
+                    version,
+                    features,
+                    max_frame_size,
+                    max_concurrent_tubes,
+                    initial_window_size,
+                );
+                log::trace!("Negotiated settings with peer: {:?}", settings);
+                return Ok(FrameHandlerResult::Settings(settings));
+            },
+
+            frame::Frame::Session { token } => {
+                if let PeerType::Server = self.peer_type {
+                    return Err(FrameHandlerError::InappropriateSessionFrameFromPeer);
+                }
+                return Ok(FrameHandlerResult::Session { token });
+            },
+
             frame::Frame::AbortAck { tube_id } => {
                 // It is now safe to re-use tube_id for a future new tube!
                 let tube_mgr = match self.get_tube_mgr(&tube_id) {
@@ -301,10 +1071,839 @@ impl<'a> FrameHandler<'a> {
                 };
                 let mut tube_mgr = tube_mgr.lock().unwrap();
                 log::trace!("Removing Tube(id={}) from list of pending Aborts.", &tube_id);
-                tube_mgr.abort_pending_id_reservation = None
+                tube_mgr.abort_pending_id_reservation = None;
+                tube_mgr.replay_log.clear();
             },
         };
 
         Ok(FrameHandlerResult::FullyHandled)
     }
 }
+
+#[cfg(test)]
+mod frame_handler_tests {
+    use super::*;
+
+    #[test]
+    fn abort_all_tubes_emits_abort_and_untracks_every_open_tube() {
+        let open_tube_mgr = Arc::new(Mutex::new(tube::TubeManager::new()));
+        let closed_tube_mgr = Arc::new(Mutex::new(tube::TubeManager::new()));
+        closed_tube_mgr.lock().unwrap().completion_state = TubeCompletionState::Closed;
+        let mut tube_mgrs = Arc::new(Mutex::new(HashMap::from([
+            (1, open_tube_mgr.clone()),
+            (3, closed_tube_mgr.clone()),
+        ])));
+
+        let mut frame_handler = FrameHandler::new(
+            PeerType::Server,
+            &mut tube_mgrs,
+            &ChannelConfig::default(),
+        );
+        frame_handler.abort_all_tubes(frame::AbortReason::ProtocolViolation);
+        assert!(tube_mgrs.lock().unwrap().is_empty());
+
+        let open_tube_mgr = open_tube_mgr.lock().unwrap();
+        assert_eq!(
+            open_tube_mgr.completion_state, 
+            TubeCompletionState::AbortedFromRemote(frame::AbortReason::ProtocolViolation),
+        );
+        assert_eq!(
+            open_tube_mgr.pending_events.front(),
+            Some(&tube::TubeEvent::Abort(frame::AbortReason::ProtocolViolation)),
+        );
+
+        let closed_tube_mgr = closed_tube_mgr.lock().unwrap();
+        assert_eq!(closed_tube_mgr.completion_state, TubeCompletionState::Closed);
+        assert!(closed_tube_mgr.pending_events.is_empty());
+    }
+
+    #[tokio::test]
+    async fn goaway_aborts_every_open_tube_and_is_returned_to_caller() {
+        let tube_mgr = Arc::new(Mutex::new(tube::TubeManager::new()));
+        let mut tube_mgrs = Arc::new(Mutex::new(HashMap::from([
+            (2, tube_mgr.clone()),
+        ])));
+        let (body_sender, _body) = hyper::Body::channel();
+        let mut body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+
+        let mut frame_handler = FrameHandler::new(
+            PeerType::Client,
+            &mut tube_mgrs,
+            &ChannelConfig::default(),
+        );
+        let result = frame_handler.handle_frame(
+            frame::Frame::GoAway {
+                last_tube_id: 3,
+                error_code: frame::GoAwayErrorCode::InternalError,
+                message: "shutting down".to_string(),
+            },
+            &mut body_sender,
+        ).await;
+        match result {
+            Ok(FrameHandlerResult::GoAway { last_tube_id: 3, error_code, message }) => {
+                assert_eq!(error_code, frame::GoAwayErrorCode::InternalError);
+                assert_eq!(message, "shutting down");
+            },
+            unexpected => panic!("Unexpected FrameHandler result: {:?}", unexpected),
+        }
+        assert!(tube_mgrs.lock().unwrap().is_empty());
+
+        let tube_mgr = tube_mgr.lock().unwrap();
+        assert_eq!(
+            tube_mgr.completion_state,
+            TubeCompletionState::AbortedFromRemote(frame::AbortReason::ChannelGoingAway),
+        );
+        assert_eq!(
+            tube_mgr.pending_events.front(),
+            Some(&tube::TubeEvent::GoAway(
+                frame::GoAwayErrorCode::InternalError,
+                "shutting down".to_string(),
+            )),
+        );
+    }
+
+    #[tokio::test]
+    async fn newtube_beyond_max_concurrent_tubes_is_rejected() {
+        use hyper::body::HttpBody;
+
+        let mut tube_mgrs = Arc::new(Mutex::new(HashMap::new()));
+        let (body_sender, mut body) = hyper::Body::channel();
+        let mut body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+        let config = ChannelConfig {
+            max_concurrent_tubes: 1,
+            ..ChannelConfig::default()
+        };
+
+        let mut frame_handler = FrameHandler::new(PeerType::Server, &mut tube_mgrs, &config);
+        frame_handler.handle_frame(
+            frame::Frame::Settings {
+                version: frame::PROTOCOL_VERSION,
+                features: frame::Features::supported(),
+                max_frame_size: u32::MAX,
+                max_concurrent_tubes: u16::MAX,
+                initial_window_size: 1024,
+            },
+            &mut body_sender,
+        ).await.unwrap();
+
+        let _pending_tube = match frame_handler.handle_frame(
+            frame::Frame::NewTube { tube_id: 1, headers: HashMap::new() },
+            &mut body_sender,
+        ).await {
+            Ok(FrameHandlerResult::NewTube(pending_tube)) => pending_tube,
+            unexpected => panic!("Unexpected FrameHandler result: {:?}", unexpected),
+        };
+        match frame_handler.handle_frame(
+            frame::Frame::NewTube { tube_id: 3, headers: HashMap::new() },
+            &mut body_sender,
+        ).await {
+            Ok(FrameHandlerResult::FullyHandled) => (),
+            unexpected => panic!("Unexpected FrameHandler result: {:?}", unexpected),
+        }
+
+        let mut decoder = crate::common::frame::Decoder::new();
+        let sent_frames = decoder.decode(body.data().await.unwrap().unwrap()).unwrap();
+        assert_eq!(Vec::from(sent_frames), vec![
+            frame::Frame::NewTubeRejected {
+                tube_id: 3,
+                code: MAX_CONCURRENT_TUBES_REJECTION_CODE,
+                headers: HashMap::new(),
+            },
+        ]);
+        assert_eq!(frame_handler.last_peer_tube_id(), 1);
+        assert_eq!(tube_mgrs.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn compressed_payload_is_decompressed_before_emitting() {
+        let mut tube_mgr = tube::TubeManager::new();
+        tube_mgr.compression = Some(Compression::Zstd);
+        let tube_mgr = Arc::new(Mutex::new(tube_mgr));
+        let mut tube_mgrs = Arc::new(Mutex::new(HashMap::from([
+            (1, tube_mgr.clone()),
+        ])));
+        let (body_sender, _body) = hyper::Body::channel();
+        let mut body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+
+        let mut frame_handler = FrameHandler::new(
+            PeerType::Server,
+            &mut tube_mgrs,
+            &ChannelConfig::default(),
+        );
+        let data = "compressible ".repeat(100);
+        let compressed_data = Compression::Zstd.compress(data.as_bytes()).unwrap();
+        frame_handler.handle_frame(
+            frame::Frame::CompressedPayload {
+                tube_id: 1,
+                ack_id: None,
+                processing_ack: false,
+                data: compressed_data.into(),
+            },
+            &mut body_sender,
+        ).await.unwrap();
+
+        assert_eq!(
+            tube_mgr.lock().unwrap().pending_events.front(),
+            Some(&tube::TubeEvent::Payload(data.into())),
+        );
+    }
+
+    #[tokio::test]
+    async fn payload_beyond_flow_control_window_errors() {
+        let mut tube_mgr = tube::TubeManager::new();
+        tube_mgr.flow_control = Some(FlowControl::new(0, 8));
+        let tube_mgr = Arc::new(Mutex::new(tube_mgr));
+        let mut tube_mgrs = Arc::new(Mutex::new(HashMap::from([
+            (1, tube_mgr.clone()),
+        ])));
+        let (body_sender, _body) = hyper::Body::channel();
+        let mut body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+
+        let mut frame_handler = FrameHandler::new(
+            PeerType::Server,
+            &mut tube_mgrs,
+            &ChannelConfig::default(),
+        );
+        // The first payload is queued for the application but never consumed.
+        frame_handler.handle_frame(
+            frame::Frame::Payload { 
+                tube_id: 1, 
+                ack_id: None, 
+                processing_ack: false, 
+                data: "12345".into(),
+            },
+            &mut body_sender,
+        ).await.unwrap();
+        let result = frame_handler.handle_frame(
+            frame::Frame::Payload { 
+                tube_id: 1, 
+                ack_id: None, 
+                processing_ack: false, 
+                data: "6789".into(),
+            },
+            &mut body_sender,
+        ).await;
+        match result {
+            Err(e @ FrameHandlerError::FlowControlWindowExceeded { tube_id: 1 }) => {
+                assert!(e.is_fatal());
+                assert_eq!(
+                    frame::GoAwayErrorCode::from(&e), 
+                    frame::GoAwayErrorCode::ProtocolError,
+                );
+            },
+            unexpected => panic!("Unexpected FrameHandler result: {:?}", unexpected),
+        }
+
+        frame_handler.handle_frame(
+            frame::Frame::WindowUpdate { tube_id: 1, credit: 3 },
+            &mut body_sender,
+        ).await.unwrap();
+        let mut tube_mgr = tube_mgr.lock().unwrap();
+        let flow_control = tube_mgr.flow_control.as_mut().unwrap();
+        assert_eq!(flow_control.take_send_credit(10), 3);
+    }
+
+    #[tokio::test]
+    async fn priority_frame_changes_tube_weight() {
+        let mut tube_mgrs = Arc::new(Mutex::new(HashMap::new()));
+        let (body_sender, _body) = hyper::Body::channel();
+        let mut body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+
+        let mut frame_handler = FrameHandler::new(
+            PeerType::Server,
+            &mut tube_mgrs,
+            &ChannelConfig::default(),
+        );
+        let result = frame_handler.handle_frame(
+            frame::Frame::NewTube {
+                tube_id: 1,
+                headers: HashMap::from([
+                    (crate::common::PRIORITY_HEADER.to_string(), "200".to_string()),
+                ]),
+            },
+            &mut body_sender,
+        ).await;
+        let tube = match result {
+            Ok(FrameHandlerResult::NewTube(pending_tube)) => 
+                pending_tube.accept(HashMap::new()).await.unwrap(),
+            unexpected => panic!("Unexpected FrameHandler result: {:?}", unexpected),
+        };
+        assert_eq!(tube.priority(), 200);
+
+        frame_handler.handle_frame(
+            frame::Frame::Priority { tube_id: 1, weight: 3 },
+            &mut body_sender,
+        ).await.unwrap();
+        assert_eq!(tube.priority(), 3);
+    }
+
+    #[tokio::test]
+    async fn processing_ack_is_only_sent_once_the_application_acks() {
+        use hyper::body::HttpBody;
+
+        let tube_mgr = Arc::new(Mutex::new(tube::TubeManager::new()));
+        let mut tube_mgrs = Arc::new(Mutex::new(HashMap::from([
+            (1, tube_mgr.clone()),
+        ])));
+        let (body_sender, mut body) = hyper::Body::channel();
+        let mut body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+
+        let mut frame_handler = FrameHandler::new(
+            PeerType::Server,
+            &mut tube_mgrs,
+            &ChannelConfig::default(),
+        );
+        frame_handler.handle_frame(
+            frame::Frame::Payload {
+                tube_id: 1,
+                ack_id: Some(7),
+                processing_ack: true,
+                data: "hello".into(),
+            },
+            &mut body_sender,
+        ).await.unwrap();
+
+        let event = tube_mgr.lock().unwrap().pending_events.pop_front();
+        let processing_ack = match event {
+            Some(tube::TubeEvent::PayloadAwaitingAck(data, processing_ack)) => {
+                assert_eq!(data, "hello");
+                processing_ack
+            },
+            unexpected => panic!("Unexpected TubeEvent: {:?}", unexpected),
+        };
+
+        processing_ack.ack().await.unwrap();
+        let mut decoder = crate::common::frame::Decoder::new();
+        let sent_frames = decoder.decode(body.data().await.unwrap().unwrap()).unwrap();
+        assert_eq!(Vec::from(sent_frames), vec![
+            frame::Frame::PayloadAck { tube_id: 1, ack_id: 7, processed: true },
+        ]);
+    }
+
+    #[tokio::test]
+    async fn acks_are_batched_into_a_cumulative_ack() {
+        use hyper::body::HttpBody;
+
+        let tube_mgr = Arc::new(Mutex::new(tube::TubeManager::new()));
+        let mut tube_mgrs = Arc::new(Mutex::new(HashMap::from([
+            (1, tube_mgr.clone()),
+        ])));
+        let (body_sender, mut body) = hyper::Body::channel();
+        let mut body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+
+        let mut frame_handler = FrameHandler::new(
+            PeerType::Server,
+            &mut tube_mgrs,
+            &ChannelConfig::default(),
+        );
+        frame_handler.handle_frame(
+            frame::Frame::Settings {
+                version: frame::PROTOCOL_VERSION,
+                features: frame::Features::supported(),
+                max_frame_size: u32::MAX,
+                max_concurrent_tubes: u16::MAX,
+                initial_window_size: 0,
+            },
+            &mut body_sender,
+        ).await.unwrap();
+        for ack_id in [1, 0, 3] {
+            frame_handler.handle_frame(
+                frame::Frame::Payload {
+                    tube_id: 1,
+                    ack_id: Some(ack_id),
+                    processing_ack: false,
+                    data: "hello".into(),
+                },
+                &mut body_sender,
+            ).await.unwrap();
+        }
+
+        let mut decoder = crate::common::frame::Decoder::new();
+        let sent_frames = decoder.decode(body.data().await.unwrap().unwrap()).unwrap();
+        assert_eq!(Vec::from(sent_frames), vec![
+            frame::Frame::CumulativePayloadAck { 
+                tube_id: 1, 
+                up_to: Some(1), 
+                ranges: vec![3..=3],
+            },
+        ]);
+        assert_eq!(tube_mgr.lock().unwrap().pending_events.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn cumulative_ack_resolves_every_covered_sendack() {
+        use futures::FutureExt;
+
+        let tube_mgr = Arc::new(Mutex::new(tube::TubeManager::new()));
+        let mut sendack_futures = vec![];
+        for ack_id in 0..6 {
+            let (future, resolver) = crate::common::InvertedFuture::<()>::new();
+            tube_mgr.lock().unwrap().sendacks.insert(ack_id, resolver);
+            sendack_futures.push(future);
+        }
+        let mut tube_mgrs = Arc::new(Mutex::new(HashMap::from([
+            (1, tube_mgr.clone()),
+        ])));
+        let (body_sender, _body) = hyper::Body::channel();
+        let mut body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+
+        let mut frame_handler = FrameHandler::new(
+            PeerType::Client,
+            &mut tube_mgrs,
+            &ChannelConfig::default(),
+        );
+        frame_handler.handle_frame(
+            frame::Frame::CumulativePayloadAck { 
+                tube_id: 1, 
+                up_to: Some(1), 
+                ranges: vec![3..=4, 10..=20],
+            },
+            &mut body_sender,
+        ).await.unwrap();
+
+        let resolved = sendack_futures.into_iter()
+            .map(|future| future.now_or_never().is_some())
+            .collect::<Vec<_>>();
+        assert_eq!(resolved, vec![true, true, false, true, true, false]);
+    }
+
+    #[tokio::test]
+    async fn acked_payloads_are_no_longer_retained_for_resumption() {
+        let tube_mgr = Arc::new(Mutex::new(tube::TubeManager::new()));
+        for ack_id in 0..3 {
+            let (_future, resolver) = crate::common::InvertedFuture::<()>::new();
+            let mut tube_mgr = tube_mgr.lock().unwrap();
+            tube_mgr.sendacks.insert(ack_id, resolver);
+            tube_mgr.replay_log.push(tube::ReplayEntry::Payload {
+                ack_id,
+                ack_id_reservation: None,
+                frames: vec![],
+            });
+        }
+        let mut tube_mgrs = Arc::new(Mutex::new(HashMap::from([
+            (1, tube_mgr.clone()),
+        ])));
+        let (body_sender, _body) = hyper::Body::channel();
+        let mut body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+
+        let mut frame_handler = FrameHandler::new(
+            PeerType::Client,
+            &mut tube_mgrs,
+            &ChannelConfig::default(),
+        );
+        frame_handler.handle_frame(
+            frame::Frame::PayloadAck { tube_id: 1, ack_id: 1, processed: false },
+            &mut body_sender,
+        ).await.unwrap();
+        frame_handler.handle_frame(
+            frame::Frame::CumulativePayloadAck { tube_id: 1, up_to: None, ranges: vec![2..=2] },
+            &mut body_sender,
+        ).await.unwrap();
+
+        let unacked_ids = tube_mgr.lock().unwrap().replay_log.iter()
+            .filter_map(|entry| match entry {
+                tube::ReplayEntry::Payload { ack_id, .. } => Some(*ack_id),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(unacked_ids, vec![0]);
+        assert_eq!(
+            Vec::from(tube_mgr.lock().unwrap().recently_settled_ack_ids.clone()),
+            vec![1, 2],
+        );
+    }
+
+    #[tokio::test]
+    async fn only_acks_for_recently_settled_payloads_are_ignored() {
+        let mut tube_mgr = tube::TubeManager::new();
+        tube_mgr.payload_sequences = true;
+        tube_mgr.record_settled_ack_id(2);
+        let mut tube_mgrs = Arc::new(Mutex::new(HashMap::from([
+            (1, Arc::new(Mutex::new(tube_mgr))),
+        ])));
+        let (body_sender, _body) = hyper::Body::channel();
+        let mut body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+
+        let mut frame_handler = FrameHandler::new(
+            PeerType::Client,
+            &mut tube_mgrs,
+            &ChannelConfig::default(),
+        );
+        frame_handler.handle_frame(
+            frame::Frame::PayloadAck { tube_id: 1, ack_id: 2, processed: false },
+            &mut body_sender,
+        ).await.unwrap();
+        match frame_handler.handle_frame(
+            frame::Frame::PayloadAck { tube_id: 1, ack_id: 3, processed: false },
+            &mut body_sender,
+        ).await {
+            Err(FrameHandlerError::UntrackedAckId { tube_id: 1, ack_id: 3 }) => (),
+            unexpected => panic!("Unexpected FrameHandler result: {:?}", unexpected),
+        }
+    }
+
+    #[tokio::test]
+    async fn redelivered_payloads_are_acked_but_only_emitted_once() {
+        use hyper::body::HttpBody;
+
+        let tube_mgr = Arc::new(Mutex::new(tube::TubeManager::new()));
+        let mut tube_mgrs = Arc::new(Mutex::new(HashMap::from([
+            (1, tube_mgr.clone()),
+        ])));
+        let (body_sender, mut body) = hyper::Body::channel();
+        let mut body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+
+        let mut frame_handler = FrameHandler::new(
+            PeerType::Server,
+            &mut tube_mgrs,
+            &ChannelConfig::default(),
+        );
+        let mut decoder = crate::common::frame::Decoder::new();
+        for (sequence, data) in [(0, "hello"), (0, "hello"), (1, "world")] {
+            frame_handler.handle_frame(
+                frame::Frame::PayloadSequence { tube_id: 1, sequence },
+                &mut body_sender,
+            ).await.unwrap();
+            frame_handler.handle_frame(
+                frame::Frame::Payload {
+                    tube_id: 1,
+                    ack_id: Some(3),
+                    processing_ack: false,
+                    data: data.into(),
+                },
+                &mut body_sender,
+            ).await.unwrap();
+
+            let sent_frames = decoder.decode(body.data().await.unwrap().unwrap()).unwrap();
+            assert_eq!(Vec::from(sent_frames), vec![
+                frame::Frame::PayloadAck { tube_id: 1, ack_id: 3, processed: false },
+            ]);
+        }
+
+        let events: Vec<_> = tube_mgr.lock().unwrap().pending_events.drain(..).collect();
+        assert_eq!(events, vec![
+            tube::TubeEvent::Payload("hello".into()),
+            tube::TubeEvent::Payload("world".into()),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn trailers_are_emitted_with_has_finished_sending() {
+        let tube_mgr = Arc::new(Mutex::new(tube::TubeManager::new()));
+        let mut tube_mgrs = Arc::new(Mutex::new(HashMap::from([
+            (1, tube_mgr.clone()),
+        ])));
+        let (body_sender, _body) = hyper::Body::channel();
+        let mut body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+        let trailers = HashMap::from([("status".to_string(), "0".to_string())]);
+
+        let mut frame_handler = FrameHandler::new(
+            PeerType::Client,
+            &mut tube_mgrs,
+            &ChannelConfig::default(),
+        );
+        frame_handler.handle_frame(
+            frame::Frame::ServerHasFinishedSending { 
+                tube_id: 1, 
+                trailers: Some(trailers.clone()),
+            },
+            &mut body_sender,
+        ).await.unwrap();
+
+        let event = tube_mgr.lock().unwrap().pending_events.pop_front();
+        assert_eq!(event, Some(tube::TubeEvent::ServerHasFinishedSending(trailers)));
+    }
+
+    #[tokio::test]
+    async fn newtube_responses_settle_the_pending_tube() {
+        let accepted_tube_mgr = Arc::new(Mutex::new(tube::TubeManager::new()));
+        let (acceptance, resolver) = crate::common::InvertedFuture::new();
+        accepted_tube_mgr.lock().unwrap().acceptance = Some(resolver);
+        let rejected_tube_mgr = Arc::new(Mutex::new(tube::TubeManager::new()));
+        let mut tube_mgrs = Arc::new(Mutex::new(HashMap::from([
+            (1, accepted_tube_mgr.clone()),
+            (3, rejected_tube_mgr.clone()),
+        ])));
+        let (body_sender, _body) = hyper::Body::channel();
+        let mut body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+        let headers = HashMap::from([("x-session".to_string(), "abc".to_string())]);
+
+        let mut frame_handler = FrameHandler::new(
+            PeerType::Client,
+            &mut tube_mgrs,
+            &ChannelConfig::default(),
+        );
+        frame_handler.handle_frame(
+            frame::Frame::NewTubeAccepted { tube_id: 1, headers: headers.clone() },
+            &mut body_sender,
+        ).await.unwrap();
+        frame_handler.handle_frame(
+            frame::Frame::NewTubeRejected { tube_id: 3, code: 403, headers: headers.clone() },
+            &mut body_sender,
+        ).await.unwrap();
+
+        assert_eq!(acceptance.await, tube::NewTubeResponse::Accepted(headers.clone()));
+
+        // Nobody was awaiting acceptance of Tube 3, so the rejection is 
+        // published as a TubeEvent instead.
+        let event = rejected_tube_mgr.lock().unwrap().pending_events.pop_front();
+        assert_eq!(event, Some(tube::TubeEvent::Rejected(403, headers)));
+        assert!(!tube_mgrs.lock().unwrap().contains_key(&3));
+    }
+
+    #[tokio::test]
+    async fn compression_is_only_confirmed_for_supported_algorithms() {
+        let (body_sender, _body) = hyper::Body::channel();
+        let mut body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+
+        // The server doesn't confirm an algorithm it doesn't support...
+        let mut server_tube_mgrs = Arc::new(Mutex::new(HashMap::new()));
+        let mut frame_handler = FrameHandler::new(
+            PeerType::Server,
+            &mut server_tube_mgrs,
+            &ChannelConfig::default(),
+        );
+        for (tube_id, algorithm) in [(1, "zstd"), (3, "brotli")] {
+            frame_handler.handle_frame(
+                frame::Frame::NewTube {
+                    tube_id,
+                    headers: HashMap::from([(
+                        crate::common::COMPRESSION_HEADER.to_string(), 
+                        algorithm.to_string(),
+                    )]),
+                },
+                &mut body_sender,
+            ).await.unwrap();
+        }
+        {
+            let server_tube_mgrs = server_tube_mgrs.lock().unwrap();
+            let zstd_tube_mgr = server_tube_mgrs[&1].lock().unwrap();
+            assert_eq!(zstd_tube_mgr.compression, Some(Compression::Zstd));
+            assert!(zstd_tube_mgr.compression_confirmed);
+            let brotli_tube_mgr = server_tube_mgrs[&3].lock().unwrap();
+            assert_eq!(brotli_tube_mgr.compression, None);
+            assert!(!brotli_tube_mgr.compression_confirmed);
+        }
+
+        // ...and the client only treats compression as confirmed once the 
+        // server has echoed the algorithm back.
+        let mut client_tube_mgrs = Arc::new(Mutex::new(HashMap::new()));
+        for tube_id in [1, 3] {
+            let mut tube_mgr = tube::TubeManager::new();
+            tube_mgr.compression = Some(Compression::Zstd);
+            client_tube_mgrs.lock().unwrap()
+                .insert(tube_id, Arc::new(Mutex::new(tube_mgr)));
+        }
+        let mut frame_handler = FrameHandler::new(
+            PeerType::Client,
+            &mut client_tube_mgrs,
+            &ChannelConfig::default(),
+        );
+        frame_handler.handle_frame(
+            frame::Frame::NewTubeAccepted {
+                tube_id: 1,
+                headers: HashMap::from([(
+                    crate::common::COMPRESSION_HEADER.to_string(), 
+                    "zstd".to_string(),
+                )]),
+            },
+            &mut body_sender,
+        ).await.unwrap();
+        frame_handler.handle_frame(
+            frame::Frame::NewTubeAccepted { tube_id: 3, headers: HashMap::new() },
+            &mut body_sender,
+        ).await.unwrap();
+        let client_tube_mgrs = client_tube_mgrs.lock().unwrap();
+        assert!(client_tube_mgrs[&1].lock().unwrap().compression_confirmed);
+        assert!(!client_tube_mgrs[&3].lock().unwrap().compression_confirmed);
+    }
+
+    #[tokio::test]
+    async fn server_errors_on_newtube_responses() {
+        let mut tube_mgrs = Arc::new(Mutex::new(HashMap::new()));
+        let (body_sender, _body) = hyper::Body::channel();
+        let mut body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+
+        let mut frame_handler = FrameHandler::new(
+            PeerType::Server,
+            &mut tube_mgrs,
+            &ChannelConfig::default(),
+        );
+        let result = frame_handler.handle_frame(
+            frame::Frame::NewTubeAccepted { tube_id: 1, headers: HashMap::new() },
+            &mut body_sender,
+        ).await;
+        match result {
+            Err(FrameHandlerError::InappropriateNewTubeResponseFromPeer) => (),
+            unexpected => panic!("Unexpected FrameHandler result: {:?}", unexpected),
+        }
+    }
+
+    #[tokio::test]
+    async fn only_the_server_may_send_session_frames() {
+        let token = 0x1234;
+        for (peer_type, expects_session) in [(PeerType::Client, true), (PeerType::Server, false)] {
+            let mut tube_mgrs = Arc::new(Mutex::new(HashMap::new()));
+            let (body_sender, _body) = hyper::Body::channel();
+            let mut body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+
+            let mut frame_handler = FrameHandler::new(
+                peer_type,
+                &mut tube_mgrs,
+                &ChannelConfig::default(),
+            );
+            let result = frame_handler.handle_frame(
+                frame::Frame::Session { token },
+                &mut body_sender,
+            ).await;
+            match result {
+                Ok(FrameHandlerResult::Session { token: received_token }) if expects_session =>
+                    assert_eq!(received_token, token),
+                Err(FrameHandlerError::InappropriateSessionFrameFromPeer) if !expects_session => (),
+                unexpected => panic!("Unexpected FrameHandler result: {:?}", unexpected),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn drain_is_published_to_every_open_tube() {
+        let tube_mgr = Arc::new(Mutex::new(tube::TubeManager::new()));
+        let mut tube_mgrs = Arc::new(Mutex::new(HashMap::from([
+            (1, tube_mgr.clone()),
+        ])));
+        let (body_sender, _body) = hyper::Body::channel();
+        let mut body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+
+        let mut frame_handler = FrameHandler::new(
+            PeerType::Client,
+            &mut tube_mgrs,
+            &ChannelConfig::default(),
+        );
+        let result = frame_handler.handle_frame(
+            frame::Frame::Drain { reason: frame::DrainReason::Shutdown },
+            &mut body_sender,
+        ).await;
+        match result {
+            Ok(FrameHandlerResult::Drain(frame::DrainReason::Shutdown)) => (),
+            unexpected => panic!("Unexpected FrameHandler result: {:?}", unexpected),
+        }
+
+        let event = tube_mgr.lock().unwrap().pending_events.pop_front();
+        assert_eq!(
+            event, 
+            Some(tube::TubeEvent::ServerMustDrain(frame::DrainReason::Shutdown)),
+        );
+    }
+
+    #[tokio::test]
+    async fn resumed_peers_ignore_replayed_frames() {
+        use hyper::body::HttpBody;
+
+        let mut tube_mgrs = Arc::new(Mutex::new(HashMap::new()));
+        let (body_sender, mut body) = hyper::Body::channel();
+        let mut body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+        let config = ChannelConfig {
+            session_resume_window: Some(std::time::Duration::from_secs(10)),
+            ..ChannelConfig::default()
+        };
+
+        let mut frame_handler = FrameHandler::new(PeerType::Server, &mut tube_mgrs, &config);
+        frame_handler.handle_frame(
+            frame::Frame::Settings {
+                version: frame::PROTOCOL_VERSION,
+                features: frame::Features::supported(),
+                max_frame_size: u32::MAX,
+                max_concurrent_tubes: u16::MAX,
+                initial_window_size: 0,
+            },
+            &mut body_sender,
+        ).await.unwrap();
+        let new_tube = frame::Frame::NewTube { tube_id: 1, headers: HashMap::new() };
+        match frame_handler.handle_frame(new_tube.clone(), &mut body_sender).await {
+            Ok(FrameHandlerResult::NewTube(_)) => (),
+            unexpected => panic!("Unexpected FrameHandler result: {:?}", unexpected),
+        }
+        match frame_handler.handle_frame(new_tube, &mut body_sender).await {
+            Ok(FrameHandlerResult::FullyHandled) => (),
+            unexpected => panic!("Unexpected FrameHandler result: {:?}", unexpected),
+        }
+        for _ in 0..2 {
+            frame_handler.handle_frame(
+                frame::Frame::ClientHasFinishedSending { tube_id: 1, trailers: None },
+                &mut body_sender,
+            ).await.unwrap();
+        }
+
+        // An Abort is acked every time it arrives, whether or not the Tube is
+        // still tracked.
+        let mut decoder = crate::common::frame::Decoder::new();
+        for tube_id in [1, 1, 5] {
+            frame_handler.handle_frame(
+                frame::Frame::Abort { tube_id, reason: frame::AbortReason::ApplicationAbort },
+                &mut body_sender,
+            ).await.unwrap();
+            let sent_frames = decoder.decode(body.data().await.unwrap().unwrap()).unwrap();
+            assert_eq!(Vec::from(sent_frames), vec![frame::Frame::AbortAck { tube_id }]);
+        }
+        frame_handler.handle_frame(
+            frame::Frame::ClientHasFinishedSending { tube_id: 1, trailers: None },
+            &mut body_sender,
+        ).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn redelivered_payloads_are_acked_again_once_processed() {
+        use hyper::body::HttpBody;
+
+        let mut tube_mgr = tube::TubeManager::new();
+        tube_mgr.resumable = true;
+        let tube_mgr = Arc::new(Mutex::new(tube_mgr));
+        let mut tube_mgrs = Arc::new(Mutex::new(HashMap::from([
+            (1, tube_mgr.clone()),
+        ])));
+        let (body_sender, mut body) = hyper::Body::channel();
+        let mut body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+
+        let mut frame_handler = FrameHandler::new(
+            PeerType::Server,
+            &mut tube_mgrs,
+            &ChannelConfig::default(),
+        );
+        let payload = frame::Frame::Payload {
+            tube_id: 1,
+            ack_id: Some(7),
+            processing_ack: true,
+            data: "hello".into(),
+        };
+        for _ in 0..2 {
+            frame_handler.handle_frame(
+                frame::Frame::PayloadSequence { tube_id: 1, sequence: 0 },
+                &mut body_sender,
+            ).await.unwrap();
+            frame_handler.handle_frame(payload.clone(), &mut body_sender).await.unwrap();
+        }
+
+        // Until the application acks the payload, a redelivery is dropped...
+        let event = tube_mgr.lock().unwrap().pending_events.pop_front();
+        let processing_ack = match event {
+            Some(tube::TubeEvent::PayloadAwaitingAck(_, processing_ack)) => processing_ack,
+            unexpected => panic!("Unexpected TubeEvent: {:?}", unexpected),
+        };
+        assert!(tube_mgr.lock().unwrap().pending_events.is_empty());
+        processing_ack.ack().await.unwrap();
+        let mut decoder = crate::common::frame::Decoder::new();
+        let ack = frame::Frame::PayloadAck { tube_id: 1, ack_id: 7, processed: true };
+        let sent_frames = decoder.decode(body.data().await.unwrap().unwrap()).unwrap();
+        assert_eq!(Vec::from(sent_frames), vec![ack.clone()]);
+
+        // ...and after that it is acked again.
+        frame_handler.handle_frame(
+            frame::Frame::PayloadSequence { tube_id: 1, sequence: 0 },
+            &mut body_sender,
+        ).await.unwrap();
+        frame_handler.handle_frame(payload, &mut body_sender).await.unwrap();
+        let sent_frames = decoder.decode(body.data().await.unwrap().unwrap()).unwrap();
+        assert_eq!(Vec::from(sent_frames), vec![ack]);
+        assert!(tube_mgr.lock().unwrap().pending_events.is_empty());
+    }
+}
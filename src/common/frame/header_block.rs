@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use bytes::Buf;
+use bytes::BufMut;
+use bytes::BytesMut;
+
+use super::decode::FrameParseError;
+use super::encode::FrameEncodeError;
+
+/**
+ * Header keys common enough to be sent as a single-byte index rather than
+ * spelled out. A KeyIndex of N refers to STATIC_HEADER_KEYS[N - 1].
+ *
+ * Entries may only ever be appended to this table: both peers must agree on
+ * what every index means.
+ */
+pub(in super) const STATIC_HEADER_KEYS: [&str; 9] = [
+    "accept",
+    "accept-encoding",
+    "authorization",
+    "content-encoding",
+    "content-type",
+    "traceparent",
+    "tracestate",
+    "user-agent",
+    "x-request-id",
+];
+
+const LITERAL_KEY_INDEX: u8 = 0;
+
+fn static_key_index(key: &str) -> Option<u8> {
+    STATIC_HEADER_KEYS.iter()
+        .position(|static_key| *static_key == key)
+        .map(|idx| (idx + 1) as u8)
+}
+
+/**
+ * The number of bytes `headers` occupies when encoded as a binary header
+ * block. Errors if any key or value is too long to be length-prefixed with a
+ * u16.
+ */
+pub(in super) fn encoded_len(
+    headers: &HashMap<String, String>,
+) -> Result<usize, FrameEncodeError> {
+    let mut len = 2; // NumHeaders(u16)
+    for (key, value) in headers {
+        if key.len() > u16::MAX as usize || value.len() > u16::MAX as usize {
+            return Err(FrameEncodeError::HeadersTooLarge(key.len() + value.len()));
+        }
+        len += 1; // KeyIndex(u8)
+        if static_key_index(key).is_none() {
+            len += 2 + key.len();
+        }
+        len += 2 + value.len();
+    }
+    Ok(len)
+}
+
+/**
+ * Appends `headers` to `buf` as a binary header block:
+ *
+ *   +------------------+-----------+-----------+-----+
+ *   |  NumHeaders(u16) |  Header0  |  Header1  | ... |
+ *   +------------------+-----------+-----------+-----+
+ *
+ * Each header is a key followed by a length-prefixed UTF-8 value. A KeyIndex
+ * of 0 means a length-prefixed UTF-8 key follows; any other KeyIndex refers
+ * to an entry in STATIC_HEADER_KEYS:
+ *
+ *   +----------------+---------------+------------+-----------------+--------------+
+ *   |  KeyIndex(u8)  | [KeyLen(u16)] | [Key(*)]   |  ValueLen(u16)  |  Value(*)    |
+ *   +----------------+---------------+------------+-----------------+--------------+
+ *
+ * Callers must check encoded_len() first.
+ */
+pub(in super) fn put_header_block(
+    buf: &mut BytesMut,
+    headers: &HashMap<String, String>,
+) {
+    buf.put_u16(headers.len() as u16);
+    for (key, value) in headers {
+        match static_key_index(key) {
+            Some(key_index) => buf.put_u8(key_index),
+            None => {
+                buf.put_u8(LITERAL_KEY_INDEX);
+                buf.put_u16(key.len() as u16);
+                buf.put_slice(key.as_bytes());
+            },
+        }
+        buf.put_u16(value.len() as u16);
+        buf.put_slice(value.as_bytes());
+    }
+}
+
+fn get_len_prefixed_str<'a>(
+    data: &mut &'a [u8],
+) -> Result<&'a str, FrameParseError> {
+    if data.remaining() < 2 {
+        return Err(FrameParseError::HeaderBlockTruncated);
+    }
+    let len = usize::from(data.get_u16());
+    if data.remaining() < len {
+        return Err(FrameParseError::HeaderBlockTruncated);
+    }
+    let (str_bytes, rest) = data.split_at(len);
+    *data = rest;
+    match std::str::from_utf8(str_bytes) {
+        Ok(str) => Ok(str),
+        Err(utf8_err) => Err(FrameParseError::HeaderUtf8Error(utf8_err)),
+    }
+}
+
+pub(in super) fn parse_header_block(
+    mut data: &[u8],
+) -> Result<HashMap<String, String>, FrameParseError> {
+    if data.remaining() < 2 {
+        return Err(FrameParseError::HeaderBlockTruncated);
+    }
+    let num_headers = data.get_u16();
+
+    let mut headers = HashMap::new();
+    for _ in 0..num_headers {
+        if !data.has_remaining() {
+            return Err(FrameParseError::HeaderBlockTruncated);
+        }
+        let key = match data.get_u8() {
+            LITERAL_KEY_INDEX => get_len_prefixed_str(&mut data)?,
+            key_index => match STATIC_HEADER_KEYS.get(usize::from(key_index) - 1) {
+                Some(key) => key,
+                None => return Err(FrameParseError::UnknownStaticHeaderIndex(key_index)),
+            },
+        };
+        let value = get_len_prefixed_str(&mut data)?;
+        headers.insert(key.to_string(), value.to_string());
+    }
+
+    if data.has_remaining() {
+        return Err(FrameParseError::HeaderBlockTrailingBytes(data.remaining()));
+    }
+    Ok(headers)
+}
+
+#[cfg(test)]
+mod header_block_tests {
+    use super::*;
+
+    #[test]
+    fn static_keys_encode_as_a_single_byte() {
+        let headers = HashMap::from([
+            ("content-type".to_string(), "a".to_string()),
+        ]);
+        let mut buf = BytesMut::new();
+        put_header_block(&mut buf, &headers);
+        assert_eq!(&buf[..], &[0, 1, 5, 0, 1, b'a']);
+        assert_eq!(encoded_len(&headers).unwrap(), buf.len());
+        assert_eq!(parse_header_block(&buf).unwrap(), headers);
+    }
+
+    #[test]
+    fn literal_and_static_keys_round_trip() {
+        let headers = HashMap::from([
+            ("authorization".to_string(), "Bearer abc".to_string()),
+            ("x-custom".to_string(), "".to_string()),
+        ]);
+        let mut buf = BytesMut::new();
+        put_header_block(&mut buf, &headers);
+        assert_eq!(encoded_len(&headers).unwrap(), buf.len());
+        assert_eq!(parse_header_block(&buf).unwrap(), headers);
+    }
+
+    #[test]
+    fn errors_on_oversized_value() {
+        let headers = HashMap::from([
+            ("x-custom".to_string(), "a".repeat(u16::MAX as usize + 1)),
+        ]);
+        match encoded_len(&headers) {
+            Err(FrameEncodeError::HeadersTooLarge(_)) => (),
+            unexpected => panic!("Unexpected encoded_len() result: {:?}", unexpected),
+        }
+    }
+
+    #[test]
+    fn errors_on_truncated_block() {
+        match parse_header_block(&[0, 1, 0, 0, 5, b'a']) {
+            Err(FrameParseError::HeaderBlockTruncated) => (),
+            unexpected => panic!("Unexpected parse_header_block() result: {:?}", unexpected),
+        }
+    }
+
+    #[test]
+    fn errors_on_unknown_static_index() {
+        match parse_header_block(&[0, 1, 200, 0, 0]) {
+            Err(FrameParseError::UnknownStaticHeaderIndex(200)) => (),
+            unexpected => panic!("Unexpected parse_header_block() result: {:?}", unexpected),
+        }
+    }
+}
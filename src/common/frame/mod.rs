@@ -1,18 +1,44 @@
+mod codec;
 mod decode;
 mod frame;
 mod frame_handler;
+mod header_block;
+mod varint;
 
+pub use codec::FrameCodec;
+pub use codec::FrameCodecError;
 pub use decode::Decoder;
+pub use decode::DecoderConfig;
+pub use decode::DecoderLimit;
+pub use decode::FrameDecodeError;
+pub use decode::FrameParseError;
 pub mod encode;
 pub use frame::AbortReason;
+pub use frame::APPLICATION_EXTENSION_FRAMETYPES;
+pub use frame::DrainReason;
+pub use frame::Features;
 pub use frame::Frame;
+pub use frame::FrameTag;
+pub use frame::GoAwayErrorCode;
+pub use frame::HeaderEncoding;
+pub use frame::MAX_ACK_ID;
+pub use frame::MAX_FIXED_WIDTH_ACK_ID;
+pub(in crate) use frame::MAX_FRAME_BODY_LEN;
+pub use frame::MAX_TUBE_ID;
+pub use frame::PROTOCOL_VERSION;
+pub use frame::RESERVED_EXTENSION_FRAMETYPES;
 pub use frame_handler::FrameHandler;
+pub use frame_handler::FrameHandlerError;
 pub use frame_handler::FrameHandlerResult;
+pub use frame_handler::MAX_CONCURRENT_TUBES_REJECTION_CODE;
 
 #[cfg(test)]
 mod codec_tests {
     use std::collections::HashMap;
 
+    use bytes::Bytes;
+    use bytes::BytesMut;
+
     use super::*;
 
     #[test]
@@ -23,19 +49,139 @@ mod codec_tests {
         let mut decoder = Decoder::new();
         let frames = decoder.decode(encoded_bytes).unwrap();
         assert_eq!(frames.len(), 1);
-        assert_eq!(frames[0], Frame::ClientHasFinishedSending { tube_id });
+        assert_eq!(frames[0], Frame::ClientHasFinishedSending { tube_id, trailers: None });
+    }
+
+    #[test]
+    fn has_finished_sending_frames_with_trailers_encode_and_decode() {
+        let tube_id = 65000;
+        let trailers = HashMap::from([
+            ("status".to_string(), "0".to_string()),
+            ("x-row-count".to_string(), "42".to_string()),
+        ]);
+
+        let mut encoded_bytes = bytes::BytesMut::new();
+        encoded_bytes.extend_from_slice(
+            &encode::client_has_finished_sending_with_trailers_frame(tube_id, &trailers).unwrap()
+        );
+        encoded_bytes.extend_from_slice(
+            &encode::server_has_finished_sending_with_trailers_frame(tube_id, &HashMap::new())
+                .unwrap()
+        );
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes.freeze()).unwrap();
+        assert_eq!(Vec::from(frames), vec![
+            Frame::ClientHasFinishedSending { tube_id, trailers: Some(trailers) },
+            Frame::ServerHasFinishedSending { tube_id, trailers: Some(HashMap::new()) },
+        ]);
+    }
+
+    #[test]
+    fn newtube_response_frames_encode_and_decode() {
+        let tube_id = 65000;
+        let headers = HashMap::from([("x-session".to_string(), "abc".to_string())]);
+
+        let mut encoded_bytes = bytes::BytesMut::new();
+        encoded_bytes.extend_from_slice(
+            &encode::newtube_accepted_frame(tube_id, &headers).unwrap()
+        );
+        encoded_bytes.extend_from_slice(
+            &encode::newtube_rejected_frame(tube_id, 429, &HashMap::new()).unwrap()
+        );
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes.freeze()).unwrap();
+        assert_eq!(Vec::from(frames), vec![
+            Frame::NewTubeAccepted { tube_id, headers },
+            Frame::NewTubeRejected { tube_id, code: 429, headers: HashMap::new() },
+        ]);
+    }
+
+    #[test]
+    fn abort_frame_with_code_encodes_and_decodes() {
+        let tube_id = 65000;
+        let reason = AbortReason::Application { 
+            code: u32::MAX, 
+            message: Some("quota exceeded".to_string()),
+        };
+
+        let mut encoded_bytes = bytes::BytesMut::new();
+        encoded_bytes.extend_from_slice(&encode::abort_frame(tube_id, reason.clone()).unwrap());
+        encoded_bytes.extend_from_slice(&encode::abort_frame(
+            tube_id, 
+            AbortReason::Application { code: 7, message: None },
+        ).unwrap());
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes.freeze()).unwrap();
+        assert_eq!(Vec::from(frames), vec![
+            Frame::Abort { tube_id, reason },
+            Frame::Abort { 
+                tube_id, 
+                reason: AbortReason::Application { code: 7, message: None },
+            },
+        ]);
     }
 
     #[test]
     fn drain_frame_encodes_and_decodes() {
-        let encoded_bytes = encode::drain_frame().unwrap();
+        let encoded_bytes = encode::drain_frame(DrainReason::Restart).unwrap();
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], Frame::Drain { reason: DrainReason::Restart });
+
+        // Drain frames with an empty body predate DrainReason.
+        let frames = decoder.decode(Bytes::from_static(&[frame::DRAIN_FRAMETYPE, 0, 0])).unwrap();
+        assert_eq!(Vec::from(frames), vec![
+            Frame::Drain { reason: DrainReason::Unspecified },
+        ]);
+    }
+
+    #[test]
+    fn extension_frame_encodes_and_decodes() {
+        let encoded_bytes = encode::extension_frame(65000, 0x2F, &[1, 2, 3]).unwrap();
+        assert_eq!(encoded_bytes[0], *APPLICATION_EXTENSION_FRAMETYPES.end());
 
         let mut decoder = Decoder::new();
         let frames = decoder.decode(encoded_bytes).unwrap();
         assert_eq!(frames.len(), 1);
-        assert_eq!(frames[0], Frame::Drain);
+        assert_eq!(frames[0], Frame::Extension {
+          tube_id: 65000,
+          ext_type: 0x2F,
+          data: Bytes::from_static(&[1, 2, 3]),
+        });
     }
 
+    #[test]
+    fn extension_frame_errors_if_ext_type_out_of_range() {
+        match encode::extension_frame(1, 0x30, &[]) {
+            Err(encode::FrameEncodeError::ExtTypeOutOfRange(0x30)) => (),
+            unexpected => panic!("Unexpected extension_frame() result: {:?}", unexpected),
+        }
+    }
+
+    #[test]
+    fn goaway_frame_encodes_and_decodes() {
+        let encoded_bytes = encode::goaway_frame(
+            65001, 
+            GoAwayErrorCode::ProtocolError, 
+            "bad frame",
+        ).unwrap();
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], Frame::GoAway {
+          last_tube_id: 65001,
+          error_code: GoAwayErrorCode::ProtocolError,
+          message: "bad frame".to_string(),
+        });
+    }
+
+    #[cfg(feature = "client")]
     #[test]
     fn newtube_frame_encodes_and_decodes() {
         let tube_id = 65000;
@@ -46,7 +192,8 @@ mod codec_tests {
         let expected_headers = encoded_headers.clone();
 
         let encoded_bytes = 
-          encode::newtube_frame(tube_id, encoded_headers).unwrap();
+          encode::newtube_frame_with_encoding(tube_id, &encoded_headers, HeaderEncoding::Json)
+            .unwrap();
 
         let mut decoder = Decoder::new();
         let frames = decoder.decode(encoded_bytes).unwrap();
@@ -57,14 +204,35 @@ mod codec_tests {
         });
     }
 
+    #[cfg(feature = "client")]
+    #[test]
+    fn newtube_frame_with_binary_headers_encodes_and_decodes() {
+        let tube_id = 65000;
+        let headers = HashMap::from([
+          ("content-type".to_string(), "application/json".to_string()),
+          ("header2".to_string(), "value2".to_string()),
+        ]);
+
+        let encoded_bytes = encode::newtube_frame_with_encoding(
+            tube_id, 
+            &headers, 
+            HeaderEncoding::Binary,
+        ).unwrap();
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], Frame::NewTube { tube_id, headers });
+    }
+
     #[test]
     fn payload_frame_with_ack_encodes_and_decodes() {
         let tube_id = 65000;
         let ack_id = 32000;
-        let data = vec![0, 1, 42, 255];
+        let data = Bytes::from(vec![0, 1, 42, 255]);
         let expected_data = data.clone();
 
-        let encoded_bytes = encode::payload_frame(tube_id, Some(ack_id), data).unwrap();
+        let encoded_bytes = encode::payload_frame(tube_id, Some(ack_id), &data).unwrap();
 
         let mut decoder = Decoder::new();
         let frames = decoder.decode(encoded_bytes).unwrap();
@@ -72,6 +240,7 @@ mod codec_tests {
         assert_eq!(frames[0], Frame::Payload {
           tube_id,
           ack_id: Some(ack_id),
+          processing_ack: false,
           data: expected_data,
         });
     }
@@ -79,10 +248,10 @@ mod codec_tests {
     #[test]
     fn payload_frame_without_ack_encodes_and_decodes() {
         let tube_id = 65000;
-        let data = vec![0, 1, 42, 255];
+        let data = Bytes::from(vec![0, 1, 42, 255]);
         let expected_data = data.clone();
 
-        let encoded_bytes = encode::payload_frame(tube_id, None, data).unwrap();
+        let encoded_bytes = encode::payload_frame(tube_id, None, &data).unwrap();
 
         let mut decoder = Decoder::new();
         let frames = decoder.decode(encoded_bytes).unwrap();
@@ -90,6 +259,7 @@ mod codec_tests {
         assert_eq!(frames[0], Frame::Payload {
           tube_id,
           ack_id: None,
+          processing_ack: false,
           data: expected_data,
         });
     }
@@ -97,9 +267,9 @@ mod codec_tests {
     #[test]
     fn payload_ack_frame_encodes_and_decodes() {
         let tube_id = 65000;
-        let ack_id: u16 = 32000;
+        let ack_id = 32000;
 
-        let encoded_bytes = encode::payload_ack_frame(tube_id, ack_id).unwrap();
+        let encoded_bytes = encode::payload_ack_frame(tube_id, ack_id, false).unwrap();
 
         let mut decoder = Decoder::new();
         let frames = decoder.decode(encoded_bytes).unwrap();
@@ -107,6 +277,189 @@ mod codec_tests {
         assert_eq!(frames[0], Frame::PayloadAck {
           tube_id,
           ack_id,
+          processed: false,
+        });
+    }
+
+    #[test]
+    fn processing_ack_frames_encode_and_decode() {
+        let tube_id = 65000;
+        let ack_id = 32000;
+        let data = Bytes::from(vec![0, 1, 42, 255]);
+
+        let mut encoded_bytes = bytes::BytesMut::new();
+        encoded_bytes.extend_from_slice(
+            &encode::processing_ack_payload_frame(tube_id, ack_id, &data).unwrap()
+        );
+        encoded_bytes.extend_from_slice(
+            &encode::payload_ack_frame(tube_id, ack_id, true).unwrap()
+        );
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes.freeze()).unwrap();
+        assert_eq!(Vec::from(frames), vec![
+            Frame::Payload {
+                tube_id,
+                ack_id: Some(ack_id),
+                processing_ack: true,
+                data,
+            },
+            Frame::PayloadAck {
+                tube_id,
+                ack_id,
+                processed: true,
+            },
+        ]);
+    }
+
+    #[test]
+    fn cumulative_payload_ack_frame_encodes_and_decodes() {
+        let tube_id = 65000;
+
+        let encoded_bytes = encode::cumulative_payload_ack_frame(
+            tube_id, 
+            Some(40), 
+            &[42..=42, 50..=32000],
+        ).unwrap();
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], Frame::CumulativePayloadAck {
+            tube_id,
+            up_to: Some(40),
+            ranges: vec![42..=42, 50..=32000],
+        });
+    }
+
+    #[test]
+    fn payload_fragment_frame_encodes_and_decodes() {
+        let tube_id = 65000;
+        let data = Bytes::from(vec![0, 1, 42, 255]);
+        let expected_data = data.clone();
+
+        let encoded_bytes = encode::payload_fragment_frame(tube_id, &data).unwrap();
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], Frame::PayloadFragment {
+          tube_id,
+          data: expected_data,
+        });
+    }
+
+    #[test]
+    fn fragmented_payload_frames_encode_and_decode() {
+        let tube_id = 65000;
+        let ack_id = 32000;
+        let data: Vec<u8> = (0..200_000).map(|i| (i % 256) as u8).collect();
+
+        let mut encoded_bytes = BytesMut::new();
+        let mut remaining = &data[..];
+        while remaining.len() > encode::payload_frame_data_capacity(tube_id, Some(ack_id)) {
+            let fragment_len = 
+                remaining.len().min(encode::payload_fragment_frame_data_capacity(tube_id));
+            let (fragment_data, rest) = remaining.split_at(fragment_len);
+            encode::payload_fragment_frame_into(&mut encoded_bytes, tube_id, fragment_data)
+                .unwrap();
+            remaining = rest;
+        }
+        encode::payload_frame_into(&mut encoded_bytes, tube_id, Some(ack_id), remaining)
+            .unwrap();
+        let encoded_bytes = encoded_bytes.freeze();
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(frames.len(), 4);
+
+        let mut reassembled_data = vec![];
+        for frame in frames.iter().take(3) {
+            match frame {
+                Frame::PayloadFragment { tube_id: frag_tube_id, data } => {
+                    assert_eq!(*frag_tube_id, tube_id);
+                    reassembled_data.extend_from_slice(data);
+                },
+                unexpected => panic!("Expected a PayloadFragment, got {:?}", unexpected),
+            }
+        }
+        match &frames[3] {
+            Frame::Payload { tube_id: payload_tube_id, ack_id: payload_ack_id, data, .. } => {
+                assert_eq!(*payload_tube_id, tube_id);
+                assert_eq!(*payload_ack_id, Some(ack_id));
+                reassembled_data.extend_from_slice(data);
+            },
+            unexpected => panic!("Expected a Payload, got {:?}", unexpected),
+        }
+        assert_eq!(reassembled_data, data);
+    }
+
+    #[test]
+    fn ping_and_pong_frames_encode_and_decode() {
+        let opaque = 0x0102_0304_0506_0708;
+        let mut encoded_bytes = bytes::BytesMut::new();
+        encode::ping_frame_into(&mut encoded_bytes, opaque).unwrap();
+        encode::pong_frame_into(&mut encoded_bytes, opaque).unwrap();
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes.freeze()).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0], Frame::Ping { opaque });
+        assert_eq!(frames[1], Frame::Pong { opaque });
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn session_frame_encodes_and_decodes() {
+        let token = 0x0102_0304_0506_0708_090A_0B0C_0D0E_0F10;
+        let encoded_bytes = encode::session_frame(token).unwrap();
+        assert_eq!(encoded_bytes.len(), 3 + 16);
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(Vec::from(frames), vec![Frame::Session { token }]);
+    }
+
+    #[test]
+    fn settings_frame_encodes_and_decodes() {
+        let encoded_bytes = encode::settings_frame(
+            PROTOCOL_VERSION,
+            Features::supported(),
+            1024,
+            100,
+            65536,
+        ).unwrap();
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], Frame::Settings {
+            version: PROTOCOL_VERSION,
+            features: Features::supported(),
+            max_frame_size: 1024,
+            max_concurrent_tubes: 100,
+            initial_window_size: 65536,
+        });
+    }
+
+    #[test]
+    fn settings_frame_without_initial_window_size_decodes() {
+        let encoded_bytes = Bytes::from_static(&[
+            0xC, 0, 11,
+            PROTOCOL_VERSION,
+            0, 0, 0, 0,
+            0, 0, 4, 0,
+            0, 100,
+        ]);
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(frames[0], Frame::Settings {
+            version: PROTOCOL_VERSION,
+            features: Features::empty(),
+            max_frame_size: 1024,
+            max_concurrent_tubes: 100,
+            initial_window_size: 0,
         });
     }
 
@@ -118,6 +471,122 @@ mod codec_tests {
         let mut decoder = Decoder::new();
         let frames = decoder.decode(encoded_bytes).unwrap();
         assert_eq!(frames.len(), 1);
-        assert_eq!(frames[0], Frame::ServerHasFinishedSending { tube_id });
+        assert_eq!(frames[0], Frame::ServerHasFinishedSending { tube_id, trailers: None });
+    }
+
+    #[test]
+    fn payload_sequence_frame_encodes_and_decodes() {
+        let encoded_bytes = encode::payload_sequence_frame(65000, 1 << 40).unwrap();
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], Frame::PayloadSequence {
+            tube_id: 65000,
+            sequence: 1 << 40,
+        });
+    }
+
+    #[test]
+    fn window_update_frame_encodes_and_decodes() {
+        let encoded_bytes = encode::window_update_frame(65000, 1 << 20).unwrap();
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], Frame::WindowUpdate {
+            tube_id: 65000,
+            credit: 1 << 20,
+        });
+    }
+
+    #[test]
+    fn priority_frame_encodes_and_decodes() {
+        let encoded_bytes = encode::priority_frame(65000, 200).unwrap();
+
+        let mut decoder = Decoder::new();
+        let frames = decoder.decode(encoded_bytes).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], Frame::Priority {
+            tube_id: 65000,
+            weight: 200,
+        });
+    }
+
+    #[test]
+    fn frames_with_large_ids_encode_and_decode() {
+        let tube_id = 1 << 40;
+        let frames = vec![
+            Frame::ClientHasFinishedSending { tube_id, trailers: None },
+            Frame::NewTube { 
+                tube_id, 
+                headers: HashMap::from([("x-test".to_string(), "1".to_string())]),
+            },
+            Frame::NewTubeAccepted { tube_id, headers: HashMap::new() },
+            Frame::NewTubeRejected { 
+                tube_id, 
+                code: 403, 
+                headers: HashMap::from([("reason".to_string(), "denied".to_string())]),
+            },
+            Frame::Payload { 
+                tube_id: 1, 
+                ack_id: Some(1 << 20), 
+                processing_ack: false, 
+                data: Bytes::from_static(b"hi"),
+            },
+            Frame::Payload { tube_id, ack_id: None, processing_ack: false, data: Bytes::new() },
+            Frame::PayloadAck { tube_id: 1, ack_id: 1 << 20, processed: true },
+            Frame::CumulativePayloadAck { tube_id, up_to: None, ranges: vec![1 << 20..=1 << 30] },
+            Frame::ServerHasFinishedSending { tube_id, trailers: None },
+            Frame::ServerHasFinishedSending { 
+                tube_id, 
+                trailers: Some(HashMap::from([("status".to_string(), "0".to_string())])),
+            },
+            Frame::Abort { tube_id, reason: AbortReason::ApplicationAbort },
+            Frame::Abort { 
+                tube_id, 
+                reason: AbortReason::Application { 
+                    code: 4021, 
+                    message: Some("invalid request".to_string()),
+                },
+            },
+            Frame::AbortAck { tube_id },
+            Frame::PayloadFragment { tube_id, data: Bytes::from_static(b"hi") },
+            Frame::CompressedPayload { 
+                tube_id, 
+                ack_id: Some(0), 
+                processing_ack: true, 
+                data: Bytes::from_static(b"hi"),
+            },
+            Frame::GoAway { 
+                last_tube_id: tube_id, 
+                error_code: GoAwayErrorCode::NoError, 
+                message: "bye".to_string(),
+            },
+            Frame::PayloadSequence { tube_id, sequence: u64::MAX },
+            Frame::WindowUpdate { tube_id, credit: 1 << 20 },
+            Frame::Priority { tube_id, weight: 200 },
+            Frame::Extension { tube_id, ext_type: 5, data: Bytes::from_static(b"hi") },
+        ];
+        let mut encoded_bytes = bytes::BytesMut::new();
+        for frame in &frames {
+            encode::frame_into(&mut encoded_bytes, frame).unwrap();
+        }
+
+        let mut decoder = Decoder::new();
+        let decoded_frames = decoder.decode(encoded_bytes.freeze()).unwrap();
+        assert_eq!(Vec::from(decoded_frames), frames);
+    }
+
+    #[test]
+    fn truncated_varint_id_errors() {
+        // A PayloadAck frame (with varint ids) whose AckId is cut short.
+        let encoded_bytes = Bytes::from_static(&[0x44, 0x00, 0x02, 0x01, 0x80]);
+
+        let mut decoder = Decoder::new();
+        match decoder.decode(encoded_bytes) {
+            Err(FrameDecodeError { parse_error: FrameParseError::VarintTruncated(0x44), .. }) => (),
+            unexpected => panic!("Unexpected decode result: {:?}", unexpected),
+        }
     }
 }
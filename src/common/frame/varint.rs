@@ -0,0 +1,86 @@
+use bytes::Buf;
+use bytes::BufMut;
+use bytes::BytesMut;
+
+/**
+ * The largest value a varint can hold (2^62 - 1).
+ */
+pub const MAX_VARINT: u64 = (1 << 62) - 1;
+
+/**
+ * Varints use the same layout as QUIC's variable-length integers: The two
+ * most significant bits of the first byte give the length of the varint
+ * (00 = 1 byte, 01 = 2 bytes, 10 = 4 bytes, 11 = 8 bytes) and the remaining
+ * bits hold the value in network byte order.
+ *
+ *   +----------------+------------------------------+
+ *   |  LenPrefix(2)  |  Value(6, 14, 30, or 62)     |
+ *   +----------------+------------------------------+
+ *
+ * Callers are responsible for ensuring that `value` <= MAX_VARINT.
+ */
+pub(in super) fn varint_len(value: u64) -> usize {
+    if value < (1 << 6) {
+        1
+    } else if value < (1 << 14) {
+        2
+    } else if value < (1 << 30) {
+        4
+    } else {
+        8
+    }
+}
+
+pub(in super) fn put_varint(buf: &mut BytesMut, value: u64) {
+    match varint_len(value) {
+        1 => buf.put_u8(value as u8),
+        2 => buf.put_u16((0b01 << 14) | value as u16),
+        4 => buf.put_u32((0b10 << 30) | value as u32),
+        _ => buf.put_u64((0b11 << 62) | value),
+    }
+}
+
+/**
+ * Reads a varint from the front of `data`, or returns None if `data` ends
+ * partway through it.
+ */
+pub(in super) fn get_varint(data: &mut &[u8]) -> Option<u64> {
+    let first_byte = *data.first()?;
+    let len = 1 << (first_byte >> 6);
+    if data.len() < len {
+        return None;
+    }
+    let value = match len {
+        1 => u64::from(data.get_u8()),
+        2 => u64::from(data.get_u16() & 0x3FFF),
+        4 => u64::from(data.get_u32() & 0x3FFF_FFFF),
+        _ => data.get_u64() & MAX_VARINT,
+    };
+    Some(value)
+}
+
+#[cfg(test)]
+mod varint_tests {
+    use super::*;
+
+    #[test]
+    fn varints_round_trip_at_every_length() {
+        for value in [0, 63, 64, 16383, 16384, (1 << 30) - 1, 1 << 30, MAX_VARINT] {
+            let mut buf = BytesMut::new();
+            put_varint(&mut buf, value);
+            assert_eq!(buf.len(), varint_len(value));
+
+            let mut data = &buf[..];
+            assert_eq!(get_varint(&mut data), Some(value));
+            assert!(data.is_empty());
+        }
+    }
+
+    #[test]
+    fn truncated_varint_is_rejected() {
+        let mut buf = BytesMut::new();
+        put_varint(&mut buf, 1 << 20);
+        let mut data = &buf[..3];
+        assert_eq!(get_varint(&mut data), None);
+    }
+}
@@ -0,0 +1,40 @@
+use std::sync::Arc;
+
+use crate::common::frame;
+
+#[derive(Debug)]
+pub enum GoAwayError {
+    AlreadyGoingAway,
+    FrameEncodeError(frame::encode::FrameEncodeError),
+    NotConnected,
+    TransportError(hyper::Error),
+}
+
+/**
+ * Sends a GoAway frame, cutting `message` short (on a character boundary) if 
+ * it wouldn't fit in the largest frame the peer accepts.
+ */
+pub(in crate) async fn send_go_away(
+    sender: &Arc<tokio::sync::Mutex<hyper::body::Sender>>,
+    last_tube_id: u64,
+    error_code: frame::GoAwayErrorCode,
+    message: &str,
+    peer_max_frame_size: Option<u32>,
+) -> Result<(), GoAwayError> {
+    let peer_max_frame_size = peer_max_frame_size
+        .map_or(usize::MAX, |size| usize::try_from(size).unwrap_or(usize::MAX));
+    let capacity = frame::encode::goaway_message_capacity(last_tube_id)
+        .saturating_sub((3 + frame::MAX_FRAME_BODY_LEN).saturating_sub(peer_max_frame_size));
+    let message = frame::encode::truncate_on_char_boundary(message, capacity);
+    let frame_data = match frame::encode::goaway_frame(last_tube_id, error_code, message) {
+        Ok(data) => data,
+        Err(e) => return Err(GoAwayError::FrameEncodeError(e)),
+    };
+
+    let mut sender = sender.lock().await;
+    log::trace!("Sending GoAway(error_code={:?})...", error_code);
+    match sender.send_data(frame_data).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(GoAwayError::TransportError(e)),
+    }
+}
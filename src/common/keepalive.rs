@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Weak;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::common::ChannelConfig;
+use crate::common::frame;
+use crate::common::tube;
+
+/**
+ * Tracks the Ping frames a channel has sent to its peer and the round-trip
+ * time measured from the Pong frames sent back in response.
+ */
+#[derive(Debug)]
+pub(in crate) struct Keepalive {
+    /**
+     * Bumped by restart() so that the Ping loop started for a previous 
+     * transport knows to exit.
+     */
+    epoch: u64,
+    /**
+     * Pings are only sent once the peer's Settings frame shows that both 
+     * peers advertised Features::KEEPALIVE.
+     */
+    is_negotiated: bool,
+    is_stopped: bool,
+    missed_pongs: u32,
+    next_opaque: u64,
+    outstanding_ping: Option<(u64, Instant)>,
+    rtt: Option<Duration>,
+}
+impl Keepalive {
+    pub fn new() -> Self {
+        Keepalive {
+            epoch: 0,
+            is_negotiated: false,
+            is_stopped: false,
+            missed_pongs: 0,
+            next_opaque: 0,
+            outstanding_ping: None,
+            rtt: None,
+        }
+    }
+
+    pub fn handle_pong(&mut self, opaque: u64) {
+        match self.outstanding_ping {
+            Some((ping_opaque, sent_at)) if ping_opaque == opaque => {
+                self.rtt = Some(sent_at.elapsed());
+                self.missed_pongs = 0;
+                self.outstanding_ping = None;
+            },
+            _ => log::trace!(
+                "Ignoring Pong(opaque={}) that doesn't match the outstanding \
+                 Ping.",
+                opaque,
+            ),
+        }
+    }
+
+    /**
+     * The round-trip time measured from the most recently answered Ping, or
+     * None if no Ping has been answered yet.
+     */
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+
+    /**
+     * Records whether both peers advertised Features::KEEPALIVE, once the 
+     * peer's Settings frame has arrived.
+     */
+    pub fn set_negotiated(&mut self, is_negotiated: bool) {
+        self.is_negotiated = is_negotiated;
+    }
+
+    /**
+     * Stops sending Pings (e.g. because the channel has closed or is going
+     * away).
+     */
+    pub fn stop(&mut self) {
+        self.is_stopped = true;
+    }
+
+    /**
+     * Forgets any outstanding Ping so that a new Ping loop can be started 
+     * (e.g. once a resumed channel has a new transport). The previous loop, if
+     * any, stops at its next interval.
+     */
+    pub fn restart(&mut self) {
+        self.epoch += 1;
+        self.is_stopped = false;
+        self.missed_pongs = 0;
+        self.outstanding_ping = None;
+    }
+
+    /**
+     * Called once per keepalive interval. Returns the Opaque value to send in
+     * the next Ping frame, or None if the peer has failed to answer
+     * `max_missed_pongs` Pings in a row.
+     */
+    fn next_ping(&mut self, max_missed_pongs: u32) -> Option<u64> {
+        if self.outstanding_ping.is_some() {
+            self.missed_pongs += 1;
+            if self.missed_pongs >= max_missed_pongs {
+                return None;
+            }
+        }
+
+        let opaque = self.next_opaque;
+        self.next_opaque = self.next_opaque.wrapping_add(1);
+        self.outstanding_ping = Some((opaque, Instant::now()));
+        Some(opaque)
+    }
+}
+
+/**
+ * Periodically sends a Ping frame to the peer until either the keepalive is
+ * stopped or the sender is dropped. If the peer misses too many Pongs in a
+ * row, every Tube on the channel is aborted with
+ * AbortReason::KeepaliveTimeout and `on_dead` is called.
+ *
+ * Does nothing if `config.keepalive_interval` is None, and no Pings are sent
+ * until the Keepalive has been marked as negotiated.
+ */
+pub(in crate) fn spawn_keepalive<F>(
+    keepalive: Arc<Mutex<Keepalive>>,
+    sender: Weak<tokio::sync::Mutex<hyper::body::Sender>>,
+    tube_managers: Arc<Mutex<HashMap<u64, Arc<Mutex<tube::TubeManager>>>>>,
+    config: &ChannelConfig,
+    on_dead: F,
+) where F: FnOnce() + Send + 'static {
+    let interval = match config.keepalive_interval {
+        Some(interval) => interval,
+        None => return,
+    };
+    let max_missed_pongs = config.max_missed_pongs;
+    let epoch = keepalive.lock().unwrap().epoch;
+
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+
+            let sender = match sender.upgrade() {
+                Some(sender) => sender,
+                None => return,
+            };
+            let next_ping = {
+                let mut keepalive = keepalive.lock().unwrap();
+                if keepalive.is_stopped || keepalive.epoch != epoch {
+                    return;
+                }
+                if !keepalive.is_negotiated {
+                    continue;
+                }
+                keepalive.next_ping(max_missed_pongs)
+            };
+
+            let opaque = match next_ping {
+                Some(opaque) => opaque,
+                None => {
+                    log::error!(
+                        "Peer missed {} Pongs in a row. Declaring the channel \
+                         dead.",
+                        max_missed_pongs,
+                    );
+                    keepalive.lock().unwrap().stop();
+                    tube::abort_all_tube_managers(
+                        &tube_managers,
+                        frame::AbortReason::KeepaliveTimeout,
+                        tube::TubeEvent::Abort(frame::AbortReason::KeepaliveTimeout),
+                    );
+                    on_dead();
+                    return;
+                },
+            };
+
+            let ping_frame_data = match frame::encode::ping_frame(opaque) {
+                Ok(data) => data,
+                Err(e) => {
+                    log::error!("Error encoding Ping frame: {:?}", e);
+                    return;
+                },
+            };
+            let mut sender = sender.lock().await;
+            log::trace!("Sending Ping(opaque={})...", opaque);
+            if let Err(e) = sender.send_data(ping_frame_data).await {
+                log::trace!("Error sending Ping frame: {:?}", e);
+                return;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod keepalive_tests {
+    use super::*;
+
+    #[test]
+    fn pong_for_outstanding_ping_measures_rtt() {
+        let mut keepalive = Keepalive::new();
+        assert_eq!(keepalive.rtt(), None);
+
+        let opaque = keepalive.next_ping(3).unwrap();
+        keepalive.handle_pong(opaque + 1);
+        assert_eq!(keepalive.rtt(), None);
+
+        keepalive.handle_pong(opaque);
+        assert!(keepalive.rtt().is_some());
+        assert!(keepalive.outstanding_ping.is_none());
+    }
+
+    #[test]
+    fn declares_dead_after_max_missed_pongs() {
+        let mut keepalive = Keepalive::new();
+        assert!(keepalive.next_ping(3).is_some());
+        assert!(keepalive.next_ping(3).is_some());
+        assert!(keepalive.next_ping(3).is_some());
+        assert_eq!(keepalive.next_ping(3), None);
+    }
+
+    #[test]
+    fn answered_pong_resets_missed_pong_count() {
+        let mut keepalive = Keepalive::new();
+        keepalive.next_ping(2).unwrap();
+        let opaque = keepalive.next_ping(2).unwrap();
+        keepalive.handle_pong(opaque);
+        assert!(keepalive.next_ping(2).is_some());
+        assert!(keepalive.next_ping(2).is_some());
+        assert_eq!(keepalive.next_ping(2), None);
+    }
+
+    #[tokio::test]
+    async fn only_pings_once_negotiated() {
+        use futures::FutureExt;
+        use hyper::body::HttpBody;
+
+        let (body_sender, mut body) = hyper::Body::channel();
+        let body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+        let keepalive = Arc::new(Mutex::new(Keepalive::new()));
+        let config = ChannelConfig {
+            keepalive_interval: Some(Duration::from_millis(1)),
+            ..ChannelConfig::default()
+        };
+        spawn_keepalive(
+            keepalive.clone(),
+            Arc::downgrade(&body_sender),
+            Arc::new(Mutex::new(HashMap::new())),
+            &config,
+            || (),
+        );
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(body.data().now_or_never().is_none());
+
+        keepalive.lock().unwrap().set_negotiated(true);
+        let data = body.data().await.unwrap().unwrap();
+        let frames = frame::Decoder::new().decode(data).unwrap();
+        assert_eq!(Vec::from(frames), vec![frame::Frame::Ping { opaque: 0 }]);
+        keepalive.lock().unwrap().stop();
+    }
+
+    #[test]
+    fn restart_forgets_missed_pongs() {
+        let mut keepalive = Keepalive::new();
+        keepalive.next_ping(2).unwrap();
+        keepalive.next_ping(2).unwrap();
+        keepalive.stop();
+
+        keepalive.restart();
+        assert!(!keepalive.is_stopped);
+        assert_eq!(keepalive.epoch, 1);
+        assert!(keepalive.next_ping(2).is_some());
+        assert!(keepalive.next_ping(2).is_some());
+        assert_eq!(keepalive.next_ping(2), None);
+    }
+}
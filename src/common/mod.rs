@@ -1,16 +1,65 @@
+mod ack_batch;
+pub mod capture;
+mod channel_config;
+mod compression;
+mod drain;
+mod flow_control;
+mod go_away;
 mod inverted_future;
+mod keepalive;
+mod send_scheduler;
+mod session;
+mod settings;
 mod unique_id_manager;
 
+pub(in crate) use ack_batch::MAX_BATCHED_ACK_IDS;
+pub(in crate) use ack_batch::ack_batch_frame;
+pub(in crate) use ack_batch::spawn_ack_batch_flush;
+pub use channel_config::ChannelConfig;
+pub use channel_config::DEFAULT_ACK_BATCH_DELAY;
+pub use channel_config::DEFAULT_INITIAL_WINDOW_SIZE;
+pub use channel_config::DEFAULT_KEEPALIVE_INTERVAL;
+pub use channel_config::DEFAULT_MAX_CONCURRENT_TUBES;
+pub use channel_config::DEFAULT_MAX_MESSAGE_SIZE;
+pub use channel_config::DEFAULT_MAX_MISSED_PONGS;
+pub use channel_config::DEFAULT_SETTINGS_TIMEOUT;
+pub use channel_config::DEFAULT_TUBE_ACCEPTANCE_TIMEOUT;
+pub use compression::COMPRESSION_HEADER;
+pub use compression::Compression;
+pub use compression::DecompressError;
+pub use compression::MIN_COMPRESSED_PAYLOAD_SIZE;
+pub use drain::DrainError;
+#[cfg(any(feature = "client", feature = "server"))] pub(in crate) use drain::send_drain;
+#[cfg(any(feature = "client", feature = "server"))] pub(in crate) use drain::spawn_drain_deadline;
+pub use flow_control::FLOW_CONTROL_HEADER;
+pub use flow_control::FlowControl;
+pub(in crate) use flow_control::spawn_window_update;
 pub mod frame;
+pub use go_away::GoAwayError;
+#[cfg(any(feature = "client", feature = "server"))] pub(in crate) use go_away::send_go_away;
 pub use inverted_future::InvertedFuture;
 pub use inverted_future::InvertedFutureResolver;
+#[cfg(any(feature = "client", feature = "server"))] pub(in crate) use keepalive::Keepalive;
+#[cfg(any(feature = "client", feature = "server"))] pub(in crate) use keepalive::spawn_keepalive;
+pub use send_scheduler::DEFAULT_TUBE_WEIGHT;
+pub use send_scheduler::PRIORITY_HEADER;
+pub(in crate) use send_scheduler::SendScheduler;
+pub(in crate) use send_scheduler::weight_from_header_value;
+#[cfg(feature = "server")] pub(in crate) use session::new_session_token;
+#[cfg(any(feature = "client", feature = "server"))] pub(in crate) use session::resume_transport;
+pub use session::SESSION_HEADER;
+#[cfg(feature = "client")] pub(in crate) use session::session_header_value;
+#[cfg(feature = "server")] pub(in crate) use session::session_token_from_header_value;
+pub use settings::NegotiatedSettings;
+pub use settings::SendSettingsError;
+#[cfg(any(feature = "client", feature = "server"))] pub(in crate) use settings::send_settings;
 pub mod tube;
 pub use unique_id_manager::UniqueId;
 pub use unique_id_manager::UniqueIdError;
 pub use unique_id_manager::UniqueIdManager;
 
 #[allow(dead_code)]
-#[derive(Copy,Clone,Debug)]
+#[derive(Copy,Clone,Debug,PartialEq)]
 pub enum PeerType {
     Client,
     Server,
@@ -0,0 +1,210 @@
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+
+/**
+ * The NewTube header that carries a Tube's initial Weight (e.g.
+ * `"tubez-priority": "200"`). Weights range from 1 to 255.
+ */
+pub const PRIORITY_HEADER: &str = "tubez-priority";
+
+/**
+ * The Weight of a Tube whose creator didn't specify one.
+ */
+pub const DEFAULT_TUBE_WEIGHT: u8 = 16;
+
+/**
+ * Parses the value of a PRIORITY_HEADER, returning None if it isn't a valid
+ * Weight.
+ */
+pub(in crate) fn weight_from_header_value(value: &str) -> Option<u8> {
+    match value.parse::<u8>() {
+        Ok(0) | Err(_) => None,
+        Ok(weight) => Some(weight),
+    }
+}
+
+/**
+ * Scales the virtual time a frame occupies so that weights divide it evenly
+ * enough to be fair at small frame sizes.
+ */
+const VIRTUAL_TIME_SCALE: u64 = 256;
+
+#[derive(Debug)]
+struct SchedulerState {
+    is_sending: bool,
+    next_seq: u64,
+    virtual_time: u64,
+    /**
+     * The (start tag, arrival order) of each frame waiting for its turn.
+     */
+    waiting: BTreeSet<(u64, u64)>,
+}
+
+/**
+ * Decides the order in which the Tubes on a channel get to send their
+ * payload frames, so that a Tube streaming a large payload can't starve the
+ * other Tubes on the channel.
+ *
+ * Frames are scheduled by start-time fair queuing: each frame is tagged with
+ * the virtual time at which its Tube becomes eligible to send it, and a Tube
+ * that sends N bytes with Weight W pushes its next frame's tag back by N/W.
+ * Whenever the channel frees up, the waiting frame with the earliest tag is
+ * sent next. Tubes that have nothing to send don't accumulate credit while
+ * idle.
+ *
+ * Control frames (acks, aborts, pings, etc) are small and time-sensitive, so
+ * they bypass the scheduler entirely.
+ */
+#[derive(Debug)]
+pub(in crate) struct SendScheduler {
+    notify: tokio::sync::Notify,
+    state: Mutex<SchedulerState>,
+}
+impl SendScheduler {
+    pub fn new() -> Self {
+        SendScheduler {
+            notify: tokio::sync::Notify::new(),
+            state: Mutex::new(SchedulerState {
+                is_sending: false,
+                next_seq: 0,
+                virtual_time: 0,
+                waiting: BTreeSet::new(),
+            }),
+        }
+    }
+
+    /**
+     * Waits until it is a Tube's turn to send a frame carrying `len` bytes.
+     * `finish_tag` is the Tube's own scheduling state; it starts at 0 and is
+     * updated every time the Tube acquires a turn. The turn ends when the
+     * returned SendTurn is dropped.
+     */
+    pub(in crate) async fn acquire(
+        &self,
+        finish_tag: &mut u64,
+        weight: u8,
+        len: usize,
+    ) -> SendTurn<'_> {
+        let key = {
+            let mut state = self.state.lock().unwrap();
+            let start_tag = state.virtual_time.max(*finish_tag);
+            let cost = (len.max(1) as u64) * VIRTUAL_TIME_SCALE / u64::from(weight.max(1));
+            *finish_tag = start_tag.saturating_add(cost);
+
+            if !state.is_sending && state.waiting.is_empty() {
+                state.is_sending = true;
+                state.virtual_time = start_tag;
+                return SendTurn { scheduler: self };
+            }
+
+            let key = (start_tag, state.next_seq);
+            state.next_seq += 1;
+            state.waiting.insert(key);
+            key
+        };
+
+        let mut waiter = Waiter { scheduler: self, key, is_waiting: true };
+        loop {
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            {
+                let mut state = self.state.lock().unwrap();
+                if !state.is_sending && state.waiting.first() == Some(&key) {
+                    state.waiting.remove(&key);
+                    state.is_sending = true;
+                    state.virtual_time = key.0;
+                    waiter.is_waiting = false;
+                    return SendTurn { scheduler: self };
+                }
+            }
+            notified.await;
+        }
+    }
+}
+impl Default for SendScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/**
+ * Removes a frame from the queue if the task waiting to send it gives up
+ * (e.g. because Tube::send() was cancelled).
+ */
+struct Waiter<'a> {
+    scheduler: &'a SendScheduler,
+    key: (u64, u64),
+    is_waiting: bool,
+}
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        if self.is_waiting {
+            self.scheduler.state.lock().unwrap().waiting.remove(&self.key);
+            self.scheduler.notify.notify_waiters();
+        }
+    }
+}
+
+/**
+ * A Tube's turn to send a frame. The next waiting frame is scheduled once
+ * this is dropped.
+ */
+pub(in crate) struct SendTurn<'a> {
+    scheduler: &'a SendScheduler,
+}
+impl Drop for SendTurn<'_> {
+    fn drop(&mut self) {
+        self.scheduler.state.lock().unwrap().is_sending = false;
+        self.scheduler.notify.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod send_scheduler_tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn weight_header_values_must_be_nonzero_u8s() {
+        assert_eq!(weight_from_header_value("1"), Some(1));
+        assert_eq!(weight_from_header_value("255"), Some(255));
+        assert_eq!(weight_from_header_value("0"), None);
+        assert_eq!(weight_from_header_value("256"), None);
+        assert_eq!(weight_from_header_value("high"), None);
+    }
+
+    #[tokio::test]
+    async fn heavier_tubes_get_a_larger_share_of_turns() {
+        let scheduler = Arc::new(SendScheduler::new());
+        let sent = Arc::new(Mutex::new(vec![]));
+
+        // Hold a turn so that both tubes queue up behind it.
+        let blocker_turn = scheduler.acquire(&mut 0, DEFAULT_TUBE_WEIGHT, 1).await;
+        let mut tasks = vec![];
+        for (name, weight) in [("light", 1), ("heavy", 3)] {
+            let scheduler = scheduler.clone();
+            let sent = sent.clone();
+            tasks.push(tokio::spawn(async move {
+                let mut finish_tag = 0;
+                for _ in 0..8 {
+                    let _turn = scheduler.acquire(&mut finish_tag, weight, 1000).await;
+                    sent.lock().unwrap().push(name);
+                    tokio::task::yield_now().await;
+                }
+            }));
+        }
+        while scheduler.state.lock().unwrap().waiting.len() < 2 {
+            tokio::task::yield_now().await;
+        }
+        drop(blocker_turn);
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let sent = sent.lock().unwrap();
+        let heavy_turns = sent[..8].iter().filter(|name| **name == "heavy").count();
+        assert_eq!(heavy_turns, 6);
+    }
+}
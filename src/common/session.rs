@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use crate::common::ChannelConfig;
+use crate::common::frame;
+use crate::common::settings;
+use crate::common::tube;
+
+/**
+ * The HTTP header a client uses to resume a channel on a new transport. Its
+ * value is the token from the server's Session frame, as 32 hex digits.
+ */
+pub const SESSION_HEADER: &str = "tubez-session";
+
+#[allow(dead_code)]
+#[derive(Debug)]
+pub(in crate) enum ResumeError {
+    FrameEncodeError(frame::encode::FrameEncodeError),
+    TransportError(hyper::Error),
+}
+
+/**
+ * Generates an unguessable token identifying a resumable channel. Anyone who
+ * knows the token can take over the channel, so it is read from the operating
+ * system's cryptographically secure random number generator.
+ */
+#[cfg(feature = "server")]
+pub(in crate) fn new_session_token() -> Result<u128, getrandom::Error> {
+    let mut token = [0; 16];
+    getrandom::fill(&mut token)?;
+    Ok(u128::from_be_bytes(token))
+}
+
+#[cfg(feature = "client")]
+pub(in crate) fn session_header_value(token: u128) -> String {
+    format!("{:032x}", token)
+}
+
+#[cfg(feature = "server")]
+pub(in crate) fn session_token_from_header_value(value: &str) -> Option<u128> {
+    if value.len() != 32 {
+        return None;
+    }
+    u128::from_str_radix(value, 16).ok()
+}
+
+/**
+ * Moves a resumed channel onto the transport behind `new_sender`. This peer's
+ * Settings frame is sent first, followed by everything in each Tube's replay
+ * log (see TubeManager::replay_log). The channel's sender is held throughout
+ * so that no other frame can be sent ahead of them.
+ *
+ * The peer may have received some of those frames already. Payloads are 
+ * numbered with PayloadSequence frames (which is why Features::
+ * SESSION_RESUMPTION is only used along with Features::PAYLOAD_SEQUENCES), so
+ * the peer only delivers each of them once, and it ignores repeated NewTube, 
+ * NewTubeAccepted and HasFinishedSending frames.
+ */
+pub(in crate) async fn resume_transport(
+    sender: &Arc<tokio::sync::Mutex<hyper::body::Sender>>,
+    new_sender: hyper::body::Sender,
+    config: &ChannelConfig,
+    tube_managers: &Mutex<HashMap<u64, Arc<Mutex<tube::TubeManager>>>>,
+) -> Result<(), ResumeError> {
+    let settings_frame_data = match settings::settings_frame(config) {
+        Ok(data) => data,
+        Err(e) => return Err(ResumeError::FrameEncodeError(e)),
+    };
+
+    let mut sender = sender.lock().await;
+    *sender = new_sender;
+    let replayed_frames = tube::resume_tube_managers(tube_managers);
+
+    log::trace!("Sending Settings(version={})...", frame::PROTOCOL_VERSION);
+    if let Err(e) = sender.send_data(settings_frame_data).await {
+        return Err(ResumeError::TransportError(e));
+    }
+    log::trace!("Sending {} unsettled frames again...", replayed_frames.len());
+    for frame_data in replayed_frames {
+        if let Err(e) = sender.send_data(frame_data).await {
+            return Err(ResumeError::TransportError(e));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod session_tests {
+    use super::*;
+
+    use hyper::body::HttpBody;
+
+    use crate::common::frame::Decoder;
+
+    #[cfg(all(feature = "client", feature = "server"))]
+    #[test]
+    fn session_tokens_round_trip_through_header_values() {
+        let token = new_session_token().unwrap();
+        assert_ne!(token, new_session_token().unwrap());
+
+        let value = session_header_value(token);
+        assert_eq!(value.len(), 32);
+        assert_eq!(session_token_from_header_value(&value), Some(token));
+        assert_eq!(session_token_from_header_value("abc"), None);
+        assert_eq!(session_token_from_header_value(&"z".repeat(32)), None);
+    }
+
+    #[tokio::test]
+    async fn resuming_sends_settings_then_replay_log() {
+        let (old_sender, _old_body) = hyper::Body::channel();
+        let sender = Arc::new(tokio::sync::Mutex::new(old_sender));
+        let (new_sender, mut new_body) = hyper::Body::channel();
+
+        let payload_frame = frame::encode::payload_frame(1, Some(4), b"hello").unwrap();
+        let mut tube_mgr = tube::TubeManager::new();
+        tube_mgr.replay_log.push(tube::ReplayEntry::Payload {
+            ack_id: 4,
+            ack_id_reservation: None,
+            frames: vec![payload_frame],
+        });
+        tube_mgr.partial_payload.extend_from_slice(b"partial");
+        let tube_mgr = Arc::new(Mutex::new(tube_mgr));
+        let tube_managers = Mutex::new(HashMap::from([(1, tube_mgr.clone())]));
+
+        let config = ChannelConfig::default();
+        let advertised_features = config.advertised_features();
+        // The new transport only accepts frames while its body is being read.
+        tokio::spawn(async move {
+            resume_transport(&sender, new_sender, &config, &tube_managers).await.unwrap();
+        });
+
+        let mut decoder = Decoder::new();
+        let mut frames = vec![];
+        while let Some(data) = new_body.data().await {
+            frames.extend(decoder.decode(data.unwrap()).unwrap());
+        }
+        assert_eq!(frames.len(), 2);
+        assert!(tube_mgr.lock().unwrap().partial_payload.is_empty());
+        match &frames[0] {
+            frame::Frame::Settings { features, .. } =>
+                assert_eq!(*features, advertised_features),
+            unexpected => panic!("Unexpected frame: {:?}", unexpected),
+        }
+        assert_eq!(frames[1], frame::Frame::Payload {
+            tube_id: 1,
+            ack_id: Some(4),
+            processing_ack: false,
+            data: "hello".into(),
+        });
+    }
+}
@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use crate::common::ChannelConfig;
+use crate::common::frame;
+
+#[derive(Debug)]
+pub enum SendSettingsError {
+    FrameEncodeError(frame::encode::FrameEncodeError),
+    TransportError(hyper::Error),
+}
+
+/**
+ * The outcome of the Settings frames exchanged by both peers at the start of a
+ * channel.
+ */
+#[derive(Clone,Debug,PartialEq)]
+pub struct NegotiatedSettings {
+    /**
+     * The protocol version both peers will speak: the lower of the two
+     * advertised versions.
+     */
+    pub version: u8,
+
+    /**
+     * The optional features supported by both peers.
+     */
+    pub features: frame::Features,
+
+    /**
+     * The largest frame (including its header) the peer will accept.
+     */
+    pub peer_max_frame_size: u32,
+
+    /**
+     * The most Tubes the peer will allow to be open on the channel at once.
+     */
+    pub peer_max_concurrent_tubes: u16,
+
+    /**
+     * The bytes of payload data the peer will accept on each flow-controlled
+     * Tube before it grants more credit.
+     */
+    pub peer_initial_window_size: u32,
+}
+impl NegotiatedSettings {
+    pub(in crate) fn new(
+        local_features: frame::Features,
+        peer_version: u8,
+        peer_features: frame::Features,
+        peer_max_frame_size: u32,
+        peer_max_concurrent_tubes: u16,
+        peer_initial_window_size: u32,
+    ) -> Self {
+        NegotiatedSettings {
+            version: peer_version.min(frame::PROTOCOL_VERSION),
+            features: local_features.negotiate(peer_features),
+            peer_max_frame_size,
+            peer_max_concurrent_tubes,
+            peer_initial_window_size,
+        }
+    }
+}
+
+pub(in crate) fn settings_frame(
+    config: &ChannelConfig,
+) -> Result<Bytes, frame::encode::FrameEncodeError> {
+    let max_frame_size =
+        u32::try_from(config.decoder_config.max_frame_size).unwrap_or(u32::MAX);
+    frame::encode::settings_frame(
+        frame::PROTOCOL_VERSION,
+        config.advertised_features(),
+        max_frame_size,
+        config.max_concurrent_tubes,
+        config.initial_window_size,
+    )
+}
+
+/**
+ * Sends this peer's Settings frame, which must be the first frame sent on a
+ * channel.
+ */
+pub(in crate) async fn send_settings(
+    sender: &Arc<tokio::sync::Mutex<hyper::body::Sender>>,
+    config: &ChannelConfig,
+) -> Result<(), SendSettingsError> {
+    let frame_data = match settings_frame(config) {
+        Ok(data) => data,
+        Err(e) => return Err(SendSettingsError::FrameEncodeError(e)),
+    };
+
+    let mut sender = sender.lock().await;
+    log::trace!("Sending Settings(version={})...", frame::PROTOCOL_VERSION);
+    match sender.send_data(frame_data).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(SendSettingsError::TransportError(e)),
+    }
+}
+
+#[cfg(test)]
+mod negotiated_settings_tests {
+    use super::*;
+
+    #[test]
+    fn negotiates_lowest_version_and_common_features() {
+        let settings = NegotiatedSettings::new(
+            frame::Features::supported(),
+            frame::PROTOCOL_VERSION + 1,
+            frame::Features::KEEPALIVE.union(frame::Features::from_bits(1 << 31)),
+            1024,
+            10,
+            4096,
+        );
+        assert_eq!(settings, NegotiatedSettings {
+            version: frame::PROTOCOL_VERSION,
+            features: frame::Features::KEEPALIVE,
+            peer_max_frame_size: 1024,
+            peer_max_concurrent_tubes: 10,
+            peer_initial_window_size: 4096,
+        });
+    }
+
+    #[test]
+    fn resumption_requires_sequences_and_excludes_flow_control() {
+        let resumable = frame::Features::SESSION_RESUMPTION
+            .union(frame::Features::PAYLOAD_SEQUENCES)
+            .union(frame::Features::FLOW_CONTROL);
+        assert_eq!(
+            resumable.negotiate(frame::Features::supported()),
+            frame::Features::SESSION_RESUMPTION.union(frame::Features::PAYLOAD_SEQUENCES),
+        );
+        assert_eq!(
+            resumable.negotiate(frame::Features::supported()
+                .difference(frame::Features::PAYLOAD_SEQUENCES)),
+            frame::Features::FLOW_CONTROL,
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use crate::common::frame;
+use super::ProcessingAck;
+
+/**
+ * Writes the control frames (WindowUpdates and acks) that a Tube produces
+ * while it is being polled, where it can't wait on the channel's sender.
+ * Frames are queued without blocking, and written in the order they were
+ * queued by a single task that runs until the Tube (and any TubeIo wrapping
+ * it) is dropped.
+ */
+#[derive(Clone,Debug)]
+pub(in crate) struct ControlFrameWriter {
+    queue: tokio::sync::mpsc::UnboundedSender<Bytes>,
+}
+impl ControlFrameWriter {
+    pub fn new(sender: Arc<tokio::sync::Mutex<hyper::body::Sender>>) -> Self {
+        let (queue, mut queued_frames) = tokio::sync::mpsc::unbounded_channel::<Bytes>();
+        tokio::spawn(async move {
+            while let Some(frame_data) = queued_frames.recv().await {
+                let mut sender = sender.lock().await;
+                if let Err(e) = sender.send_data(frame_data).await {
+                    log::trace!("Error sending queued control frame: {:?}", e);
+                    return;
+                }
+            }
+        });
+        ControlFrameWriter { queue }
+    }
+
+    fn queue(&self, frame_data: Bytes) {
+        // The writer only stops early if the transport has failed, in which
+        // case there is nobody left to send the frame to.
+        let _ = self.queue.send(frame_data);
+    }
+
+    pub fn queue_processing_ack(&self, processing_ack: &ProcessingAck) {
+        match processing_ack.ack_frame() {
+            Ok(frame_data) => {
+                processing_ack.record_processed();
+                self.queue(frame_data);
+            },
+            Err(e) => log::error!("Error encoding processing ack frame: {:?}", e),
+        }
+    }
+
+    pub fn queue_window_update(&self, tube_id: u64, credit: u32) {
+        log::trace!("Queueing WindowUpdate(tube_id={}, credit={})...", tube_id, credit);
+        match frame::encode::window_update_frame(tube_id, credit) {
+            Ok(frame_data) => self.queue(frame_data),
+            Err(e) => log::error!("Error encoding WindowUpdate frame: {:?}", e),
+        }
+    }
+}
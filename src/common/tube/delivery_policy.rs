@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+/**
+ * How hard Tube::send() tries to get a payload acked before giving up with
+ * SendError::TimedOutWaitingOnAck (see Tube::set_delivery_policy()).
+ *
+ * Each attempt waits up to send()'s ack_timeout for the ack. If it doesn't
+ * arrive, the payload is sent again after `backoff` (which doubles with each
+ * retry), up to `max_retries` times or until `deadline` has elapsed since
+ * send() was called, whichever comes first. The error reports how many 
+ * attempts were made and how long they took in all.
+ *
+ * If both peers advertised Features::PAYLOAD_SEQUENCES, a payload that is
+ * sent again carries the same sequence number as before and the peer drops
+ * any copy it has already delivered, so the peer's application sees each
+ * payload once. Otherwise a payload whose ack (rather than the payload
+ * itself) was lost is delivered more than once.
+ */
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct DeliveryPolicy {
+    pub max_retries: u32,
+    pub backoff: Duration,
+    pub deadline: Option<Duration>,
+}
+impl DeliveryPolicy {
+    /**
+     * Gives up as soon as the first attempt times out.
+     */
+    pub const fn no_retries() -> Self {
+        DeliveryPolicy {
+            max_retries: 0,
+            backoff: Duration::ZERO,
+            deadline: None,
+        }
+    }
+
+    pub const fn new_with_retries(
+        max_retries: u32,
+        backoff: Duration,
+        deadline: Option<Duration>,
+    ) -> Self {
+        DeliveryPolicy {
+            max_retries,
+            backoff,
+            deadline,
+        }
+    }
+}
+impl Default for DeliveryPolicy {
+    fn default() -> Self {
+        DeliveryPolicy::no_retries()
+    }
+}
@@ -1,12 +1,28 @@
+mod control_frame_writer;
+mod delivery_policy;
+mod pending_tube;
+mod processing_ack;
 mod tube;
 mod tube_event;
+mod tube_io;
 mod tube_manager;
+mod tube_receiver;
 
+pub use delivery_policy::DeliveryPolicy;
+pub use pending_tube::PendingTube;
+pub use processing_ack::ProcessingAck;
 pub use tube::error;
 pub use tube::Tube;
 pub use tube_event::TubeEvent;
 pub use tube_event::TubeEvent_StreamError;
 pub use tube_event::TubeEventTag;
+pub use tube_io::TubeIo;
 
+pub(in crate) use tube_manager::abort_all_tube_managers;
+pub(in crate) use tube_manager::abort_open_tube_managers;
+pub(in crate) use tube_manager::drain_all_tube_managers;
+pub(in crate) use tube_manager::resume_tube_managers;
 pub(in crate::common) use tube_manager::TubeCompletionState;
+pub(in crate) use tube_manager::NewTubeResponse;
+pub(in crate) use tube_manager::ReplayEntry;
 pub use tube_manager::TubeManager;
@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use crate::common::frame;
+use super::error;
+use super::ReplayEntry;
+use super::Tube;
+use super::TubeCompletionState;
+use super::TubeManager;
+
+/**
+ * A Tube that the client has asked to make, handed to the application via
+ * ChannelEvent::NewTube. The application either accept()s it (getting back
+ * the Tube) or reject()s it.
+ *
+ * If Features::NEWTUBE_HANDSHAKE wasn't negotiated the client is never told
+ * about the acceptance, and rejecting the Tube aborts it instead.
+ */
+#[derive(Debug)]
+pub struct PendingTube {
+    handshake: bool,
+    headers: HashMap<String, String>,
+    sender: Arc<tokio::sync::Mutex<hyper::body::Sender>>,
+    tube: Tube,
+    tube_manager: Arc<Mutex<TubeManager>>,
+    tube_managers: Arc<Mutex<HashMap<u64, Arc<Mutex<TubeManager>>>>>,
+}
+impl PendingTube {
+    pub(in crate) fn new(
+        tube: Tube,
+        headers: HashMap<String, String>,
+        handshake: bool,
+        sender: Arc<tokio::sync::Mutex<hyper::body::Sender>>,
+        tube_manager: Arc<Mutex<TubeManager>>,
+        tube_managers: Arc<Mutex<HashMap<u64, Arc<Mutex<TubeManager>>>>>,
+    ) -> Self {
+        PendingTube {
+            handshake,
+            headers,
+            sender,
+            tube,
+            tube_manager,
+            tube_managers,
+        }
+    }
+
+    /**
+     * Accepts the Tube, sending `headers` back to the client if it is
+     * waiting on the response (see Channel::make_tube_and_await_acceptance()).
+     *
+     * If the client requested a compression algorithm that we support, it is
+     * echoed back in the COMPRESSION_HEADER so that the client knows it may 
+     * compress its payloads.
+     */
+    pub async fn accept(
+        self,
+        mut headers: HashMap<String, String>,
+    ) -> Result<Tube, error::AcceptError> {
+        if !self.handshake {
+            return Ok(self.tube);
+        }
+
+        match self.tube_manager.lock().unwrap().compression {
+            Some(compression) => headers.insert(
+                crate::common::COMPRESSION_HEADER.to_string(), 
+                compression.header_value().to_string(),
+            ),
+            None => headers.remove(crate::common::COMPRESSION_HEADER),
+        };
+
+        let tube_id = self.tube.get_id();
+        let frame_data = match frame::encode::newtube_accepted_frame(tube_id, &headers) {
+            Ok(frame_data) => frame_data,
+            Err(e) => return Err(error::AcceptError::FrameEncodeError(e)),
+        };
+        {
+            let mut sender = self.sender.lock().await;
+            let resumable = {
+                let mut tube_mgr = self.tube_manager.lock().unwrap();
+                if tube_mgr.resumable {
+                    tube_mgr.replay_log.push(ReplayEntry::NewTubeAccepted(frame_data.clone()));
+                }
+                tube_mgr.resumable
+            };
+            log::trace!("Sending NewTubeAccepted(tube_id={})...", tube_id);
+            match sender.send_data(frame_data).await {
+                Ok(_) => (),
+                // It is sent again once the channel resumes.
+                Err(_) if resumable => (),
+                Err(e) => return Err(error::AcceptError::TransportError(e)),
+            }
+        }
+        Ok(self.tube)
+    }
+
+    pub fn get_id(&self) -> u64 {
+        self.tube.get_id()
+    }
+
+    /**
+     * The headers the client sent along with the NewTube frame.
+     */
+    pub fn headers(&self) -> &HashMap<String, String> {
+        &self.headers
+    }
+
+    #[cfg(feature = "server")]
+    pub(in crate) fn into_tube(self) -> Tube {
+        self.tube
+    }
+
+    pub async fn reject(self, code: u16) -> Result<(), error::RejectError> {
+        self.reject_with_headers(code, HashMap::new()).await
+    }
+
+    /**
+     * Refuses the Tube with an application-defined `code` and response
+     * headers. If the client didn't advertise Features::NEWTUBE_HANDSHAKE the
+     * Tube is aborted with AbortReason::ApplicationAbort instead.
+     *
+     * The Tube stops being tracked as soon as it is rejected, so any frames 
+     * the client sent on it before the rejection reached it (e.g. payloads 
+     * sent without waiting on acceptance) are dropped, and logged as 
+     * FrameHandlerError::UntrackedTubeId errors.
+     */
+    pub async fn reject_with_headers(
+        mut self,
+        code: u16,
+        headers: HashMap<String, String>,
+    ) -> Result<(), error::RejectError> {
+        if !self.handshake {
+            return match self.tube.abort().await {
+                Ok(()) => Ok(()),
+                Err(e) => Err(error::RejectError::AbortError(e)),
+            };
+        }
+
+        let tube_id = self.tube.get_id();
+        let frame_data = match frame::encode::newtube_rejected_frame(tube_id, code, &headers) {
+            Ok(frame_data) => frame_data,
+            Err(e) => return Err(error::RejectError::FrameEncodeError(e)),
+        };
+
+        // A rejected Tube is finished as far as both peers are concerned, so
+        // mark it as such before the Tube is dropped (which would otherwise
+        // abort it).
+        self.tube_manager.lock().unwrap().completion_state =
+            TubeCompletionState::AbortedFromLocal(frame::AbortReason::ApplicationAbort);
+        self.tube_managers.lock().unwrap().remove(&tube_id);
+
+        let mut sender = self.sender.lock().await;
+        log::trace!("Sending NewTubeRejected(tube_id={}, code={})...", tube_id, code);
+        if let Err(e) = sender.send_data(frame_data).await {
+            return Err(error::RejectError::TransportError(e));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod pending_tube_tests {
+    use super::*;
+
+    use hyper::body::HttpBody;
+
+    use crate::common::frame::Decoder;
+    use crate::common::PeerType;
+    use crate::common::SendScheduler;
+    use crate::common::UniqueId;
+
+    #[tokio::test]
+    async fn reject_sends_newtube_rejected_and_forgets_the_tube() {
+        let (body_sender, mut body) = hyper::Body::channel();
+        let body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+        let tube_manager = Arc::new(Mutex::new(TubeManager::new()));
+        let tube_managers = Arc::new(Mutex::new(HashMap::from([
+            (2, tube_manager.clone()),
+        ])));
+        let tube = Tube::new(
+            PeerType::Server,
+            UniqueId::new(2, None),
+            body_sender.clone(),
+            tube_manager.clone(),
+            Arc::new(SendScheduler::new()),
+            crate::common::DEFAULT_MAX_MESSAGE_SIZE,
+        );
+        let pending_tube = PendingTube::new(
+            tube,
+            HashMap::new(),
+            true,
+            body_sender,
+            tube_manager,
+            tube_managers.clone(),
+        );
+
+        pending_tube.reject(403).await.unwrap();
+        assert!(tube_managers.lock().unwrap().is_empty());
+
+        let frame_data = body.data().await.unwrap().unwrap();
+        let frames = Decoder::new().decode(frame_data).unwrap();
+        assert_eq!(Vec::from(frames), vec![
+            frame::Frame::NewTubeRejected { tube_id: 2, code: 403, headers: HashMap::new() },
+        ]);
+    }
+
+    #[tokio::test]
+    async fn accept_echoes_the_compression_algorithm() {
+        let (body_sender, mut body) = hyper::Body::channel();
+        let body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+        let mut tube_manager = TubeManager::new();
+        tube_manager.compression = Some(crate::common::Compression::Zstd);
+        let tube_manager = Arc::new(Mutex::new(tube_manager));
+        let tube = Tube::new(
+            PeerType::Server,
+            UniqueId::new(2, None),
+            body_sender.clone(),
+            tube_manager.clone(),
+            Arc::new(SendScheduler::new()),
+            crate::common::DEFAULT_MAX_MESSAGE_SIZE,
+        );
+        let pending_tube = PendingTube::new(
+            tube,
+            HashMap::new(),
+            true,
+            body_sender,
+            tube_manager,
+            Arc::new(Mutex::new(HashMap::new())),
+        );
+
+        let _tube = pending_tube.accept(HashMap::new()).await.unwrap();
+        let frame_data = body.data().await.unwrap().unwrap();
+        let frames = Decoder::new().decode(frame_data).unwrap();
+        assert_eq!(Vec::from(frames), vec![
+            frame::Frame::NewTubeAccepted { 
+                tube_id: 2, 
+                headers: HashMap::from([(
+                    crate::common::COMPRESSION_HEADER.to_string(), 
+                    "zstd".to_string(),
+                )]),
+            },
+        ]);
+    }
+}
@@ -0,0 +1,90 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Weak;
+
+use bytes::Bytes;
+
+use crate::common::frame;
+use super::error;
+use super::TubeManager;
+
+/**
+ * Delivered alongside a payload whose sender asked to be told once the
+ * payload has been processed (see Tube::send_with_processing_ack()). Call
+ * ack() once the application is done with the payload. If it is dropped
+ * without being acked, the sender eventually times out waiting on the ack.
+ */
+#[derive(Clone)]
+pub struct ProcessingAck {
+    ack_id: u64,
+    sender: Arc<tokio::sync::Mutex<hyper::body::Sender>>,
+    tube_id: u64,
+    tube_manager: Weak<Mutex<TubeManager>>,
+}
+impl ProcessingAck {
+    pub(in crate) fn new(
+        tube_id: u64,
+        ack_id: u64,
+        sender: Arc<tokio::sync::Mutex<hyper::body::Sender>>,
+        tube_manager: &Arc<Mutex<TubeManager>>,
+    ) -> Self {
+        ProcessingAck {
+            ack_id,
+            sender,
+            tube_id,
+            tube_manager: Arc::downgrade(tube_manager),
+        }
+    }
+
+    pub(in crate) fn ack_frame(&self) -> Result<Bytes, frame::encode::FrameEncodeError> {
+        frame::encode::payload_ack_frame(self.tube_id, self.ack_id, true)
+    }
+
+    /**
+     * Remembers that the payload has been processed on a resumable Tube, so 
+     * that its ack can be sent again if the peer sends the payload again (see
+     * TubeManager::processed_ack_ids).
+     */
+    pub(in crate) fn record_processed(&self) {
+        if let Some(tube_manager) = self.tube_manager.upgrade() {
+            let mut tube_mgr = tube_manager.lock().unwrap();
+            if tube_mgr.resumable {
+                tube_mgr.processed_ack_ids.insert(self.ack_id);
+            }
+        }
+    }
+
+    /**
+     * Tells the sender that the payload has been processed.
+     */
+    pub async fn ack(self) -> Result<(), error::AckError> {
+        let frame_data = match self.ack_frame() {
+            Ok(frame_data) => frame_data,
+            Err(e) => return Err(error::AckError::FrameEncodeError(e)),
+        };
+        self.record_processed();
+        let mut sender = self.sender.lock().await;
+        log::trace!(
+            "Sending PayloadAck(tube_id={}, ack_id={}, processed=true)...",
+            self.tube_id,
+            self.ack_id,
+        );
+        if let Err(e) = sender.send_data(frame_data).await {
+            return Err(error::AckError::TransportError(e));
+        }
+        Ok(())
+    }
+}
+impl std::fmt::Debug for ProcessingAck {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ProcessingAck")
+            .field("tube_id", &self.tube_id)
+            .field("ack_id", &self.ack_id)
+            .finish()
+    }
+}
+impl PartialEq for ProcessingAck {
+    fn eq(&self, other: &Self) -> bool {
+        self.tube_id == other.tube_id && self.ack_id == other.ack_id
+    }
+}
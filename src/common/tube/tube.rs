@@ -1,23 +1,43 @@
+use bytes::Bytes;
 use futures;
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::Duration;
 
+use crate::common::Compression;
 use crate::common::frame;
 use crate::common::InvertedFuture;
+use crate::common::MIN_COMPRESSED_PAYLOAD_SIZE;
 use crate::common::PeerType;
+use crate::common::SendScheduler;
 use crate::common::UniqueId;
 use crate::common::UniqueIdError;
 use crate::common::UniqueIdManager;
+use super::control_frame_writer::ControlFrameWriter;
+use super::DeliveryPolicy;
 use super::TubeEvent;
-use super::TubeEventTag;
+use super::tube_manager::ReplayEntry;
 use super::tube_manager::TubeCompletionState;
 use super::tube_manager::TubeManager;
+use super::tube_receiver::TubeReceiver;
 
 pub mod error {
     use super::Duration;
     use super::frame;
 
+    #[derive(Debug)]
+    pub enum AckError {
+        FrameEncodeError(frame::encode::FrameEncodeError),
+        TransportError(hyper::Error),
+    }
+
+    #[derive(Debug)]
+    pub enum AcceptError {
+        FrameEncodeError(frame::encode::FrameEncodeError),
+        TransportError(hyper::Error),
+    }
+
     #[derive(Debug)]
     pub enum AbortError {
         AlreadyAborted(frame::AbortReason),
@@ -32,27 +52,119 @@ pub mod error {
         FrameEncodeError(frame::encode::FrameEncodeError),
         InternalError(String),
         FatalTransportError(hyper::Error),
+        /**
+         * The peer didn't advertise Features::TRAILERS.
+         */
+        TrailersNotSupported,
         TubeAlreadyAborted(frame::AbortReason),
     }
 
+    #[derive(Debug)]
+    pub enum RejectError {
+        /**
+         * The peer didn't advertise Features::NEWTUBE_HANDSHAKE, so the Tube 
+         * was aborted instead of rejected...but the Abort couldn't be sent.
+         */
+        AbortError(AbortError),
+        FrameEncodeError(frame::encode::FrameEncodeError),
+        TransportError(hyper::Error),
+    }
+
+    #[derive(Debug)]
+    pub enum SetPriorityError {
+        FrameEncodeError(frame::encode::FrameEncodeError),
+        /**
+         * Weights range from 1 to 255.
+         */
+        InvalidWeight,
+        TransportError(hyper::Error),
+    }
+
     #[derive(Debug)]
     pub enum SendError {
         AckIdAlreadyInUseInternalError,
         AckIdsExhausted,
+        CompressionError(std::io::Error),
         FrameEncodeError(frame::encode::FrameEncodeError),
-        TimedOutWaitingOnAck(Duration),
+        MessageTooLarge {
+            size: usize,
+            max_message_size: usize,
+        },
+        /**
+         * The payload doesn't fit in a single frame that the peer will accept,
+         * and the peer didn't advertise Features::PAYLOAD_FRAGMENTATION.
+         */
+        PayloadFragmentationNotSupported,
+        /**
+         * The peer didn't advertise Features::PROCESSING_ACKS.
+         */
+        ProcessingAcksNotSupported,
+        /**
+         * The payload is larger than the flow control window the peer grants
+         * a Tube, and the peer didn't advertise 
+         * Features::PAYLOAD_FRAGMENTATION, so it could never be sent whole.
+         */
+        PayloadExceedsFlowControlWindow {
+            size: usize,
+            window: u32,
+        },
+        /**
+         * The payload was sent `attempts` times (see DeliveryPolicy) without
+         * being acked, and `elapsed` is how long was spent trying.
+         */
+        TimedOutWaitingOnAck {
+            attempts: u32,
+            elapsed: Duration,
+        },
+        /**
+         * The Tube was aborted while waiting on flow control credit to send 
+         * the payload.
+         */
+        TubeAborted(frame::AbortReason),
         TransportError(hyper::Error),
         UnknownTransportError,
     }
 }
 
+/**
+ * The most payload data for Tube `tube_id` that fits in a single Payload 
+ * frame with `ack_id`, given the largest frame the peer will accept.
+ */
+fn payload_chunk_capacity(tube_id: u64, ack_id: Option<u64>, peer_max_frame_size: usize) -> usize {
+    frame::encode::payload_frame_data_capacity(tube_id, ack_id)
+        .saturating_sub((3 + frame::MAX_FRAME_BODY_LEN).saturating_sub(peer_max_frame_size))
+}
+
 async fn send_abort(
     tube_id: &mut UniqueId,
     reason: frame::AbortReason,
     tube_manager: &Arc<Mutex<TubeManager>>,
     sender: &Arc<tokio::sync::Mutex<hyper::body::Sender>>,
 ) -> Result<(), error::AbortError> {
-    let frame_data = match frame::encode::abort_frame(tube_id.val(), reason.clone()) {
+    // Peers that don't support AbortReason::Application are sent the closest
+    // fixed reason instead.
+    let (abort_codes, peer_max_frame_size) = {
+        let tube_mgr = tube_manager.lock().unwrap();
+        (tube_mgr.abort_codes, tube_mgr.peer_max_frame_size)
+    };
+    let sent_reason = match &reason {
+        frame::AbortReason::Application { .. } if !abort_codes => 
+            frame::AbortReason::ApplicationError,
+        frame::AbortReason::Application { code, message: Some(message) } => {
+            let capacity = frame::encode::abort_message_capacity(tube_id.val())
+                .saturating_sub(
+                    (3 + frame::MAX_FRAME_BODY_LEN).saturating_sub(peer_max_frame_size)
+                );
+            frame::AbortReason::Application { 
+                code: *code,
+                message: Some(
+                    frame::encode::truncate_on_char_boundary(message, capacity).to_string()
+                ),
+            }
+        },
+        reason => reason.clone(),
+    };
+    let frame_data = match frame::encode::abort_frame(tube_id.val(), sent_reason) {
         Ok(frame_data) => frame_data,
         Err(e) => return Err(error::AbortError::FrameEncodeError(e)),
     };
@@ -73,14 +185,34 @@ async fn send_abort(
         tube_mgr.completion_state = TubeCompletionState::AbortedFromLocal(reason);
         log::trace!("Tracking Tube(id={}) as a pending abort...", tube_id);
         tube_mgr.abort_pending_id_reservation = Some(tube_id.take());
+        if let Some(flow_control) = &tube_mgr.flow_control {
+            flow_control.wake_senders();
+        }
     };
 
     // TODO: Stick a timeout on these awaits so that some kind of pathological 
     //       hyper issue doesn't block the tube_mgr Mutex forever or something
     let mut sender = sender.lock().await;
+    let resumable = {
+        // Nothing else sent on an aborted Tube needs to reach the peer.
+        let mut tube_mgr = tube_manager.lock().unwrap();
+        if tube_mgr.resumable {
+            tube_mgr.replay_log.clear();
+            tube_mgr.replay_log.push(ReplayEntry::Abort(frame_data.clone()));
+        }
+        tube_mgr.resumable
+    };
     log::trace!("Sending Abort(tube_id={})...", tube_id);
-    match sender.send_data(frame_data.into()).await {
+    match sender.send_data(frame_data).await {
         Ok(_) => Ok(()),
+        Err(_) if resumable => {
+            log::trace!(
+                "Transport lost while sending an Abort on Tube(id={}). It will \
+                 be sent again once the channel resumes.",
+                tube_id,
+            );
+            Ok(())
+        },
         // TODO: Should this just be a panic? If we get into this state we don't
         //       really know if the client and server are synchronized on the 
         //       state of this Tube...havoc?
@@ -96,14 +228,25 @@ async fn send_abort(
 async fn send_has_finished_sending(
     peer_type: PeerType,
     tube_id: &mut UniqueId,
+    trailers: Option<&HashMap<String, String>>,
     tube_manager: &Arc<Mutex<TubeManager>>,
     sender: &Arc<tokio::sync::Mutex<hyper::body::Sender>>,
 ) -> Result<(), error::HasFinishedSendingError> {
-    let maybe_frame_data = match peer_type {
-        PeerType::Client => 
+    let maybe_frame_data = match (peer_type, trailers) {
+        (PeerType::Client, None) => 
             frame::encode::client_has_finished_sending_frame(tube_id.val()),
-        PeerType::Server => 
+        (PeerType::Client, Some(trailers)) => 
+            frame::encode::client_has_finished_sending_with_trailers_frame(
+                tube_id.val(), 
+                trailers,
+            ),
+        (PeerType::Server, None) => 
             frame::encode::server_has_finished_sending_frame(tube_id.val()),
+        (PeerType::Server, Some(trailers)) => 
+            frame::encode::server_has_finished_sending_with_trailers_frame(
+                tube_id.val(), 
+                trailers,
+            ),
     };
     let frame_data = match maybe_frame_data {
         Ok(data) => data,
@@ -112,6 +255,10 @@ async fn send_has_finished_sending(
 
     {
         let mut tube_mgr = tube_manager.lock().unwrap();
+        if trailers.is_some() && !tube_mgr.trailers {
+            return Err(error::HasFinishedSendingError::TrailersNotSupported);
+        }
+
         use TubeCompletionState::*;
         use PeerType::*;
         let new_state = match (&tube_mgr.completion_state, &peer_type) {
@@ -137,7 +284,25 @@ async fn send_has_finished_sending(
     //       hyper issue doesn't block the tube_mgr Mutex forever or something
     let transport_error = {
         let mut sender = sender.lock().await;
-        sender.send_data(frame_data.into()).await
+        let resumable = {
+            let mut tube_mgr = tube_manager.lock().unwrap();
+            if tube_mgr.resumable {
+                tube_mgr.replay_log.push(ReplayEntry::HasFinishedSending(frame_data.clone()));
+            }
+            tube_mgr.resumable
+        };
+        match sender.send_data(frame_data).await {
+            Err(_) if resumable => {
+                log::trace!(
+                    "Transport lost while sending HasFinishedSending on \
+                     Tube(id={}). It will be sent again once the channel \
+                     resumes.",
+                    tube_id,
+                );
+                Ok(())
+            },
+            result => result,
+        }
     };
 
     // If the transmit failed, we can't be certain if the HasFinishedSending was
@@ -169,7 +334,11 @@ async fn send_has_finished_sending(
 #[derive(Debug)]
 pub struct Tube {
     ackid_manager: UniqueIdManager,
-    last_tube_event: Option<TubeEventTag>,
+    delivery_policy: DeliveryPolicy,
+    max_message_size: usize,
+    receiver: Arc<TubeReceiver>,
+    send_finish_tag: u64,
+    send_scheduler: Arc<SendScheduler>,
     sender: Arc<tokio::sync::Mutex<hyper::body::Sender>>,
     tube_id: UniqueId,
     tube_manager: Arc<Mutex<TubeManager>>,
@@ -180,6 +349,24 @@ impl Tube {
         self.abort_internal(frame::AbortReason::ApplicationAbort).await
     }
     
+    /**
+     * Aborts the Tube with an application-defined `code` and optional 
+     * `message` explaining why, which the peer receives as a 
+     * TubeEvent::Abort(AbortReason::Application). If the peer didn't 
+     * advertise Features::ABORT_CODES it is sent AbortReason::ApplicationError
+     * instead.
+     *
+     * A `message` too long to fit in a single frame that the peer accepts is 
+     * truncated (on a character boundary) before it is sent.
+     */
+    pub async fn abort_with(
+        &mut self,
+        code: u32,
+        message: Option<String>,
+    ) -> Result<(), error::AbortError> {
+        self.abort_internal(frame::AbortReason::Application { code, message }).await
+    }
+    
     pub(in crate) async fn abort_internal(
         &mut self, 
         reason: frame::AbortReason,
@@ -192,14 +379,328 @@ impl Tube {
         ).await
     }
 
-    pub fn get_id(&self) -> u16 {
+    fn check_message_size(&self, data: &[u8]) -> Result<(), error::SendError> {
+        if data.len() > self.max_message_size {
+            return Err(error::SendError::MessageTooLarge {
+                size: data.len(),
+                max_message_size: self.max_message_size,
+            });
+        }
+        Ok(())
+    }
+
+    /**
+     * Compresses `data` if the peer has confirmed compression for this Tube 
+     * and `data` is large enough to be worth compressing. Returns None if 
+     * `data` should be sent uncompressed.
+     */
+    fn compress_payload(&self, data: &[u8]) -> Result<Option<Vec<u8>>, error::SendError> {
+        let compression = {
+            let tube_mgr = self.tube_manager.lock().unwrap();
+            if tube_mgr.compression_confirmed { tube_mgr.compression } else { None }
+        };
+        let compressed_data = match compression {
+            Some(compression) if data.len() >= MIN_COMPRESSED_PAYLOAD_SIZE => {
+                match compression.compress(data) {
+                    Ok(compressed_data) => compressed_data,
+                    Err(e) => return Err(error::SendError::CompressionError(e)),
+                }
+            },
+            _ => return Ok(None),
+        };
+
+        // Only send compressed data if compressing actually helped.
+        if compressed_data.len() < data.len() {
+            Ok(Some(compressed_data))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /**
+     * Sends `data` as a payload, compressing it first if the peer has 
+     * confirmed compression for this Tube and `data` is large enough to be 
+     * worth compressing.
+     *
+     * Large payloads are split into a sequence of PayloadFragment frames, and 
+     * each frame waits for this Tube's turn from the channel's SendScheduler 
+     * so that the frames of other Tubes can be interleaved with them. On a 
+     * flow-controlled Tube each frame is also only sent once the peer has 
+     * granted enough credit for its data, so this may wait on the peer's 
+     * application to consume earlier payloads.
+     *
+     * If `processing_ack` is set, the peer is asked to hold ack_id's ack until
+     * its application has processed the payload. If `sequence` is set, the 
+     * payload is preceded by a PayloadSequence frame so that the peer can 
+     * recognize it if it is sent again.
+     */
+    async fn send_payload(
+        &mut self,
+        ack_id: Option<u64>,
+        processing_ack: bool,
+        sequence: Option<u64>,
+        data: &[u8],
+    ) -> Result<(), error::SendError> {
+        let compressed_data = self.compress_payload(data)?;
+        let is_compressed = compressed_data.is_some();
+        let mut remaining = match &compressed_data {
+            Some(compressed_data) => &compressed_data[..],
+            None => data,
+        };
+        // Every chunk must fit in whichever frame ends up carrying it (and in
+        // the largest frame the peer will accept), and a Payload frame never 
+        // has room for more data than a PayloadFragment.
+        let (payload_fragmentation, peer_max_frame_size, send_window) = {
+            let tube_mgr = self.tube_manager.lock().unwrap();
+            (
+                tube_mgr.payload_fragmentation,
+                tube_mgr.peer_max_frame_size,
+                tube_mgr.flow_control.as_ref()
+                    .map(|flow_control| flow_control.initial_send_window()),
+            )
+        };
+        let max_chunk_len = 
+            payload_chunk_capacity(self.tube_id.val(), ack_id, peer_max_frame_size);
+        if remaining.len() > max_chunk_len {
+            if !payload_fragmentation {
+                return Err(error::SendError::PayloadFragmentationNotSupported);
+            }
+            if max_chunk_len == 0 {
+                return Err(error::SendError::FrameEncodeError(
+                    frame::encode::FrameEncodeError::DataTooLarge(remaining.len())
+                ));
+            }
+        }
+        // Without fragmentation a payload is only sent once there is credit 
+        // for all of it, which there never will be if it exceeds the window.
+        if let Some(window) = send_window {
+            if !payload_fragmentation && remaining.len() > window as usize {
+                return Err(error::SendError::PayloadExceedsFlowControlWindow {
+                    size: remaining.len(),
+                    window,
+                });
+            }
+        }
+        // On a resumable Tube, payloads are retained until they are acked so
+        // that they can be sent again if the transport is lost before then 
+        // (see TubeManager::replay_log).
+        let retained_ack_id = {
+            let mut tube_mgr = self.tube_manager.lock().unwrap();
+            match ack_id {
+                Some(ack_id) if tube_mgr.resumable => {
+                    // A payload that is sent again replaces its earlier frames.
+                    tube_mgr.settle_payload(ack_id);
+                    tube_mgr.replay_log.push(ReplayEntry::Payload {
+                        ack_id,
+                        ack_id_reservation: None,
+                        frames: vec![],
+                    });
+                    Some(ack_id)
+                },
+                _ => None,
+            }
+        };
+        let mut sequence_frame_data = match sequence {
+            Some(sequence) => 
+                match frame::encode::payload_sequence_frame(self.tube_id.val(), sequence) {
+                    Ok(frame_data) => Some(frame_data),
+                    Err(e) => return Err(error::SendError::FrameEncodeError(e)),
+                },
+            None => None,
+        };
+        loop {
+            let wanted = remaining.len().min(max_chunk_len);
+            let credit = if wanted == 0 {
+                0
+            } else {
+                self.take_send_credit(wanted, !payload_fragmentation).await?
+            };
+
+            let (chunk, rest) = remaining.split_at(credit);
+            remaining = rest;
+            let tube_id = self.tube_id.val();
+            let frame_data = if !remaining.is_empty() {
+                frame::encode::payload_fragment_frame(tube_id, chunk)
+            } else {
+                use frame::encode::*;
+                match (is_compressed, ack_id) {
+                    (true, Some(ack_id)) if processing_ack => 
+                        processing_ack_compressed_payload_frame(tube_id, ack_id, chunk),
+                    (false, Some(ack_id)) if processing_ack => 
+                        processing_ack_payload_frame(tube_id, ack_id, chunk),
+                    (true, _) => compressed_payload_frame(tube_id, ack_id, chunk),
+                    (false, _) => payload_frame(tube_id, ack_id, chunk),
+                }
+            };
+            let frame_data = match frame_data {
+                Ok(frame_data) => frame_data,
+                Err(e) => return Err(error::SendError::FrameEncodeError(e)),
+            };
+            // The PayloadSequence frame goes out along with the payload's 
+            // first frame so that nothing can be sent on this Tube in between.
+            let frame_data = match sequence_frame_data.take() {
+                Some(sequence_frame_data) => 
+                    [sequence_frame_data, frame_data].concat().into(),
+                None => frame_data,
+            };
+
+            let weight = self.tube_manager.lock().unwrap().weight;
+            let _turn = self.send_scheduler.acquire(
+                &mut self.send_finish_tag,
+                weight,
+                frame_data.len(),
+            ).await;
+            let mut sender = self.sender.lock().await;
+            if let Some(retained_ack_id) = retained_ack_id {
+                // Retained while holding the sender so that a resumption in 
+                // between two frames replays exactly the frames sent before 
+                // it.
+                let mut tube_mgr = self.tube_manager.lock().unwrap();
+                let retained_frames = tube_mgr.replay_log.iter_mut()
+                    .find_map(|entry| match entry {
+                        ReplayEntry::Payload { ack_id, frames, .. } 
+                            if *ack_id == retained_ack_id => Some(frames),
+                        _ => None,
+                    });
+                if let Some(frames) = retained_frames {
+                    frames.push(frame_data.clone());
+                }
+            }
+            if let Err(e) = sender.send_data(frame_data).await {
+                if retained_ack_id.is_none() {
+                    return Err(error::SendError::TransportError(e));
+                }
+                log::trace!(
+                    "Transport lost while sending a payload on Tube(id={}). \
+                     It will be sent again once the channel resumes.",
+                    self.tube_id,
+                );
+            }
+            if remaining.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+
+    /**
+     * Waits until the peer has granted some flow control credit and takes up 
+     * to `wanted` bytes of it. If `whole` is set, waits until all `wanted` 
+     * bytes can be taken at once (for a payload that can't be fragmented).
+     */
+    async fn take_send_credit(
+        &self,
+        wanted: usize,
+        whole: bool,
+    ) -> Result<usize, error::SendError> {
+        loop {
+            let credit_notify = {
+                let mut tube_mgr = self.tube_manager.lock().unwrap();
+                match &tube_mgr.completion_state {
+                    TubeCompletionState::AbortedFromLocal(reason)
+                    | TubeCompletionState::AbortedFromRemote(reason) =>
+                        return Err(error::SendError::TubeAborted(reason.clone())),
+                    _ => (),
+                }
+                let flow_control = match &mut tube_mgr.flow_control {
+                    Some(flow_control) => flow_control,
+                    None => return Ok(wanted),
+                };
+                if whole {
+                    if flow_control.take_whole_send_credit(wanted) {
+                        return Ok(wanted);
+                    }
+                } else {
+                    let credit = flow_control.take_send_credit(wanted);
+                    if credit > 0 {
+                        return Ok(credit);
+                    }
+                }
+                flow_control.credit_notify()
+            };
+            log::trace!("Tube(id={}) is waiting on flow control credit...", self.tube_id);
+            credit_notify.notified().await;
+        }
+    }
+
+    /**
+     * The algorithm requested for payloads on this Tube, if any. Payloads are
+     * only sent compressed once the peer has confirmed it (see 
+     * COMPRESSION_HEADER).
+     */
+    pub fn compression(&self) -> Option<Compression> {
+        self.tube_manager.lock().unwrap().compression
+    }
+
+    /**
+     * A handle on the receiving half of this Tube, for polling its events 
+     * while the Tube itself is busy sending.
+     */
+    pub(in crate) fn receiver(&self) -> Arc<TubeReceiver> {
+        self.receiver.clone()
+    }
+
+    pub fn get_id(&self) -> u64 {
         return self.tube_id.val();
     }
 
+    /**
+     * The largest payload that may be sent on this Tube (see 
+     * ChannelConfig::max_message_size).
+     */
+    pub fn max_message_size(&self) -> usize {
+        self.max_message_size
+    }
+
+    /**
+     * The largest payload that send_and_forget() can send on this Tube. 
+     * Without Features::PAYLOAD_FRAGMENTATION, a payload must fit in a single
+     * frame (and in the flow control window, if the Tube is flow controlled).
+     */
+    pub(in crate) fn max_send_and_forget_size(&self) -> usize {
+        let tube_mgr = self.tube_manager.lock().unwrap();
+        if tube_mgr.payload_fragmentation {
+            return self.max_message_size;
+        }
+        // A resumable Tube's payloads carry an ack id, which may be as wide as
+        // any ack id can be.
+        let ack_id = if tube_mgr.resumable { Some(frame::MAX_ACK_ID) } else { None };
+        let mut max_size = payload_chunk_capacity(
+            self.tube_id.val(),
+            ack_id,
+            tube_mgr.peer_max_frame_size,
+        ).min(self.max_message_size);
+        if let Some(flow_control) = &tube_mgr.flow_control {
+            max_size = max_size.min(flow_control.initial_send_window() as usize);
+        }
+        max_size
+    }
+
     pub async fn has_finished_sending(&mut self) -> Result<(), error::HasFinishedSendingError> {
         send_has_finished_sending(
             self.peer_type,
             &mut self.tube_id,
+            None,
+            &self.tube_manager,
+            &self.sender,
+        ).await
+    }
+
+    /**
+     * Like has_finished_sending(), but also sends the peer some final 
+     * metadata about the Tube (e.g. a status code or a checksum of the 
+     * payloads that were sent). The peer receives the trailers with its 
+     * ClientHasFinishedSending or ServerHasFinishedSending TubeEvent.
+     *
+     * Trailers can only be sent if both peers advertised Features::TRAILERS.
+     */
+    pub async fn has_finished_sending_with_trailers(
+        &mut self,
+        trailers: HashMap<String, String>,
+    ) -> Result<(), error::HasFinishedSendingError> {
+        send_has_finished_sending(
+            self.peer_type,
+            &mut self.tube_id,
+            Some(&trailers),
             &self.tube_manager,
             &self.sender,
         ).await
@@ -210,10 +711,29 @@ impl Tube {
         tube_id: UniqueId,
         sender: Arc<tokio::sync::Mutex<hyper::body::Sender>>, 
         tube_manager: Arc<Mutex<TubeManager>>,
+        send_scheduler: Arc<SendScheduler>,
+        max_message_size: usize,
     ) -> Self {
+        let mut ackid_manager = UniqueIdManager::new();
+        ackid_manager.set_max_id(
+            if tube_manager.lock().unwrap().varint_ids {
+                frame::MAX_ACK_ID
+            } else {
+                frame::MAX_FIXED_WIDTH_ACK_ID
+            }
+        );
         Tube {
-            ackid_manager: UniqueIdManager::new(),
-            last_tube_event: None,
+            ackid_manager,
+            delivery_policy: DeliveryPolicy::default(),
+            max_message_size,
+            receiver: Arc::new(TubeReceiver::new(
+                peer_type,
+                tube_id.val(),
+                tube_manager.clone(),
+                ControlFrameWriter::new(sender.clone()),
+            )),
+            send_finish_tag: 0,
+            send_scheduler,
             sender,
             tube_id,
             tube_manager,
@@ -221,69 +741,258 @@ impl Tube {
         }
     }
 
+    /**
+     * This Tube's Weight (see PRIORITY_HEADER), as most recently set by either
+     * peer.
+     */
+    pub fn priority(&self) -> u8 {
+        self.tube_manager.lock().unwrap().weight
+    }
+
+    /**
+     * Changes this Tube's Weight. If both peers advertised 
+     * Features::PRIORITY, the peer is told about the change with a Priority 
+     * frame so that it can schedule the frames it sends on this Tube 
+     * accordingly.
+     */
+    pub async fn set_priority(&mut self, weight: u8) -> Result<(), error::SetPriorityError> {
+        if weight == 0 {
+            return Err(error::SetPriorityError::InvalidWeight);
+        }
+
+        let announces_priority = {
+            let mut tube_mgr = self.tube_manager.lock().unwrap();
+            tube_mgr.weight = weight;
+            tube_mgr.announces_priority
+        };
+        if !announces_priority {
+            return Ok(());
+        }
+
+        let frame_data = match frame::encode::priority_frame(self.tube_id.val(), weight) {
+            Ok(frame_data) => frame_data,
+            Err(e) => return Err(error::SetPriorityError::FrameEncodeError(e)),
+        };
+        let mut sender = self.sender.lock().await;
+        log::trace!("Sending Priority(tube_id={}, weight={})...", self.tube_id, weight);
+        if let Err(e) = sender.send_data(frame_data).await {
+            return Err(error::SetPriorityError::TransportError(e));
+        }
+        Ok(())
+    }
+
     pub async fn send(
         &mut self, 
-        data: Vec<u8>,
+        data: Bytes,
+        ack_timeout: Duration,
+    ) -> Result<(), error::SendError> {
+        let delivery_policy = self.delivery_policy;
+        self.send_with_ack(data, ack_timeout, false, delivery_policy).await
+    }
+
+    /**
+     * Changes how send() retries payloads whose acks don't arrive in time. 
+     * See DeliveryPolicy.
+     */
+    pub fn set_delivery_policy(&mut self, delivery_policy: DeliveryPolicy) {
+        self.delivery_policy = delivery_policy;
+    }
+
+    pub fn delivery_policy(&self) -> DeliveryPolicy {
+        self.delivery_policy
+    }
+
+    /**
+     * Like send(), but rather than waiting for the peer to receive the 
+     * payload, waits for the peer's application to process it. The peer 
+     * receives the payload as a TubeEvent::PayloadAwaitingAck and acks it 
+     * with ProcessingAck::ack().
+     *
+     * Requires both peers to have advertised Features::PROCESSING_ACKS.
+     */
+    pub async fn send_with_processing_ack(
+        &mut self, 
+        data: Bytes,
+        ack_timeout: Duration,
+    ) -> Result<(), error::SendError> {
+        if !self.tube_manager.lock().unwrap().processing_acks {
+            return Err(error::SendError::ProcessingAcksNotSupported);
+        }
+        // A processing ack only arrives once the peer's application has 
+        // processed the payload, which sending it again wouldn't speed up.
+        self.send_with_ack(data, ack_timeout, true, DeliveryPolicy::no_retries()).await
+    }
+
+    async fn send_with_ack(
+        &mut self, 
+        data: Bytes,
         ack_timeout: Duration,
+        processing_ack: bool,
+        delivery_policy: DeliveryPolicy,
     ) -> Result<(), error::SendError> {
+        self.check_message_size(&data)?;
+
         let ack_id = match self.ackid_manager.take_id() {
             Ok(ack_id) => ack_id,
             Err(UniqueIdError::NoIdsAvailable) => return Err(error::SendError::AckIdsExhausted),
         };
 
-        let frame_data = match frame::encode::payload_frame(
-            self.tube_id.val(), 
-            Some(ack_id.val()), 
-            data,
-        ) {
-            Ok(frame_data) => frame_data,
-            Err(e) => return Err(error::SendError::FrameEncodeError(e)),
-        };
-
-        let (sendack_future, sendack_resolver) = InvertedFuture::<()>::new();
+        let (mut sendack_future, sendack_resolver) = InvertedFuture::<()>::new();
         {
             let mut tube_mgr = self.tube_manager.lock().unwrap();
-            if let Err(_) = tube_mgr.sendacks.try_insert(ack_id.val(), sendack_resolver) {
+            let sendacks = 
+                if processing_ack {
+                    &mut tube_mgr.processing_sendacks
+                } else {
+                    &mut tube_mgr.sendacks
+                };
+            if sendacks.try_insert(ack_id.val(), sendack_resolver).is_err() {
                 return Err(error::SendError::AckIdAlreadyInUseInternalError)
             }
         }
 
-        {
-            let mut sender = self.sender.lock().await;
-            if let Err(e) = sender.send_data(frame_data.into()).await {
-                let mut tube_mgr = self.tube_manager.lock().unwrap();
+        let remove_sendack = |tube_manager: &Mutex<TubeManager>| {
+            let mut tube_mgr = tube_manager.lock().unwrap();
+            if processing_ack {
+                tube_mgr.processing_sendacks.remove(&ack_id.val());
+            } else {
                 tube_mgr.sendacks.remove(&ack_id.val());
-                return Err(error::SendError::TransportError(e))
             }
+            tube_mgr.settle_payload(ack_id.val());
+            tube_mgr.record_settled_ack_id(ack_id.val());
         };
 
-        let sendack_future_with_timeout = 
-            tokio::time::timeout(ack_timeout, sendack_future);
-        let sendack_future_result = sendack_future_with_timeout.await;
-
-        {
+        // Every attempt at sending the payload uses the same ack id and 
+        // Sequence, so an ack for any of them settles the send.
+        let sequence = {
             let mut tube_mgr = self.tube_manager.lock().unwrap();
-            tube_mgr.sendacks.remove(&ack_id.val());
-        }
+            if tube_mgr.payload_sequences {
+                let sequence = tube_mgr.next_payload_sequence;
+                tube_mgr.next_payload_sequence += 1;
+                Some(sequence)
+            } else {
+                None
+            }
+        };
+        let started_at = tokio::time::Instant::now();
+        let mut retries_left = delivery_policy.max_retries;
+        let mut backoff = delivery_policy.backoff;
+        let result = loop {
+            if let Err(e) = self.send_payload(
+                Some(ack_id.val()), 
+                processing_ack, 
+                sequence, 
+                &data,
+            ).await {
+                break Err(e);
+            }
 
-        if let Err(_) = sendack_future_result {
-            return Err(error::SendError::TimedOutWaitingOnAck(ack_timeout));
-        }
+            let attempt_timeout = match delivery_policy.deadline {
+                Some(deadline) => 
+                    ack_timeout.min(deadline.saturating_sub(started_at.elapsed())),
+                None => ack_timeout,
+            };
+            if tokio::time::timeout(attempt_timeout, &mut sendack_future).await.is_ok() {
+                break Ok(());
+            }
+
+            let past_deadline = delivery_policy.deadline
+                .is_some_and(|deadline| started_at.elapsed() + backoff >= deadline);
+            if retries_left == 0 || past_deadline {
+                break Err(error::SendError::TimedOutWaitingOnAck {
+                    attempts: delivery_policy.max_retries - retries_left + 1,
+                    elapsed: started_at.elapsed(),
+                });
+            }
+            retries_left -= 1;
 
+            // An ack that turns up late still counts.
+            if tokio::time::timeout(backoff, &mut sendack_future).await.is_ok() {
+                break Ok(());
+            }
+            backoff = backoff.saturating_mul(2);
+            log::trace!(
+                "Timed out waiting on ack {} for Tube(id={}). Sending the payload \
+                 again...",
+                ack_id.val(),
+                self.tube_id,
+            );
+        };
+
+        remove_sendack(&self.tube_manager);
+        result
+    }
+
+    /**
+     * Sends an application-defined Extension frame on this Tube. The peer 
+     * receives it as a TubeEvent::Extension(ext_type, data) rather than as a 
+     * TubeEvent::Payload. ext_type must be an offset into 
+     * frame::APPLICATION_EXTENSION_FRAMETYPES.
+     */
+    pub async fn send_extension(
+        &mut self, 
+        ext_type: u8, 
+        data: Bytes,
+    ) -> Result<(), error::SendError> {
+        let frame_data = match frame::encode::extension_frame(
+            self.tube_id.val(), 
+            ext_type, 
+            &data,
+        ) {
+            Ok(frame_data) => frame_data,
+            Err(e) => return Err(error::SendError::FrameEncodeError(e)),
+        };
+
+        let mut sender = self.sender.lock().await;
+        if let Err(e) = sender.send_data(frame_data).await {
+            return Err(error::SendError::TransportError(e));
+        }
         Ok(())
     }
 
-    pub async fn send_and_forget(&mut self, data: Vec<u8>) -> Result<(), error::SendError> {
-        match frame::encode::payload_frame(self.tube_id.val(), None, data) {
-            Ok(frame_data) => {
-                let mut sender = self.sender.lock().await;
-                if let Err(e) = sender.send_data(frame_data.into()).await {
-                    return Err(error::SendError::TransportError(e));
-                }
-                Ok(())
-            },
-            Err(e) => Err(error::SendError::FrameEncodeError(e)),
+    /**
+     * Sends `data` without waiting to find out whether the peer received it.
+     *
+     * On a resumable Tube the payload is still acked and numbered behind the
+     * scenes, so that it can be sent again (and delivered only once) if the 
+     * transport is lost before it arrives.
+     */
+    pub async fn send_and_forget(&mut self, data: Bytes) -> Result<(), error::SendError> {
+        self.check_message_size(&data)?;
+        if !self.tube_manager.lock().unwrap().resumable {
+            return self.send_payload(None, false, None, &data).await;
         }
+
+        let ack_id = match self.ackid_manager.take_id() {
+            Ok(ack_id) => ack_id,
+            Err(UniqueIdError::NoIdsAvailable) => return Err(error::SendError::AckIdsExhausted),
+        };
+        let sequence = {
+            let mut tube_mgr = self.tube_manager.lock().unwrap();
+            let sequence = tube_mgr.next_payload_sequence;
+            tube_mgr.next_payload_sequence += 1;
+            sequence
+        };
+        let result = 
+            self.send_payload(Some(ack_id.val()), false, Some(sequence), &data).await;
+
+        // The ack id stays reserved until the payload is acked, unless that 
+        // has already happened.
+        let mut tube_mgr = self.tube_manager.lock().unwrap();
+        if result.is_err() {
+            tube_mgr.settle_payload(ack_id.val());
+            return result;
+        }
+        let ack_id_val = ack_id.val();
+        let entry = tube_mgr.replay_log.iter_mut().find_map(|entry| match entry {
+            ReplayEntry::Payload { ack_id, ack_id_reservation, .. } 
+                if *ack_id == ack_id_val => Some(ack_id_reservation),
+            _ => None,
+        });
+        if let Some(ack_id_reservation) = entry {
+            *ack_id_reservation = Some(ack_id);
+        }
+        Ok(())
     }
 }
 impl futures::stream::Stream for Tube {
@@ -293,37 +1002,7 @@ impl futures::stream::Stream for Tube {
         self: core::pin::Pin<&mut Self>,
         cx: &mut futures::task::Context,
     ) -> futures::task::Poll<Option<Self::Item>> {
-        let mut tube_mgr = self.tube_manager.lock().unwrap();
-        tube_mgr.waker = Some(cx.waker().clone());
-
-        match (self.last_tube_event.as_ref(), tube_mgr.pending_events.pop_front()) {
-            // No more pending_events
-            (_, None) => {
-                use TubeCompletionState::*;
-                match (&self.peer_type, &tube_mgr.completion_state) {
-                    (_, AbortedFromLocal(_)) |
-                        (_, AbortedFromRemote(_)) => {
-                        // TODO: Error all pending SendAcks
-                        futures::task::Poll::Ready(None)
-                    },
-
-                    (&PeerType::Client, &Open | &ClientHasFinishedSending) |
-                    (&PeerType::Server, &Open | &ServerHasFinishedSending) => 
-                        futures::task::Poll::Pending,
-
-                    (&PeerType::Client, &Closed | &ServerHasFinishedSending) |
-                    (&PeerType::Server, &Closed | &ClientHasFinishedSending) =>
-                        futures::task::Poll::Ready(None),
-                }
-            },
-
-            // TODO: Enumerate various TubeEvents and validate state transitions 
-            //       here. Issue a 
-            //       TubeEvent::StreamError(InvalidTubeEventTransition) when the
-            //       transition doesn't make sense.
-            (_, Some(tube_event)) => 
-                futures::task::Poll::Ready(Some(tube_event)),
-        }
+        self.receiver.poll_next_event(cx)
     }
 }
 impl Drop for Tube {
@@ -357,6 +1036,7 @@ impl Drop for Tube {
                     if let Err(e) = send_has_finished_sending(
                         peer_type,
                         &mut tube_id,
+                        None,
                         &tube_manager,
                         &sender,
                     ).await {
@@ -415,6 +1095,7 @@ impl Drop for Tube {
 mod tube_tests {
     use super::*;
 
+    use crate::common::FlowControl;
     use crate::common::InvertedFuture;
     use crate::tube;
 
@@ -434,6 +1115,8 @@ mod tube_tests {
             tube_id,
             body_sender,
             tube_manager.clone(),
+            Arc::new(SendScheduler::new()),
+            crate::common::DEFAULT_MAX_MESSAGE_SIZE,
         );
 
         (tube, TestTubeStuff {
@@ -468,13 +1151,314 @@ mod tube_tests {
         }
     }
 
+    #[tokio::test]
+    async fn send_errors_if_message_exceeds_max_message_size() {
+        let (mut tube, tube_stuff) = make_test_tube();
+        let data = vec![0; crate::common::DEFAULT_MAX_MESSAGE_SIZE + 1];
+        match tube.send(data.into(), Duration::from_millis(100)).await {
+            Err(tube::error::SendError::MessageTooLarge { size, max_message_size }) => {
+                assert_eq!(size, crate::common::DEFAULT_MAX_MESSAGE_SIZE + 1);
+                assert_eq!(max_message_size, crate::common::DEFAULT_MAX_MESSAGE_SIZE);
+
+                // No ack should have been reserved for a payload that was 
+                // never sent
+                let tube_mgr = tube_stuff.tube_manager.lock().unwrap();
+                assert_eq!(tube_mgr.sendacks.len(), 0);
+            },
+
+            unexpected => assert!(
+                false,
+                "Unexpected result from Tube::send(): {:?}",
+                unexpected,
+            ),
+        }
+    }
+
+    #[tokio::test]
+    async fn send_only_fragments_to_the_peers_max_frame_size_if_negotiated() {
+        use hyper::body::HttpBody;
+
+        let (mut tube, tube_stuff) = make_test_tube();
+        tube_stuff.tube_manager.lock().unwrap().peer_max_frame_size = 64;
+        let data = Bytes::from(vec![7; 100]);
+        match tube.send_and_forget(data.clone()).await {
+            Err(tube::error::SendError::PayloadFragmentationNotSupported) => (),
+            unexpected => panic!(
+                "Unexpected result from Tube::send_and_forget(): {:?}",
+                unexpected,
+            ),
+        }
+
+        tube_stuff.tube_manager.lock().unwrap().payload_fragmentation = true;
+        let mut req_body = tube_stuff.req_body;
+        let peer = tokio::spawn(async move {
+            let mut decoder = frame::Decoder::new();
+            let mut frames = vec![];
+            while let Some(data) = req_body.data().await {
+                let data = data.unwrap();
+                assert!(data.len() <= 64);
+                frames.extend(decoder.decode(data).unwrap());
+                if let Some(frame::Frame::Payload { .. }) = frames.last() {
+                    break;
+                }
+            }
+            frames
+        });
+        tube.send_and_forget(data.clone()).await.unwrap();
+
+        let mut reassembled_data = vec![];
+        for frame in peer.await.unwrap() {
+            match frame {
+                frame::Frame::PayloadFragment { data, .. } 
+                | frame::Frame::Payload { data, .. } => reassembled_data.extend(data),
+                unexpected => panic!("Unexpected frame: {:?}", unexpected),
+            }
+        }
+        assert_eq!(Bytes::from(reassembled_data), data);
+    }
+
+    /**
+     * Feeds the frames written to `body` to a FrameHandler tracking 
+     * `tube_manager`, as the peer's channel would.
+     */
+    fn spawn_frame_pump(
+        mut body: hyper::Body,
+        peer_type: PeerType,
+        tube_id: u64,
+        tube_manager: Arc<Mutex<TubeManager>>,
+        mut data_sender: Arc<tokio::sync::Mutex<hyper::body::Sender>>,
+    ) -> tokio::task::JoinHandle<()> {
+        use hyper::body::HttpBody;
+
+        tokio::spawn(async move {
+            let mut tube_mgrs = Arc::new(Mutex::new(HashMap::from([
+                (tube_id, tube_manager),
+            ])));
+            let mut frame_handler = frame::FrameHandler::new(
+                peer_type,
+                &mut tube_mgrs,
+                &crate::common::ChannelConfig::default(),
+            );
+            let mut decoder = frame::Decoder::new();
+            while let Some(data) = body.data().await {
+                for frame in decoder.decode(data.unwrap()).unwrap() {
+                    frame_handler.handle_frame(frame, &mut data_sender).await.unwrap();
+                }
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn payloads_larger_than_the_flow_control_window_are_delivered() {
+        let window = 1024;
+        let (mut tube, tube_stuff) = make_test_tube();
+        let tube_id = tube.get_id();
+        {
+            let mut tube_mgr = tube_stuff.tube_manager.lock().unwrap();
+            tube_mgr.flow_control = Some(FlowControl::new(window, window));
+            tube_mgr.payload_fragmentation = true;
+            tube_mgr.peer_max_frame_size = 256;
+        }
+
+        let (server_sender, server_body) = hyper::Body::channel();
+        let server_sender = Arc::new(tokio::sync::Mutex::new(server_sender));
+        let server_tube_manager = Arc::new(Mutex::new(TubeManager::new()));
+        server_tube_manager.lock().unwrap().flow_control = 
+            Some(FlowControl::new(window, window));
+        let mut server_tube = Tube::new(
+            PeerType::Server,
+            UniqueIdManager::new().take_id().unwrap(),
+            server_sender.clone(),
+            server_tube_manager.clone(),
+            Arc::new(SendScheduler::new()),
+            crate::common::DEFAULT_MAX_MESSAGE_SIZE,
+        );
+        let (unused_sender, _unused_body) = hyper::Body::channel();
+        let client_pump = spawn_frame_pump(
+            tube_stuff.req_body,
+            PeerType::Server,
+            tube_id,
+            server_tube_manager,
+            server_sender,
+        );
+        let server_pump = spawn_frame_pump(
+            server_body,
+            PeerType::Client,
+            tube_id,
+            tube_stuff.tube_manager.clone(),
+            Arc::new(tokio::sync::Mutex::new(unused_sender)),
+        );
+
+        let data: Bytes = (0..5 * window).map(|i| i as u8).collect();
+        let received = tokio::time::timeout(Duration::from_secs(5), async {
+            let ((), received) = futures::join!(
+                async {
+                    tube.send_and_forget(data.clone()).await.unwrap();
+                    tube.send_and_forget(data.clone()).await.unwrap();
+                },
+                async {
+                    use futures::StreamExt;
+                    let mut received = vec![];
+                    while received.len() < 2 {
+                        match server_tube.next().await {
+                            Some(TubeEvent::Payload(data)) => received.push(data),
+                            unexpected => panic!("Unexpected TubeEvent: {:?}", unexpected),
+                        }
+                    }
+                    received
+                },
+            );
+            received
+        }).await.expect("Payloads larger than the window should not deadlock");
+        assert_eq!(received, vec![data.clone(), data]);
+
+        client_pump.abort();
+        server_pump.abort();
+    }
+
+    #[tokio::test]
+    async fn unfragmented_payloads_wait_for_credit_for_all_of_their_data() {
+        use hyper::body::HttpBody;
+
+        let (mut tube, mut tube_stuff) = make_test_tube();
+        let tube_id = tube.get_id();
+        {
+            let mut flow_control = FlowControl::new(100, 100);
+            // Only part of the window is left.
+            flow_control.take_send_credit(60);
+            tube_stuff.tube_manager.lock().unwrap().flow_control = Some(flow_control);
+        }
+
+        match tube.send_and_forget(Bytes::from(vec![0; 101])).await {
+            Err(tube::error::SendError::PayloadExceedsFlowControlWindow { 
+                size: 101, 
+                window: 100,
+            }) => (),
+            unexpected => panic!("Unexpected result from Tube::send_and_forget(): {:?}", unexpected),
+        }
+
+        let data = Bytes::from(vec![7; 50]);
+        let send = tube.send_and_forget(data.clone());
+        tokio::pin!(send);
+        assert!(tokio::time::timeout(Duration::from_millis(20), &mut send).await.is_err());
+        tube_stuff.tube_manager.lock().unwrap().flow_control.as_mut().unwrap()
+            .add_send_credit(10);
+        send.await.unwrap();
+
+        let frame_data = tube_stuff.req_body.data().await.unwrap().unwrap();
+        assert_eq!(Vec::from(frame::Decoder::new().decode(frame_data).unwrap()), vec![
+            frame::Frame::Payload { tube_id, ack_id: None, processing_ack: false, data },
+        ]);
+    }
+
+    #[tokio::test]
+    async fn tube_io_writes_are_cut_down_to_a_single_frame_without_fragmentation() {
+        use hyper::body::HttpBody;
+        use tokio::io::AsyncWriteExt;
+
+        let (tube, mut tube_stuff) = make_test_tube();
+        let tube_id = tube.get_id();
+        tube_stuff.tube_manager.lock().unwrap().payload_fragmentation = false;
+        let capacity = payload_chunk_capacity(tube_id, None, usize::MAX);
+
+        let mut tube_io = crate::common::tube::TubeIo::new(tube);
+        let data = vec![7; 2 * capacity];
+        let written = tube_io.write(&data).await.unwrap();
+        assert_eq!(written, capacity);
+
+        let frame_data = tube_stuff.req_body.data().await.unwrap().unwrap();
+        assert_eq!(Vec::from(frame::Decoder::new().decode(frame_data).unwrap()), vec![
+            frame::Frame::Payload {
+                tube_id,
+                ack_id: None,
+                processing_ack: false,
+                data: Bytes::from(data[..capacity].to_vec()),
+            },
+        ]);
+    }
+
+    #[tokio::test]
+    async fn tube_io_reads_while_a_write_waits_on_flow_control() {
+        use tokio::io::AsyncReadExt;
+        use tokio::io::AsyncWriteExt;
+
+        // Every write is a payload small enough for a single frame, so the 
+        // peer only gives back its credit once it has read the payload.
+        let window = 1024;
+        let max_message_size = 256;
+        let make_tube = |peer_type| {
+            let (sender, body) = hyper::Body::channel();
+            let sender = Arc::new(tokio::sync::Mutex::new(sender));
+            let tube_manager = Arc::new(Mutex::new(TubeManager::new()));
+            tube_manager.lock().unwrap().flow_control = Some(FlowControl::new(window, window));
+            let tube = Tube::new(
+                peer_type,
+                UniqueIdManager::new().take_id().unwrap(),
+                sender.clone(),
+                tube_manager.clone(),
+                Arc::new(SendScheduler::new()),
+                max_message_size,
+            );
+            (tube, sender, body, tube_manager)
+        };
+        let (client_tube, client_sender, client_body, client_tube_manager) = 
+            make_tube(PeerType::Client);
+        let (server_tube, server_sender, server_body, server_tube_manager) = 
+            make_tube(PeerType::Server);
+        let tube_id = client_tube.get_id();
+        let client_pump = spawn_frame_pump(
+            client_body,
+            PeerType::Server,
+            tube_id,
+            server_tube_manager,
+            server_sender,
+        );
+        let server_pump = spawn_frame_pump(
+            server_body,
+            PeerType::Client,
+            tube_id,
+            client_tube_manager,
+            client_sender,
+        );
+
+        // Each peer writes several windows' worth of data before reading any,
+        // so neither write can finish unless the other peer reads while its
+        // own write is waiting on credit.
+        let data: Vec<u8> = (0..3 * window).map(|i| i as u8).collect();
+        let exchange = |tube: Tube| {
+            let data = data.clone();
+            async move {
+                let (mut reader, mut writer) = 
+                    tokio::io::split(crate::common::tube::TubeIo::new(tube));
+                let mut received = vec![0; data.len()];
+                let (written, read) = futures::join!(
+                    writer.write_all(&data),
+                    reader.read_exact(&mut received),
+                );
+                written.unwrap();
+                read.unwrap();
+                received
+            }
+        };
+        let (client_received, server_received) = tokio::time::timeout(
+            Duration::from_secs(5),
+            async { futures::join!(exchange(client_tube), exchange(server_tube)) },
+        ).await.expect("Reads should carry on while a write waits on credit");
+        assert_eq!(client_received, data);
+        assert_eq!(server_received, data);
+
+        client_pump.abort();
+        server_pump.abort();
+    }
+
     #[tokio::test]
     async fn send_errors_if_ack_not_received_in_time() {
         let (mut tube, tube_stuff) = make_test_tube();
         let timeout = Duration::from_nanos(1);
         match tube.send("test data".into(), timeout.clone()).await {
-            Err(tube::error::SendError::TimedOutWaitingOnAck(err_timeout)) => {
-                assert_eq!(err_timeout, timeout);
+            Err(tube::error::SendError::TimedOutWaitingOnAck { attempts, elapsed }) => {
+                assert_eq!(attempts, 1);
+                assert!(elapsed >= timeout);
 
                 // There should be no SendAck entry left polluting the TubeManager
                 let tube_mgr = tube_stuff.tube_manager.lock().unwrap();
@@ -488,6 +1472,197 @@ mod tube_tests {
             ),
         }
     }
+
+    #[tokio::test]
+    async fn send_timeout_reports_every_attempt() {
+        use hyper::body::HttpBody;
+
+        let (mut tube, mut tube_stuff) = make_test_tube();
+        tube.set_delivery_policy(tube::DeliveryPolicy::new_with_retries(
+            2,
+            Duration::from_millis(1),
+            None,
+        ));
+        let peer = tokio::spawn(async move {
+            while tube_stuff.req_body.data().await.is_some() {}
+        });
+
+        let timeout = Duration::from_millis(5);
+        match tube.send("test data".into(), timeout).await {
+            Err(tube::error::SendError::TimedOutWaitingOnAck { attempts, elapsed }) => {
+                assert_eq!(attempts, 3);
+                assert!(elapsed >= timeout * 3);
+            },
+            unexpected => panic!("Unexpected result from Tube::send(): {:?}", unexpected),
+        }
+        peer.abort();
+    }
+
+    #[tokio::test]
+    async fn send_retries_with_the_same_sequence_until_acked() {
+        use hyper::body::HttpBody;
+
+        let (mut tube, tube_stuff) = make_test_tube();
+        tube_stuff.tube_manager.lock().unwrap().payload_sequences = true;
+        tube.set_delivery_policy(tube::DeliveryPolicy::new_with_retries(
+            3,
+            Duration::from_millis(1),
+            None,
+        ));
+
+        // Only ack the payload once it has been sent a second time.
+        let mut req_body = tube_stuff.req_body;
+        let tube_manager = tube_stuff.tube_manager.clone();
+        let peer = tokio::spawn(async move {
+            let mut decoder = frame::Decoder::new();
+            let mut frames = vec![];
+            while let Some(data) = req_body.data().await {
+                frames.extend(decoder.decode(data.unwrap()).unwrap());
+                if frames.len() == 4 {
+                    let mut tube_mgr = tube_manager.lock().unwrap();
+                    tube_mgr.sendacks.get_mut(&0).unwrap().resolve(());
+                    return frames;
+                }
+            }
+            frames
+        });
+
+        tube.send("test data".into(), Duration::from_millis(20)).await.unwrap();
+        let tube_id = tube.get_id();
+        let payload = frame::Frame::Payload {
+            tube_id,
+            ack_id: Some(0),
+            processing_ack: false,
+            data: "test data".into(),
+        };
+        assert_eq!(peer.await.unwrap(), vec![
+            frame::Frame::PayloadSequence { tube_id, sequence: 0 },
+            payload.clone(),
+            frame::Frame::PayloadSequence { tube_id, sequence: 0 },
+            payload,
+        ]);
+        assert_eq!(tube_stuff.tube_manager.lock().unwrap().next_payload_sequence, 1);
+    }
+
+    #[tokio::test]
+    async fn resumable_tubes_log_what_they_send_until_it_is_settled() {
+        use hyper::body::HttpBody;
+
+        let (mut tube, mut tube_stuff) = make_test_tube();
+        {
+            let mut tube_mgr = tube_stuff.tube_manager.lock().unwrap();
+            tube_mgr.resumable = true;
+            tube_mgr.payload_sequences = true;
+        }
+        let logged_kinds = |tube_manager: &Mutex<TubeManager>| {
+            tube_manager.lock().unwrap().replay_log.iter()
+                .map(|entry| match entry {
+                    ReplayEntry::Payload { ack_id, ack_id_reservation, .. } => 
+                        format!("Payload({}, reserved={})", ack_id, ack_id_reservation.is_some()),
+                    entry => format!("{:?}", entry).split('(').next().unwrap().to_string(),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Payloads sent without an ack are still acked (and numbered) behind 
+        // the scenes.
+        tube.send_and_forget("hello".into()).await.unwrap();
+        let tube_id = tube.get_id();
+        let frame_data = tube_stuff.req_body.data().await.unwrap().unwrap();
+        assert_eq!(Vec::from(frame::Decoder::new().decode(frame_data).unwrap()), vec![
+            frame::Frame::PayloadSequence { tube_id, sequence: 0 },
+            frame::Frame::Payload {
+                tube_id,
+                ack_id: Some(0),
+                processing_ack: false,
+                data: "hello".into(),
+            },
+        ]);
+        tube.has_finished_sending().await.unwrap();
+        tube_stuff.req_body.data().await.unwrap().unwrap();
+        assert_eq!(logged_kinds(&tube_stuff.tube_manager), vec![
+            "Payload(0, reserved=true)".to_string(),
+            "HasFinishedSending".to_string(),
+        ]);
+
+        // Nothing but the Abort needs to reach the peer once the Tube aborts.
+        tube.abort().await.unwrap();
+        tube_stuff.req_body.data().await.unwrap().unwrap();
+        assert_eq!(logged_kinds(&tube_stuff.tube_manager), vec!["Abort".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn trailers_error_unless_both_peers_support_them() {
+        let (mut tube, tube_stuff) = make_test_tube();
+        let trailers = HashMap::from([("status".to_string(), "ok".to_string())]);
+        match tube.has_finished_sending_with_trailers(trailers).await {
+            Err(tube::error::HasFinishedSendingError::TrailersNotSupported) => {
+                // The Tube should still be open
+                let tube_mgr = tube_stuff.tube_manager.lock().unwrap();
+                assert_eq!(tube_mgr.completion_state, TubeCompletionState::Open);
+            },
+
+            unexpected => panic!(
+                "Unexpected result from Tube::has_finished_sending_with_trailers(): {:?}",
+                unexpected,
+            ),
+        }
+    }
+
+    #[tokio::test]
+    async fn abort_with_falls_back_to_application_error() {
+        use hyper::body::HttpBody;
+
+        let (mut tube, mut tube_stuff) = make_test_tube();
+        tube.abort_with(4021, Some("invalid request".to_string())).await.unwrap();
+
+        // The application's code is still tracked locally...
+        let reason = frame::AbortReason::Application { 
+            code: 4021, 
+            message: Some("invalid request".to_string()),
+        };
+        assert_eq!(
+            tube_stuff.tube_manager.lock().unwrap().completion_state,
+            TubeCompletionState::AbortedFromLocal(reason),
+        );
+
+        // ...but a peer that doesn't support ABORT_CODES can't decode it.
+        let frame_data = tube_stuff.req_body.data().await.unwrap().unwrap();
+        assert_eq!(
+            frame_data,
+            frame::encode::abort_frame(tube.get_id(), frame::AbortReason::ApplicationError)
+                .unwrap(),
+        );
+    }
+
+    #[tokio::test]
+    async fn abort_with_truncates_long_messages_on_a_char_boundary() {
+        use hyper::body::HttpBody;
+
+        let (mut tube, mut tube_stuff) = make_test_tube();
+        {
+            let mut tube_mgr = tube_stuff.tube_manager.lock().unwrap();
+            tube_mgr.abort_codes = true;
+            tube_mgr.peer_max_frame_size = 64;
+        }
+        // Each "é" is 2 bytes long, and the frame only has room for 64 - 3 
+        // (header) - 2 (tube id) - 4 (code) = 55 bytes of message.
+        let message = "é".repeat(100);
+        tube.abort_with(4021, Some(message)).await.unwrap();
+
+        let frame_data = tube_stuff.req_body.data().await.unwrap().unwrap();
+        assert!(frame_data.len() <= 64);
+        let frames = frame::Decoder::new().decode(frame_data).unwrap();
+        assert_eq!(Vec::from(frames), vec![
+            frame::Frame::Abort {
+                tube_id: tube.get_id(),
+                reason: frame::AbortReason::Application { 
+                    code: 4021, 
+                    message: Some("é".repeat(27)),
+                },
+            },
+        ]);
+    }
 /*
     use futures::StreamExt;
     use hyper;
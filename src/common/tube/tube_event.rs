@@ -1,11 +1,9 @@
-use crate::common::frame;
+use std::collections::HashMap;
 
-// TODO
-/*
-#[derive(Clone, Debug, PartialEq)]
-#[allow(non_camel_case_types)]
-pub enum TubeEvent_DrainReason {}
-*/
+use bytes::Bytes;
+
+use crate::common::frame;
+use super::ProcessingAck;
 
 #[derive(Clone, Debug, PartialEq)]
 #[allow(non_camel_case_types)]
@@ -18,15 +16,40 @@ pub enum TubeEvent_StreamError {
 pub enum TubeEvent {
     Abort(frame::AbortReason),
     AuthenticatedAndReady,
-    ClientHasFinishedSending,
-    Payload(Vec<u8>),
+    /**
+     * The client's trailers (see Tube::has_finished_sending_with_trailers()),
+     * or an empty map if it sent none.
+     */
+    ClientHasFinishedSending(HashMap<String, String>),
+    /**
+     * An application-defined Extension frame (ExtType, Data) sent by the peer
+     * via Tube::send_extension().
+     */
+    Extension(u8, Bytes),
+    GoAway(frame::GoAwayErrorCode, String),
+    Payload(Bytes),
+    /**
+     * A payload sent with Tube::send_with_processing_ack(). The sender is 
+     * only acked once the application calls ProcessingAck::ack().
+     */
+    PayloadAwaitingAck(Bytes, ProcessingAck),
+    /**
+     * The server refused the Tube with an application-defined code and 
+     * response headers (see PendingTube::reject_with_headers()). No further
+     * events follow.
+     */
+    Rejected(u16, HashMap<String, String>),
     StreamError(TubeEvent_StreamError),
-    ServerHasFinishedSending,
-
-    // TODO
-    /*
-    ServerMustDrain(TubeEvent_DrainReason),
-    */
+    /**
+     * Like ClientHasFinishedSending, but for the server's trailers.
+     */
+    ServerHasFinishedSending(HashMap<String, String>),
+    /**
+     * The channel is draining (see Channel::drain()). The Tube should be 
+     * wound down, as it is aborted if it is still open once the draining 
+     * peer's deadline expires.
+     */
+    ServerMustDrain(frame::DrainReason),
 }
 
 // TODO: Is there a way to macro-ize this so TubeEvent and 
@@ -36,30 +59,30 @@ pub enum TubeEventTag {
     Abort,
     Uninitialized,
     AuthenticatedAndReady,
+    Extension,
+    GoAway,
     Payload,
+    PayloadAwaitingAck,
+    Rejected,
     ClientHasFinishedSending,
     StreamError,
     ServerHasFinishedSending,
-
-    // TODO
-    /*
     ServerMustDrain,
-    */
 }
 impl From<&TubeEvent> for TubeEventTag {
     fn from(event: &TubeEvent) -> Self {
         match event {
             TubeEvent::Abort(_) => TubeEventTag::Abort,
             TubeEvent::AuthenticatedAndReady => TubeEventTag::AuthenticatedAndReady,
+            TubeEvent::Extension(_, _) => TubeEventTag::Extension,
+            TubeEvent::GoAway(_, _) => TubeEventTag::GoAway,
             TubeEvent::Payload(_) => TubeEventTag::Payload,
-            TubeEvent::ClientHasFinishedSending => TubeEventTag::ClientHasFinishedSending,
+            TubeEvent::PayloadAwaitingAck(_, _) => TubeEventTag::PayloadAwaitingAck,
+            TubeEvent::Rejected(_, _) => TubeEventTag::Rejected,
+            TubeEvent::ClientHasFinishedSending(_) => TubeEventTag::ClientHasFinishedSending,
             TubeEvent::StreamError(_) => TubeEventTag::StreamError,
-            TubeEvent::ServerHasFinishedSending => TubeEventTag::ServerHasFinishedSending,
-
-            // TODO
-            /*
+            TubeEvent::ServerHasFinishedSending(_) => TubeEventTag::ServerHasFinishedSending,
             TubeEvent::ServerMustDrain(_) => TubeEventTag::ServerMustDrain,
-            */
         }
     }
 }
@@ -0,0 +1,417 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+
+use bytes::Buf;
+use bytes::Bytes;
+
+use super::error;
+use super::ProcessingAck;
+use super::Tube;
+use super::TubeEvent;
+use super::tube_receiver::TubeReceiver;
+
+type TubeFuture = Pin<Box<dyn Future<Output = (Tube, io::Result<()>)> + Send>>;
+
+enum WriteState {
+    Idle,
+    /**
+     * Sending a payload of the given length.
+     */
+    Writing(TubeFuture, usize),
+    ShuttingDown(TubeFuture),
+}
+
+fn abort_error(tube_id: u64, reason: impl std::fmt::Debug) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::ConnectionAborted,
+        format!("Tube(id={}) was aborted: {:?}", tube_id, reason),
+    )
+}
+
+fn send_error_to_io_error(tube_id: u64, err: error::SendError) -> io::Error {
+    match err {
+        error::SendError::TubeAborted(reason) => abort_error(tube_id, reason),
+        error::SendError::TransportError(e) =>
+            io::Error::new(io::ErrorKind::BrokenPipe, e),
+        err => io::Error::other(
+            format!("Error sending on Tube(id={}): {:?}", tube_id, err),
+        ),
+    }
+}
+
+fn has_finished_sending_error_to_io_error(
+    tube_id: u64,
+    err: error::HasFinishedSendingError,
+) -> io::Error {
+    match err {
+        error::HasFinishedSendingError::TubeAlreadyAborted(reason) =>
+            abort_error(tube_id, reason),
+        error::HasFinishedSendingError::FatalTransportError(e) =>
+            io::Error::new(io::ErrorKind::BrokenPipe, e),
+        err => io::Error::other(
+            format!("Error finishing sending on Tube(id={}): {:?}", tube_id, err),
+        ),
+    }
+}
+
+/**
+ * Adapts a Tube to tokio's AsyncRead and AsyncWrite so that byte-oriented
+ * code (e.g. tokio::io::copy(), TLS or a compression codec) can be piped
+ * through it.
+ *
+ * Reads yield the data of the peer's payloads back to back, and reach EOF once
+ * the peer has finished sending. Each write is sent as a payload via
+ * Tube::send_and_forget() (so writes larger than the Tube's max_message_size
+ * are only partially written), and shutting down marks the Tube as finished
+ * sending. Reads carry on while a write is waiting (e.g. on flow control 
+ * credit), so both directions can be driven at once (e.g. with 
+ * tokio::io::copy_bidirectional()). If the Tube is aborted, reads and writes
+ * fail with io::ErrorKind::ConnectionAborted.
+ *
+ * Events other than payloads, aborts and the peer finishing sending are
+ * skipped. A payload sent with Tube::send_with_processing_ack() is acked once
+ * all of its data has been read.
+ */
+pub struct TubeIo {
+    has_shut_down: bool,
+    /**
+     * The ack for the payload being read, if its sender asked for one.
+     */
+    processing_ack: Option<ProcessingAck>,
+    read_buf: Bytes,
+    read_eof: bool,
+    /**
+     * Reads poll the Tube's events (and send processing acks) through this,
+     * so that they carry on while the Tube is lent to a write.
+     */
+    receiver: Arc<TubeReceiver>,
+    /**
+     * Lent to the future of an in-flight write or shutdown, if any.
+     */
+    tube: Option<Tube>,
+    tube_id: u64,
+    write_state: WriteState,
+}
+impl TubeIo {
+    pub fn new(tube: Tube) -> Self {
+        TubeIo {
+            has_shut_down: false,
+            processing_ack: None,
+            read_buf: Bytes::new(),
+            read_eof: false,
+            receiver: tube.receiver(),
+            tube_id: tube.get_id(),
+            tube: Some(tube),
+            write_state: WriteState::Idle,
+        }
+    }
+
+    pub fn get_id(&self) -> u64 {
+        self.tube_id
+    }
+
+    /**
+     * Gives back the Tube, unless a write or shutdown is still in flight. Any
+     * payload data that has been received but not yet read is lost.
+     */
+    pub fn into_inner(self) -> Option<Tube> {
+        self.tube
+    }
+
+    /**
+     * Drives the in-flight write or shutdown (if any) to completion,
+     * returning the number of bytes written by a write.
+     */
+    fn poll_write_state(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<usize>> {
+        let (tube_future, written) = match &mut self.write_state {
+            WriteState::Idle => return Poll::Ready(Ok(0)),
+            WriteState::Writing(tube_future, len) => (tube_future, *len),
+            WriteState::ShuttingDown(tube_future) => (tube_future, 0),
+        };
+        let (tube, result) = match tube_future.as_mut().poll(cx) {
+            Poll::Ready(ready) => ready,
+            Poll::Pending => return Poll::Pending,
+        };
+        if let WriteState::ShuttingDown(_) = self.write_state {
+            self.has_shut_down = result.is_ok();
+        }
+        self.write_state = WriteState::Idle;
+        self.tube = Some(tube);
+        Poll::Ready(result.map(|_| written))
+    }
+
+    fn finish_reading_payload(&mut self) {
+        if let Some(processing_ack) = self.processing_ack.take() {
+            self.receiver.control_frame_writer().queue_processing_ack(&processing_ack);
+        }
+    }
+}
+impl tokio::io::AsyncRead for TubeIo {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if !this.read_buf.is_empty() {
+                let len = this.read_buf.len().min(buf.remaining());
+                buf.put_slice(&this.read_buf[..len]);
+                this.read_buf.advance(len);
+                if this.read_buf.is_empty() {
+                    this.finish_reading_payload();
+                }
+                return Poll::Ready(Ok(()));
+            }
+            if this.read_eof {
+                return Poll::Ready(Ok(()));
+            }
+
+            match this.receiver.poll_next_event(cx) {
+                Poll::Ready(Some(TubeEvent::Payload(data))) => this.read_buf = data,
+                Poll::Ready(Some(TubeEvent::PayloadAwaitingAck(data, processing_ack))) => {
+                    this.read_buf = data;
+                    this.processing_ack = Some(processing_ack);
+                    // An empty payload has no data to wait on being read.
+                    if this.read_buf.is_empty() {
+                        this.finish_reading_payload();
+                    }
+                },
+                Poll::Ready(Some(TubeEvent::Abort(reason))) =>
+                    return Poll::Ready(Err(abort_error(this.tube_id, reason))),
+                Poll::Ready(Some(TubeEvent::Rejected(code, _))) =>
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::ConnectionRefused,
+                        format!("Tube(id={}) was rejected with code {}", this.tube_id, code),
+                    ))),
+                Poll::Ready(Some(TubeEvent::StreamError(err))) =>
+                    return Poll::Ready(Err(io::Error::other(
+                        format!("Stream error on Tube(id={}): {:?}", this.tube_id, err),
+                    ))),
+                Poll::Ready(Some(
+                    TubeEvent::ClientHasFinishedSending(_)
+                    | TubeEvent::ServerHasFinishedSending(_)
+                ))
+                | Poll::Ready(None) => this.read_eof = true,
+                Poll::Ready(Some(event)) =>
+                    log::trace!(
+                        "TubeIo for Tube(id={}) is skipping {:?}.",
+                        this.tube_id,
+                        event,
+                    ),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+impl tokio::io::AsyncWrite for TubeIo {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        // A write that returned Pending is called again with the same data,
+        // so an in-flight write is driven to completion rather than started
+        // over.
+        if let WriteState::Writing(_, _) = this.write_state {
+            return this.poll_write_state(cx);
+        }
+        if let Poll::Ready(Err(e)) = this.poll_write_state(cx) {
+            return Poll::Ready(Err(e));
+        }
+        if this.has_shut_down {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                format!("Tube(id={}) has already finished sending", this.tube_id),
+            )));
+        }
+        let mut tube = match this.tube.take() {
+            Some(tube) => tube,
+            None => return Poll::Pending,
+        };
+        if buf.is_empty() {
+            this.tube = Some(tube);
+            return Poll::Ready(Ok(0));
+        }
+
+        // A short write is fine, so a write is cut down to whatever can be 
+        // sent as a single payload.
+        let data = Bytes::copy_from_slice(&buf[..buf.len().min(tube.max_send_and_forget_size())]);
+        let len = data.len();
+        let tube_id = this.tube_id;
+        this.write_state = WriteState::Writing(Box::pin(async move {
+            let result = tube.send_and_forget(data).await
+                .map_err(|e| send_error_to_io_error(tube_id, e));
+            (tube, result)
+        }), len);
+        this.poll_write_state(cx)
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        // Payloads are handed to the transport as they're written, so there's
+        // nothing to flush beyond an in-flight write.
+        self.get_mut().poll_write_state(cx).map(|result| result.map(|_| ()))
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if let WriteState::Writing(_, _) = this.write_state {
+            if let Err(e) = futures::ready!(this.poll_write_state(cx)) {
+                return Poll::Ready(Err(e));
+            }
+        }
+        if let WriteState::Idle = this.write_state {
+            if this.has_shut_down {
+                return Poll::Ready(Ok(()));
+            }
+            let mut tube = match this.tube.take() {
+                Some(tube) => tube,
+                None => return Poll::Pending,
+            };
+            let tube_id = this.tube_id;
+            this.write_state = WriteState::ShuttingDown(Box::pin(async move {
+                let result = match tube.has_finished_sending().await {
+                    Ok(())
+                    | Err(error::HasFinishedSendingError::AlreadyMarkedAsFinishedSending) =>
+                        Ok(()),
+                    Err(e) => Err(has_finished_sending_error_to_io_error(tube_id, e)),
+                };
+                (tube, result)
+            }));
+        }
+        this.poll_write_state(cx).map(|result| result.map(|_| ()))
+    }
+}
+impl std::fmt::Debug for TubeIo {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("TubeIo")
+            .field("tube_id", &self.tube_id)
+            .field("read_buf_len", &self.read_buf.len())
+            .field("read_eof", &self.read_eof)
+            .field("has_shut_down", &self.has_shut_down)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tube_io_tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use hyper::body::HttpBody;
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+
+    use crate::common::frame;
+    use crate::common::PeerType;
+    use crate::common::SendScheduler;
+    use crate::common::UniqueId;
+    use super::super::TubeManager;
+
+    fn make_test_tube_io() -> (TubeIo, Arc<Mutex<TubeManager>>, hyper::Body) {
+        let (body_sender, body) = hyper::Body::channel();
+        let tube_manager = Arc::new(Mutex::new(TubeManager::new()));
+        let tube = Tube::new(
+            PeerType::Client,
+            UniqueId::new(1, None),
+            Arc::new(tokio::sync::Mutex::new(body_sender)),
+            tube_manager.clone(),
+            Arc::new(SendScheduler::new()),
+            crate::common::DEFAULT_MAX_MESSAGE_SIZE,
+        );
+        (TubeIo::new(tube), tube_manager, body)
+    }
+
+    #[tokio::test]
+    async fn reads_payloads_until_the_peer_finishes_sending() {
+        let (mut tube_io, tube_manager, _body) = make_test_tube_io();
+        tube_manager.lock().unwrap().pending_events.extend([
+            TubeEvent::AuthenticatedAndReady,
+            TubeEvent::Payload("hello ".into()),
+            TubeEvent::Payload("world".into()),
+            TubeEvent::ServerHasFinishedSending(Default::default()),
+        ]);
+
+        let mut data = String::new();
+        tube_io.read_to_string(&mut data).await.unwrap();
+        assert_eq!(data, "hello world");
+    }
+
+    #[tokio::test]
+    async fn reading_a_payload_queues_its_processing_ack() {
+        let (mut tube_io, tube_manager, mut body) = make_test_tube_io();
+        let (unused_sender, _unused_body) = hyper::Body::channel();
+        let processing_ack = ProcessingAck::new(
+            1,
+            3,
+            Arc::new(tokio::sync::Mutex::new(unused_sender)),
+            &tube_manager,
+        );
+        tube_manager.lock().unwrap().pending_events.push_back(
+            TubeEvent::PayloadAwaitingAck("hello".into(), processing_ack),
+        );
+
+        let mut data = [0; 5];
+        tube_io.read_exact(&mut data).await.unwrap();
+        assert_eq!(&data, b"hello");
+        let frame_data = body.data().await.unwrap().unwrap();
+        assert_eq!(Vec::from(frame::Decoder::new().decode(frame_data).unwrap()), vec![
+            frame::Frame::PayloadAck { tube_id: 1, ack_id: 3, processed: true },
+        ]);
+    }
+
+    #[tokio::test]
+    async fn abort_is_an_io_error() {
+        let (mut tube_io, tube_manager, _body) = make_test_tube_io();
+        tube_manager.lock().unwrap().pending_events.push_back(
+            TubeEvent::Abort(frame::AbortReason::ApplicationAbort),
+        );
+
+        let mut data = vec![];
+        match tube_io.read_to_end(&mut data).await {
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::ConnectionAborted),
+            unexpected => panic!("Unexpected read result: {:?}", unexpected),
+        }
+    }
+
+    #[tokio::test]
+    async fn writes_are_sent_as_payloads_and_shutdown_finishes_sending() {
+        let (mut tube_io, _tube_manager, mut body) = make_test_tube_io();
+
+        // Frames are only sent while the body is being read.
+        let peer = tokio::spawn(async move {
+            let mut decoder = frame::Decoder::new();
+            let mut frames = vec![];
+            while frames.len() < 2 {
+                let data = body.data().await.unwrap().unwrap();
+                frames.extend(decoder.decode(data).unwrap());
+            }
+            frames
+        });
+
+        tube_io.write_all(b"hello").await.unwrap();
+        tube_io.shutdown().await.unwrap();
+        assert_eq!(peer.await.unwrap(), vec![
+            frame::Frame::Payload {
+                tube_id: 1,
+                ack_id: None,
+                processing_ack: false,
+                data: "hello".into(),
+            },
+            frame::Frame::ClientHasFinishedSending { tube_id: 1, trailers: None },
+        ]);
+    }
+}
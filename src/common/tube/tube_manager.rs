@@ -1,12 +1,27 @@
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
 use std::task;
 
+use bytes::Bytes;
+use bytes::BytesMut;
+
+use crate::common::Compression;
+use crate::common::DEFAULT_TUBE_WEIGHT;
+use crate::common::FlowControl;
 use crate::common::frame;
 use crate::common::InvertedFutureResolver;
 use crate::common::UniqueId;
 use super::tube_event;
 
+/**
+ * The most settled ack ids a Tube remembers (see 
+ * TubeManager::recently_settled_ack_ids).
+ */
+pub(in crate) const MAX_RECENTLY_SETTLED_ACK_IDS: usize = 64;
+
 #[derive(Clone,Debug,PartialEq)]
 pub enum TubeCompletionState {
     Open,
@@ -17,27 +32,416 @@ pub enum TubeCompletionState {
     AbortedFromRemote(frame::AbortReason),
 }
 
+/**
+ * How the peer responded to a NewTube frame (see 
+ * Channel::make_tube_and_await_acceptance()).
+ */
+#[derive(Clone,Debug,PartialEq)]
+pub enum NewTubeResponse {
+    Accepted(HashMap<String, String>),
+    Rejected {
+        code: u16,
+        headers: HashMap<String, String>,
+    },
+    Aborted(frame::AbortReason),
+}
+
+/**
+ * Something a resumable Tube has sent that the peer may not have received, 
+ * which is sent again if the channel resumes on a new transport before it is
+ * settled (see TubeManager::replay_log).
+ */
+#[derive(Debug)]
+pub enum ReplayEntry {
+    /**
+     * Settled by the first frame the peer sends on the Tube.
+     */
+    NewTube(Bytes),
+    /**
+     * Never settled, since the client may send frames on the Tube before the
+     * NewTubeAccepted reaches it. The client ignores it if it arrives again.
+     */
+    NewTubeAccepted(Bytes),
+    /**
+     * The frames sent so far for a payload. Settled by the payload's ack. 
+     * Payloads sent with Tube::send_and_forget() are only acked on resumable
+     * Tubes, and hold on to their ack id until then.
+     */
+    Payload {
+        ack_id: u64,
+        ack_id_reservation: Option<UniqueId>,
+        frames: Vec<Bytes>,
+    },
+    /**
+     * Never settled, since the peer doesn't acknowledge it. It is sent again
+     * for as long as the Tube is tracked.
+     */
+    HasFinishedSending(Bytes),
+    /**
+     * Settled by the peer's AbortAck.
+     */
+    Abort(Bytes),
+}
+impl ReplayEntry {
+    pub fn frames(&self) -> &[Bytes] {
+        match self {
+            ReplayEntry::NewTube(frame_data)
+            | ReplayEntry::NewTubeAccepted(frame_data)
+            | ReplayEntry::HasFinishedSending(frame_data)
+            | ReplayEntry::Abort(frame_data) => std::slice::from_ref(frame_data),
+            ReplayEntry::Payload { frames, .. } => frames,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct TubeManager {
+    /**
+     * Set if both peers advertised Features::ABORT_CODES.
+     */
+    pub abort_codes: bool,
     /**
      * When we send an Abort to our peer, this holds a UniqueId object alive
      * until the peer acknowledges the Abort (at which point it is removed from
      * here, ultimately dropped, and the TubeId can then be re-used).
      */
     pub abort_pending_id_reservation: Option<UniqueId>,
+    /**
+     * Resolved once the peer accepts or rejects the Tube, if the application
+     * is waiting to find out.
+     */
+    pub acceptance: Option<InvertedFutureResolver<NewTubeResponse>>,
+    /**
+     * The algorithm used to compress payloads on this Tube (as requested via 
+     * its NewTube headers), if any.
+     */
+    pub compression: Option<Compression>,
+    /**
+     * Set once the peer is known to decompress payloads with `compression`: 
+     * immediately for the peer that receives the NewTube, and once the 
+     * algorithm is echoed back in the NewTubeAccepted headers for the peer 
+     * that sent it. Payloads are only sent compressed once this is set.
+     */
+    pub compression_confirmed: bool,
+    /**
+     * Set when a fragmented payload from the peer exceeds the channel's 
+     * max_message_size. The payload's remaining PayloadFragment frames and its
+     * terminating Payload frame are dropped until this is cleared.
+     */
+    pub discarding_oversized_payload: bool,
+    /**
+     * Set if payloads on this Tube are subject to flow control (as requested
+     * via its NewTube headers).
+     */
+    pub flow_control: Option<FlowControl>,
+    /**
+     * The Sequence of the most recent payload delivered to the application,
+     * if the peer numbers its payloads. A payload whose Sequence isn't past 
+     * this one has already been delivered and is dropped.
+     */
+    pub last_payload_sequence: Option<u64>,
+    /**
+     * The Sequence to give the next payload sent on this Tube.
+     */
+    pub next_payload_sequence: u64,
+    /**
+     * Data received via PayloadFragment frames that is waiting on its 
+     * terminating Payload frame to be reassembled into a TubeEvent::Payload.
+     */
+    pub partial_payload: BytesMut,
+    /**
+     * Set if both peers advertised Features::PAYLOAD_FRAGMENTATION, in which 
+     * case payloads too large for a single frame may be sent as a sequence of
+     * PayloadFragment frames.
+     */
+    pub payload_fragmentation: bool,
+    /**
+     * Set if both peers advertised Features::PAYLOAD_SEQUENCES, in which case 
+     * payloads sent with Tube::send() are each preceded by a PayloadSequence 
+     * frame.
+     */
+    pub payload_sequences: bool,
+    /**
+     * The ack ids of received payloads whose acks are being batched into a 
+     * single frame (see ChannelConfig::ack_batch_delay).
+     */
+    pub pending_acks: Vec<u64>,
     pub pending_events: VecDeque<tube_event::TubeEvent>,
-    pub sendacks: HashMap<u16, InvertedFutureResolver<()>>,
+    /**
+     * The Sequence from the peer's most recent PayloadSequence frame, which 
+     * applies to the next payload the peer finishes sending.
+     */
+    pub pending_payload_sequence: Option<u64>,
+    /**
+     * The largest frame (including its header) the peer will accept, as 
+     * advertised in its Settings frame.
+     */
+    pub peer_max_frame_size: usize,
+    /**
+     * Set if both peers advertised Features::PRIORITY, in which case changes
+     * to weight are announced to the peer with a Priority frame.
+     */
+    pub announces_priority: bool,
+    /**
+     * This Tube's share of the channel relative to the other Tubes on it 
+     * (see SendScheduler). Either peer may change it.
+     */
+    pub weight: u8,
+    pub sendacks: HashMap<u64, InvertedFutureResolver<()>>,
+    /**
+     * Like sendacks, but for payloads sent with 
+     * Tube::send_with_processing_ack(), which are only resolved by a 
+     * PayloadAck with Processed set.
+     */
+    pub processing_sendacks: HashMap<u64, InvertedFutureResolver<()>>,
+    /**
+     * Set if both peers advertised Features::PROCESSING_ACKS.
+     */
+    pub processing_acks: bool,
+    /**
+     * The ack ids of received payloads that the application has acked with
+     * ProcessingAck::ack(), on a resumable Tube. If the peer sends one of 
+     * them again because the ack was lost along with the transport, it is 
+     * acked again rather than waiting on the application. An id is forgotten
+     * once the peer reuses it for a new payload.
+     */
+    pub processed_ack_ids: HashSet<u64>,
+    /**
+     * Set if both peers advertised Features::SESSION_RESUMPTION, in which case
+     * this Tube may outlive the channel's current transport.
+     */
+    pub resumable: bool,
+    /**
+     * Set if both peers advertised Features::TRAILERS.
+     */
+    pub trailers: bool,
+    /**
+     * The ack ids of the payloads most recently settled on this Tube, oldest
+     * first. An ack for one of them (e.g. because its payload was sent more 
+     * than once) is ignored rather than treated as untracked.
+     */
+    pub recently_settled_ack_ids: VecDeque<u64>,
+    /**
+     * Everything this peer has sent on a resumable Tube that has yet to be 
+     * settled, in the order it was sent. It is sent again, in the same order,
+     * once the channel has a new transport.
+     */
+    pub replay_log: Vec<ReplayEntry>,
+    /**
+     * Set if both peers advertised Features::VARINT_IDS, in which case ack ids
+     * on this Tube may exceed frame::MAX_FIXED_WIDTH_ACK_ID.
+     */
+    pub varint_ids: bool,
     pub completion_state: TubeCompletionState,
     pub waker: Option<task::Waker>,
 }
 impl TubeManager {
     pub fn new() -> Self {
         TubeManager {
+            abort_codes: false,
             abort_pending_id_reservation: None,
+            acceptance: None,
             completion_state: TubeCompletionState::Open,
+            compression: None,
+            compression_confirmed: false,
+            discarding_oversized_payload: false,
+            flow_control: None,
+            last_payload_sequence: None,
+            next_payload_sequence: 0,
+            partial_payload: BytesMut::new(),
+            payload_fragmentation: false,
+            payload_sequences: false,
+            pending_acks: vec![],
             pending_events: VecDeque::new(),
+            pending_payload_sequence: None,
+            peer_max_frame_size: 3 + frame::MAX_FRAME_BODY_LEN,
+            announces_priority: false,
+            weight: DEFAULT_TUBE_WEIGHT,
             sendacks: HashMap::new(),
+            processing_sendacks: HashMap::new(),
+            processing_acks: false,
+            processed_ack_ids: HashSet::new(),
+            recently_settled_ack_ids: VecDeque::new(),
+            replay_log: vec![],
+            resumable: false,
+            trailers: false,
+            varint_ids: false,
             waker: None,
         }
     }
+
+    /**
+     * Settles the replay log's entry for the payload sent with `ack_id`, if 
+     * any. Returns false if there was no such entry.
+     */
+    pub fn settle_payload(&mut self, ack_id: u64) -> bool {
+        let len = self.replay_log.len();
+        self.replay_log.retain(|entry| !matches!(
+            entry,
+            ReplayEntry::Payload { ack_id: id, .. } if *id == ack_id
+        ));
+        if self.replay_log.len() == len {
+            return false;
+        }
+        self.record_settled_ack_id(ack_id);
+        true
+    }
+
+    /**
+     * Adds `ack_id` to recently_settled_ack_ids (as its newest id), 
+     * forgetting the oldest id there if it is full.
+     */
+    pub fn record_settled_ack_id(&mut self, ack_id: u64) {
+        self.recently_settled_ack_ids.retain(|id| *id != ack_id);
+        if self.recently_settled_ack_ids.len() == MAX_RECENTLY_SETTLED_ACK_IDS {
+            self.recently_settled_ack_ids.pop_front();
+        }
+        self.recently_settled_ack_ids.push_back(ack_id);
+    }
+
+    /**
+     * Settles a pending Channel::make_tube_and_await_acceptance() call, if 
+     * any. Returns false if nobody was waiting on the response.
+     */
+    pub fn resolve_acceptance(&mut self, response: NewTubeResponse) -> bool {
+        match self.acceptance.take() {
+            Some(mut resolver) => {
+                resolver.resolve(response);
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+/**
+ * Terminates every Tube in `tube_managers` that isn't already closed or 
+ * aborted. Each one is marked as AbortedFromRemote(reason), receives `event` 
+ * as its final TubeEvent, and is no longer tracked.
+ */
+pub fn abort_all_tube_managers(
+    tube_managers: &Mutex<HashMap<u64, Arc<Mutex<TubeManager>>>>,
+    reason: frame::AbortReason,
+    event: tube_event::TubeEvent,
+) {
+    let tube_mgrs = {
+        let mut tube_mgrs = tube_managers.lock().unwrap();
+        std::mem::take(&mut *tube_mgrs)
+    };
+
+    for (tube_id, tube_mgr) in tube_mgrs {
+        let mut tube_mgr = tube_mgr.lock().unwrap();
+        match tube_mgr.completion_state {
+            TubeCompletionState::Closed
+            | TubeCompletionState::AbortedFromLocal(_)
+            | TubeCompletionState::AbortedFromRemote(_) => continue,
+            _ => (),
+        }
+
+        log::trace!("Aborting Tube(id={}) with reason {:?}.", tube_id, reason);
+        tube_mgr.completion_state = 
+            TubeCompletionState::AbortedFromRemote(reason.clone());
+        tube_mgr.resolve_acceptance(NewTubeResponse::Aborted(reason.clone()));
+        tube_mgr.pending_events.push_back(event.clone());
+        if let Some(waker) = tube_mgr.waker.take() {
+            waker.wake();
+        }
+        if let Some(flow_control) = &tube_mgr.flow_control {
+            flow_control.wake_senders();
+        }
+    }
+}
+
+/**
+ * Readies every Tube in `tube_managers` for a resumed channel's new transport.
+ * Any partially received payload is discarded (the peer sends it again in 
+ * full), and the frames of every Tube's replay log are returned, in the order
+ * they must be sent again.
+ */
+pub fn resume_tube_managers(
+    tube_managers: &Mutex<HashMap<u64, Arc<Mutex<TubeManager>>>>,
+) -> Vec<Bytes> {
+    let mut tube_mgrs: Vec<_> = tube_managers.lock().unwrap()
+        .iter()
+        .map(|(tube_id, tube_mgr)| (*tube_id, tube_mgr.clone()))
+        .collect();
+    tube_mgrs.sort_unstable_by_key(|(tube_id, _)| *tube_id);
+
+    let mut replayed_frames = vec![];
+    for (_, tube_mgr) in tube_mgrs {
+        let mut tube_mgr = tube_mgr.lock().unwrap();
+        tube_mgr.partial_payload.clear();
+        tube_mgr.discarding_oversized_payload = false;
+        tube_mgr.pending_payload_sequence = None;
+        for entry in &tube_mgr.replay_log {
+            replayed_frames.extend(entry.frames().iter().cloned());
+        }
+    }
+    replayed_frames
+}
+
+/**
+ * Publishes TubeEvent::ServerMustDrain(reason) on every Tube in 
+ * `tube_managers` that isn't already closed or aborted.
+ */
+pub fn drain_all_tube_managers(
+    tube_managers: &Mutex<HashMap<u64, Arc<Mutex<TubeManager>>>>,
+    reason: frame::DrainReason,
+) {
+    let tube_mgrs: Vec<_> = tube_managers.lock().unwrap().values().cloned().collect();
+    for tube_mgr in tube_mgrs {
+        let mut tube_mgr = tube_mgr.lock().unwrap();
+        match tube_mgr.completion_state {
+            TubeCompletionState::Closed
+            | TubeCompletionState::AbortedFromLocal(_)
+            | TubeCompletionState::AbortedFromRemote(_) => continue,
+            _ => (),
+        }
+
+        tube_mgr.pending_events.push_back(tube_event::TubeEvent::ServerMustDrain(reason));
+        if let Some(waker) = tube_mgr.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/**
+ * Like abort_all_tube_managers(), but each Tube is marked as 
+ * AbortedFromLocal(reason) and remains tracked until the peer acknowledges 
+ * the Abort. Returns the ids of the Tubes that the caller must send Abort 
+ * frames for.
+ */
+pub fn abort_open_tube_managers(
+    tube_managers: &Mutex<HashMap<u64, Arc<Mutex<TubeManager>>>>,
+    reason: frame::AbortReason,
+) -> Vec<u64> {
+    let tube_mgrs: Vec<_> = tube_managers.lock().unwrap()
+        .iter()
+        .map(|(tube_id, tube_mgr)| (*tube_id, tube_mgr.clone()))
+        .collect();
+
+    let mut aborted_tube_ids = vec![];
+    for (tube_id, tube_mgr) in tube_mgrs {
+        let mut tube_mgr = tube_mgr.lock().unwrap();
+        match tube_mgr.completion_state {
+            TubeCompletionState::Closed
+            | TubeCompletionState::AbortedFromLocal(_)
+            | TubeCompletionState::AbortedFromRemote(_) => continue,
+            _ => (),
+        }
+
+        log::trace!("Aborting Tube(id={}) with reason {:?}.", tube_id, reason);
+        tube_mgr.completion_state = 
+            TubeCompletionState::AbortedFromLocal(reason.clone());
+        tube_mgr.resolve_acceptance(NewTubeResponse::Aborted(reason.clone()));
+        tube_mgr.pending_events.push_back(tube_event::TubeEvent::Abort(reason.clone()));
+        if let Some(waker) = tube_mgr.waker.take() {
+            waker.wake();
+        }
+        if let Some(flow_control) = &tube_mgr.flow_control {
+            flow_control.wake_senders();
+        }
+        aborted_tube_ids.push(tube_id);
+    }
+    aborted_tube_ids
 }
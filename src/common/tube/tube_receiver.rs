@@ -0,0 +1,88 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
+
+use crate::common::PeerType;
+use super::control_frame_writer::ControlFrameWriter;
+use super::TubeEvent;
+use super::TubeEventTag;
+use super::tube_manager::TubeCompletionState;
+use super::tube_manager::TubeManager;
+
+/**
+ * The receiving half of a Tube, which polls for the events the peer has sent
+ * on it. A TubeIo holds on to its own so that reads can carry on while the
+ * Tube is lent to a write.
+ */
+#[derive(Debug)]
+pub(in crate) struct TubeReceiver {
+    control_frames: ControlFrameWriter,
+    last_tube_event: Option<TubeEventTag>,
+    peer_type: PeerType,
+    tube_id: u64,
+    tube_manager: Arc<Mutex<TubeManager>>,
+}
+impl TubeReceiver {
+    pub fn new(
+        peer_type: PeerType,
+        tube_id: u64,
+        tube_manager: Arc<Mutex<TubeManager>>,
+        control_frames: ControlFrameWriter,
+    ) -> Self {
+        TubeReceiver {
+            control_frames,
+            last_tube_event: None,
+            peer_type,
+            tube_id,
+            tube_manager,
+        }
+    }
+
+    pub fn control_frame_writer(&self) -> &ControlFrameWriter {
+        &self.control_frames
+    }
+
+    pub fn poll_next_event(&self, cx: &mut Context<'_>) -> Poll<Option<TubeEvent>> {
+        let mut tube_mgr = self.tube_manager.lock().unwrap();
+        tube_mgr.waker = Some(cx.waker().clone());
+
+        let pending_event = tube_mgr.pending_events.pop_front();
+        if let Some(TubeEvent::Payload(_) | TubeEvent::PayloadAwaitingAck(_, _)) = pending_event {
+            let credit = tube_mgr.flow_control.as_mut()
+                .and_then(|flow_control| flow_control.consume_payload());
+            if let Some(credit) = credit {
+                self.control_frames.queue_window_update(self.tube_id, credit);
+            }
+        }
+
+        match (self.last_tube_event.as_ref(), pending_event) {
+            // No more pending_events
+            (_, None) => {
+                use TubeCompletionState::*;
+                match (&self.peer_type, &tube_mgr.completion_state) {
+                    (_, AbortedFromLocal(_)) |
+                        (_, AbortedFromRemote(_)) => {
+                        // TODO: Error all pending SendAcks
+                        Poll::Ready(None)
+                    },
+
+                    (&PeerType::Client, &Open | &ClientHasFinishedSending) |
+                    (&PeerType::Server, &Open | &ServerHasFinishedSending) =>
+                        Poll::Pending,
+
+                    (&PeerType::Client, &Closed | &ServerHasFinishedSending) |
+                    (&PeerType::Server, &Closed | &ClientHasFinishedSending) =>
+                        Poll::Ready(None),
+                }
+            },
+
+            // TODO: Enumerate various TubeEvents and validate state transitions
+            //       here. Issue a
+            //       TubeEvent::StreamError(InvalidTubeEventTransition) when the
+            //       transition doesn't make sense.
+            (_, Some(tube_event)) =>
+                Poll::Ready(Some(tube_event)),
+        }
+    }
+}
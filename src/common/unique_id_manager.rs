@@ -17,12 +17,12 @@ pub enum UniqueIdIncrementPolicy {
 
 #[derive(Debug)]
 pub struct UniqueId {
-    avail_ids: Option<Arc<Mutex<VecDeque<u16>>>>,
-    id: u16,
+    avail_ids: Option<Arc<Mutex<VecDeque<u64>>>>,
+    id: u64,
     taken: bool,
 }
 impl UniqueId {
-    pub fn new(id: u16, avail_ids: Option<Arc<Mutex<VecDeque<u16>>>>) -> Self {
+    pub fn new(id: u64, avail_ids: Option<Arc<Mutex<VecDeque<u64>>>>) -> Self {
         UniqueId {
             avail_ids,
             id,
@@ -52,7 +52,7 @@ impl UniqueId {
         new
     }
 
-    pub fn val(&self) -> u16 {
+    pub fn val(&self) -> u64 {
         self.id
     }
 }
@@ -80,10 +80,10 @@ impl PartialEq for UniqueId {
 
 #[derive(Debug)]
 pub struct UniqueIdManager { 
-    avail_ids: Arc<Mutex<VecDeque<u16>>>,
-    counter: u16,
-    ids_exhausted: bool,
+    avail_ids: Arc<Mutex<VecDeque<u64>>>,
+    counter: u64,
     increment_policy: UniqueIdIncrementPolicy,
+    max_id: u64,
 }
 impl UniqueIdManager {
     fn new_impl(increment_policy: UniqueIdIncrementPolicy) -> Self {
@@ -94,8 +94,8 @@ impl UniqueIdManager {
                 UniqueIdIncrementPolicy::Odd => 1,
                 UniqueIdIncrementPolicy::Sequential => 0,
             },
-            ids_exhausted: false,
             increment_policy,
+            max_id: u16::MAX.into(),
         }
     }
 
@@ -111,9 +111,17 @@ impl UniqueIdManager {
         UniqueIdManager::new_impl(UniqueIdIncrementPolicy::Odd)
     }
 
+    /**
+     * Sets the largest id this manager will hand out (u16::MAX by default). 
+     * Raising it lets more ids be taken once the original range is exhausted.
+     */
+    pub fn set_max_id(&mut self, max_id: u64) {
+        self.max_id = max_id;
+    }
+
     pub fn take_id(&mut self) -> Result<UniqueId, UniqueIdError> {
         // TODO: This implementation will grow the avail_ids vec up to 
-        //       ~65k (2^16) if a large number of ids are taken without being
+        //       max_id if a large number of ids are taken without being
         //       returned fast enough.
         //
         //       This is probably fine for now, but we could probably do a 
@@ -123,22 +131,18 @@ impl UniqueIdManager {
         let id = match avail_ids.pop_front() {
             Some(id) => id,
             None => {
-                if self.ids_exhausted {
+                let id = self.counter;
+                if id > self.max_id {
                     return Err(UniqueIdError::NoIdsAvailable);
                 }
 
-                let id = self.counter;
-                if id == u16::MAX {
-                    self.ids_exhausted = true;
-                } else {
-                    match self.increment_policy {
-                        UniqueIdIncrementPolicy::Even
-                        | UniqueIdIncrementPolicy::Odd =>
-                            self.counter += 2,
-
-                        UniqueIdIncrementPolicy::Sequential =>
-                            self.counter += 1,
-                    }
+                match self.increment_policy {
+                    UniqueIdIncrementPolicy::Even
+                    | UniqueIdIncrementPolicy::Odd =>
+                        self.counter += 2,
+
+                    UniqueIdIncrementPolicy::Sequential =>
+                        self.counter += 1,
                 }
                 id
             }
@@ -177,6 +181,18 @@ mod uniqueidmanager_tests {
         }
     }
 
+    #[test]
+    fn raising_max_id_makes_more_ids_available() {
+        let mut idman = UniqueIdManager::new_with_odd_ids();
+        idman.set_max_id(3);
+        let _id1 = idman.take_id().unwrap();
+        let _id3 = idman.take_id().unwrap();
+        assert!(idman.take_id().is_err());
+
+        idman.set_max_id(u64::from(u16::MAX) + 2);
+        assert_eq!(idman.take_id().unwrap().val(), 5);
+    }
+
     #[test]
     fn reuses_ids_after_they_are_dropped() {
         let mut idman = UniqueIdManager::new();
@@ -3,6 +3,50 @@
 
 mod common;
 
+pub use common::capture;
+pub use common::ChannelConfig;
+pub use common::COMPRESSION_HEADER;
+pub use common::Compression;
+pub use common::DecompressError;
+pub use common::DEFAULT_ACK_BATCH_DELAY;
+pub use common::DEFAULT_INITIAL_WINDOW_SIZE;
+pub use common::DEFAULT_KEEPALIVE_INTERVAL;
+pub use common::DEFAULT_MAX_CONCURRENT_TUBES;
+pub use common::DEFAULT_MAX_MESSAGE_SIZE;
+pub use common::DEFAULT_MAX_MISSED_PONGS;
+pub use common::DEFAULT_SETTINGS_TIMEOUT;
+pub use common::DEFAULT_TUBE_ACCEPTANCE_TIMEOUT;
+pub use common::DEFAULT_TUBE_WEIGHT;
+pub use common::DrainError;
+pub use common::FLOW_CONTROL_HEADER;
+pub use common::GoAwayError;
+pub use common::frame::AbortReason;
+pub use common::frame::APPLICATION_EXTENSION_FRAMETYPES;
+pub use common::frame::Decoder;
+pub use common::frame::DecoderConfig;
+pub use common::frame::DecoderLimit;
+pub use common::frame::DrainReason;
+pub use common::frame::encode::FrameEncodeError;
+pub use common::frame::Features;
+pub use common::frame::Frame;
+pub use common::frame::FrameCodec;
+pub use common::frame::FrameCodecError;
+pub use common::frame::FrameHandlerError;
+pub use common::frame::FrameHandlerResult;
+pub use common::frame::FrameDecodeError;
+pub use common::frame::FrameParseError;
+pub use common::frame::FrameTag;
+pub use common::frame::GoAwayErrorCode;
+pub use common::frame::HeaderEncoding;
+pub use common::frame::MAX_CONCURRENT_TUBES_REJECTION_CODE;
+pub use common::frame::MAX_TUBE_ID;
+pub use common::frame::PROTOCOL_VERSION;
+pub use common::frame::RESERVED_EXTENSION_FRAMETYPES;
+pub use common::NegotiatedSettings;
+pub use common::PeerType;
+pub use common::PRIORITY_HEADER;
+pub use common::SendSettingsError;
+pub use common::SESSION_HEADER;
 pub use common::tube;
 
 // "client"-feature exports
@@ -1,23 +1,91 @@
+use std::collections::HashMap;
 use std::collections::VecDeque;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Duration;
 
-use crate::common::tube::Tube;
+use crate::common::capture;
+use crate::common::DrainError;
+use crate::common::frame;
+use crate::common::GoAwayError;
+use crate::common::Keepalive;
+use crate::common::NegotiatedSettings;
+use crate::common::send_drain;
+use crate::common::send_go_away;
+use crate::common::SendScheduler;
+use crate::common::spawn_drain_deadline;
+use crate::common::tube;
+use crate::common::tube::PendingTube;
 
 #[derive(Debug)]
 pub enum ChannelEvent {
-    NewTube(Tube),
+    GoAway {
+        last_tube_id: u64,
+        error_code: frame::GoAwayErrorCode,
+        message: String,
+    },
+    NewTube(PendingTube),
 }
 
 #[derive(Debug)]
 pub(in crate::server) struct ChannelContext {
+    /**
+     * Set once the client's request has arrived and the response body (which
+     * carries frames to the client) has been created.
+     */
+    pub(in crate::server) body_sender: Option<Arc<tokio::sync::Mutex<hyper::body::Sender>>>,
+    pub(in crate::server) id: u64,
+    /**
+     * Set once either peer has started draining the channel. New Tubes from
+     * the client are refused after this.
+     */
+    pub(in crate::server) is_draining: bool,
+    /**
+     * Set once either peer has sent a GoAway. No further events will be
+     * published after this.
+     */
+    pub(in crate::server) is_going_away: bool,
+    pub(in crate::server) keepalive: Arc<Mutex<Keepalive>>,
+    pub(in crate::server) last_peer_tube_id: u64,
     pub(in crate::server) pending_events: VecDeque<ChannelEvent>,
+    /**
+     * Shared by the Tubes made over every transport this channel is attached
+     * to.
+     */
+    pub(in crate::server) send_scheduler: Arc<SendScheduler>,
+    /**
+     * Set once this channel has sent the client a Session frame, in which 
+     * case the client may resume it on a new transport.
+     */
+    pub(in crate::server) session_token: Option<u128>,
+    /**
+     * Set once the client's Settings frame has arrived.
+     */
+    pub(in crate::server) settings: Option<NegotiatedSettings>,
+    pub(in crate::server) tube_managers: Arc<Mutex<HashMap<u64, Arc<Mutex<tube::TubeManager>>>>>,
+    /**
+     * Counts the transports this channel has been attached to, so that the 
+     * loss of a transport that has since been replaced can be told apart from
+     * the loss of the current one.
+     */
+    pub(in crate::server) transport_id: u64,
     pub(in crate::server) waker: Option<std::task::Waker>,
 }
 impl ChannelContext {
     pub fn new() -> Self {
         ChannelContext {
+            body_sender: None,
+            id: capture::next_channel_id(),
+            is_draining: false,
+            is_going_away: false,
+            keepalive: Arc::new(Mutex::new(Keepalive::new())),
+            last_peer_tube_id: 0,
             pending_events: VecDeque::new(),
+            send_scheduler: Arc::new(SendScheduler::new()),
+            session_token: None,
+            settings: None,
+            tube_managers: Arc::new(Mutex::new(HashMap::new())),
+            transport_id: 0,
             waker: None,
         }
     }
@@ -35,6 +103,120 @@ impl Channel {
             ctx,
         }
     }
+
+    /**
+     * Gracefully shuts down the channel: every open Tube receives a 
+     * TubeEvent::ServerMustDrain(reason), new Tubes from the client are 
+     * refused, and any Tube still open once `deadline` has elapsed is 
+     * aborted with AbortReason::ChannelGoingAway.
+     *
+     * The client is only sent a Drain frame (so that its Tubes receive the 
+     * same event) if both peers advertised Features::DRAIN.
+     */
+    pub async fn drain(
+        &mut self,
+        reason: frame::DrainReason,
+        deadline: Duration,
+    ) -> Result<(), DrainError> {
+        let (body_sender, features, tube_managers) = {
+            let mut ctx = self.ctx.lock().unwrap();
+            if ctx.is_going_away {
+                return Err(DrainError::AlreadyGoingAway);
+            }
+            if ctx.is_draining {
+                return Err(DrainError::AlreadyDraining);
+            }
+            let body_sender = match &ctx.body_sender {
+                Some(body_sender) => body_sender.clone(),
+                None => return Err(DrainError::NotConnected),
+            };
+            ctx.is_draining = true;
+            let features = match &ctx.settings {
+                Some(settings) => settings.features,
+                None => frame::Features::empty(),
+            };
+            (body_sender, features, ctx.tube_managers.clone())
+        };
+
+        tube::drain_all_tube_managers(&tube_managers, reason);
+        spawn_drain_deadline(body_sender.clone(), tube_managers, deadline);
+        if features.contains(frame::Features::DRAIN) {
+            send_drain(&body_sender, reason).await
+        } else {
+            Ok(())
+        }
+    }
+
+    /**
+     * Tells the client that this channel is being torn down and terminates
+     * every Tube on it.
+     */
+    pub async fn go_away(
+        &mut self,
+        error_code: frame::GoAwayErrorCode,
+        message: &str,
+    ) -> Result<(), GoAwayError> {
+        let (body_sender, last_peer_tube_id, tube_managers, peer_max_frame_size) = {
+            let mut ctx = self.ctx.lock().unwrap();
+            if ctx.is_going_away {
+                return Err(GoAwayError::AlreadyGoingAway);
+            }
+            let body_sender = match &ctx.body_sender {
+                Some(body_sender) => body_sender.clone(),
+                None => return Err(GoAwayError::NotConnected),
+            };
+            ctx.is_going_away = true;
+            ctx.keepalive.lock().unwrap().stop();
+            if let Some(waker) = ctx.waker.take() {
+                waker.wake();
+            }
+            (
+                body_sender,
+                ctx.last_peer_tube_id,
+                ctx.tube_managers.clone(),
+                ctx.settings.as_ref().map(|settings| settings.peer_max_frame_size),
+            )
+        };
+
+        tube::abort_all_tube_managers(
+            &tube_managers,
+            frame::AbortReason::ChannelGoingAway,
+            tube::TubeEvent::GoAway(error_code, message.to_string()),
+        );
+        send_go_away(
+            &body_sender,
+            last_peer_tube_id,
+            error_code,
+            message,
+            peer_max_frame_size,
+        ).await
+    }
+
+    /**
+     * Identifies this channel's frames in a capture (see 
+     * ChannelConfig::frame_recorder).
+     */
+    pub fn id(&self) -> u64 {
+        self.ctx.lock().unwrap().id
+    }
+
+    /**
+     * The round-trip time measured from the most recent keepalive Ping that 
+     * the client answered, or None if no Ping has been answered yet.
+     */
+    pub fn rtt(&self) -> Option<Duration> {
+        let ctx = self.ctx.lock().unwrap();
+        let rtt = ctx.keepalive.lock().unwrap().rtt();
+        rtt
+    }
+
+    /**
+     * The settings negotiated with the client, or None if the client's 
+     * Settings frame hasn't arrived yet.
+     */
+    pub fn settings(&self) -> Option<NegotiatedSettings> {
+        self.ctx.lock().unwrap().settings.clone()
+    }
 }
 impl futures::stream::Stream for Channel {
     type Item = ChannelEvent;
@@ -48,7 +230,12 @@ impl futures::stream::Stream for Channel {
 
         match ctx.pending_events.pop_front() {
             Some(channel_event) => futures::task::Poll::Ready(Some(channel_event)),
-            None => futures::task::Poll::Pending,
+            None =>
+                if ctx.is_going_away {
+                    futures::task::Poll::Ready(None)
+                } else {
+                    futures::task::Poll::Pending
+                },
         }
     }
 }
@@ -3,31 +3,132 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::Weak;
+use std::time::Duration;
 
 use hyper::body::HttpBody;
 
+use crate::common::capture;
+use crate::common::ChannelConfig;
 use crate::common::frame;
+use crate::common::Keepalive;
+use crate::common::new_session_token;
 use crate::common::PeerType;
+use crate::common::resume_transport;
+use crate::common::send_go_away;
+use crate::common::SendScheduler;
+use crate::common::send_settings;
+use crate::common::SESSION_HEADER;
+use crate::common::session_token_from_header_value;
+use crate::common::spawn_keepalive;
+use crate::common::tube;
 use super::channel::Channel;
 use super::channel::ChannelContext;
 use super::channel::ChannelEvent;
 use super::server_context::ServerContext;
 use super::server_event::ServerEvent;
 
+/**
+ * Gives the client of a resumable channel whose transport was lost `window` to
+ * resume it. If the channel is still without a transport by then, every Tube 
+ * on it is aborted and its session is forgotten.
+ */
+fn spawn_session_expiry(
+    server_ctx: Arc<Mutex<ServerContext>>,
+    channel_ctx: Weak<Mutex<ChannelContext>>,
+    transport_id: u64,
+    window: Duration,
+) {
+    tokio::spawn(async move {
+        tokio::time::sleep(window).await;
+        let channel_ctx = match Weak::upgrade(&channel_ctx) {
+            Some(channel_ctx) => channel_ctx,
+            None => return,
+        };
+        let (token, tube_managers) = {
+            let mut channel_ctx = channel_ctx.lock().unwrap();
+            if channel_ctx.transport_id != transport_id || channel_ctx.is_going_away {
+                return;
+            }
+            channel_ctx.is_going_away = true;
+            if let Some(waker) = channel_ctx.waker.take() {
+                waker.wake();
+            }
+            (channel_ctx.session_token, channel_ctx.tube_managers.clone())
+        };
+
+        log::error!(
+            "Client didn't resume the channel within {:?}. Aborting every Tube \
+             on it.",
+            window,
+        );
+        tube::abort_all_tube_managers(
+            &tube_managers,
+            frame::AbortReason::TransportErrorWhileSynchronizingTubeState,
+            tube::TubeEvent::Abort(
+                frame::AbortReason::TransportErrorWhileSynchronizingTubeState
+            ),
+        );
+        if let Some(token) = token {
+            server_ctx.lock().unwrap().sessions.remove(&token);
+        }
+    });
+}
+
 pub(in crate::server) struct TubezHttpReq {
+    /**
+     * The channel made for this connection, until it is published to the 
+     * application. A connection whose request resumes an existing channel 
+     * never publishes its own.
+     */
+    channel: Option<Channel>,
+    channel_config: ChannelConfig,
     channel_ctx: Weak<Mutex<ChannelContext>>,
     server_ctx: Arc<Mutex<ServerContext>>,
 }
 impl TubezHttpReq {
     fn new(
         server_ctx: Arc<Mutex<ServerContext>>,
+        channel: Channel,
         channel_ctx: Weak<Mutex<ChannelContext>>,
+        channel_config: ChannelConfig,
     ) -> Self {
         TubezHttpReq {
+            channel: Some(channel),
+            channel_config,
             channel_ctx,
             server_ctx,
         }
     }
+
+    /**
+     * The resumable channel identified by a request's SESSION_HEADER, if it 
+     * hasn't expired.
+     */
+    fn find_session(
+        &self,
+        value: &hyper::header::HeaderValue,
+    ) -> Option<Arc<Mutex<ChannelContext>>> {
+        let token = session_token_from_header_value(value.to_str().ok()?)?;
+        let channel_ctx = self.server_ctx.lock().unwrap().sessions.get(&token)?.upgrade()?;
+        if channel_ctx.lock().unwrap().is_going_away {
+            return None;
+        }
+        Some(channel_ctx)
+    }
+
+    fn publish_channel(&mut self) {
+        let channel = match self.channel.take() {
+            Some(channel) => channel,
+            None => return,
+        };
+        let mut server_ctx = self.server_ctx.lock().unwrap();
+        server_ctx.pending_events.push_back(
+            Ok(ServerEvent::NewChannel(channel))
+        );
+        if let Some(waker) = server_ctx.waker.take() {
+            waker.wake();
+        }
+    }
 }
 impl hyper::service::Service<hyper::Request<hyper::Body>> for TubezHttpReq {
     type Response = hyper::Response<hyper::Body>;
@@ -42,22 +143,134 @@ impl hyper::service::Service<hyper::Request<hyper::Body>> for TubezHttpReq {
     }
 
     fn call(&mut self, req: hyper::Request<hyper::Body>) -> Self::Future {
+        let resumed_channel_ctx = match req.headers().get(SESSION_HEADER) {
+            Some(value) => match self.find_session(value) {
+                Some(channel_ctx) => Some(channel_ctx),
+                None => {
+                    log::error!(
+                        "Client tried to resume a channel that doesn't exist \
+                         or has expired."
+                    );
+                    let mut res = hyper::Response::new(hyper::Body::empty());
+                    *res.status_mut() = hyper::StatusCode::NOT_FOUND;
+                    return future::ok(res);
+                },
+            },
+            None => {
+                self.publish_channel();
+                None
+            },
+        };
+        let is_resuming = resumed_channel_ctx.is_some();
+        let channel_ctx = match &resumed_channel_ctx {
+            Some(channel_ctx) => Arc::downgrade(channel_ctx),
+            None => self.channel_ctx.clone(),
+        };
+
         let (body_sender, body) = hyper::Body::channel();
-        let mut body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+        let channel_id = match Weak::upgrade(&channel_ctx) {
+            Some(channel_ctx) => channel_ctx.lock().unwrap().id,
+            None => capture::next_channel_id(),
+        };
+        let body = match &self.channel_config.frame_recorder {
+            Some(recorder) => capture::record_sent_body(
+                body,
+                recorder.clone(),
+                channel_id,
+                PeerType::Server,
+            ),
+            None => body,
+        };
         let res = hyper::Response::new(body);
 
         // TODO: Sanitize these headers (e.g. blank out auth, app-headers, etc)
         log::trace!("Http request received. Headers: {:?}", req.headers());
 
-        let channel_ctx = self.channel_ctx.clone();
+        let channel_config = self.channel_config.clone();
+        let server_ctx = self.server_ctx.clone();
         let mut body = req.into_body();
         tokio::spawn(async move {
-            let mut frame_decoder = frame::Decoder::new();
-            let mut tube_store = Arc::new(Mutex::new(HashMap::new()));
-            let mut frame_handler = frame::FrameHandler::new(
+            let mut frame_decoder = frame::Decoder::new_with_config(
+                channel_config.decoder_config.clone(),
+            );
+            let (
+                mut tube_store,
+                keepalive,
+                channel_body_sender,
+                transport_id,
+                send_scheduler,
+                last_peer_tube_id,
+            ) = match Weak::upgrade(&channel_ctx) {
+                Some(channel_ctx) => {
+                    let mut channel_ctx = channel_ctx.lock().unwrap();
+                    channel_ctx.transport_id += 1;
+                    (
+                        channel_ctx.tube_managers.clone(), 
+                        channel_ctx.keepalive.clone(),
+                        channel_ctx.body_sender.clone(),
+                        channel_ctx.transport_id,
+                        channel_ctx.send_scheduler.clone(),
+                        channel_ctx.last_peer_tube_id,
+                    )
+                },
+                None => (
+                    Arc::new(Mutex::new(HashMap::new())),
+                    Arc::new(Mutex::new(Keepalive::new())),
+                    None,
+                    0,
+                    Arc::new(SendScheduler::new()),
+                    0,
+                ),
+            };
+            let mut body_sender = match channel_body_sender {
+                Some(channel_body_sender) if is_resuming => {
+                    log::trace!("Resuming channel on a new transport...");
+                    keepalive.lock().unwrap().restart();
+                    if let Err(e) = resume_transport(
+                        &channel_body_sender,
+                        body_sender,
+                        &channel_config,
+                        &tube_store,
+                    ).await {
+                        log::error!("Error resuming channel: {:?}", e);
+                    }
+                    channel_body_sender
+                },
+                _ => {
+                    let body_sender = Arc::new(tokio::sync::Mutex::new(body_sender));
+                    if let Some(channel_ctx) = Weak::upgrade(&channel_ctx) {
+                        channel_ctx.lock().unwrap().body_sender = Some(body_sender.clone());
+                    }
+                    if let Err(e) = send_settings(&body_sender, &channel_config).await {
+                        log::error!("Error sending Settings: {:?}", e);
+                    }
+                    body_sender
+                },
+            };
+
+            let dead_channel_ctx = channel_ctx.clone();
+            spawn_keepalive(
+                keepalive.clone(),
+                Arc::downgrade(&body_sender),
+                tube_store.clone(),
+                &channel_config,
+                move || {
+                    if let Some(channel_ctx) = Weak::upgrade(&dead_channel_ctx) {
+                        let mut channel_ctx = channel_ctx.lock().unwrap();
+                        channel_ctx.is_going_away = true;
+                        if let Some(waker) = channel_ctx.waker.take() {
+                            waker.wake();
+                        }
+                    }
+                },
+            );
+            let mut frame_handler = frame::FrameHandler::new_with_send_scheduler(
                 PeerType::Server,
                 &mut tube_store,
+                &channel_config,
+                send_scheduler,
             );
+            frame_handler.set_last_peer_tube_id(last_peer_tube_id);
 
             while let Some(data_result) = body.data().await {
                 let raw_data = match data_result {
@@ -71,55 +284,226 @@ impl hyper::service::Service<hyper::Request<hyper::Body>> for TubezHttpReq {
                     },
                 };
 
-                let mut new_frames = match frame_decoder.decode(raw_data.to_vec()) {
-                    Ok(frames) => frames,
-                    Err(e) => {
-                        // TODO: What happens if we get weird data from the client? Should we 
-                        //       log and dump it? Trash the request (sec implications of that?)?
-                        // 
-                        //       For now just log and ignore to avoid some kind of hand-wavy 
-                        //       DDOS situation
-                        log::error!("Frame decode error: {:?}", e);
-                        return;
-                    },
+                if let Some(recorder) = &channel_config.frame_recorder {
+                    recorder.record_chunk(
+                        channel_id,
+                        PeerType::Server,
+                        capture::Direction::Received,
+                        &raw_data,
+                    );
+                }
+
+                // Frames that were decoded before any malformed data in this 
+                // chunk are handled before the decode error is.
+                let (mut new_frames, decode_error) = match frame_decoder.decode(raw_data) {
+                    Ok(frames) => (frames, None),
+                    Err(mut e) => (std::mem::take(&mut e.decoded_frames), Some(e)),
                 };
 
+                let mut protocol_violation = None;
                 while let Some(frame) = new_frames.pop_front() {
                     log::trace!("New frame received: {:?}", frame);
                     match frame_handler.handle_frame(frame, &mut body_sender).await {
-                        Ok(frame::FrameHandlerResult::NewTube(mut tube)) => {
+                        Ok(frame::FrameHandlerResult::NewTube(pending_tube)) => {
+                            let rejected_tube = match Weak::upgrade(&channel_ctx) {
+                                Some(channel_ctx) => {
+                                    let mut channel_ctx = channel_ctx.lock().unwrap();
+                                    if channel_ctx.is_going_away || channel_ctx.is_draining {
+                                        log::error!(
+                                            "Received a new Tube(id={}) from \
+                                             the client on a channel that is \
+                                             going away or draining!",
+                                             pending_tube.get_id(),
+                                        );
+                                        Some((pending_tube, frame::AbortReason::ChannelGoingAway))
+                                    } else {
+                                        channel_ctx.last_peer_tube_id = pending_tube.get_id();
+                                        channel_ctx.pending_events.push_back(
+                                            ChannelEvent::NewTube(pending_tube)
+                                        );
+                                        if let Some(waker) = channel_ctx.waker.take() {
+                                            waker.wake();
+                                        }
+                                        None
+                                    }
+                                },
+                                None => {
+                                    log::error!(
+                                        "Received a new Tube(id={}) from the \
+                                         client on a channel that has been \
+                                         dropped!",
+                                         pending_tube.get_id(),
+                                    );
+                                    Some((pending_tube, frame::AbortReason::ApplicationError))
+                                },
+                            };
+
+                            if let Some((pending_tube, reason)) = rejected_tube {
+                                match pending_tube.into_tube().abort_internal(reason).await {
+                                    Ok(()) => (),
+                                    Err(e) => log::error!(
+                                        "Error aborting tube: `{:?}`", 
+                                        e,
+                                    ),
+                                }
+                            }
+                        },
+                        Ok(frame::FrameHandlerResult::GoAway {
+                            last_tube_id,
+                            error_code,
+                            message,
+                        }) => {
                             if let Some(channel_ctx) = Weak::upgrade(&channel_ctx) {
                                 let mut channel_ctx = channel_ctx.lock().unwrap();
                                 channel_ctx.pending_events.push_back(
-                                    ChannelEvent::NewTube(tube)
+                                    ChannelEvent::GoAway {
+                                        last_tube_id,
+                                        error_code,
+                                        message,
+                                    }
                                 );
+                                channel_ctx.is_going_away = true;
                                 if let Some(waker) = channel_ctx.waker.take() {
                                     waker.wake();
                                 }
-                            } else {
-                                log::error!(
-                                    "Received a new Tube(id={}) from the \
-                                     client on a channel that has been \
-                                     dropped!",
-                                     tube.get_id(),
-                                );
-                                match tube.abort_internal(
-                                    frame::AbortReason::ApplicationError
-                                ).await {
-                                    Ok(()) => (),
+                            }
+                            keepalive.lock().unwrap().stop();
+                        },
+                        Ok(frame::FrameHandlerResult::Drain(reason)) => {
+                            log::trace!("Client is draining the channel ({:?}).", reason);
+                            if let Some(channel_ctx) = Weak::upgrade(&channel_ctx) {
+                                channel_ctx.lock().unwrap().is_draining = true;
+                            }
+                        },
+                        Ok(frame::FrameHandlerResult::Pong { opaque }) => {
+                            keepalive.lock().unwrap().handle_pong(opaque);
+                        },
+                        Ok(frame::FrameHandlerResult::Settings(settings)) => {
+                            keepalive.lock().unwrap().set_negotiated(
+                                settings.features.contains(frame::Features::KEEPALIVE)
+                            );
+                            let is_resumable = settings.features.contains(
+                                frame::Features::SESSION_RESUMPTION
+                            );
+                            let new_token = match Weak::upgrade(&channel_ctx) {
+                                Some(channel_ctx) => {
+                                    let mut channel_ctx = channel_ctx.lock().unwrap();
+                                    channel_ctx.settings = Some(settings);
+                                    if is_resumable && channel_ctx.session_token.is_none() {
+                                        match new_session_token() {
+                                            Ok(token) => {
+                                                channel_ctx.session_token = Some(token);
+                                                Some(token)
+                                            },
+                                            Err(e) => {
+                                                log::error!(
+                                                    "Error generating a session \
+                                                     token, so the channel can't \
+                                                     be resumed: {:?}",
+                                                    e,
+                                                );
+                                                None
+                                            },
+                                        }
+                                    } else {
+                                        None
+                                    }
+                                },
+                                None => None,
+                            };
+                            if let Some(token) = new_token {
+                                {
+                                    let mut server_ctx = server_ctx.lock().unwrap();
+                                    server_ctx.sessions.retain(|_, channel_ctx| 
+                                        channel_ctx.strong_count() > 0
+                                    );
+                                    server_ctx.sessions.insert(token, channel_ctx.clone());
+                                }
+                                match frame::encode::session_frame(token) {
+                                    Ok(frame_data) => {
+                                        let mut sender = body_sender.lock().await;
+                                        log::trace!("Sending Session...");
+                                        if let Err(e) = sender.send_data(frame_data).await {
+                                            log::error!("Error sending Session: {:?}", e);
+                                        }
+                                    },
                                     Err(e) => log::error!(
-                                        "Error aborting tube: `{:?}`", 
+                                        "Error encoding Session frame: {:?}", 
                                         e,
                                     ),
                                 }
                             }
                         },
+                        // The FrameHandler refuses Session frames from the 
+                        // client, so this is never returned to a server.
+                        Ok(frame::FrameHandlerResult::Session { .. }) => (),
                         Ok(frame::FrameHandlerResult::FullyHandled) => (),
+                        Err(e) if e.is_fatal() => {
+                            protocol_violation = Some((
+                                frame::GoAwayErrorCode::from(&e),
+                                format!("{:?}", e),
+                            ));
+                            break;
+                        },
                         Err(e) => log::error!("Error handling frame: {:?}", e),
                     }
                 }
+
+                // A fatal FrameHandler error ends the channel right away, but a 
+                // decode error only does once the frames before it are handled.
+                let protocol_violation = protocol_violation.or_else(|| decode_error.map(|e| (
+                    frame::GoAwayErrorCode::from(&e.parse_error),
+                    format!("{:?}", e.parse_error),
+                )));
+                if let Some((error_code, message)) = protocol_violation {
+                    // The client has violated the protocol, so nothing else it 
+                    // sends on this channel can be trusted. Tell the client why, 
+                    // terminate every Tube, and stop reading from the client.
+                    log::error!("Protocol violation from client: {}", message);
+                    if let Err(e) = send_go_away(
+                        &body_sender,
+                        frame_handler.last_peer_tube_id(),
+                        error_code,
+                        &message,
+                        frame_handler.peer_max_frame_size(),
+                    ).await {
+                        log::error!("Error sending GoAway: {:?}", e);
+                    }
+                    frame_handler.abort_all_tubes(
+                        frame::AbortReason::ProtocolViolation
+                    );
+                    keepalive.lock().unwrap().stop();
+                    if let Some(channel_ctx) = Weak::upgrade(&channel_ctx) {
+                        let mut channel_ctx = channel_ctx.lock().unwrap();
+                        channel_ctx.is_going_away = true;
+                        if let Some(waker) = channel_ctx.waker.take() {
+                            waker.wake();
+                        }
+                    }
+                    return;
+                }
             }
             log::trace!("Stream of httprequest data from client has ended.");
+
+            let (is_current_transport, is_resumable) = match Weak::upgrade(&channel_ctx) {
+                Some(channel_ctx) => {
+                    let channel_ctx = channel_ctx.lock().unwrap();
+                    (
+                        channel_ctx.transport_id == transport_id,
+                        channel_ctx.session_token.is_some() && !channel_ctx.is_going_away,
+                    )
+                },
+                None => (true, false),
+            };
+            if !is_current_transport {
+                // The channel has already been resumed on a newer transport.
+                return;
+            }
+            keepalive.lock().unwrap().stop();
+            if let (true, Some(window)) = (is_resumable, channel_config.session_resume_window) {
+                log::trace!("Waiting up to {:?} for the client to resume the channel...", window);
+                spawn_session_expiry(server_ctx, channel_ctx, transport_id, window);
+            }
         });
 
         future::ok(res)
@@ -127,24 +511,19 @@ impl hyper::service::Service<hyper::Request<hyper::Body>> for TubezHttpReq {
 }
 
 pub(in crate::server) struct TubezMakeSvc {
+    channel_config: ChannelConfig,
     server_ctx: Arc<Mutex<ServerContext>>,
 }
 impl TubezMakeSvc {
-    pub fn new(server_ctx: Arc<Mutex<ServerContext>>) -> Self {
+    pub fn new(
+        server_ctx: Arc<Mutex<ServerContext>>,
+        channel_config: ChannelConfig,
+    ) -> Self {
         TubezMakeSvc {
+            channel_config,
             server_ctx,
         }
     }
-
-    fn publish_channel(&mut self, channel: Channel) {
-        let mut server_ctx = self.server_ctx.lock().unwrap();
-        server_ctx.pending_events.push_back(
-            Ok(ServerEvent::NewChannel(channel))
-        );
-        if let Some(waker) = server_ctx.waker.take() {
-            waker.wake();
-        }
-    }
 }
 impl<T> hyper::service::Service<T> for TubezMakeSvc {
     type Response = TubezHttpReq;
@@ -162,10 +541,11 @@ impl<T> hyper::service::Service<T> for TubezMakeSvc {
         let channel_ctx = Arc::new(Mutex::new(ChannelContext::new()));
         let weak_channel = Arc::downgrade(&channel_ctx);
         let channel = Channel::new(channel_ctx);
-        self.publish_channel(channel);
         future::ok(TubezHttpReq::new(
             self.server_ctx.clone(),
+            channel,
             weak_channel,
+            self.channel_config.clone(),
         ))
     }
 }
@@ -1,8 +1,10 @@
+use std::collections::HashMap;
 use std::collections::VecDeque;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::sync::Mutex;
 
+use crate::common::ChannelConfig;
 use super::hyper_tubez_service::TubezMakeSvc;
 use super::server_context::ServerContext;
 use super::server_error::ServerError;
@@ -13,16 +15,28 @@ pub struct Server {
 }
 impl Server {
     pub async fn new(addr: &SocketAddr) -> Self {
+        Self::new_with_config(addr, ChannelConfig::default()).await
+    }
+
+    /**
+     * Like Server::new(), but every channel accepted by this server will use 
+     * the provided ChannelConfig.
+     */
+    pub async fn new_with_config(
+        addr: &SocketAddr,
+        channel_config: ChannelConfig,
+    ) -> Self {
         let server_ctx = Arc::new(Mutex::new(ServerContext {
             is_complete: false,
             pending_events: VecDeque::new(),
+            sessions: HashMap::new(),
             waker: None,
         }));
 
         let hyper_server = 
             hyper::Server::bind(&addr)
                 .http2_only(true)
-                .serve(TubezMakeSvc::new(server_ctx.clone()));
+                .serve(TubezMakeSvc::new(server_ctx.clone(), channel_config));
 
         let tubez_server = Server {
             server_ctx: server_ctx.clone(),
@@ -1,11 +1,20 @@
+use std::collections::HashMap;
 use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::Weak;
 use std::task;
 
+use super::channel::ChannelContext;
 use super::server_error::ServerError;
 use super::server_event::ServerEvent;
 
 pub(in crate::server) struct ServerContext {
     pub(in crate::server) is_complete: bool,
     pub(in crate::server) pending_events: VecDeque<Result<ServerEvent, ServerError>>,
+    /**
+     * The resumable channels on this server, keyed by the token that a client
+     * presents to resume one (see SESSION_HEADER).
+     */
+    pub(in crate::server) sessions: HashMap<u128, Weak<Mutex<ChannelContext>>>,
     pub(in crate::server) waker: Option<task::Waker>,
 }